@@ -2,11 +2,17 @@ use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use std::fmt;
 use std::fs::File;
 use std::io;
-use std::path::Path;
-use symphonia::core::audio::AudioBufferRef;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::Decoder;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatReader, SeekMode, SeekTo};
 use symphonia::core::io::MediaSourceStream;
-use symphonia::core::probe::Hint;
-use hound::{WavSpec, WavWriter};
+use symphonia::core::meta::MetadataRevision;
+use symphonia::core::probe::{Hint, ProbeResult};
 
 // Custom error type for audio loading errors
 #[derive(Debug)]
@@ -14,6 +20,7 @@ pub enum AudioError {
     Io(io::Error),
     UnsupportedFormat,
     DecodingError(String),
+    Cancelled,
 }
 
 impl fmt::Display for AudioError {
@@ -22,6 +29,7 @@ impl fmt::Display for AudioError {
             AudioError::Io(err) => write!(f, "IO error: {}", err),
             AudioError::UnsupportedFormat => write!(f, "Unsupported audio format"),
             AudioError::DecodingError(msg) => write!(f, "Decoding error: {}", msg),
+            AudioError::Cancelled => write!(f, "Decoding cancelled"),
         }
     }
 }
@@ -58,17 +66,17 @@ impl Audio {
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
                 callback(data);
             },
-            |err| eprintln!("Stream error: {}", err),
+            |err| log::error!(target: "audio", "Stream error: {}", err),
             None,
         ) {
             Ok(stream) => {
                 if let Err(e) = stream.pause() {
-                    eprintln!("Failed to pause new stream: {}", e);
+                    log::error!(target: "audio", "Failed to pause new stream: {}", e);
                 }
                 Some(stream)
             }
             Err(e) => {
-                eprintln!("Failed to create audio stream: {}", e);
+                log::error!(target: "audio", "Failed to create audio stream: {}", e);
                 None
             }
         }
@@ -76,98 +84,342 @@ impl Audio {
 }
 
 pub fn load_audio(path: &Path) -> Result<(Vec<f32>, u32), AudioError> {
+    let decoded = decode_audio(path, &mut |_| true)?;
+    Ok((decoded.samples, decoded.sample_rate))
+}
+
+/// A whole file decoded by `decode_audio`
+pub struct DecodedSamples {
+    pub samples: Vec<f32>, // Interleaved
+    pub sample_rate: u32,
+    pub damaged_packets: usize, // Skipped, so the audio has gaps where they were
+}
+
+/// Decode a whole file to interleaved f32 samples.
+/// `on_progress` gets the fraction decoded so far (0.0 to 1.0) and returns false to cancel.
+pub fn decode_audio(
+    path: &Path,
+    on_progress: &mut dyn FnMut(f32) -> bool,
+) -> Result<DecodedSamples, AudioError> {
+    let mut reader = AudioReader::open(path)?;
+    let mut samples = Vec::new();
+
+    while let Some(position) = reader.read_packet(&mut samples)? {
+        let progress = match reader.total_frames {
+            Some(total) if total > 0 => (position as f32 / total as f32).min(1.0),
+            _ => 0.0,
+        };
+        if !on_progress(progress) {
+            return Err(AudioError::Cancelled);
+        }
+    }
+
+    if reader.damaged_packets > 0 {
+        log::warn!(target: "audio",
+            "Skipped {} damaged packets in {}",
+            reader.damaged_packets,
+            path.display()
+        );
+    }
+    Ok(DecodedSamples {
+        samples,
+        sample_rate: reader.sample_rate,
+        damaged_packets: reader.damaged_packets,
+    })
+}
+
+// Probe the container of an audio file
+fn open_format(path: &Path) -> Result<ProbeResult, AudioError> {
     let file = File::open(path)?;
     let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
     let mut hint = Hint::new();
     hint.with_extension(path.extension().and_then(|s| s.to_str()).unwrap_or(""));
 
-    let probed = symphonia::default::get_probe()
+    symphonia::default::get_probe()
         .format(&hint, mss, &Default::default(), &Default::default())
-        .map_err(|e| AudioError::DecodingError(e.to_string()))?;
+        .map_err(|e| match e {
+            SymphoniaError::IoError(e) => AudioError::Io(e),
+            SymphoniaError::Unsupported(_) => AudioError::UnsupportedFormat,
+            e => AudioError::DecodingError(e.to_string()),
+        })
+}
 
-    let mut format = probed.format;
-    let track = format
-        .default_track()
-        .ok_or_else(|| AudioError::DecodingError("No default track found".to_string()))?;
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &Default::default())
-        .map_err(|e| AudioError::DecodingError(e.to_string()))?;
-
-    let sample_rate = track
-        .codec_params
-        .sample_rate
-        .ok_or_else(|| AudioError::DecodingError("No sample rate found".to_string()))?;
-    let mut samples = Vec::new();
+/// Packet by packet decoder for one audio file, for reading files too long to hold in memory.
+/// Damaged packets are skipped and counted rather than failing the whole file.
+pub struct AudioReader {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    sample_buffer: Option<SampleBuffer<f32>>, // Reused for converting decoded packets
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub total_frames: Option<u64>, // From the headers, if the container knows it
+    pub damaged_packets: usize,
+}
 
-    while let Ok(packet) = format.next_packet() {
-        let buffer = decoder
-            .decode(&packet)
-            .map_err(|e| AudioError::DecodingError(e.to_string()))?;
-        match buffer {
-            AudioBufferRef::F32(buf) => {
-                let planes_binding = buf.planes();
-                let planes = planes_binding.planes();
-                for i in 0..planes[0].len() {
-                    for plane in planes.iter() {
-                        samples.push(plane[i]);
-                    }
+impl AudioReader {
+    pub fn open(path: &Path) -> Result<Self, AudioError> {
+        let format = open_format(path)?.format;
+        let track = format
+            .default_track()
+            .ok_or_else(|| AudioError::DecodingError("No default track found".to_string()))?;
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &Default::default())
+            .map_err(|e| match e {
+                SymphoniaError::Unsupported(_) => AudioError::UnsupportedFormat,
+                e => AudioError::DecodingError(e.to_string()),
+            })?;
+
+        let sample_rate = track
+            .codec_params
+            .sample_rate
+            .ok_or_else(|| AudioError::DecodingError("No sample rate found".to_string()))?;
+
+        Ok(Self {
+            track_id: track.id,
+            sample_rate,
+            channels: track.codec_params.channels.map(|c| c.count() as u16).unwrap_or(1),
+            total_frames: track.codec_params.n_frames,
+            sample_buffer: None,
+            damaged_packets: 0,
+            format,
+            decoder,
+        })
+    }
+
+    /// Decode the next packet and append its samples, interleaved, to `samples`.
+    /// Returns the frame the packet started at, or None at the end of the file.
+    pub fn read_packet(&mut self, samples: &mut Vec<f32>) -> Result<Option<u64>, AudioError> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                // The end of the stream is reported as an unexpected EOF
+                Err(SymphoniaError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Ok(None);
                 }
+                // A new chained stream starts here; this reader only plays the first one
+                Err(SymphoniaError::ResetRequired) => return Ok(None),
+                Err(SymphoniaError::IoError(e)) => return Err(AudioError::Io(e)),
+                Err(e) => return Err(AudioError::DecodingError(e.to_string())),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
             }
-            AudioBufferRef::S32(buf) => {
-                let planes_binding = buf.planes();
-                let planes = planes_binding.planes();
-                for i in 0..planes[0].len() {
-                    for plane in planes.iter() {
-                        samples.push(plane[i] as f32 / i32::MAX as f32);
+
+            match self.decoder.decode(&packet) {
+                Ok(buffer) => {
+                    let spec = *buffer.spec();
+                    let needed = buffer.capacity() * spec.channels.count();
+                    if self.sample_buffer.as_ref().is_none_or(|b| b.capacity() < needed) {
+                        self.sample_buffer = Some(SampleBuffer::new(buffer.capacity() as u64, spec));
                     }
+                    let sample_buffer = self.sample_buffer.as_mut().unwrap();
+                    sample_buffer.copy_interleaved_ref(buffer);
+                    samples.extend_from_slice(sample_buffer.samples());
+                    return Ok(Some(packet.ts()));
                 }
-            }
-            AudioBufferRef::S16(buf) => {
-                let planes_binding = buf.planes();
-                let planes = planes_binding.planes();
-                for i in 0..planes[0].len() {
-                    for plane in planes.iter() {
-                        samples.push(plane[i] as f32 / i16::MAX as f32);
-                    }
+                // Damaged data: drop this packet and carry on with the next one
+                Err(SymphoniaError::DecodeError(e)) => {
+                    self.damaged_packets += 1;
+                    log::debug!(target: "audio", "Skipping damaged packet at {}: {}", packet.ts(), e);
                 }
-            }
-            AudioBufferRef::U8(buf) => {
-                let planes_binding = buf.planes();
-                let planes = planes_binding.planes();
-                for i in 0..planes[0].len() {
-                    for plane in planes.iter() {
-                        samples.push((plane[i] as f32 - 128.0) / 128.0);
-                    }
+                Err(SymphoniaError::IoError(e)) => {
+                    self.damaged_packets += 1;
+                    log::debug!(target: "audio", "Skipping unreadable packet at {}: {}", packet.ts(), e);
                 }
+                Err(e) => return Err(AudioError::DecodingError(e.to_string())),
+            }
+        }
+    }
+
+    /// Move to `frame`. Returns the frame decoding actually resumes at, which can be a little earlier.
+    pub fn seek(&mut self, frame: u64) -> Result<u64, AudioError> {
+        let seeked = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::TimeStamp {
+                    ts: frame,
+                    track_id: self.track_id,
+                },
+            )
+            .map_err(|e| AudioError::DecodingError(e.to_string()))?;
+        self.decoder.reset();
+        Ok(seeked.actual_ts)
+    }
+}
+
+/// Convert interleaved samples from one sample rate to another with a windowed sinc filter
+pub fn resample(samples: &[f32], channels: u16, from: u32, to: u32) -> Vec<f32> {
+    const TAPS: isize = 16; // Filter half-width in input samples
+
+    let channels = channels.max(1) as usize;
+    if from == to || from == 0 || to == 0 || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let frames_in = samples.len() / channels;
+    let frames_out = (frames_in as u64 * to as u64 / from as u64) as usize;
+    let step = from as f64 / to as f64;
+    // Below the lower of the two Nyquist frequencies, so downsampling doesn't alias
+    let cutoff = (to as f64 / from as f64).min(1.0);
+    let mut output = Vec::with_capacity(frames_out * channels);
+    let mut sums = vec![0.0f64; channels];
+
+    for frame in 0..frames_out {
+        let position = frame as f64 * step;
+        let center = position.floor() as isize;
+        sums.fill(0.0);
+        let mut total_weight = 0.0;
+
+        for k in (center - TAPS + 1)..=(center + TAPS) {
+            if k < 0 || k as usize >= frames_in {
+                continue;
             }
-            _ => {
-                return Err(AudioError::UnsupportedFormat);
+            let x = position - k as f64;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                let a = std::f64::consts::PI * x * cutoff;
+                a.sin() / a
+            };
+            // Hann window across the filter width
+            let window = 0.5 + 0.5 * (std::f64::consts::PI * x / TAPS as f64).cos();
+            let weight = sinc * window;
+            total_weight += weight;
+            let input = &samples[k as usize * channels..(k as usize + 1) * channels];
+            for (sum, &sample) in sums.iter_mut().zip(input) {
+                *sum += sample as f64 * weight;
             }
         }
+
+        for sum in &sums {
+            let value = if total_weight != 0.0 { sum / total_weight } else { 0.0 };
+            output.push(value as f32);
+        }
     }
 
-    Ok((samples, sample_rate))
+    output
+}
+
+/// Formats the decoder is built with, as (name, file extensions). The extensions are only used to
+/// filter file dialogs; whether a file is audio is decided by `is_audio_file` from its contents.
+pub const AUDIO_FORMATS: &[(&str, &[&str])] = &[
+    ("WAV", &["wav", "wave"]),
+    ("AIFF", &["aif", "aiff", "aifc"]),
+    ("MP3", &["mp3"]),
+    ("Ogg Vorbis", &["ogg", "oga"]),
+    ("FLAC", &["flac"]),
+    ("AAC", &["aac", "adts"]),
+    ("MPEG-4 Audio (AAC, ALAC)", &["m4a", "mp4", "m4b"]),
+    ("Matroska Audio", &["mka", "webm"]),
+];
+
+/// Every extension in `AUDIO_FORMATS`, for file dialog filters
+pub fn audio_extensions() -> Vec<&'static str> {
+    AUDIO_FORMATS
+        .iter()
+        .flat_map(|(_, extensions)| extensions.iter().copied())
+        .collect()
 }
 
-pub fn save_audio(path: &Path, samples: &[f32], sample_rate: u32) -> Result<(), AudioError> {
-    let spec = WavSpec {
-        channels: 1,  // mono
-        sample_rate,
-        bits_per_sample: 32,
-        sample_format: hound::SampleFormat::Float,
+// Probe results by path, so lists redrawn every frame don't reopen files
+type ProbeCache = HashMap<PathBuf, (Option<SystemTime>, bool)>;
+static PROBED: OnceLock<Mutex<ProbeCache>> = OnceLock::new();
+
+// Entries kept before the probe cache starts over, so browsing many folders doesn't grow it forever
+const PROBE_CACHE_LIMIT: usize = 4096;
+
+/// Whether the file holds audio the decoder can play. Decided by the file's contents, so
+/// misnamed files and files without an extension are recognised too.
+pub fn is_audio_file(path: &Path) -> bool {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
     };
+    if !metadata.is_file() {
+        return false;
+    }
+    let modified = metadata.modified().ok();
 
-    let mut writer = WavWriter::create(path, spec)
-        .map_err(|e| AudioError::Io(io::Error::new(io::ErrorKind::Other, e)))?;
+    let cache = PROBED.get_or_init(Default::default);
+    if let Some((cached_modified, is_audio)) = cache.lock().unwrap().get(path) {
+        if *cached_modified == modified {
+            return *is_audio;
+        }
+    }
 
-    for &sample in samples {
-        writer.write_sample(sample)
-            .map_err(|e| AudioError::Io(io::Error::new(io::ErrorKind::Other, e)))?;
+    // The container has to be recognised and carry a track we have a decoder for
+    let is_audio = open_format(path).is_ok_and(|probed| {
+        probed.format.default_track().is_some_and(|track| {
+            symphonia::default::get_codecs()
+                .make(&track.codec_params, &Default::default())
+                .is_ok()
+        })
+    });
+    log::trace!(target: "audio", "Probed {}: audio = {}", path.display(), is_audio);
+    let mut cache = cache.lock().unwrap();
+    if cache.len() >= PROBE_CACHE_LIMIT {
+        cache.clear();
     }
+    cache.insert(path.to_path_buf(), (modified, is_audio));
+    is_audio
+}
+
+/// Format details read from a file's headers without decoding it
+#[derive(Debug, Clone)]
+pub struct AudioInfo {
+    pub codec: String,
+    pub bit_depth: Option<u32>, // Of the stored samples; lossy codecs have none
+    pub channels: Option<u16>,
+    pub sample_rate: Option<u32>,
+    pub duration: Option<f64>, // Seconds
+    pub tags: Vec<(String, String)>, // Title, artist and so on, as named by the file
+}
+
+pub fn probe_audio(path: &Path) -> Result<AudioInfo, AudioError> {
+    let mut probed = open_format(path)?;
+
+    let track = probed
+        .format
+        .default_track()
+        .ok_or_else(|| AudioError::DecodingError("No default track found".to_string()))?;
+    let params = track.codec_params.clone();
 
-    writer.finalize()
-        .map_err(|e| AudioError::Io(io::Error::new(io::ErrorKind::Other, e)))?;
+    let codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map(|descriptor| descriptor.short_name.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    // Tags can sit in front of the container (ID3) or inside it
+    let mut tags = Vec::new();
+    let mut collect = |revision: &MetadataRevision| {
+        for tag in revision.tags() {
+            let key = match tag.std_key {
+                Some(std_key) => format!("{:?}", std_key),
+                None => tag.key.clone(),
+            };
+            tags.push((key, tag.value.to_string()));
+        }
+    };
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            collect(revision);
+        }
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        collect(revision);
+    }
 
-    Ok(())
+    Ok(AudioInfo {
+        codec,
+        bit_depth: params.bits_per_sample.or(params.bits_per_coded_sample),
+        channels: params.channels.map(|c| c.count() as u16),
+        sample_rate: params.sample_rate,
+        duration: params
+            .n_frames
+            .zip(params.sample_rate)
+            .map(|(frames, rate)| frames as f64 / rate as f64),
+        tags,
+    })
 }
@@ -1,16 +1,31 @@
-use crate::audio::{load_audio, Audio};
-use crate::group::Group;
-use crate::config::{load_waveform_data, save_waveform_data, WaveformData};
+use crate::audio::{self, load_audio, Audio};
+use crate::group::{self, Group, GroupRenderer};
+use crate::library;
+use crate::loudness::{self, Loudness};
+use crate::media::{self, MissingMedia};
+use crate::media_pool::{self, MediaPool, PoolEntry};
+use crate::pcm_cache::{self, Pcm};
+use crate::peaks::{PeakCache, Peaks};
+use crate::decode::{self, DecodeHandle, DecodedAudio};
+use crate::export::{self, ExportSettings, StemOptions, StemSource, StemTap};
+use crate::stream::{DiskStream, Playback};
+use crate::project::{self, GroupLoad};
+use crate::recovery;
+use crate::config::load_waveform_data;
 use cpal::traits::StreamTrait;
 use rfd::FileDialog;
-use rfd::MessageDialog;
+use rfd::{MessageButtons, MessageDialog, MessageDialogResult};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs;
+use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 // --- Define SelectionRect Struct HERE ---
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -37,28 +52,91 @@ pub enum DawAction {
     ToggleTrackSolo(usize),
     ToggleTrackRecord(usize),
     AddSampleToTrack(usize, PathBuf),
+    CancelLoading, // Stop decoding audio files and drop the clips still waiting for them
     MoveSample(usize, usize, f32), // track_id, sample_id, new_position
     MoveSampleBetweenTracks(usize, usize, usize, f32), // source_track_id, sample_id, target_track_id, new_position
     SetSampleLength(usize, usize, f32),                // track_id, sample_id, new_length
     DeleteSample(usize, usize),                        // track_id, sample_id
     SetSampleTrimPoints(usize, usize, f32, f32),       // track_id, sample_id, start, end
+    SetSamplePlayback(usize, usize, Playback),         // track_id, sample_id, memory or disk
+    AnalyzeLoudness(usize, usize),                     // Measure a clip and show the result: track_id, sample_id
     UpdateScrollPosition(f32, f32),                    // h_scroll, v_scroll
     SetSelection(Option<SelectionRect>),               // Use Option<SelectionRect>
     ToggleLoopSelection,       // Toggle looping within the current selection
     UpdateLoopRange(bool, f32, f32), // Update loop enabled, start time, end time in seconds
-    RenderSelection(PathBuf),  // Path to save the rendered WAV file
+    RenderSelection(PathBuf, ExportSettings), // Mix the selection down and export it to this path
+    ExportStems(PathBuf, ExportSettings, StemOptions), // Export one aligned file per track or Group into this folder
     SetZoomLevel(f32),         // Set the zoom level for the grid
     SetLoopRangeFromSelection, // Set loop range from current selection without toggling loop state
     CreateGroup(String),       // Create a new Group with the given name
     RenameGroup(String, String), // Rename a Group (old_name, new_name)
     DeleteGroup(String),       // Delete a Group by name
-    AddGroupToTrack(usize, String), // Add a Group to a track (track_id, group_name)
+    UndoDeleteGroup,           // Bring back the most recently deleted Group
+    ExportGroupToLibrary(String), // Copy a Group of the project into the user's Group library
+    ImportGroupFromLibrary(String), // Copy a Group from the user's Group library into the project
+    AddGroupToTrack(usize, String, f32), // Add an instance of a Group to a track (track_id, group_name, position in beats)
     RenderGroupFromSelection(String), // Render the current selection to a Group
-    OpenGroupInNewTab(String), // Open a Group in a new tab (group_name)
+    GroupSelection(String), // Replace the selected clips with an instance of a new Group holding them
+    Ungroup(usize, usize), // Replace a Group instance with the clips it plays (track_id, sample_id)
+    OpenGroupInNewTab(String, Option<String>), // Open a Group variant in a new tab (group_name, variant)
+    CreateGroupVariant(String, String), // Add a variant to a Group, copied from its main arrangement (group_name, variant)
+    SetGroupVariant(usize, usize, Option<String>), // Choose the variant a Group instance plays (track_id, sample_id, variant)
     SwitchToTab(usize),        // Switch to a different tab by ID
+    SetPlaybackScope(PlaybackScope), // Choose what plays while the active tab is shown
+    Undo,                      // Undo the last edit in the active tab
+    Redo,                      // Redo the last undone edit in the active tab
     CloseTab(usize),           // Close a tab by ID
     SaveGroup(String),         // Save current Group state and update render.wav
     CreateTrack,
+    FreezeTrack(usize),        // Render a track's clips and play the render instead (track_id)
+    UnfreezeTrack(usize),      // Play a frozen track's clips again (track_id)
+    BounceSelection,           // Replace the selected clips of each track with one clip of their mix
+    SetLogLevel(log::LevelFilter), // Change the runtime log level and remember it in the config
+    SetPcmCacheLimit(u64),     // Size limit of the decoded audio cache in MB, remembered in the config
+    ClearPcmCache,
+    RelinkMedia(Vec<(PathBuf, PathBuf)>), // Point every sample using a missing file at its replacement (old_path, new_path)
+    DismissMissingMedia,       // Keep the missing files missing and stop asking
+}
+
+impl DawAction {
+    /// Whether the action changes the contents of the arrangement it is applied to
+    fn edits_arrangement(&self) -> bool {
+        matches!(
+            self,
+            DawAction::SetBpm(_)
+                | DawAction::ToggleTrackMute(_)
+                | DawAction::ToggleTrackSolo(_)
+                | DawAction::AddSampleToTrack(..)
+                | DawAction::MoveSample(..)
+                | DawAction::MoveSampleBetweenTracks(..)
+                | DawAction::SetSampleLength(..)
+                | DawAction::DeleteSample(..)
+                | DawAction::SetSampleTrimPoints(..)
+                | DawAction::AddGroupToTrack(..)
+                | DawAction::SetGroupVariant(..)
+                | DawAction::GroupSelection(_)
+                | DawAction::Ungroup(..)
+                | DawAction::FreezeTrack(_)
+                | DawAction::UnfreezeTrack(_)
+                | DawAction::BounceSelection
+                | DawAction::CreateTrack
+                | DawAction::Undo
+                | DawAction::Redo
+        )
+    }
+
+    // What a continuous edit, sent on every frame of a drag, is changing: the kind of edit and
+    // the clip (track_id, sample_id). None for edits that are each undone on their own.
+    fn drag_target(&self) -> Option<(std::mem::Discriminant<DawAction>, Option<(usize, usize)>)> {
+        let clip = match self {
+            DawAction::SetBpm(_) => None,
+            DawAction::MoveSample(track_id, sample_id, _)
+            | DawAction::SetSampleLength(track_id, sample_id, _)
+            | DawAction::SetSampleTrimPoints(track_id, sample_id, ..) => Some((*track_id, *sample_id)),
+            _ => return None,
+        };
+        Some((std::mem::discriminant(self), clip))
+    }
 }
 
 const SAMPLE_RATE: u32 = 44100;
@@ -94,7 +172,7 @@ pub struct Sample {
     #[serde(skip)]
     sample_index: Arc<AtomicUsize>,
     #[serde(skip)]
-    audio_buffer: Arc<Mutex<Vec<f32>>>,
+    audio_buffer: Arc<Mutex<Arc<Pcm>>>,
     pub current_position: f32,
     #[serde(skip)]
     pub waveform: Option<SampleWaveform>,
@@ -107,8 +185,35 @@ pub struct Sample {
     pub trim_end: f32,        // End trim position in seconds (0.0 = use full sample length)
     #[serde(skip)]
     total_frames: usize,
+    #[serde(skip)]
+    pub channels: u16, // Interleaved in audio_buffer
     #[serde(default)]
     pub item_type: TrackItemType, // Type of track item (Sample or AudioBox)
+    #[serde(default)]
+    pub audio_size: Option<u64>, // Size of audio_file when it was last loaded, used for relinking
+    #[serde(default)]
+    pub audio_hash: Option<String>, // Content hash of audio_file when it was last loaded
+    #[serde(default)]
+    pub pool_id: Option<String>, // Media pool entry audio_file points at, if it is in the pool
+    #[serde(default)]
+    pub variant: Option<String>, // Variant a Group instance plays, None for the Group's main one
+    #[serde(skip)]
+    pub peaks: Option<Arc<Peaks>>, // Multi-resolution peaks for drawing, loaded in the background
+    #[serde(skip)]
+    decoding: Option<PendingDecode>, // Set while the audio file is being decoded
+    #[serde(default)]
+    pub playback: Playback, // Whether the audio is held in memory or streamed from disk
+    #[serde(skip)]
+    streamed: bool, // The audio is played from disk, audio_buffer stays empty
+    #[serde(skip)]
+    disk_stream: Option<(Arc<DiskStream>, f32)>, // Reader feeding the stream, and the device/file rate ratio
+}
+
+#[derive(Clone)]
+struct PendingDecode {
+    path: PathBuf, // File being decoded, even if the sample is pointed elsewhere meanwhile
+    handle: DecodeHandle,
+    new_clip: bool, // Added by the user rather than loaded with the project
 }
 
 // Manual clone implementation for Sample to handle non-cloneable fields
@@ -121,7 +226,7 @@ impl Clone for Sample {
             waveform_file: self.waveform_file.clone(),
             stream: None, // Stream can't be cloned
             sample_index: Arc::new(AtomicUsize::new(0)),
-            audio_buffer: Arc::new(Mutex::new(Vec::new())),
+            audio_buffer: Arc::new(Mutex::new(Arc::new(Pcm::default()))),
             current_position: self.current_position,
             waveform: self.waveform.clone(),
             is_playing: self.is_playing,
@@ -132,7 +237,17 @@ impl Clone for Sample {
             trim_start: self.trim_start,
             trim_end: self.trim_end,
             total_frames: self.total_frames,
+            channels: self.channels,
             item_type: self.item_type,
+            audio_size: self.audio_size,
+            audio_hash: self.audio_hash.clone(),
+            pool_id: self.pool_id.clone(),
+            variant: self.variant.clone(),
+            peaks: self.peaks.clone(),
+            decoding: self.decoding.clone(),
+            playback: self.playback,
+            streamed: self.streamed,
+            disk_stream: None,
         }
     }
 }
@@ -146,7 +261,7 @@ impl Default for Sample {
             waveform_file: None,
             stream: None,
             sample_index: Arc::new(AtomicUsize::new(0)),
-            audio_buffer: Arc::new(Mutex::new(vec![])),
+            audio_buffer: Arc::new(Mutex::new(Arc::new(Pcm::default()))),
             current_position: 0.0,
             waveform: None,
             is_playing: false,
@@ -157,7 +272,17 @@ impl Default for Sample {
             trim_start: 0.0,
             trim_end: 0.0,
             total_frames: 0,
+            channels: 1,
             item_type: TrackItemType::Sample,
+            audio_size: None,
+            audio_hash: None,
+            pool_id: None,
+            variant: None,
+            peaks: None,
+            decoding: None,
+            playback: Playback::Auto,
+            streamed: false,
+            disk_stream: None,
         }
     }
 }
@@ -166,39 +291,49 @@ impl Default for Sample {
 impl Sample {
     pub fn create_stream(&mut self, audio: &Audio) {
         if self.audio_file.is_none() {
-            eprintln!("Cannot create stream: No audio file loaded");
+            log::warn!(target: "audio", "Cannot create stream: No audio file loaded");
             return;
         }
 
         if self.stream.is_some() {
             if let Err(e) = self.stream.as_ref().unwrap().pause() {
-                eprintln!("Failed to pause existing stream: {}", e);
+                log::error!(target: "audio", "Failed to pause existing stream: {}", e);
             }
             self.stream = None;
+            self.disk_stream = None;
             self.is_playing = false;
         }
 
+        // The stream is created once a background decode finishes
+        if self.decoding.is_some() {
+            return;
+        }
+
+        if self.streamed {
+            self.create_disk_stream(audio);
+            return;
+        }
+
         // Make sure we have a valid audio buffer
         if let Ok(buffer) = self.audio_buffer.lock() {
             if buffer.is_empty() && self.audio_file.is_some() {
                 drop(buffer); // Release the lock before loading audio
                 if let Some(path) = &self.audio_file {
-                    match load_audio(path) {
-                        Ok((samples, sample_rate)) => {
+                    match decode::decode_now(path) {
+                        Ok(decoded) => {
                             if let Ok(mut buffer) = self.audio_buffer.lock() {
-                                buffer.clear();
-                                buffer.extend_from_slice(&samples);
-                                self.total_frames = samples.len();
-                                eprintln!(
+                                *buffer = Arc::clone(&decoded.samples);
+                                self.total_frames = decoded.samples.len();
+                                log::debug!(target: "audio",
                                     "Loaded {} samples into memory at {}Hz",
-                                    samples.len(),
-                                    sample_rate
+                                    decoded.samples.len(),
+                                    decoded.sample_rate
                                 );
                             }
                         }
                         Err(err) => {
                             // Just log the error but don't show a dialog since that's handled elsewhere
-                            eprintln!("Failed to load audio in create_stream: {}", err);
+                            log::error!(target: "audio", "Failed to load audio in create_stream: {}", err);
                             return; // Exit early if we can't load the audio
                         }
                     }
@@ -217,7 +352,7 @@ impl Sample {
         let device_sample_rate = audio.output_config.sample_rate.0;
         let rate_ratio = device_sample_rate as f32 / source_sample_rate as f32;
 
-        eprintln!(
+        log::debug!(target: "audio",
             "Audio source rate: {}Hz, device rate: {}Hz, ratio: {}",
             source_sample_rate, device_sample_rate, rate_ratio
         );
@@ -310,13 +445,78 @@ impl Sample {
             self.stream = Some(stream);
             self.sample_index = sample_index;
             self.is_playing = false;
-            eprintln!(
+            log::debug!(target: "audio",
                 "Created new multi-channel audio stream (paused) with device rate {}Hz",
                 device_sample_rate
             );
         }
     }
 
+    // Play the file from disk through a read-ahead buffer instead of from memory
+    fn create_disk_stream(&mut self, audio: &Audio) {
+        let Some(path) = self.audio_file.clone() else {
+            return;
+        };
+        let (disk_stream, mut window) = match DiskStream::open(&path) {
+            Ok((disk_stream, window)) => (Arc::new(disk_stream), window),
+            Err(e) => {
+                log::error!(target: "audio", "Failed to stream {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        let source_sample_rate = disk_stream.sample_rate;
+        let device_sample_rate = audio.output_config.sample_rate.0;
+        let rate_ratio = device_sample_rate as f32 / source_sample_rate as f32;
+        let num_channels = audio.output_config.channels as usize;
+
+        // Trim points in frames, computed the same way as for audio held in memory
+        let start_frame = (self.trim_start * source_sample_rate as f32) as u64;
+        let end_frame = if self.trim_end <= 0.0 {
+            u64::MAX
+        } else {
+            (self.trim_end * source_sample_rate as f32) as u64
+        };
+
+        let sample_index = Arc::new(AtomicUsize::new(0));
+        let sample_index_clone = Arc::clone(&sample_index);
+
+        if let Some(stream) = audio.create_stream_with_callback(move |out_buffer: &mut [f32]| {
+            let mut index = sample_index_clone.load(Ordering::Relaxed);
+            window.sync();
+
+            for frame in out_buffer.chunks_mut(num_channels) {
+                let mut position = (index as f32 / rate_ratio) as u64;
+                if position < start_frame {
+                    index = (start_frame as f32 * rate_ratio) as usize;
+                    position = start_frame;
+                }
+                for (channel, out) in frame.iter_mut().enumerate() {
+                    // Silence past the end, and while the reader catches up after a jump
+                    *out = if position >= end_frame || window.is_past_end(position) {
+                        0.0
+                    } else {
+                        window.sample(position, channel).unwrap_or(0.0)
+                    };
+                }
+                index += 1;
+            }
+
+            window.advance((index as f32 / rate_ratio) as u64);
+            sample_index_clone.store(index, Ordering::Relaxed);
+        }) {
+            self.stream = Some(stream);
+            self.sample_index = sample_index;
+            self.disk_stream = Some((disk_stream, rate_ratio));
+            self.is_playing = false;
+            log::debug!(target: "audio",
+                "Created disk stream (paused) for {} with device rate {}Hz",
+                self.name,
+                device_sample_rate
+            );
+        }
+    }
+
     pub fn seek_to(&mut self, position: f32) {
         // Apply trim_start offset to the position
         let effective_position = self.trim_start + position;
@@ -328,9 +528,13 @@ impl Sample {
 
         let frame_position = (effective_position * sample_rate as f32) as usize;
         self.sample_index.store(frame_position, Ordering::Relaxed);
+        // Let the reader start filling from the new position before playback gets there
+        if let Some((disk_stream, rate_ratio)) = &self.disk_stream {
+            disk_stream.seek((frame_position as f32 / rate_ratio) as u64);
+        }
         self.current_position = position;
 
-        eprintln!(
+        log::trace!(target: "audio",
             "Seeked to position {}s (frame {})",
             effective_position, frame_position
         );
@@ -339,36 +543,38 @@ impl Sample {
     pub fn play(&mut self) {
         if let Some(stream) = &self.stream {
             if let Err(e) = stream.play() {
-                eprintln!("Failed to play stream: {}", e);
+                log::error!(target: "audio", "Failed to play stream: {}", e);
                 return;
             }
             self.is_playing = true;
-            eprintln!(
+            log::debug!(target: "audio",
                 "Started playing audio from position {} (effective: {})",
                 self.current_position,
                 self.trim_start + self.current_position
             );
+        } else if self.decoding.is_some() {
+            log::debug!(target: "audio", "{} is still loading", self.name);
         } else {
             // Try to recreate the stream if we have an audio file but no stream
             if self.audio_file.is_some() {
-                eprintln!("Recreating audio stream for {}", self.name);
+                log::debug!(target: "audio", "Recreating audio stream for {}", self.name);
                 let audio = Audio::new();
                 self.create_stream(&audio);
                 if let Some(new_stream) = &self.stream {
                     if let Err(e) = new_stream.play() {
-                        eprintln!("Failed to play recreated stream: {}", e);
+                        log::error!(target: "audio", "Failed to play recreated stream: {}", e);
                         return;
                     }
                     self.is_playing = true;
-                    eprintln!(
+                    log::debug!(target: "audio",
                         "Started playing recreated audio from position {}",
                         self.current_position
                     );
                 } else {
-                    eprintln!("Failed to recreate audio stream for {}", self.name);
+                    log::error!(target: "audio", "Failed to recreate audio stream for {}", self.name);
                 }
             } else {
-                eprintln!("No audio stream available for sample {}", self.name);
+                log::warn!(target: "audio", "No audio stream available for sample {}", self.name);
             }
         }
     }
@@ -376,107 +582,182 @@ impl Sample {
     pub fn pause(&mut self) {
         if let Some(stream) = &self.stream {
             if let Err(e) = stream.pause() {
-                eprintln!("Failed to pause stream: {}", e);
+                log::error!(target: "audio", "Failed to pause stream: {}", e);
                 return;
             }
             self.is_playing = false;
-            eprintln!("Paused audio at position {}", self.current_position);
+            log::debug!(target: "audio", "Paused audio at position {}", self.current_position);
         }
     }
 
-    pub fn load_waveform(&mut self, project_path: Option<&Path>, bpm: Option<f32>) {
-        if let Some(path) = &self.audio_file {
-            // Load the audio data
-            match load_audio(path) {
-                Ok((samples, sample_rate)) => {
-                    let duration: f32 = samples.len() as f32 / sample_rate as f32;
-                    self.total_frames = samples.len();
+    /// Arrangement key of the Group variant this instance plays, for Group instances
+    pub fn group_key(&self) -> Option<String> {
+        (self.item_type == TrackItemType::Group).then(|| group::variant_key(&self.name, self.variant.as_deref()))
+    }
 
-                    // Load samples into the audio buffer
-                    if let Ok(mut buffer) = self.audio_buffer.lock() {
-                        buffer.clear();
-                        buffer.extend_from_slice(&samples);
-                        eprintln!("Loaded {} samples into memory", samples.len());
-                    }
+    /// A clip that plays the render of a Group. It is sized to the render once that is loaded.
+    pub fn group_instance(name: &str, group_path: &Path, position: f32, bpm: f32, audio: &Audio) -> Self {
+        let mut sample = Sample::default();
+        sample.name = name.to_string();
+        sample.item_type = TrackItemType::Group;
+        sample.grid_position = position;
+        // The Group's length sizes the clip until its render is loaded, one bar if it has none
+        sample.grid_length = group::load_manifest(group_path)
+            .and_then(|manifest| manifest.length_seconds())
+            .map_or(4.0, |seconds| seconds * bpm / 60.0);
+        let render_path = group::render_path(group_path);
+        if render_path.exists() {
+            sample.audio_file = Some(render_path);
+            sample.request_audio(bpm, audio, false);
+        }
+        sample.update_grid_times(bpm);
+        sample
+    }
 
-                    // Initialize trim_end to the full duration if it's not set
-                    if self.trim_end == 0.0 {
-                        self.trim_end = duration;
-                    }
+    /// Start decoding the audio file in the background. Audio that is already decoded is used right away.
+    pub fn request_audio(&mut self, bpm: f32, audio: &Audio, new_clip: bool) {
+        let Some(path) = self.audio_file.clone() else {
+            return;
+        };
+        match decode::request(&path, self.playback) {
+            decode::Request::Ready(decoded) => self.apply_decoded(&decoded, bpm, audio),
+            decode::Request::Pending(handle) => {
+                self.decoding = Some(PendingDecode { path, handle, new_clip });
+            }
+        }
+    }
 
-                    // Calculate grid length based on the trimmed duration
-                    let beats_per_second = bpm.unwrap_or(120.0) / 60.0;
-                    let effective_duration = if self.trim_end <= 0.0 {
-                        duration - self.trim_start
-                    } else {
-                        self.trim_end - self.trim_start
-                    };
+    /// Fraction of the audio file decoded so far, while it is loading
+    pub fn decode_progress(&self) -> Option<f32> {
+        self.decoding.as_ref().map(|pending| pending.handle.progress())
+    }
 
-                    // Calculate grid length based on the trimmed duration
-                    self.grid_length = effective_duration * beats_per_second * 0.5;
+    /// Use decoded audio for this sample: fill the playback buffer, size the clip, and create the stream
+    pub fn apply_decoded(&mut self, decoded: &DecodedAudio, bpm: f32, audio: &Audio) {
+        self.decoding = None;
+        self.streamed = decoded.streamed;
+        let samples = &decoded.samples;
+        let sample_rate = decoded.sample_rate;
+        let duration = decoded.duration();
+        self.total_frames = (decoded.frames * decoded.channels as u64) as usize;
+        self.channels = decoded.channels.max(1);
+
+        // Remember what the file looked like so it can be found again if it moves
+        if decoded.hash.is_some() && decoded.hash != self.audio_hash {
+            self.audio_hash = decoded.hash.clone();
+            self.peaks = None;
+        }
+        self.audio_size = decoded.size.or(self.audio_size);
 
-                    eprintln!(
-                        "Sample loaded with duration: {:.2}s, effective duration: {:.2}s, grid_length (beats): {:.2}",
-                        duration, effective_duration, self.grid_length
-                    );
+        // Share the decoded samples with every other sample playing this file
+        if let Ok(mut buffer) = self.audio_buffer.lock() {
+            *buffer = Arc::clone(samples);
+            if decoded.streamed {
+                log::debug!(target: "audio", "{} will be streamed from disk", self.name);
+            } else {
+                log::debug!(target: "audio", "Loaded {} samples into memory", samples.len());
+            }
+        }
 
-                    // Generate downsampled waveform for display
-                    let downsample_factor = samples.len() / 1000;
-                    let waveform_samples: Vec<f32> = samples
-                        .chunks(downsample_factor.max(1)) // Ensure at least 1
-                        .map(|chunk| chunk.iter().map(|&s| s.abs()).fold(0.0, f32::max))
-                        .collect();
-
-                    // Save waveform data if we have a project path
-                    if let Some(project_path) = project_path {
-                        let waveform_data = WaveformData {
-                            samples: waveform_samples.clone(),
-                            sample_rate,
-                            duration,
-                        };
-                        if let Some(waveform_path) =
-                            save_waveform_data(project_path, &self.name, &waveform_data)
-                        {
-                            self.waveform_file = Some(waveform_path.clone());
-                            eprintln!("Saved waveform data to {}", waveform_path.display());
-                        }
-                    }
+        // Initialize trim_end to the full duration if it's not set
+        if self.trim_end == 0.0 {
+            self.trim_end = duration;
+        }
+        // A file that got shorter, such as a new Group render, keeps the trims that still fit
+        if self.trim_end > duration {
+            self.trim_end = duration;
+        }
+        self.trim_start = self.trim_start.min(self.trim_end);
 
-                    self.waveform = Some(SampleWaveform {
-                        samples: waveform_samples,
-                        sample_rate,
-                        duration,
-                    });
-                }
-                Err(err) => {
-                    // Just log the error but don't show a dialog since that's handled elsewhere
-                    eprintln!("Failed to load audio in load_waveform: {}", err);
+        // Calculate grid length based on the trimmed duration
+        let beats_per_second = bpm / 60.0;
+        let effective_duration = if self.trim_end <= 0.0 {
+            duration - self.trim_start
+        } else {
+            self.trim_end - self.trim_start
+        };
+
+        // Calculate grid length based on the trimmed duration
+        self.grid_length = effective_duration * beats_per_second * 0.5;
+
+        log::debug!(target: "audio",
+            "Sample loaded with duration: {:.2}s, effective duration: {:.2}s, grid_length (beats): {:.2}",
+            duration, effective_duration, self.grid_length
+        );
+
+        // Generate downsampled waveform for display
+        let downsample_factor = samples.len() / 1000;
+        let waveform_samples: Vec<f32> = samples
+            .chunks(downsample_factor.max(1)) // Ensure at least 1
+            .map(|chunk| chunk.iter().map(|&s| s.abs()).fold(0.0, f32::max))
+            .collect();
+
+        self.waveform = Some(SampleWaveform {
+            samples: waveform_samples,
+            sample_rate,
+            duration,
+        });
+
+        self.update_grid_times(bpm);
+        self.create_stream(audio);
+    }
+
+    /// All samples of the audio file, for offline rendering. Streamed files are decoded on the
+    /// calling thread just for this.
+    pub fn audio_data(&self) -> Option<Arc<Pcm>> {
+        if self.streamed {
+            let path = self.audio_file.as_ref()?;
+            return match load_audio(path) {
+                Ok((samples, _)) => Some(Arc::new(Pcm::from(samples))),
+                Err(e) => {
+                    log::error!(target: "audio", "Failed to read {} for rendering: {}", path.display(), e);
+                    None
                 }
-            }
-        } else if let Some(waveform_path) = &self.waveform_file {
-            // Try to load waveform data from file
+            };
+        }
+        self.audio_buffer.lock().ok().map(|buffer| Arc::clone(&buffer))
+    }
+
+    /// Loudness of the part of the file the clip plays
+    pub fn loudness(&self) -> Option<Loudness> {
+        let audio_data = self.audio_data()?;
+        let sample_rate = self.waveform.as_ref()?.sample_rate;
+        let channels = self.channels.max(1) as usize;
+        let available = audio_data.len() / channels;
+        let first = ((self.trim_start * sample_rate as f32).max(0.0) as usize).min(available);
+        let last = if self.trim_end > 0.0 {
+            ((self.trim_end * sample_rate as f32) as usize).clamp(first, available)
+        } else {
+            available
+        };
+        Some(loudness::analyze(
+            &audio_data[first * channels..last * channels],
+            self.channels,
+            sample_rate,
+        ))
+    }
+
+    /// Show the waveform saved by older versions for a sample whose audio file is missing
+    pub fn load_saved_waveform(&mut self) {
+        if let Some(waveform_path) = &self.waveform_file {
             if let Some(waveform_data) = load_waveform_data(waveform_path) {
                 self.waveform = Some(SampleWaveform {
                     samples: waveform_data.samples,
                     sample_rate: waveform_data.sample_rate,
                     duration: waveform_data.duration,
                 });
-
-                // Calculate the effective duration and grid length
-                let beats_per_second = bpm.unwrap_or(120.0) / 60.0;
-                let effective_duration = if self.trim_end <= 0.0 {
-                    waveform_data.duration - self.trim_start
-                } else {
-                    self.trim_end - self.trim_start
-                };
-                self.grid_length = effective_duration * beats_per_second * 0.5;
-
-                eprintln!("Loaded waveform data from {} with duration: {:.2}s, effective duration: {:.2}s, grid_length (beats): {:.2}", 
-                         waveform_path.display(), waveform_data.duration, effective_duration, self.grid_length);
             }
         }
     }
 
+    /// Point this sample at a file in the media pool
+    pub fn set_pool_entry(&mut self, pool: &MediaPool, entry: &PoolEntry) {
+        self.audio_file = pool.path_of(&entry.id);
+        self.audio_size = Some(entry.size);
+        self.audio_hash = Some(entry.id.clone());
+        self.pool_id = Some(entry.id.clone());
+    }
+
     pub fn update_grid_times(&mut self, bpm: f32) {
         self.grid_start_time = self.grid_position * (60.0 / bpm);
         self.grid_end_time = (self.grid_position + self.grid_length) * (60.0 / bpm);
@@ -485,7 +766,7 @@ impl Sample {
     pub fn reset_playback(&mut self) {
         if let Some(stream) = &self.stream {
             if let Err(e) = stream.pause() {
-                eprintln!("Failed to pause stream during reset: {}", e);
+                log::error!(target: "audio", "Failed to pause stream during reset: {}", e);
             }
         }
 
@@ -503,6 +784,15 @@ pub struct Track {
     pub soloed: bool,
     pub recording: bool,
     pub samples: Vec<Sample>,
+    #[serde(default)]
+    pub frozen: Option<FrozenTrack>, // Render played instead of the clips while the track is frozen
+}
+
+/// Mixdown of a frozen track's clips, played from the media pool in their place
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FrozenTrack {
+    pub clip: Sample,
+    pub fingerprint: u64, // Track::clips_fingerprint when the render was made
 }
 
 impl Default for Track {
@@ -514,6 +804,7 @@ impl Default for Track {
             soloed: false,
             recording: false,
             samples: Vec::new(),
+            frozen: None,
         }
     }
 }
@@ -528,7 +819,30 @@ impl Track {
             soloed: false,
             recording: false,
             samples: Vec::new(),
+            frozen: None,
+        }
+    }
+
+    // The track's clips and its freeze render, for starting and stopping playback
+    pub fn clips_mut(&mut self) -> impl Iterator<Item = &mut Sample> {
+        self.samples.iter_mut().chain(self.frozen.as_mut().map(|f| &mut f.clip))
+    }
+
+    /// Changes whenever what the clips play changes, so a freeze render can tell it is out of date
+    pub fn clips_fingerprint(&self, bpm: f32) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        bpm.to_bits().hash(&mut hasher);
+        for sample in &self.samples {
+            sample.audio_file.hash(&mut hasher);
+            // Group renders are rewritten in place, so the path alone does not say what plays
+            sample.audio_hash.hash(&mut hasher);
+            sample.variant.hash(&mut hasher);
+            (sample.item_type == TrackItemType::Group).hash(&mut hasher);
+            for value in [sample.grid_position, sample.grid_length, sample.trim_start, sample.trim_end] {
+                value.to_bits().hash(&mut hasher);
+            }
         }
+        hasher.finish()
     }
     
     // Add a sample to the track
@@ -569,6 +883,256 @@ impl Track {
             sample.update_grid_times(bpm);
         }
     }
+
+    // Shorten samples that start before the given sample so they end where it begins
+    pub fn trim_overlaps(&mut self, sample_id: usize, bpm: f32) {
+        let Some(sample) = self.get_sample(sample_id) else {
+            return;
+        };
+        let start = sample.grid_position;
+
+        for other in self.samples.iter_mut().filter(|s| s.id != sample_id) {
+            if other.grid_position < start && start < other.grid_position + other.grid_length {
+                let new_length = start - other.grid_position;
+                log::debug!(target: "project", "Adjusting sample {} length from {} to {} due to overlap with new sample {}",
+                         other.id, other.grid_length, new_length, sample_id);
+                other.grid_length = new_length;
+                other.update_grid_times(bpm);
+            }
+        }
+    }
+}
+
+// Start, follow and stop the clips of an arrangement for the playhead at `timeline_pos` seconds.
+// Returns whether any clip is still playing.
+fn play_tracks(tracks: &mut [Track], bpm: f32, timeline_pos: f32) -> bool {
+    let mut any_sample_playing = false;
+    let any_track_soloed = tracks.iter().any(|t| t.soloed);
+
+    for track in tracks.iter_mut() {
+        if track.muted || (any_track_soloed && !track.soloed) {
+            continue;
+        }
+
+        // A frozen track plays its render and leaves its clips quiet
+        let clips = match &mut track.frozen {
+            Some(frozen) => {
+                for sample in &mut track.samples {
+                    if sample.is_playing {
+                        sample.pause();
+                    }
+                }
+                std::slice::from_mut(&mut frozen.clip)
+            }
+            None => &mut track.samples[..],
+        };
+
+        for sample in clips {
+            // A Group instance without a render has nothing to play
+            if sample.item_type == TrackItemType::Group && sample.audio_file.is_none() {
+                continue;
+            }
+
+            sample.update_grid_times(bpm);
+            let should_play = timeline_pos >= sample.grid_start_time
+                && timeline_pos < sample.grid_end_time;
+
+            if should_play {
+                if !sample.is_playing {
+                    let relative_position = timeline_pos - sample.grid_start_time;
+                    sample.seek_to(relative_position);
+                    sample.play();
+                }
+                let relative_position = timeline_pos - sample.grid_start_time;
+                sample.current_position = relative_position;
+
+                if let Some(_waveform) = &sample.waveform {
+                    let effective_position = sample.trim_start + relative_position;
+                    if sample.trim_end > 0.0 && effective_position >= sample.trim_end {
+                        if sample.is_playing {
+                            sample.pause();
+                        }
+                    } else {
+                        any_sample_playing = true;
+                    }
+                } else {
+                    any_sample_playing = true;
+                }
+            } else {
+                if sample.is_playing {
+                    sample.pause();
+                }
+            }
+        }
+    }
+    any_sample_playing
+}
+
+/// The tracks and tempo a tab edits: the project's main arrangement or a Group's own one
+#[derive(Clone)]
+pub struct Arrangement {
+    pub bpm: f32,
+    pub tracks: Vec<Track>,
+    pub next_track_id: usize,
+}
+
+impl Arrangement {
+    /// An empty arrangement with the tracks of a new project
+    pub fn new(bpm: f32) -> Self {
+        let defaults = DawState::default();
+        Self {
+            bpm,
+            tracks: defaults.tracks,
+            next_track_id: defaults.next_track_id,
+        }
+    }
+}
+
+// Undo steps kept per tab
+const HISTORY_LIMIT: usize = 100;
+// Steps of a drag on the same clip this close together are undone as one
+const HISTORY_MERGE_WINDOW: Duration = Duration::from_millis(1000);
+
+/// Undo and redo snapshots of a tab's arrangement
+#[derive(Clone, Default)]
+pub struct History {
+    undo: Vec<Arrangement>,
+    redo: Vec<Arrangement>,
+    last_drag: Option<((std::mem::Discriminant<DawAction>, Option<(usize, usize)>), Instant)>,
+}
+
+impl History {
+    // Remember the arrangement as it was before an edit
+    fn record(&mut self, action: &DawAction, before: impl FnOnce() -> Arrangement) {
+        let target = action.drag_target();
+        let now = Instant::now();
+        let merged = target.is_some()
+            && self
+                .last_drag
+                .is_some_and(|(last, at)| Some(last) == target && now.duration_since(at) < HISTORY_MERGE_WINDOW);
+        self.last_drag = target.map(|target| (target, now));
+        if merged {
+            return;
+        }
+        self.undo.push(before());
+        if self.undo.len() > HISTORY_LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    // Trade the current arrangement for the one before the last edit
+    fn undo(&mut self, current: Arrangement) -> Option<Arrangement> {
+        let previous = self.undo.pop()?;
+        self.redo.push(current);
+        self.last_drag = None;
+        Some(previous)
+    }
+
+    // Trade the current arrangement for the one the last undo went back from
+    fn redo(&mut self, current: Arrangement) -> Option<Arrangement> {
+        let next = self.redo.pop()?;
+        self.undo.push(current);
+        self.last_drag = None;
+        Some(next)
+    }
+
+    // Change the clips of every snapshot, for edits made to the whole project such as renaming a
+    // Group, so that undo and redo don't bring back what they replaced
+    fn patch_clips(&mut self, mut patch: impl FnMut(&mut Sample)) {
+        for arrangement in self.undo.iter_mut().chain(&mut self.redo) {
+            for sample in arrangement.tracks.iter_mut().flat_map(Track::clips_mut) {
+                patch(sample);
+            }
+        }
+    }
+
+    // Whether any snapshot holds a clip like this
+    fn has_clip(&self, matches: impl Fn(&Sample) -> bool) -> bool {
+        self.undo
+            .iter()
+            .chain(&self.redo)
+            .flat_map(|a| &a.tracks)
+            .flat_map(|t| &t.samples)
+            .any(matches)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+/// What plays while a tab is active
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PlaybackScope {
+    /// The tab's own arrangement
+    #[default]
+    Tab,
+    /// The main arrangement, to hear a Group in context. Its instances play the Group's render,
+    /// which follows the edits made in the tab.
+    Main,
+}
+
+/// Playhead, zoom, scroll, selection and loop of a tab. The active tab's view lives in the state.
+#[derive(Clone)]
+pub struct TabView {
+    pub timeline_position: f32,
+    pub last_clicked_bar: f32,
+    pub last_clicked_position: f32,
+    pub h_scroll_offset: f32,
+    pub v_scroll_offset: f32,
+    pub zoom_level: f32,
+    pub selection: Option<SelectionRect>,
+    pub loop_enabled: bool,
+    pub loop_range: Option<(f32, f32)>,
+}
+
+impl Default for TabView {
+    fn default() -> Self {
+        Self {
+            timeline_position: 0.0,
+            last_clicked_bar: 0.0,
+            last_clicked_position: 0.0,
+            h_scroll_offset: 0.0,
+            v_scroll_offset: 0.0,
+            zoom_level: default_zoom_level(),
+            selection: None,
+            loop_enabled: false,
+            loop_range: default_loop_range(),
+        }
+    }
+}
+
+impl TabView {
+    fn of(state: &DawState) -> Self {
+        Self {
+            timeline_position: state.timeline_position,
+            last_clicked_bar: state.last_clicked_bar,
+            last_clicked_position: state.last_clicked_position,
+            h_scroll_offset: state.h_scroll_offset,
+            v_scroll_offset: state.v_scroll_offset,
+            zoom_level: state.zoom_level,
+            selection: state.selection.clone(),
+            loop_enabled: state.loop_enabled,
+            loop_range: state.loop_range,
+        }
+    }
+
+    fn apply(self, state: &mut DawState) {
+        state.timeline_position = self.timeline_position;
+        state.last_clicked_bar = self.last_clicked_bar;
+        state.last_clicked_position = self.last_clicked_position;
+        state.h_scroll_offset = self.h_scroll_offset;
+        state.v_scroll_offset = self.v_scroll_offset;
+        state.zoom_level = self.zoom_level;
+        state.selection = self.selection;
+        state.loop_enabled = self.loop_enabled;
+        state.loop_range = self.loop_range;
+    }
 }
 
 /// Represents a tab in the DAW UI
@@ -578,6 +1142,18 @@ pub struct Tab {
     pub name: String,
     pub is_group: bool,
     pub group_name: Option<String>,
+    #[serde(default)]
+    pub variant: Option<String>, // Variant of the Group the tab edits, None for its main arrangement
+    #[serde(default)]
+    pub playback_scope: PlaybackScope,
+    // The arrangement and view of this tab while another tab is active. The active tab's
+    // arrangement and view live in the state.
+    #[serde(skip)]
+    pub arrangement: Option<Arrangement>,
+    #[serde(skip)]
+    pub view: Option<TabView>,
+    #[serde(skip)]
+    pub history: History,
 }
 
 impl Default for Tab {
@@ -587,10 +1163,24 @@ impl Default for Tab {
             name: "Main".to_string(),
             is_group: false,
             group_name: None,
+            variant: None,
+            playback_scope: PlaybackScope::Tab,
+            arrangement: None,
+            view: None,
+            history: History::default(),
         }
     }
 }
 
+impl Tab {
+    /// Arrangement key of the Group variant the tab edits
+    pub fn group_key(&self) -> Option<String> {
+        self.group_name
+            .as_deref()
+            .map(|name| group::variant_key(name, self.variant.as_deref()))
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct DawState {
     pub timeline_position: f32,
@@ -622,6 +1212,10 @@ pub struct DawState {
     pub active_tab_id: usize, // Currently active tab ID
     #[serde(default)]
     pub audio_boxes: Vec<String>, // List of AudioBox names in this project
+    // Arrangements of Groups without a tab, loaded to render them again after a Group inside
+    // them changed
+    #[serde(skip)]
+    pub background_groups: HashMap<String, Arrangement>,
     pub next_track_id: usize,
     pub modified: bool,
 }
@@ -652,6 +1246,7 @@ impl Default for DawState {
                     soloed: false,
                     recording: false,
                     samples: Vec::new(),
+                    frozen: None,
                 })
                 .collect(),
             grid_division: 0.25,
@@ -668,17 +1263,62 @@ impl Default for DawState {
             tabs: default_tabs(),
             active_tab_id: 0,
             audio_boxes: Vec::new(),
+            background_groups: HashMap::new(),
             next_track_id: 5,
             modified: false,
         }
     }
 }
 
+impl DawState {
+    /// Arrangements that aren't active: those of the other tabs and of Groups rendered in the background
+    pub fn parked_arrangements_mut(&mut self) -> impl Iterator<Item = &mut Arrangement> {
+        self.tabs
+            .iter_mut()
+            .filter_map(|tab| tab.arrangement.as_mut())
+            .chain(self.background_groups.values_mut())
+    }
+
+    /// Bpm and tracks of the arrangement a tab edits, whether or not it is active
+    pub fn tab_tracks_mut(&mut self, tab_id: usize) -> Option<(f32, &mut Vec<Track>)> {
+        if tab_id == self.active_tab_id {
+            return Some((self.bpm, &mut self.tracks));
+        }
+        let arrangement = self.tabs.iter_mut().find(|t| t.id == tab_id)?.arrangement.as_mut()?;
+        Some((arrangement.bpm, &mut arrangement.tracks))
+    }
+
+    /// Bpm and tracks of a loaded Group variant, from its tab or from the background
+    pub fn group_tracks_mut(&mut self, key: &str) -> Option<(f32, &mut Vec<Track>)> {
+        if let Some(tab_id) = self.tabs.iter().find(|t| t.group_key().as_deref() == Some(key)).map(|t| t.id) {
+            return self.tab_tracks_mut(tab_id);
+        }
+        let arrangement = self.background_groups.get_mut(key)?;
+        Some((arrangement.bpm, &mut arrangement.tracks))
+    }
+
+    /// Tracks of every loaded arrangement, the active one first
+    pub fn all_tracks_mut(&mut self) -> impl Iterator<Item = &mut Track> {
+        let parked = self
+            .tabs
+            .iter_mut()
+            .filter_map(|tab| tab.arrangement.as_mut())
+            .chain(self.background_groups.values_mut());
+        self.tracks.iter_mut().chain(parked.flat_map(|arrangement| arrangement.tracks.iter_mut()))
+    }
+}
+
 pub struct DawApp {
     pub state: DawState,
     pub last_update: std::time::Instant,
     pub seek_position: Option<f32>,
     pub audio: Audio,
+    pub last_autosave: Instant,
+    last_autosave_contents: Option<String>, // What the recovery file currently holds
+    pub missing_media: Vec<MissingMedia>, // Audio files the open project couldn't find
+    peak_cache: PeakCache,
+    group_renderer: GroupRenderer,
+    deleted_groups: Vec<DeletedGroup>, // Deleted Groups that can still be brought back, the latest last
 }
 
 impl DawApp {
@@ -687,11 +1327,16 @@ impl DawApp {
         Path::new(&home).join(".monlam").join("config.json")
     }
 
-    fn save_config(project_path: Option<PathBuf>) {
-        let config = Config {
-            latest_project: project_path,
-        };
-        if let Ok(serialized) = serde_json::to_string_pretty(&config) {
+    fn read_config() -> Config {
+        let config_path = Self::get_config_path();
+        fs::read_to_string(config_path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Config>(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn write_config(config: &Config) {
+        if let Ok(serialized) = serde_json::to_string_pretty(config) {
             let config_path = Self::get_config_path();
             if let Some(parent) = config_path.parent() {
                 let _ = std::fs::create_dir_all(parent);
@@ -700,57 +1345,53 @@ impl DawApp {
         }
     }
 
+    fn save_config(project_path: Option<PathBuf>) {
+        // Keep the other settings that live in the config file
+        let mut config = Self::read_config();
+        config.latest_project = project_path;
+        Self::write_config(&config);
+    }
+
     fn load_config() -> Option<PathBuf> {
-        let config_path = Self::get_config_path();
-        if let Ok(contents) = fs::read_to_string(config_path) {
-            if let Ok(config) = serde_json::from_str::<Config>(&contents) {
-                return config.latest_project;
-            }
-        }
-        None
+        Self::read_config().latest_project
+    }
+
+    // Load the log level saved in the config, if any
+    /// Settings of the last export, for the export dialog
+    pub fn load_export_settings() -> ExportSettings {
+        Self::read_config().export
+    }
+
+    /// Options of the last stem export, for the export dialog
+    pub fn load_stem_options() -> StemOptions {
+        Self::read_config().stems
+    }
+
+    pub fn load_log_level() -> Option<log::LevelFilter> {
+        Self::read_config()
+            .log_level
+            .and_then(|level| level.parse().ok())
     }
 
     pub fn save_project(&mut self) {
         // Check if we already have a project path (existing project)
         if let Some(existing_path) = &self.state.file_path.clone() {  // Clone here to avoid borrowing issues
-            eprintln!("Saving to existing project path: {}", existing_path.display());
+            log::debug!(target: "project", "Saving to existing project path: {}", existing_path.display());
             let project_folder = existing_path.parent().unwrap_or(Path::new("")).to_path_buf();
-            
-            // Create waveforms directory
-            let waveforms_dir = project_folder.join("waveforms");
-            if !waveforms_dir.exists() {
-                if let Err(e) = std::fs::create_dir_all(&waveforms_dir) {
-                    eprintln!("Failed to create waveforms directory: {}", e);
-                }
+
+            // Samples added since the last save may still point outside the project
+            for path in self.import_into_pool(&project_folder) {
+                log::warn!(target: "project", "Could not import {} into the media pool", path.display());
             }
+            self.save_open_groups();
             
-            // Save waveform data for each sample in each track
-            for track in &self.state.tracks {
-                for sample in &track.samples {
-                    if let Some(waveform) = &sample.waveform {
-                        let waveform_data = WaveformData {
-                            samples: waveform.samples.clone(),
-                            sample_rate: waveform.sample_rate,
-                            duration: waveform.duration,
-                        };
-                        if let Some(waveform_path) =
-                            save_waveform_data(existing_path, &sample.name, &waveform_data)
-                        {
-                            eprintln!("Saved waveform data to {}", waveform_path.display());
-                        }
-                    }
-                }
-            }
-
             // Save the project state to the existing file
-            if let Ok(serialized) = serde_json::to_string_pretty(&self.state) {
-                if fs::write(existing_path, serialized).is_ok() {
-                    eprintln!("Project saved successfully to {}", existing_path.display());
-                } else {
-                    eprintln!("Failed to write project file to {}", existing_path.display());
+            match project::save(existing_path, &self.project_state()) {
+                Ok(()) => {
+                    self.on_project_saved();
+                    log::info!(target: "project", "Project saved successfully to {}", existing_path.display());
                 }
-            } else {
-                eprintln!("Failed to serialize project state");
+                Err(e) => log::error!(target: "project", "{}", e),
             }
             return;
         }
@@ -772,114 +1413,218 @@ impl DawApp {
             // Create the project folder if it doesn't exist
             if !project_folder.exists() {
                 if let Err(e) = std::fs::create_dir_all(&project_folder) {
-                    eprintln!("Failed to create project folder: {}", e);
+                    log::error!(target: "project", "Failed to create project folder: {}", e);
                     return;
                 }
             }
             
-            // Create waveforms directory
-            let waveforms_dir = project_folder.join("waveforms");
-            if !waveforms_dir.exists() {
-                if let Err(e) = std::fs::create_dir_all(&waveforms_dir) {
-                    eprintln!("Failed to create waveforms directory: {}", e);
-                }
+            // The new project gets its own copy of every audio file
+            for path in self.import_into_pool(&project_folder) {
+                log::warn!(target: "project", "Could not import {} into the media pool", path.display());
             }
-            
+
             // Save the project file inside the folder
             let project_file_path = project_folder.join("project.json");
             
-            // First save waveform data for each sample in each track
-            for track in &self.state.tracks {
-                for sample in &track.samples {
-                    if let Some(waveform) = &sample.waveform {
-                        let waveform_data = WaveformData {
-                            samples: waveform.samples.clone(),
-                            sample_rate: waveform.sample_rate,
-                            duration: waveform.duration,
-                        };
-                        if let Some(waveform_path) =
-                            save_waveform_data(&project_file_path, &sample.name, &waveform_data)
-                        {
-                            eprintln!("Saved waveform data to {}", waveform_path.display());
-                        }
-                    }
-                }
-            }
-
             // Then save the project state to project.json file in the project folder
-            if let Ok(serialized) = serde_json::to_string_pretty(&self.state) {
-                if fs::write(&project_file_path, serialized).is_ok() {
+            match project::save(&project_file_path, &self.project_state()) {
+                Ok(()) => {
+                    // The unsaved work now lives in a real project
+                    recovery::remove_recovery(self.state.file_path.as_deref());
+
                     // Update the file_path in the state
                     self.state.file_path = Some(project_file_path.clone());
                     
-                    // Save updated state with new file path
-                    if let Ok(updated_serialized) = serde_json::to_string_pretty(&self.state) {
-                        let _ = fs::write(&project_file_path, updated_serialized);
-                    }
-                    
                     Self::save_config(Some(project_file_path.clone()));
-                    eprintln!("Project saved successfully to {}", project_file_path.display());
-                } else {
-                    eprintln!("Failed to write project file to {}", project_file_path.display());
+                    self.on_project_saved();
+                    log::info!(target: "project", "Project saved successfully to {}", project_file_path.display());
                 }
-            } else {
-                eprintln!("Failed to serialize project state");
+                Err(e) => log::error!(target: "project", "{}", e),
+            }
+        }
+    }
+
+    // Called after every successful save of the open project
+    fn on_project_saved(&mut self) {
+        recovery::remove_recovery(self.state.file_path.as_deref());
+        recovery::update_session(self.state.file_path.as_deref());
+        self.state.modified = false;
+        self.last_autosave = Instant::now();
+        self.last_autosave_contents = project::serialize(&self.project_state(), self.state.file_path.as_deref()).ok();
+    }
+
+    // Write the open project to its recovery file. The project file itself is left untouched.
+    pub fn autosave_project(&mut self) -> bool {
+        let serialized = match project::serialize(&self.project_state(), self.state.file_path.as_deref()) {
+            Ok(serialized) => serialized,
+            Err(e) => {
+                log::error!(target: "project", "{}", e);
+                return false;
             }
+        };
+
+        self.last_autosave = Instant::now();
+
+        // Nothing changed since the last save or autosave
+        if self.last_autosave_contents.as_deref() == Some(serialized.as_str()) {
+            return true;
+        }
+
+        if recovery::write_recovery(self.state.file_path.as_deref(), &serialized) {
+            self.last_autosave_contents = Some(serialized);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Autosave to the recovery file every AUTOSAVE_INTERVAL, called once per frame
+    pub fn autosave_if_due(&mut self) {
+        if self.last_autosave.elapsed() >= recovery::AUTOSAVE_INTERVAL {
+            self.autosave_project();
         }
     }
 
-    pub fn autosave_project(&self) -> bool {
-        if let Some(project_file_path) = Self::load_config() {
-            // Make sure the parent directory exists
-            if let Some(project_folder) = project_file_path.parent() {
-                if !project_folder.exists() {
-                    if let Err(e) = std::fs::create_dir_all(project_folder) {
-                        eprintln!("Failed to create project folder for auto-save: {}", e);
-                        return false;
+    // Hand finished decodes to the samples waiting for them. Called once per frame.
+    pub fn update_decoding(&mut self) {
+        let finished = decode::take_finished();
+        if finished.is_empty() {
+            return;
+        }
+
+        let bpm = self.state.bpm;
+        let mut new_clips = Vec::new();
+        for (path, result) in finished {
+            match result {
+                Ok(decoded) => {
+                    for track in &mut self.state.tracks {
+                        for sample in &mut track.samples {
+                            let Some(pending) = &sample.decoding else {
+                                continue;
+                            };
+                            if pending.path != path {
+                                continue;
+                            }
+                            if pending.new_clip {
+                                new_clips.push((track.id, sample.id));
+                            }
+                            sample.apply_decoded(&decoded, bpm, &self.audio);
+                        }
                     }
-                }
-                
-                // Create waveforms directory
-                let waveforms_dir = project_folder.join("waveforms");
-                if !waveforms_dir.exists() {
-                    if let Err(e) = std::fs::create_dir_all(&waveforms_dir) {
-                        eprintln!("Failed to create waveforms directory for auto-save: {}", e);
+                    // Arrangements of other tabs get their audio too
+                    for arrangement in self.state.parked_arrangements_mut() {
+                        let bpm = arrangement.bpm;
+                        for sample in arrangement.tracks.iter_mut().flat_map(|t| &mut t.samples) {
+                            if sample.decoding.as_ref().is_some_and(|pending| pending.path == path) {
+                                sample.apply_decoded(&decoded, bpm, &self.audio);
+                            }
+                        }
+                    }
+                    log::info!(target: "audio", "Loaded {}", path.display());
+                    if decoded.damaged_packets > 0 {
+                        MessageDialog::new()
+                            .set_level(rfd::MessageLevel::Warning)
+                            .set_title("Damaged Audio")
+                            .set_description(format!(
+                                "{} damaged packet(s) in {} were skipped. The audio has gaps where they were.",
+                                decoded.damaged_packets,
+                                path.display()
+                            ))
+                            .show();
                     }
                 }
-                
-                // Save waveform data for each sample in each track
-                for track in &self.state.tracks {
-                    for sample in &track.samples {
-                        if let Some(waveform) = &sample.waveform {
-                            let waveform_data = WaveformData {
-                                samples: waveform.samples.clone(),
-                                sample_rate: waveform.sample_rate,
-                                duration: waveform.duration,
-                            };
-                            if let Some(waveform_path) = 
-                                save_waveform_data(&project_file_path, &sample.name, &waveform_data) 
-                            {
-                                eprintln!("Auto-saved waveform data to {}", waveform_path.display());
+                Err(err) => {
+                    log::error!(target: "audio", "Failed to load {}: {}", path.display(), err);
+                    // Clips the user just added go away again, loaded ones stay so they can be relinked
+                    for track in self.state.all_tracks_mut() {
+                        track.samples.retain(|sample| {
+                            !sample
+                                .decoding
+                                .as_ref()
+                                .is_some_and(|pending| pending.path == path && pending.new_clip)
+                        });
+                        for sample in &mut track.samples {
+                            if sample.decoding.as_ref().is_some_and(|pending| pending.path == path) {
+                                sample.decoding = None;
                             }
                         }
                     }
+                    MessageDialog::new()
+                        .set_title("Audio Error")
+                        .set_description(format!("Failed to load {}: {}", path.display(), err))
+                        .show();
                 }
-                
-                if let Ok(serialized) = serde_json::to_string_pretty(&self.state) {
-                    if let Err(e) = fs::write(&project_file_path, serialized) {
-                        eprintln!("Failed to auto-save project: {}", e);
-                        return false;
-                    } else {
-                        eprintln!("Project auto-saved successfully to {}", project_file_path.display());
-                        return true;
+            }
+        }
+
+        for (track_id, sample_id) in new_clips {
+            self.finish_new_clip(track_id, sample_id);
+        }
+    }
+
+    // Number of files still being decoded
+    pub fn loading_count(&self) -> usize {
+        let mut paths: Vec<&PathBuf> = self
+            .state
+            .tracks
+            .iter()
+            .flat_map(|t| &t.samples)
+            .filter_map(|s| s.decoding.as_ref().map(|pending| &pending.path))
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths.len()
+    }
+
+    // A clip added by the user has its audio: copy it into the media pool and make room for it
+    fn finish_new_clip(&mut self, track_id: usize, sample_id: usize) {
+        let project_folder = self
+            .state
+            .file_path
+            .as_ref()
+            .and_then(|p| p.parent())
+            .map(|p| p.to_path_buf());
+        let bpm = self.state.bpm;
+        let Some(track) = self.state.tracks.iter_mut().find(|t| t.id == track_id) else {
+            return;
+        };
+
+        // Saved projects keep their own copy of the file in the media pool
+        if let Some(project_folder) = &project_folder {
+            if let Some(sample) = track.get_sample_mut(sample_id) {
+                if let Some(path) = sample.audio_file.clone() {
+                    let mut pool = MediaPool::open(project_folder);
+                    match pool.import(&path) {
+                        Ok(entry) => sample.set_pool_entry(&pool, &entry),
+                        Err(e) => log::error!(target: "project", "{}", e),
+                    }
+                }
+            }
+        }
+
+        track.trim_overlaps(sample_id, bpm);
+    }
+
+    // Hand finished peaks to the samples that use them and start loading missing ones.
+    // Called once per frame.
+    pub fn update_peaks(&mut self) {
+        self.peak_cache.poll();
+        for track in self.state.all_tracks_mut() {
+            for sample in &mut track.samples {
+                if sample.peaks.is_some() {
+                    continue;
+                }
+                if let (Some(hash), Some(path)) = (&sample.audio_hash, &sample.audio_file) {
+                    sample.peaks = self.peak_cache.get(hash);
+                    // No peak file yet: build one once the audio is decoded
+                    if sample.peaks.is_none() && self.peak_cache.needs_build(hash) {
+                        if let Some(decoded) = decode::cached(path) {
+                            self.peak_cache.build(hash, path, &decoded);
+                        }
                     }
-                } else {
-                    eprintln!("Failed to serialize project state for auto-save");
-                    return false;
                 }
             }
         }
-        false
     }
 
     pub fn load_project(&mut self) {
@@ -894,46 +1639,48 @@ impl DawApp {
     }
 
     fn load_project_from_path(&mut self, path: PathBuf) -> bool {
-        eprintln!("Attempting to load project from {}", path.display());
-        if let Ok(contents) = fs::read_to_string(&path) {
-            if let Ok(mut loaded_state) = serde_json::from_str::<DawState>(&contents) {
+        if !self.load_project_file(&path, Some(path.clone())) {
+            return false;
+        }
+        recovery::update_session(Some(&path));
+        self.last_autosave = Instant::now();
+        self.last_autosave_contents = project::serialize(&self.state, self.state.file_path.as_deref()).ok();
+        true
+    }
+
+    // Load `file` as the project stored at `project_path`.
+    // These only differ when restoring from a recovery file.
+    fn load_project_file(&mut self, file: &Path, project_path: Option<PathBuf>) -> bool {
+        log::debug!(target: "project", "Attempting to load project from {}", file.display());
+        match project::load(file) {
+            Ok(project_file) => {
+                // Files still loading for the previous project aren't needed anymore
+                decode::cancel_all();
+
+                let mut loaded_state = project_file.into_state(project_path.as_deref());
                 // Get the project folder (parent directory of the project file)
-                let project_folder = path.parent().unwrap_or(Path::new("")).to_path_buf();
-                
-                // Ensure waveforms directory exists
-                let waveforms_dir = project_folder.join("waveforms");
-                if !waveforms_dir.exists() {
-                    if let Err(e) = std::fs::create_dir_all(&waveforms_dir) {
-                        eprintln!("Failed to create waveforms directory for loading: {}", e);
-                    }
-                }
+                let project_folder = project_path
+                    .as_deref()
+                    .map(|path| path.parent().unwrap_or(Path::new("")).to_path_buf());
                 
                 // Set the file path in the loaded state
-                loaded_state.file_path = Some(path.clone());
+                loaded_state.file_path = project_path.clone();
                 
                 // Process each track and its samples
                 for track in &mut loaded_state.tracks {
-                    for sample in &mut track.samples {
-                        if sample.audio_file.is_some() {
-                            eprintln!(
+                    let track_name = track.name.clone();
+                    for sample in track.clips_mut() {
+                        if sample.audio_file.as_ref().is_some_and(|p| p.exists()) {
+                            log::debug!(target: "project",
                                 "Loading audio file for sample {} in track {}",
-                                sample.name, track.name
+                                sample.name, track_name
                             );
-                            sample.load_waveform(Some(&path), Some(loaded_state.bpm));
-
-                            // Create audio stream for the sample
-                            sample.create_stream(&self.audio);
-
-                            // Verify stream was created successfully
-                            if sample.stream.is_none() {
-                                eprintln!("Warning: Failed to create audio stream on project load");
-                                // Try one more time with a new Audio instance
-                                let audio = Audio::new();
-                                sample.create_stream(&audio);
-                            }
-
+                            // Clips show up right away and fill in as their files are decoded
+                            sample.request_audio(loaded_state.bpm, &self.audio, false);
                             sample.is_playing = false;
                             sample.current_position = 0.0;
+                        } else {
+                            sample.load_saved_waveform();
                         }
                     }
                 }
@@ -941,26 +1688,14 @@ impl DawApp {
                 // Scan for AudioBoxes in the project directory
                 if loaded_state.audio_boxes.is_empty() {
                     // Only scan if we don't have any boxes in our state
-                    if let Ok(entries) = std::fs::read_dir(&project_folder) {
+                    if let Some(Ok(entries)) = project_folder.as_ref().map(std::fs::read_dir) {
                         for entry in entries.filter_map(|e| e.ok()) {
                             let path = entry.path();
-                            if path.is_dir() {
-                                // Skip the waveforms directory 
-                                if path.file_name().and_then(|n| n.to_str()) == Some("waveforms") {
-                                    continue;
-                                }
-                                
-                                // Check if this directory has a render.wav or state.json file
-                                let render_path = path.join("render.wav");
-                                let state_path = path.join("state.json");
-                                
-                                if render_path.exists() || state_path.exists() {
-                                    // This is likely an AudioBox
-                                    if let Some(box_name) = path.file_name().and_then(|n| n.to_str()) {
-                                        if !loaded_state.audio_boxes.contains(&box_name.to_string()) {
-                                            loaded_state.audio_boxes.push(box_name.to_string());
-                                            eprintln!("Found AudioBox: {}", box_name);
-                                        }
+                            if group::is_group_folder(&path) {
+                                if let Some(box_name) = path.file_name().and_then(|n| n.to_str()) {
+                                    if !loaded_state.audio_boxes.contains(&box_name.to_string()) {
+                                        loaded_state.audio_boxes.push(box_name.to_string());
+                                        log::debug!(target: "project", "Found AudioBox: {}", box_name);
                                     }
                                 }
                             }
@@ -969,28 +1704,63 @@ impl DawApp {
                 }
 
                 loaded_state.is_playing = false;
+
+                // Report audio files that have gone missing since the project was saved
+                self.missing_media = media::find_missing(&loaded_state);
+                for missing in &self.missing_media {
+                    log::warn!(target: "project",
+                        "Missing audio file {} (used by {} clips)",
+                        missing.path.display(),
+                        missing.clip_count
+                    );
+                }
+
+                // Groups open in the previous project keep their edits
+                self.save_open_groups();
+                // Deleted Groups of the previous project can't be brought back anymore
+                if let Some(folder) = self.state.file_path.as_ref().and_then(|p| p.parent()) {
+                    group::empty_trash(folder);
+                }
+                self.deleted_groups.clear();
                 self.state = loaded_state;
-                eprintln!("Project loaded successfully");
-                return true;
-            } else {
-                eprintln!("Failed to parse project file: {}", path.display());
+                decode::evict_unused();
+                self.peak_cache.evict_unused();
+                // Leftovers of a session that ended without emptying it
+                if let Some(folder) = self.state.file_path.as_ref().and_then(|p| p.parent()) {
+                    group::empty_trash(folder);
+                }
+                log::info!(target: "project", "Project loaded successfully");
+                true
+            }
+            Err(e) => {
+                log::error!(target: "project", "{}", e);
+                false
             }
-        } else {
-            eprintln!("Failed to read project file: {}", path.display());
         }
-        false
     }
 
     pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
         // Create a new audio engine
         let audio = Audio::new();
 
+        // Decoded audio is resampled to the device rate and cached on disk
+        decode::set_target_rate(audio.output_config.sample_rate.0);
+        if let Some(limit) = Self::read_config().pcm_cache_limit_mb {
+            pcm_cache::set_limit_mb(limit);
+        }
+
         // Initialize with default state
         let mut app = Self {
             state: DawState::default(),
             last_update: std::time::Instant::now(),
             seek_position: None,
             audio,
+            last_autosave: Instant::now(),
+            last_autosave_contents: None,
+            missing_media: Vec::new(),
+            peak_cache: PeakCache::default(),
+            group_renderer: GroupRenderer::default(),
+            deleted_groups: Vec::new(),
         };
 
         // Create a default loop range from bar 1 to bar 4 if not set
@@ -1001,21 +1771,28 @@ impl DawApp {
             app.state.loop_range = Some((default_start, default_end));
         }
 
+        // Note whether the previous session crashed before we mark this one as running
+        let crashed_session = recovery::begin_session(None);
+
         // Try to load last project if exists
         if let Some(path) = Self::load_config() {
             if path.exists() {
-                eprintln!("Loading last project from {}", path.display());
+                log::info!(target: "project", "Loading last project from {}", path.display());
                 app.load_project_from_path(path);
             } else {
-                eprintln!("Last project path not found: {}", path.display());
+                log::warn!(target: "project", "Last project path not found: {}", path.display());
             }
         }
+
+        if let Some(crashed_session) = crashed_session {
+            app.offer_recovery(&crashed_session);
+        }
         
         // Debug output
         if let Some(path) = &app.state.file_path {
-            eprintln!("Current project file path set to: {}", path.display());
+            log::info!(target: "project", "Current project file path set to: {}", path.display());
         } else {
-            eprintln!("No project file path set in state");
+            log::info!(target: "project", "No project file path set in state");
         }
 
         // Ensure tracks are in the right state
@@ -1035,11 +1812,22 @@ impl DawApp {
 
     // Process a DAW action and update the state accordingly
     pub fn dispatch(&mut self, action: DawAction) {
+        // Instances of a Group play its render, which has to follow its contents
+        if action.edits_arrangement() {
+            // Grouping also makes a Group folder, which restoring a snapshot can't take back
+            if !matches!(action, DawAction::Undo | DawAction::Redo | DawAction::GroupSelection(_)) {
+                self.record_history(&action);
+            }
+            if let Some(name) = self.active_group() {
+                self.group_renderer.invalidate(&name);
+            }
+        }
+
         match action {
             DawAction::SetTimelinePosition(position) => {
                 self.state.timeline_position = position;
                 for track in &mut self.state.tracks {
-                    for sample in &mut track.samples {
+                    for sample in track.clips_mut() {
                         sample.update_grid_times(self.state.bpm);
                         if position >= sample.grid_start_time && position < sample.grid_end_time {
                             let relative_position = position - sample.grid_start_time;
@@ -1055,7 +1843,7 @@ impl DawApp {
                 // Update last_clicked_position too unless it's explicitly set elsewhere
                 self.state.last_clicked_position = position;
                 for track in &mut self.state.tracks {
-                    for sample in &mut track.samples {
+                    for sample in track.clips_mut() {
                         sample.update_grid_times(self.state.bpm);
                         if position >= sample.grid_start_time && position < sample.grid_end_time {
                             let relative_position = position - sample.grid_start_time;
@@ -1068,7 +1856,7 @@ impl DawApp {
                 // Only update the clicked position marker without affecting the playhead
                 self.state.last_clicked_position = position;
                 // No need to update sample playback positions
-                eprintln!("Setting clicked position to: {}", position);
+                log::debug!(target: "ui", "Setting clicked position to: {}", position);
             }
             DawAction::TogglePlayback => {
                 let was_playing = self.state.is_playing;
@@ -1079,7 +1867,7 @@ impl DawApp {
                 if !was_playing {
                     // Reset sample playback states
                     for track in &mut self.state.tracks {
-                        for sample in &mut track.samples {
+                        for sample in track.clips_mut() {
                             sample.reset_playback();
                         }
                     }
@@ -1134,7 +1922,7 @@ impl DawApp {
             DawAction::RewindTimeline => {
                 self.state.timeline_position = 0.0;
                 for track in &mut self.state.tracks {
-                    for sample in &mut track.samples {
+                    for sample in track.clips_mut() {
                         sample.is_playing = false;
                         sample.current_position = 0.0;
                         sample.seek_to(0.0);
@@ -1144,7 +1932,7 @@ impl DawApp {
             DawAction::ForwardTimeline(bars) => {
                 self.state.timeline_position += bars;
                 for track in &mut self.state.tracks {
-                    for sample in &mut track.samples {
+                    for sample in track.clips_mut() {
                         sample.is_playing = false;
                         sample.current_position = self.state.timeline_position;
                         sample.seek_to(self.state.timeline_position);
@@ -1167,6 +1955,7 @@ impl DawApp {
                 }
             }
             DawAction::AddSampleToTrack(track_id, path) => {
+                let bpm = self.state.bpm;
                 if let Some(track) = self.state.tracks.iter_mut().find(|t| t.id == track_id) {
                     let mut sample = Sample::default();
                     sample.audio_file = Some(path.clone());
@@ -1177,83 +1966,19 @@ impl DawApp {
                         .to_string();
                     sample.item_type = TrackItemType::Sample; // Mark this sample as a sample
 
-                    // Try to load the audio file first
-                    if let Some(path_ref) = &sample.audio_file {
-                        match load_audio(path_ref) {
-                            Ok((samples, _sample_rate)) => {
-                                // Only initialize the sample if loading succeeded
-                                sample.total_frames = samples.len();
-
-                                // Load samples into the audio buffer
-                                if let Ok(mut buffer) = sample.audio_buffer.lock() {
-                                    buffer.clear();
-                                    buffer.extend_from_slice(&samples);
-                                }
-
-                                // Initialize waveform and create stream
-                                sample.load_waveform(None, Some(self.state.bpm));
-                                sample.update_grid_times(self.state.bpm);
-                                sample.create_stream(&self.audio);
-
-                                // Add the sample to the track only if loading succeeded
-                                track.add_sample(sample);
-                                let new_sample_id = track.samples.last().map(|s| s.id).unwrap_or(0);
-
-                                // Handle overlapping samples
-                                if let Some(new_sample) = track.get_sample(new_sample_id) {
-                                    let current_sample_start = new_sample.grid_position;
-                                    let current_sample_end =
-                                        new_sample.grid_position + new_sample.grid_length;
-
-                                    // Find overlapping samples
-                                    let overlapping_samples: Vec<usize> = track
-                                        .samples
-                                        .iter()
-                                        .filter(|s| s.id != new_sample_id) // Skip the newly added sample
-                                        .filter(|s| {
-                                            let other_start = s.grid_position;
-                                            let other_end = s.grid_position + s.grid_length;
-
-                                            // Check if the samples overlap
-                                            current_sample_start < other_end
-                                                && current_sample_end > other_start
-                                        })
-                                        .map(|s| s.id)
-                                        .collect();
-
-                                    // Adjust the length of overlapping samples
-                                    for overlap_id in overlapping_samples {
-                                        if let Some(other_sample) = track.get_sample_mut(overlap_id)
-                                        {
-                                            // If this is a sample that starts before our new sample
-                                            if other_sample.grid_position < current_sample_start {
-                                                // Adjust its length to end exactly at the start of our new sample
-                                                let new_length = current_sample_start
-                                                    - other_sample.grid_position;
-                                                eprintln!("Adjusting sample {} length from {} to {} due to overlap with new sample {}", 
-                                                         other_sample.id, other_sample.grid_length, new_length, new_sample_id);
-                                                other_sample.grid_length = new_length;
-                                                other_sample.update_grid_times(self.state.bpm);
-                                            }
-                                        }
-                                    }
-                                }
+                    // The clip shows up as a placeholder until the file is decoded
+                    sample.request_audio(bpm, &self.audio, true);
+                    let loading = sample.decoding.is_some();
+                    track.add_sample(sample);
+                    let new_sample_id = track.samples.last().map(|s| s.id).unwrap_or(0);
+                    log::debug!(target: "project",
+                        "Added sample to track {}: {}",
+                        track.name,
+                        path.display()
+                    );
 
-                                eprintln!(
-                                    "Added sample to track {}: {}",
-                                    track.name,
-                                    path.display()
-                                );
-                            }
-                            Err(err) => {
-                                eprintln!("Failed to add sample to track: {}", err);
-                                // Show error message dialog
-                                MessageDialog::new()
-                                    .set_title("Audio Error")
-                                    .set_description(&err.to_string())
-                                    .show();
-                            }
-                        }
+                    if !loading {
+                        self.finish_new_clip(track_id, new_sample_id);
                     }
                 }
             }
@@ -1412,7 +2137,7 @@ impl DawApp {
                                     // Adjust its length to end exactly at the start of our current sample
                                     let new_length =
                                         current_sample_start - other_sample.grid_position;
-                                    eprintln!("Adjusting sample {} length from {} to {} due to overlap with sample {}", 
+                                    log::debug!(target: "project", "Adjusting sample {} length from {} to {} due to overlap with sample {}", 
                                             other_sample.id, other_sample.grid_length, new_length, sample_id);
                                     other_sample.grid_length = new_length;
                                     other_sample.update_grid_times(self.state.bpm);
@@ -1423,9 +2148,77 @@ impl DawApp {
                 }
             }
             DawAction::DeleteSample(track_id, sample_id) => {
+                let mut removed = None;
                 if let Some(track) = self.state.tracks.iter_mut().find(|t| t.id == track_id) {
                     if let Some(sample) = track.remove_sample(sample_id) {
-                        eprintln!("Removed sample {} from track {}", sample.name, track.name);
+                        log::debug!(target: "project", "Removed sample {} from track {}", sample.name, track.name);
+                        removed = sample.decoding.map(|pending| pending.path);
+                    }
+                }
+
+                // Stop decoding a file nothing is waiting for anymore
+                if let Some(path) = removed {
+                    let still_needed = self.state.tracks.iter().flat_map(|t| &t.samples).any(|s| {
+                        s.decoding.as_ref().is_some_and(|pending| pending.path == path)
+                    });
+                    if !still_needed {
+                        decode::cancel(&path);
+                    }
+                }
+            }
+            DawAction::CancelLoading => {
+                decode::cancel_all();
+                for track in &mut self.state.tracks {
+                    track
+                        .samples
+                        .retain(|s| !s.decoding.as_ref().is_some_and(|pending| pending.new_clip));
+                    for sample in &mut track.samples {
+                        sample.decoding = None;
+                    }
+                }
+            }
+            DawAction::AnalyzeLoudness(track_id, sample_id) => {
+                let Some(sample) = self
+                    .state
+                    .tracks
+                    .iter()
+                    .find(|t| t.id == track_id)
+                    .and_then(|t| t.samples.iter().find(|s| s.id == sample_id))
+                else {
+                    return;
+                };
+                match sample.loudness() {
+                    Some(loudness) => {
+                        log::info!(target: "audio", "Loudness of {}: {}", sample.name, loudness.summary().replace('\n', ", "));
+                        MessageDialog::new()
+                            .set_title(format!("Loudness of {}", sample.name))
+                            .set_description(loudness.summary())
+                            .show();
+                    }
+                    None => log::warn!(target: "audio", "Cannot analyze {}: its audio isn't loaded", sample.name),
+                }
+            }
+            DawAction::SetSamplePlayback(track_id, sample_id, playback) => {
+                let bpm = self.state.bpm;
+                if let Some(track) = self.state.tracks.iter_mut().find(|t| t.id == track_id) {
+                    if let Some(sample) = track.get_sample_mut(sample_id) {
+                        if sample.playback == playback {
+                            return;
+                        }
+                        sample.playback = playback;
+                        log::info!(target: "audio", "{}: {}", sample.name, playback.label());
+
+                        // Reload the audio the new way
+                        if let Some(stream) = sample.stream.take() {
+                            let _ = stream.pause();
+                        }
+                        sample.disk_stream = None;
+                        sample.is_playing = false;
+                        if let Ok(mut buffer) = sample.audio_buffer.lock() {
+                            *buffer = Arc::new(Pcm::default());
+                        }
+                        sample.request_audio(bpm, &self.audio, false);
+                        self.state.modified = true;
                     }
                 }
             }
@@ -1472,16 +2265,83 @@ impl DawApp {
                 self.state.loop_enabled = enabled;
                 self.state.loop_range = Some((start_time, end_time));
             }
-            DawAction::RenderSelection(path) => {
+            DawAction::RenderSelection(path, settings) => {
+                // Keep the delivery format for the next export
+                let mut config = Self::read_config();
+                config.export = settings;
+                Self::write_config(&config);
+
                 if let Some(selection) = &self.state.selection {
-                    self.render_selection(&path, selection);
+                    self.render_selection(&path, selection, &settings);
                 } else {
-                    eprintln!("Cannot render: No selection active");
+                    log::warn!(target: "audio", "Cannot render: No selection active");
                 }
             }
+            DawAction::ExportStems(folder, settings, options) => {
+                let mut config = Self::read_config();
+                config.export = settings;
+                config.stems = options;
+                Self::write_config(&config);
+
+                self.export_stems(&folder, &settings, &options);
+            }
             DawAction::SetZoomLevel(level) => {
                 self.state.zoom_level = level.clamp(0.1, 10.0);
             }
+            DawAction::SetLogLevel(level) => {
+                crate::logging::set_level(level);
+                let mut config = Self::read_config();
+                config.log_level = Some(level.to_string());
+                Self::write_config(&config);
+                log::info!(target: "ui", "Log level set to {}", level);
+            }
+            DawAction::SetPcmCacheLimit(limit) => {
+                pcm_cache::set_limit_mb(limit);
+                let mut config = Self::read_config();
+                config.pcm_cache_limit_mb = Some(limit);
+                Self::write_config(&config);
+                log::info!(target: "audio", "Decoded audio cache limit set to {} MB", limit);
+            }
+            DawAction::ClearPcmCache => {
+                pcm_cache::clear();
+            }
+            DawAction::RelinkMedia(relinks) => {
+                let bpm = self.state.bpm;
+                for (old_path, new_path) in &relinks {
+                    let mut relinked = 0;
+                    for track in &mut self.state.tracks {
+                        for sample in &mut track.samples {
+                            if sample.audio_file.as_ref() != Some(old_path) {
+                                continue;
+                            }
+                            sample.audio_file = Some(new_path.clone());
+                            sample.request_audio(bpm, &self.audio, false);
+                            relinked += 1;
+                        }
+                    }
+                    log::info!(target: "project",
+                        "Relinked {} clips from {} to {}",
+                        relinked,
+                        old_path.display(),
+                        new_path.display()
+                    );
+                }
+                // Undo and redo keep playing the replacements too
+                for tab in &mut self.state.tabs {
+                    tab.history.patch_clips(|sample| {
+                        let relink = relinks.iter().find(|(old_path, _)| sample.audio_file.as_ref() == Some(old_path));
+                        if let Some((_, new_path)) = relink {
+                            sample.audio_file = Some(new_path.clone());
+                        }
+                    });
+                }
+                self.missing_media
+                    .retain(|m| !relinks.iter().any(|(old_path, _)| old_path == &m.path));
+                self.state.modified = true;
+            }
+            DawAction::DismissMissingMedia => {
+                self.missing_media.clear();
+            }
             DawAction::SetLoopRangeFromSelection => {
                 if let Some(selection) = &self.state.selection {
                     let start_time = selection.start_beat * (60.0 / self.state.bpm);
@@ -1494,27 +2354,25 @@ impl DawApp {
                 if let Some(project_path) = self.state.file_path.as_ref() {
                     if let Some(project_dir) = project_path.parent() {
                         match Group::new(&name, project_dir) {
-                            Ok(_) => {
-                                eprintln!("Created new Group: {}", name);
+                            Ok(mut group) => {
+                                log::info!(target: "groups", "Created new Group: {}", name);
+                                
+                                // The Group starts at the project's tempo
+                                group.manifest.bpm = self.state.bpm;
+                                if let Err(e) = group::save_manifest(&group.path, &group.manifest) {
+                                    log::error!(target: "groups", "{}", e);
+                                }
                                 
                                 // Add this Group to the list of known groups
                                 if !self.state.audio_boxes.contains(&name) {
                                     self.state.audio_boxes.push(name.clone());
                                 }
                                 
-                                // Create samples directory for the Group
-                                let box_path = project_dir.join(&name);
-                                let samples_dir = box_path.join("samples");
-                                if !samples_dir.exists() {
-                                    if let Err(e) = std::fs::create_dir_all(&samples_dir) {
-                                        eprintln!("Failed to create samples directory: {}", e);
-                                    }
-                                }
-                                
-                                // Copy selected samples to the Group if there's a selection
+                                // Reference the selected samples' audio through the media pool
                                 if let Some(selection) = &self.state.selection {
-                                    let mut copied_samples = 0;
-                                    
+                                    let mut pool = MediaPool::open(project_dir);
+                                    let mut pool_ids: Vec<String> = Vec::new();
+
                                     // Iterate through selected tracks
                                     for track_idx in selection.start_track_idx..=selection.end_track_idx {
                                         if let Some(track) = self.state.tracks.get(track_idx) {
@@ -1524,15 +2382,15 @@ impl DawApp {
                                                    sample.grid_position <= selection.end_beat {
                                                     if let Some(source_path) = &sample.audio_file {
                                                         if source_path.exists() {
-                                                            let filename = source_path.file_name().unwrap_or_else(|| std::ffi::OsStr::new("sample.wav"));
-                                                            let target_path = samples_dir.join(filename);
-                                                            
-                                                            // Copy the file
-                                                            if let Err(e) = std::fs::copy(source_path, &target_path) {
-                                                                eprintln!("Failed to copy sample to Group: {}", e);
-                                                            } else {
-                                                                copied_samples += 1;
-                                                                eprintln!("Copied sample to Group: {:?}", target_path);
+                                                            match pool.import(source_path) {
+                                                                Ok(entry) => {
+                                                                    if !pool_ids.contains(&entry.id) {
+                                                                        pool_ids.push(entry.id);
+                                                                    }
+                                                                }
+                                                                Err(e) => {
+                                                                    log::error!(target: "groups", "Failed to add sample to Group: {}", e);
+                                                                }
                                                             }
                                                         }
                                                     }
@@ -1540,146 +2398,115 @@ impl DawApp {
                                             }
                                         }
                                     }
-                                    
-                                    eprintln!("Copied {} samples to Group '{}'", copied_samples, name);
+
+                                    if let Err(e) = group::save_media(&project_dir.join(&name), &pool_ids) {
+                                        log::error!(target: "groups", "{}", e);
+                                    }
+                                    log::info!(target: "groups", "Added {} samples to Group '{}'", pool_ids.len(), name);
                                 }
                             }
                             Err(e) => {
-                                eprintln!("Failed to create Group: {}", e);
+                                log::error!(target: "groups", "Failed to create Group: {}", e);
                             }
                         }
                     }
                 } else {
-                    eprintln!("No project file path set, cannot create Group");
+                    log::warn!(target: "groups", "No project file path set, cannot create Group");
                 }
             }
             DawAction::RenameGroup(old_name, new_name) => {
-                // Implementation of renaming a Group
-                if let Some(project_path) = self.state.file_path.as_ref() {
-                    if let Some(project_dir) = project_path.parent() {
-                        let box_path = project_dir.join(&old_name);
-                        if let Ok(mut audio_box) = Group::load(&box_path) {
-                            if let Err(e) = audio_box.rename(&new_name, project_dir) {
-                                eprintln!("Failed to rename Group: {}", e);
-                            } else {
-                                // Update the entry in audio_boxes
-                                if let Some(index) = self.state.audio_boxes.iter().position(|n| n == &old_name) {
-                                    self.state.audio_boxes[index] = new_name.clone();
-                                } else {
-                                    // If not found, add it
-                                    self.state.audio_boxes.push(new_name.clone());
-                                }
-                                
-                                eprintln!("Renamed AudioBox from {} to {}", old_name, new_name);
-                                
-                                // Update tab names for this AudioBox
-                                for tab in &mut self.state.tabs {
-                                    if tab.is_group && tab.group_name.as_ref().map_or(false, |n| n == &old_name) {
-                                        tab.name = format!("Box: {}", new_name);
-                                        tab.group_name = Some(new_name.clone());
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+                self.rename_group(&old_name, &new_name);
             }
             DawAction::DeleteGroup(name) => {
-                // Implementation of deleting a Group
-                if let Some(project_path) = self.state.file_path.as_ref() {
-                    if let Some(project_dir) = project_path.parent() {
-                        // ... existing code ...
-                    }
+                self.delete_group(&name);
+            }
+            DawAction::UndoDeleteGroup => {
+                self.undo_delete_group();
+            }
+            DawAction::ExportGroupToLibrary(name) => {
+                let Some(project_folder) = self.group_path(&name).and_then(|p| p.parent().map(Path::to_path_buf)) else {
+                    log::warn!(target: "groups", "No project path available to export Group '{}'", name);
+                    return;
+                };
+                // An open Group is exported as it is being edited
+                if self.group_arrangement(&name).is_some() {
+                    self.save_group(&name);
+                }
+                if let Err(e) = library::export(&project_folder, &name) {
+                    log::error!(target: "groups", "Failed to export Group '{}': {}", name, e);
+                    MessageDialog::new()
+                        .set_title("Export Group")
+                        .set_description(format!("Failed to export Group '{}': {}", name, e))
+                        .show();
                 }
             }
-            DawAction::AddGroupToTrack(track_id, box_name) => {
-                // Implementation of adding a Group to a track
-                if let Some(project_path) = self.state.file_path.as_ref() {
-                    if let Some(project_dir) = project_path.parent() {
-                        let box_path = project_dir.join(&box_name);
-                        if box_path.exists() {
-                            // Load the Group
-                            if let Ok(group) = Group::load(&box_path) {
-                                // First, check if this group exists in ANY track and remove it
-                                let mut source_track_id = None;
-                                let mut source_sample_id = None;
-                                
-                                // Search all tracks for this group
-                                for (track_idx, track) in self.state.tracks.iter().enumerate() {
-                                    if let Some(sample_idx) = track.samples.iter().position(|s| 
-                                        s.item_type == TrackItemType::Group && s.name == box_name) {
-                                        source_track_id = Some(track.id);
-                                        source_sample_id = Some(track.samples[sample_idx].id);
-                                        eprintln!("Found existing group '{}' in track {} with sample ID {}", 
-                                            box_name, track.id, track.samples[sample_idx].id);
-                                        break;
-                                    }
-                                }
-                                
-                                // If we found the group in another track, remove it
-                                if let (Some(source_track), Some(source_sample)) = (source_track_id, source_sample_id) {
-                                    if source_track != track_id {
-                                        // Remove from source track
-                                        if let Some(track) = self.state.tracks.iter_mut().find(|t| t.id == source_track) {
-                                            if let Some(pos) = track.samples.iter().position(|s| s.id == source_sample) {
-                                                track.samples.remove(pos);
-                                                eprintln!("Removed group '{}' from source track {}", box_name, source_track);
-                                            }
-                                        }
-                                    } else {
-                                        // Group is already in the target track, just update its position
-                                        eprintln!("Group '{}' is already in target track {}", box_name, track_id);
-                                        return;
-                                    }
-                                }
-                                
-                                // Find the target track
-                                if let Some(track) = self.state.tracks.iter_mut().find(|t| t.id == track_id) {
-                                    // Check if the group already exists in this track and remove it
-                                    let existing_group_index = track.samples.iter().position(|s| 
-                                        s.item_type == TrackItemType::Group && s.name == box_name);
-                                    
-                                    if let Some(index) = existing_group_index {
-                                        // Remove the existing group
-                                        track.samples.remove(index);
-                                        eprintln!("Removed existing group '{}' from track {}", box_name, track_id);
-                                    }
-                                    
-                                    // Create a new sample to represent the Group
-                                    let mut sample = Sample::default();
-                                    sample.name = box_name.clone();
-                                    sample.item_type = TrackItemType::Group;
-                                    sample.grid_position = 0.0; // This will be updated by the drag system
-                                    sample.grid_length = 4.0; // Default length of 4 beats
-                                    sample.waveform = Some(SampleWaveform {
-                                        samples: group.waveform.clone(),
-                                        sample_rate: 44100,
-                                        duration: 4.0, // Default duration of 4 seconds
-                                    });
-                                    
-                                    // Add the sample to the track
-                                    track.add_sample(sample);
-                                    eprintln!("Added Group '{}' to track {}", box_name, track_id);
-                                }
-                            } else {
-                                eprintln!("Failed to load Group: {}", box_name);
-                            }
-                        } else {
-                            eprintln!("Group path does not exist: {:?}", box_path);
+            DawAction::ImportGroupFromLibrary(name) => {
+                let Some(project_folder) = self.state.file_path.as_ref().and_then(|p| p.parent()).map(Path::to_path_buf) else {
+                    MessageDialog::new()
+                        .set_title("Import Group")
+                        .set_description("Save the project first, Groups are imported into its folder.")
+                        .show();
+                    return;
+                };
+                match library::import(&name, &project_folder) {
+                    Ok(imported) => {
+                        if !self.state.audio_boxes.contains(&imported) {
+                            self.state.audio_boxes.push(imported);
                         }
+                        self.state.modified = true;
+                    }
+                    Err(e) => {
+                        log::error!(target: "groups", "Failed to import Group '{}': {}", name, e);
+                        MessageDialog::new()
+                            .set_title("Import Group")
+                            .set_description(format!("Failed to import Group '{}': {}", name, e))
+                            .show();
                     }
                 }
             }
-            DawAction::RenderGroupFromSelection(box_name) => {
-                // Implementation of rendering the current selection to an AudioBox
-                if let Some(selection) = &self.state.selection {
-                    if let Some(project_path) = self.state.file_path.as_ref() {
-                        if let Some(project_dir) = project_path.parent() {
-                            // Create the AudioBox
-                            match Group::new(&box_name, project_dir) {
-                                Ok(mut audio_box) => {
-                                    // Get the selection data
-                                    let start_track_idx = selection.start_track_idx;
+            DawAction::AddGroupToTrack(track_id, box_name, position) => {
+                let Some(group_path) = self.group_path(&box_name) else {
+                    log::warn!(target: "groups", "No project path available to locate Group");
+                    return;
+                };
+                if !group_path.is_dir() {
+                    log::warn!(target: "groups", "Group path does not exist: {:?}", group_path);
+                    return;
+                }
+                // A Group can hold other Groups, but never itself, however deep
+                if let Some(outer) = self.active_group() {
+                    if outer == box_name || self.group_contains(&box_name, &outer) {
+                        log::warn!(target: "groups", "Group '{}' can't go inside '{}', it would contain itself", box_name, outer);
+                        MessageDialog::new()
+                            .set_title("Add Group")
+                            .set_description(format!(
+                                "'{}' can't be added to '{}' because it would end up containing itself.",
+                                box_name, outer
+                            ))
+                            .show();
+                        return;
+                    }
+                }
+                let bpm = self.state.bpm;
+                let Some(track) = self.state.tracks.iter_mut().find(|t| t.id == track_id) else {
+                    return;
+                };
+
+                // Every instance plays the Group's render, so they all change when the Group does
+                track.add_sample(Sample::group_instance(&box_name, &group_path, position, bpm, &self.audio));
+                self.state.modified = true;
+                log::info!(target: "groups", "Added instance of Group '{}' to track {}", box_name, track_id);
+            }
+            DawAction::RenderGroupFromSelection(box_name) => {
+                // Implementation of rendering the current selection to an AudioBox
+                if let Some(selection) = &self.state.selection {
+                    if let Some(project_path) = self.state.file_path.as_ref() {
+                        if let Some(project_dir) = project_path.parent() {
+                            // Create the AudioBox
+                            match Group::new(&box_name, project_dir) {
+                                Ok(mut audio_box) => {
+                                    // Get the selection data
+                                    let start_track_idx = selection.start_track_idx;
                                     let end_track_idx = selection.end_track_idx;
                                     let start_beat = selection.start_beat;
                                     let end_beat = selection.end_beat;
@@ -1691,9 +2518,16 @@ impl DawApp {
                                     // Calculate the duration in seconds
                                     let duration = end_time - start_time;
                                     if duration <= 0.0 {
-                                        eprintln!("Cannot render with zero or negative duration");
+                                        log::warn!(target: "groups", "Cannot render with zero or negative duration");
                                         return;
                                     }
+
+                                    // The Group is as long as the selection, at the project's tempo
+                                    audio_box.manifest.bpm = self.state.bpm;
+                                    audio_box.manifest.length_beats = Some(end_beat - start_beat);
+                                    if let Err(e) = group::save_manifest(&audio_box.path, &audio_box.manifest) {
+                                        log::error!(target: "groups", "{}", e);
+                                    }
                                     
                                     // Create a buffer for the mixed audio
                                     let sample_rate = 44100; // Standard sample rate
@@ -1710,7 +2544,7 @@ impl DawApp {
                                                 }
                                                 
                                                 // Get the audio data
-                                                if let Ok(audio_data) = sample.audio_buffer.lock() {
+                                                if let Some(audio_data) = sample.audio_data() {
                                                     // Calculate the relative position in the mixed buffer
                                                     let sample_offset = ((sample.grid_start_time - start_time) * sample_rate as f32).max(0.0) as usize;
                                                     
@@ -1751,449 +2585,210 @@ impl DawApp {
                                     
                                     // Render the mixed buffer to the AudioBox
                                     if let Err(e) = audio_box.render(&mixed_buffer, sample_rate) {
-                                        eprintln!("Failed to render AudioBox: {}", e);
+                                        log::error!(target: "groups", "Failed to render AudioBox: {}", e);
                                     } else {
-                                        eprintln!("Successfully rendered selection to AudioBox: {}", box_name);
+                                        log::info!(target: "groups", "Successfully rendered selection to AudioBox: {}", box_name);
                                     }
                                 }
                                 Err(e) => {
-                                    eprintln!("Failed to create AudioBox: {}", e);
+                                    log::error!(target: "groups", "Failed to create AudioBox: {}", e);
                                 }
                             }
                         }
                     }
                 } else {
-                    eprintln!("Cannot render: No selection active");
-                }
-            }
-            DawAction::OpenGroupInNewTab(box_name) => {
-                // Implementation of opening an AudioBox in a new tab
-                // First check if we already have a tab open for this box
-                if let Some(existing_tab) = self.state.tabs.iter().find(|t| 
-                    t.is_group && 
-                    t.group_name.as_ref().map_or(false, |name| name == &box_name)
-                ) {
-                    // Stop any current playback before switching tabs
-                    if self.state.is_playing {
-                        self.state.is_playing = false;
-                        
-                        // Make sure all samples are paused
-                        for track in &mut self.state.tracks {
-                            for sample in &mut track.samples {
-                                if sample.is_playing {
-                                    sample.pause();
-                                }
-                            }
-                        }
-                        
-                        eprintln!("Paused playback when opening box in existing tab");
-                    }
-                    
-                    // Box is already open in a tab, switch to it
-                    self.state.active_tab_id = existing_tab.id;
-                    eprintln!("Switched to existing tab for AudioBox '{}'", box_name);
+                    log::warn!(target: "groups", "Cannot render: No selection active");
+                }
+            }
+            DawAction::GroupSelection(box_name) => {
+                self.group_selection(&box_name);
+            }
+            DawAction::Ungroup(track_id, sample_id) => {
+                self.ungroup(track_id, sample_id);
+            }
+            DawAction::OpenGroupInNewTab(box_name, variant) => {
+                let key = group::variant_key(&box_name, variant.as_deref());
+                // A Group that is already open just gets its tab back
+                if let Some(tab_id) = self
+                    .state
+                    .tabs
+                    .iter()
+                    .find(|t| t.group_key().as_deref() == Some(key.as_str()))
+                    .map(|t| t.id)
+                {
+                    self.activate_tab(tab_id);
+                    log::debug!(target: "groups", "Switched to existing tab for Group '{}'", box_name);
                     return;
                 }
-                
-                // Stop any current playback before creating a new tab
-                if self.state.is_playing {
-                    self.state.is_playing = false;
-                    
-                    // Make sure all samples are paused
-                    for track in &mut self.state.tracks {
-                        for sample in &mut track.samples {
-                            if sample.is_playing {
-                                sample.pause();
-                            }
-                        }
-                    }
-                    
-                    eprintln!("Paused playback when opening box in new tab");
+
+                let Some(group_path) = self.group_path(&key) else {
+                    log::warn!(target: "groups", "No project path available to locate Group");
+                    return;
+                };
+                if !group_path.is_dir() {
+                    log::warn!(target: "groups", "Group not found: {}", key);
+                    return;
                 }
-                
-                if let Some(project_path) = self.state.file_path.as_ref() {
-                    if let Some(project_dir) = project_path.parent() {
-                        let box_path = project_dir.join(&box_name);
-                        if box_path.exists() && box_path.is_dir() {
-                            // Check if there's a state.json file for the full state
-                            let box_state_path = box_path.join("state.json");
-                            if box_state_path.exists() {
-                                eprintln!("Found state.json file for AudioBox '{}'", box_name);
-                                
-                                // Load the state from the file
-                                if let Ok(contents) = fs::read_to_string(&box_state_path) {
-                                    if let Ok(mut loaded_state) = serde_json::from_str::<DawState>(&contents) {
-                                        // Create a new tab for this audio box
-                                        let tab_id = if self.state.tabs.is_empty() {
-                                            0
-                                        } else {
-                                            self.state.tabs.iter().map(|t| t.id).max().unwrap_or(0) + 1
-                                        };
-                                        
-                                        // Create a new tab
-                                        let tab = Tab {
-                                            id: tab_id,
-                                            name: format!("Box: {}", box_name),
-                                            is_group: true,
-                                            group_name: Some(box_name.clone()),
-                                        };
-                                        
-                                        // Store the current state
-                                        let current_state = self.state.clone();
-                                        
-                                        // Replace the state with the loaded state
-                                        self.state = loaded_state;
-                                        
-                                        // But keep some settings from the current state
-                                        self.state.tabs = current_state.tabs;
-                                        self.state.tabs.push(tab);
-                                        self.state.active_tab_id = tab_id;
-                                        
-                                        // Load audio data for all samples
-                                        for track in &mut self.state.tracks {
-                                            for sample in &mut track.samples {
-                                                if let Some(path) = &sample.audio_file {
-                                                    match load_audio(path) {
-                                                        Ok((samples, sample_rate)) => {
-                                                            // Initialize sample with loaded audio data
-                                                            let buffer = Arc::new(Mutex::new(samples.clone()));
-                                                            sample.audio_buffer = buffer;
-                                                            
-                                                            // Initialize sample index
-                                                            sample.sample_index = Arc::new(AtomicUsize::new(0));
-                                                            
-                                                            // Create audio stream
-                                                            sample.create_stream(&self.audio);
-                                                            
-                                                            // Generate waveform
-                                                            let duration = samples.len() as f32 / sample_rate as f32;
-                                                            sample.waveform = Some(SampleWaveform {
-                                                                samples: generate_waveform(&samples, 1000),
-                                                                sample_rate,
-                                                                duration,
-                                                            });
-                                                        }
-                                                        Err(e) => {
-                                                            eprintln!("Error loading audio: {:?}", e);
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        
-                                        eprintln!("Loaded full state for AudioBox '{}'", box_name);
-                                        return;
-                                    } else {
-                                        eprintln!("Failed to parse AudioBox state file");
-                                    }
-                                } else {
-                                    eprintln!("Failed to read AudioBox state file");
-                                }
-                            }
-                            
-                            // Fallback to the old method if no state.json file or failed to load it
-                            // Create a new tab for this audio box
-                            let tab_id = if self.state.tabs.is_empty() {
-                                0
-                            } else {
-                                self.state.tabs.iter().map(|t| t.id).max().unwrap_or(0) + 1
-                            };
-                            
-                            // Create a new tab
-                            let tab = Tab {
-                                id: tab_id,
-                                name: format!("Box: {}", box_name),
-                                is_group: true,
-                                group_name: Some(box_name.clone()),
-                            };
-                            
-                            // Add the tab to tabs list
-                            self.state.tabs.push(tab);
-                            
-                            // Set this tab as active
-                            self.state.active_tab_id = tab_id;
-                            
-                            // Load samples folder from the box path
-                            let samples_dir = box_path.join("samples");
-                            if samples_dir.exists() && samples_dir.is_dir() {
-                                // We'll load any samples in this folder to the first track in the AudioBox
-                                if let Ok(entries) = std::fs::read_dir(&samples_dir) {
-                                    for entry in entries.filter_map(|e| e.ok()) {
-                                        let sample_path = entry.path();
-                                        
-                                        // Check if it's an audio file
-                                        if sample_path.is_file() {
-                                            let extension = sample_path.extension()
-                                                .and_then(|ext| ext.to_str())
-                                                .unwrap_or("")
-                                                .to_lowercase();
-                                                
-                                            if ["wav", "mp3", "ogg", "flac"].contains(&extension.as_str()) {
-                                                // The first track has ID 0 in our AudioBox
-                                                // Load this sample in the box's context
-                                                eprintln!("Loading sample for AudioBox: {:?}", sample_path);
-                                                
-                                                // This will be handled by the UI to add to the correct context
-                                                if let Some(track) = self.state.tracks.iter_mut().find(|t| t.id == 0) {
-                                                    let mut sample = Sample::default();
-                                                    sample.audio_file = Some(sample_path.clone());
-                                                    sample.name = sample_path.file_name()
-                                                        .and_then(|n| n.to_str())
-                                                        .unwrap_or("Unknown")
-                                                        .to_string();
-                                                    
-                                                    // Try to load the audio file
-                                                    if let Some(path) = &sample.audio_file {
-                                                        match load_audio(path) {
-                                                            Ok((samples, sample_rate)) => {
-                                                                // Initialize sample with loaded audio data
-                                                                let duration = samples.len() as f32 / sample_rate as f32;
-                                                                
-                                                                // Initialize AudioBuffer
-                                                                let buffer = Arc::new(Mutex::new(samples));
-                                                                sample.audio_buffer = buffer;
-                                                                
-                                                                // Initialize sample index
-                                                                sample.sample_index = Arc::new(AtomicUsize::new(0));
-                                                                
-                                                                // Calculate grid length based on duration
-                                                                sample.grid_length = duration * (self.state.bpm / 60.0);
-                                                                sample.update_grid_times(self.state.bpm);
-                                                                
-                                                                // Get a unique ID
-                                                                sample.id = track.samples.len(); // Use length as new ID
-                                                                
-                                                                // Set the position to a sensible place
-                                                                sample.grid_position = track.samples.iter()
-                                                                    .map(|s| s.grid_position + s.grid_length)
-                                                                    .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-                                                                    .unwrap_or(0.0);
-                                                                sample.update_grid_times(self.state.bpm);
-                                                                
-                                                                // Create audio stream
-                                                                sample.create_stream(&self.audio);
-                                                                
-                                                                // Generate waveform
-                                                                {
-                                                                    let buffer_guard = sample.audio_buffer.lock().unwrap();
-                                                                    
-                                                                    // Generate a smaller waveform for display
-                                                                    sample.waveform = Some(SampleWaveform {
-                                                                        samples: generate_waveform(&buffer_guard, 1000),
-                                                                        sample_rate,
-                                                                        duration,
-                                                                    });
-                                                                }
-                                                                
-                                                                // Add sample to track
-                                                                track.samples.push(sample);
-                                                            }
-                                                            Err(e) => {
-                                                                eprintln!("Error loading audio: {:?}", e);
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            
-                            eprintln!("Opened AudioBox '{}' in new tab", box_name);
-                        } else {
-                            eprintln!("AudioBox not found: {}", box_name);
-                        }
+
+                // A Group being rendered in the background is already loaded
+                let arrangement = match self.state.background_groups.remove(&key) {
+                    Some(arrangement) => arrangement,
+                    None => match self.load_group_arrangement(&group_path) {
+                        Some(arrangement) => arrangement,
+                        None => return,
+                    },
+                };
+                let tab_id = self.state.tabs.iter().map(|t| t.id + 1).max().unwrap_or(0);
+                self.state.tabs.push(Tab {
+                    id: tab_id,
+                    name: match &variant {
+                        Some(variant) => format!("Box: {} ({})", box_name, variant),
+                        None => format!("Box: {}", box_name),
+                    },
+                    is_group: true,
+                    group_name: Some(box_name.clone()),
+                    variant,
+                    arrangement: Some(arrangement),
+                    ..Tab::default()
+                });
+                self.activate_tab(tab_id);
+                log::info!(target: "groups", "Opened Group '{}' in new tab", key);
+            }
+            DawAction::CreateGroupVariant(box_name, variant) => {
+                let Some(group_path) = self.group_path(&box_name) else {
+                    log::warn!(target: "groups", "No project path available to locate Group");
+                    return;
+                };
+                // The variant starts from the main arrangement as it is being edited
+                if self.group_arrangement(&box_name).is_some() {
+                    self.save_group(&box_name);
+                }
+                match group::create_variant(&group_path, &variant, None) {
+                    Ok(()) => log::info!(target: "groups", "Added variant '{}' to Group '{}'", variant, box_name),
+                    Err(e) => log::error!(target: "groups", "{}", e),
+                }
+            }
+            DawAction::SetGroupVariant(track_id, sample_id, variant) => {
+                let Some(sample) = self
+                    .state
+                    .tracks
+                    .iter()
+                    .find(|t| t.id == track_id)
+                    .and_then(|t| t.samples.iter().find(|s| s.id == sample_id))
+                else {
+                    return;
+                };
+                let key = group::variant_key(&sample.name, variant.as_deref());
+                if let Some(outer) = self.active_group() {
+                    if outer == key || self.group_contains(&key, &outer) {
+                        log::warn!(target: "groups", "'{}' can't play inside '{}', it would contain itself", key, outer);
+                        return;
                     }
+                }
+                let Some(render_path) = self.group_path(&key).map(|path| group::render_path(&path)) else {
+                    return;
+                };
+
+                let bpm = self.state.bpm;
+                let Some(sample) = self
+                    .state
+                    .tracks
+                    .iter_mut()
+                    .find(|t| t.id == track_id)
+                    .and_then(|t| t.get_sample_mut(sample_id))
+                else {
+                    return;
+                };
+                if sample.is_playing {
+                    sample.pause();
+                }
+                // Each variant has its own render, which clamps the trims once it is decoded
+                sample.variant = variant;
+                if render_path.exists() {
+                    sample.audio_file = Some(render_path);
+                    sample.request_audio(bpm, &self.audio, false);
                 } else {
-                    eprintln!("No project path available to locate AudioBox");
+                    sample.audio_file = None;
+                    sample.waveform = None;
+                }
+                self.state.modified = true;
+            }
+            DawAction::SetPlaybackScope(scope) => {
+                let active_id = self.state.active_tab_id;
+                let Some(tab) = self.state.tabs.iter_mut().find(|t| t.id == active_id) else {
+                    return;
+                };
+                // Only a Group has a context to be heard in
+                if !tab.is_group || tab.playback_scope == scope {
+                    return;
                 }
+                tab.playback_scope = scope;
+                self.stop_main_audition();
+                for sample in self.state.tracks.iter_mut().flat_map(|t| &mut t.samples) {
+                    sample.reset_playback();
+                }
+                self.last_update = Instant::now();
+            }
+            DawAction::Undo => {
+                self.step_history(false);
+            }
+            DawAction::Redo => {
+                self.step_history(true);
             }
             DawAction::SwitchToTab(tab_id) => {
-                // Implementation of switching to a different tab
-                if let Some(tab) = self.state.tabs.iter().find(|t| t.id == tab_id) {
-                    // Check if we're switching between different tab types (audio box vs project)
-                    let current_tab = self.state.tabs.iter().find(|t| t.id == self.state.active_tab_id);
-                    let is_switching_tab_types = current_tab.map_or(false, |current| {
-                        current.is_group != tab.is_group
-                    });
-                    
-                    // If we're switching between tab types and playback is active, pause playback
-                    if is_switching_tab_types && self.state.is_playing {
-                        self.state.is_playing = false;
-                        
-                        // Make sure all samples are paused
-                        for track in &mut self.state.tracks {
-                            for sample in &mut track.samples {
-                                if sample.is_playing {
-                                    sample.pause();
-                                }
-                            }
-                        }
-                        
-                        eprintln!("Paused playback when switching between different editor types");
-                    }
-                    
-                    self.state.active_tab_id = tab_id;
-                    eprintln!("Switched to tab: {}", tab.name);
+                if self.state.tabs.iter().any(|t| t.id == tab_id) {
+                    self.activate_tab(tab_id);
+                    log::debug!(target: "ui", "Switched to tab: {}", tab_id);
                 } else {
-                    eprintln!("Tab not found: {}", tab_id);
+                    log::warn!(target: "ui", "Tab not found: {}", tab_id);
                 }
             }
             DawAction::CloseTab(tab_id) => {
-                // Implementation of closing a tab
-                if let Some(tab_index) = self.state.tabs.iter().position(|t| t.id == tab_id) {
-                    // Stop any current playback when closing a tab
-                    if self.state.is_playing {
-                        self.state.is_playing = false;
-                        
-                        // Make sure all samples are paused
-                        for track in &mut self.state.tracks {
-                            for sample in &mut track.samples {
-                                if sample.is_playing {
-                                    sample.pause();
-                                }
-                            }
-                        }
-                        
-                        eprintln!("Paused playback when closing tab");
+                let Some(tab) = self.state.tabs.iter().find(|t| t.id == tab_id) else {
+                    log::warn!(target: "ui", "Tab not found: {}", tab_id);
+                    return;
+                };
+                // The main arrangement can't be closed
+                if !tab.is_group {
+                    return;
+                }
+
+                // Closing the active tab goes back to the main arrangement first
+                if self.state.active_tab_id == tab_id {
+                    if let Some(main_id) = self.state.tabs.iter().find(|t| !t.is_group).map(|t| t.id) {
+                        self.activate_tab(main_id);
                     }
-                    
-                    self.state.tabs.remove(tab_index);
-                    
-                    // If we're closing the active tab, switch to another tab
-                    if self.state.active_tab_id == tab_id {
-                        if let Some(first_tab) = self.state.tabs.first() {
-                            self.state.active_tab_id = first_tab.id;
-                        }
+                }
+
+                // Keep the Group's edits before its arrangement goes away
+                if let Some(name) = self.state.tabs.iter().find(|t| t.id == tab_id).and_then(|t| t.group_key()) {
+                    if self.save_group(&name) && self.group_renderer.is_stale(&name) {
+                        self.start_group_render(&name);
                     }
-                    
-                    eprintln!("Closed tab: {}", tab_id);
-                } else {
-                    eprintln!("Tab not found: {}", tab_id);
                 }
+                self.state.tabs.retain(|t| t.id != tab_id);
+                log::debug!(target: "ui", "Closed tab: {}", tab_id);
             }
             DawAction::SaveGroup(box_name) => {
-                // Implementation of saving an AudioBox state and updating its render.wav
-                if let Some(project_path) = self.state.file_path.as_ref() {
-                    if let Some(project_dir) = project_path.parent() {
-                        let box_path = project_dir.join(&box_name);
-                        
-                        // Ensure the box directory exists
-                        if !box_path.exists() {
-                            if let Err(e) = std::fs::create_dir_all(&box_path) {
-                                eprintln!("Failed to create AudioBox directory: {:?}", e);
-                                return;
-                            }
-                        }
-                        
-                        // Ensure samples directory exists
-                        let samples_dir = box_path.join("samples");
-                        if !samples_dir.exists() {
-                            if let Err(e) = std::fs::create_dir_all(&samples_dir) {
-                                eprintln!("Failed to create samples directory: {:?}", e);
-                                return;
-                            }
-                        }
-                        
-                        // Save full project state to support multi-track audio boxes
-                        let box_state_path = box_path.join("state.json");
-                        
-                        if let Some(tab) = self.state.tabs.iter().find(|t| 
-                            t.is_group && 
-                            t.group_name.as_ref().map_or(false, |name| name == &box_name)
-                        ) {
-                            // Create a copy of the current state to save
-                            let mut box_state = self.state.clone();
-                            
-                            // Keep track of this AudioBox in the main project
-                            if !self.state.audio_boxes.contains(&box_name) {
-                                self.state.audio_boxes.push(box_name.clone());
-                            }
-                            
-                            // Update metadata
-                            box_state.project_name = box_name.clone();
-                            box_state.file_path = Some(box_state_path.clone());
-                            
-                            // Serialize and save the state
-                            if let Ok(serialized) = serde_json::to_string_pretty(&box_state) {
-                                if let Err(e) = fs::write(&box_state_path, serialized) {
-                                    eprintln!("Failed to write AudioBox state file: {:?}", e);
-                                } else {
-                                    eprintln!("Saved full state for AudioBox '{}'", box_name);
-                                }
-                            } else {
-                                eprintln!("Failed to serialize AudioBox state");
-                            }
-                            
-                            // Render tracks to a single audio file
-                            let render_path = box_path.join("render.wav");
-                            
-                            // For now, just mix all active samples
-                            let mut final_samples = Vec::new();
-                            let mut final_rate = 44100;
-                            
-                            for track in &self.state.tracks {
-                                if track.muted {
-                                    continue;
-                                }
-                                
-                                for sample in &track.samples {
-                                    if let Some(waveform) = &sample.waveform {
-                                        if let Ok(buffer_guard) = sample.audio_buffer.lock() {
-                                            // Apply trim points
-                                            let start_frame = (sample.trim_start * waveform.sample_rate as f32) as usize;
-                                            let end_frame = if sample.trim_end <= 0.0 {
-                                                buffer_guard.len()
-                                            } else {
-                                                (sample.trim_end * waveform.sample_rate as f32) as usize
-                                            };
-                                            
-                                            // Get trimmed audio
-                                            let trimmed_audio: Vec<f32> = buffer_guard
-                                                .iter()
-                                                .skip(start_frame)
-                                                .take(end_frame.saturating_sub(start_frame))
-                                                .cloned()
-                                                .collect();
-                                            
-                                            // Mix with existing audio
-                                            if final_samples.is_empty() {
-                                                final_samples = trimmed_audio;
-                                                final_rate = waveform.sample_rate;
-                                            } else {
-                                                // Simple mixing - in a real implementation we'd need to handle sample rate conversion
-                                                // and different lengths better
-                                                let max_len = final_samples.len().max(trimmed_audio.len());
-                                                let mut mixed = Vec::with_capacity(max_len);
-                                                
-                                                for i in 0..max_len {
-                                                    let a = if i < final_samples.len() { final_samples[i] } else { 0.0 };
-                                                    let b = if i < trimmed_audio.len() { trimmed_audio[i] } else { 0.0 };
-                                                    
-                                                    // Simple mixing - 0.5 scale to avoid clipping
-                                                    mixed.push((a + b) * 0.5);
-                                                }
-                                                
-                                                final_samples = mixed;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            
-                            // Save the render.wav file
-                            if !final_samples.is_empty() {
-                                if let Err(e) = crate::audio::save_audio(&render_path, &final_samples, final_rate) {
-                                    eprintln!("Failed to save AudioBox render: {:?}", e);
-                                } else {
-                                    eprintln!("Updated AudioBox '{}' render file", box_name);
-                                }
-                            }
-                        }
+                if self.save_group(&box_name) {
+                    self.start_group_render(&box_name);
+                }
+            }
+            DawAction::FreezeTrack(track_id) => {
+                self.freeze_track(track_id);
+            }
+            DawAction::UnfreezeTrack(track_id) => {
+                if let Some(mut frozen) = self.state.tracks.iter_mut().find(|t| t.id == track_id).and_then(|t| t.frozen.take()) {
+                    if frozen.clip.is_playing {
+                        frozen.clip.pause();
                     }
+                    self.state.modified = true;
+                    log::info!(target: "audio", "Unfroze track {}", track_id);
                 }
             }
+            DawAction::BounceSelection => {
+                self.bounce_selection();
+            }
             DawAction::CreateTrack => {
                 // Create a new track with a unique ID
                 let new_id = self.state.next_track_id;
@@ -2224,6 +2819,7 @@ impl DawApp {
     }
 
     pub fn update_playback(&mut self) {
+        self.thaw_edited_tracks();
         if self.state.is_playing {
             let now = std::time::Instant::now();
             let elapsed = now.duration_since(self.last_update).as_secs_f32();
@@ -2232,138 +2828,131 @@ impl DawApp {
             // Update timeline position
             self.state.timeline_position += elapsed;
 
+            // A Group tab auditioning in context plays the main arrangement and keeps its own tracks quiet
+            let audition = self.auditions_main();
+            if audition {
+                for sample in self.state.tracks.iter_mut().flat_map(Track::clips_mut) {
+                    if sample.is_playing {
+                        sample.pause();
+                    }
+                }
+            }
+
             // Handle looping based on loop range only (independent from selection)
+            let mut looped = false;
             if self.state.loop_enabled {
                 // Use loop range for looping
                 if let Some((start, end)) = self.state.loop_range {
                     if self.state.timeline_position >= end {
-                        eprintln!("Looping back from loop range end to start");
+                        log::trace!(target: "audio", "Looping back from loop range end to start");
                         self.state.timeline_position = start;
-
-                        // Reset playback state for all samples
-                        for track in &mut self.state.tracks {
-                            for sample in &mut track.samples {
-                                sample.reset_playback();
-                            }
-                        }
+                        looped = true;
                     }
                 }
             }
 
             let timeline_pos = self.state.timeline_position;
-            let mut any_sample_playing = false;
-
-            let any_track_soloed = self.state.tracks.iter().any(|t| t.soloed);
-            
-            // Check if we're in an audio box tab
-            let active_tab = self.state.tabs.iter().find(|t| t.id == self.state.active_tab_id);
-            let is_audio_box_tab = active_tab.map_or(false, |tab| tab.is_group);
-            let audio_box_name = active_tab.and_then(|tab| tab.group_name.clone());
-
-            for track in &mut self.state.tracks {
-                if track.muted || (any_track_soloed && !track.soloed) {
-                    continue;
-                }
-
-                for sample in &mut track.samples {
-                    // Skip samples that don't belong to the current tab type
-                    if is_audio_box_tab {
-                        // In an audio box tab, only play samples with item_type == AudioBox that
-                        // match the current audio box name
-                        match &sample.item_type {
-                            TrackItemType::Group => {
-                                // If this sample doesn't represent the current audio box, skip it
-                                if sample.name != audio_box_name.clone().unwrap_or_default() {
-                                    // Ensure we pause it if it's somehow playing
-                                    if sample.is_playing {
-                                        sample.pause();
-                                    }
-                                    continue;
-                                }
-                            },
-                            TrackItemType::Sample => {
-                                // If we're in an audio box tab, don't play regular samples
-                                // from the project
-                                if sample.is_playing {
-                                    sample.pause();
-                                }
-                                continue;
-                            }
-                        }
-                    } else {
-                        // In the main project tab, only play regular samples, not audio boxes
-                        if let TrackItemType::Group = sample.item_type {
-                            // If we're in the main project tab, don't play AudioBox samples
-                            if sample.is_playing {
-                                sample.pause();
-                            }
-                            continue;
-                        }
-                    }
-                    
-                    sample.update_grid_times(self.state.bpm);
-                    let should_play = timeline_pos >= sample.grid_start_time
-                        && timeline_pos < sample.grid_end_time;
+            let loop_enabled = self.state.loop_enabled;
+            let (tracks, bpm) = match audition {
+                true => match self.parked_main_mut() {
+                    Some(main) => (&mut main.tracks, main.bpm),
+                    None => return,
+                },
+                false => (&mut self.state.tracks, self.state.bpm),
+            };
 
-                    if should_play {
-                        if !sample.is_playing {
-                            let relative_position = timeline_pos - sample.grid_start_time;
-                            sample.seek_to(relative_position);
-                            sample.play();
-                        }
-                        let relative_position = timeline_pos - sample.grid_start_time;
-                        sample.current_position = relative_position;
-
-                        if let Some(_waveform) = &sample.waveform {
-                            let effective_position = sample.trim_start + relative_position;
-                            if sample.trim_end > 0.0 && effective_position >= sample.trim_end {
-                                if sample.is_playing {
-                                    sample.pause();
-                                }
-                            } else {
-                                any_sample_playing = true;
-                            }
-                        } else {
-                            any_sample_playing = true;
-                        }
-                    } else {
-                        if sample.is_playing {
-                            sample.pause();
-                        }
-                    }
+            // Reset playback state for all samples
+            if looped {
+                for sample in tracks.iter_mut().flat_map(Track::clips_mut) {
+                    sample.reset_playback();
                 }
             }
 
+            let any_sample_playing = play_tracks(tracks, bpm, timeline_pos);
+
             // Check if we've reached the end of all samples
-            if !any_sample_playing && !self.state.loop_enabled {
-                let all_samples_past = self.state.tracks.iter().all(|track| {
+            if !any_sample_playing && !loop_enabled {
+                let all_samples_past = tracks.iter().all(|track| {
                     track
                         .samples
                         .iter()
-                        .all(|sample| self.state.timeline_position >= sample.grid_end_time)
+                        .all(|sample| timeline_pos >= sample.grid_end_time)
                 });
 
-                if all_samples_past && !self.state.tracks.iter().all(|t| t.samples.is_empty()) {
+                if all_samples_past && !tracks.iter().all(|t| t.samples.is_empty()) {
                     // We've reached the end of all samples, restart from beginning
-                    eprintln!("Reached end of all samples, rewinding");
+                    log::debug!(target: "audio", "Reached end of all samples, rewinding");
+                    self.stop_main_audition();
                     self.dispatch(DawAction::RewindTimeline);
                 }
             }
         } else {
             // If not playing, make sure all samples are paused
             for track in &mut self.state.tracks {
-                for sample in &mut track.samples {
+                for sample in track.clips_mut() {
                     if sample.is_playing {
                         sample.pause();
                     }
                 }
             }
+            self.stop_main_audition();
         }
     }
 
     pub fn on_exit(&mut self) {
-        eprintln!("Application exiting, auto-saving project...");
-        self.autosave_project();
+        log::info!(target: "project", "Application exiting, saving project...");
+        if let Some(path) = self.state.file_path.clone() {
+            self.save_open_groups();
+            match project::save(&path, &self.project_state()) {
+                Ok(()) => recovery::remove_recovery(Some(&path)),
+                Err(e) => {
+                    // Keep the recovery file so the work can be restored on next start
+                    log::error!(target: "project", "{}", e);
+                    self.autosave_project();
+                }
+            }
+            if let Some(folder) = path.parent() {
+                group::empty_trash(folder);
+            }
+        } else {
+            recovery::remove_recovery(None);
+        }
+        recovery::end_session();
+    }
+
+    // Ask whether to restore the autosave left behind by a crashed session
+    fn offer_recovery(&mut self, crashed_session: &recovery::SessionMarker) {
+        let Some(recovery_file) = recovery::find_recovery(crashed_session) else {
+            return;
+        };
+
+        let project_name = crashed_session
+            .project
+            .as_ref()
+            .and_then(|p| p.parent())
+            .and_then(|p| p.file_name())
+            .and_then(|n| n.to_str())
+            .unwrap_or("an unsaved project");
+
+        let restore = MessageDialog::new()
+            .set_title("Restore Unsaved Changes")
+            .set_description(format!(
+                "Monlam did not shut down cleanly. Restore the autosaved changes to {}?",
+                project_name
+            ))
+            .set_buttons(MessageButtons::YesNo)
+            .show();
+
+        if restore == MessageDialogResult::Yes {
+            if self.load_project_file(&recovery_file, crashed_session.project.clone()) {
+                // Restored work is unsaved until the user saves it
+                self.state.modified = true;
+                recovery::update_session(self.state.file_path.as_deref());
+                log::info!(target: "project", "Restored project from {}", recovery_file.display());
+            }
+        } else {
+            let _ = fs::remove_file(&recovery_file);
+        }
     }
 
     // Update track and sample grid_start_time and grid_end_time when BPM changes
@@ -2397,12 +2986,19 @@ impl DawApp {
                 tabs: default_tabs(),
                 active_tab_id: 0,
                 audio_boxes: Vec::new(),
+                background_groups: HashMap::new(),
                 next_track_id: 5,
                 modified: false,
             },
             audio: Audio::new(),
             last_update: std::time::Instant::now(),
             seek_position: None,
+            last_autosave: Instant::now(),
+            last_autosave_contents: None,
+            missing_media: Vec::new(),
+            peak_cache: PeakCache::default(),
+            group_renderer: GroupRenderer::default(),
+            deleted_groups: Vec::new(),
         };
 
         // Create a default test track
@@ -2434,8 +3030,13 @@ impl DawApp {
         time * (self.state.bpm / 60.0)
     }
 
-    pub fn render_selection(&self, output_path: &Path, selection: &SelectionRect) -> bool {
-        eprintln!("Starting render to {}", output_path.display());
+    pub fn render_selection(
+        &self,
+        output_path: &Path,
+        selection: &SelectionRect,
+        settings: &ExportSettings,
+    ) -> bool {
+        log::info!(target: "audio", "Starting render to {}", output_path.display());
 
         // Calculate time range from the selection (in seconds)
         let start_time = selection.start_beat * (60.0 / self.state.bpm);
@@ -2443,131 +3044,1696 @@ impl DawApp {
         let duration = end_time - start_time;
 
         if duration <= 0.0 {
-            eprintln!("Cannot render: Invalid selection duration");
+            log::warn!(target: "audio", "Cannot render: Invalid selection duration");
             return false;
         }
 
-        // Target sample rate and channels for rendering
-        let target_sample_rate = 44100;
-        let num_channels = 2; // Stereo output
-
-        // Create the WAV file with appropriate specs
-        use hound::{WavSpec, WavWriter};
-        let spec = WavSpec {
-            channels: num_channels as u16,
-            sample_rate: target_sample_rate,
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
-        };
-
-        let writer = match WavWriter::create(output_path, spec) {
-            Ok(writer) => writer,
-            Err(e) => {
-                eprintln!("Failed to create WAV file: {}", e);
-                return false;
-            }
-        };
-
         // Get track range for the selection
         let track_start = selection.start_track_idx;
         let track_end = selection.end_track_idx;
 
         // Check if we have valid track indices
         if track_end >= self.state.tracks.len() || track_start > track_end {
-            eprintln!("Cannot render: Invalid track selection");
+            log::warn!(target: "audio", "Cannot render: Invalid track selection");
             return false;
         }
 
-        // Rest of your rendering code here...
-        
-        // Finalize the WAV file
-        if let Err(e) = writer.finalize() {
-            eprintln!("Error finalizing WAV writer: {}", e);
+        // Mix at the rate the audio was decoded at; the export converts to the delivery rate
+        let mix_rate = self.audio.output_config.sample_rate.0;
+        let mixed = self.mix_down(track_start..=track_end, start_time, end_time, mix_rate);
+
+        if let Err(e) = export::export(output_path, &mixed, 2, mix_rate, settings) {
+            log::error!(target: "audio", "Failed to export {}: {}", output_path.display(), e);
             return false;
         }
 
-        eprintln!(
+        // Measure what was actually delivered
+        match loudness::analyze_file(output_path) {
+            Ok(loudness) => log::info!(target: "audio",
+                "Loudness of {}: {}",
+                output_path.display(),
+                loudness.summary().replace('\n', ", ")
+            ),
+            Err(e) => log::warn!(target: "audio", "Failed to measure {}: {}", output_path.display(), e),
+        }
+
+        log::info!(target: "audio",
             "Successfully rendered selection to {}",
             output_path.display()
         );
         true
     }
 
-    pub fn save_project_as(&mut self) {
-        // Always prompt for folder location for "Save As"
-        if let Some(folder_path) = FileDialog::new()
-            .set_title("Save Project As")
-            .pick_folder()
-        {
-            // Create a folder with the project name
-            let project_name = if self.state.project_name.trim().is_empty() {
-                "Untitled Project"
-            } else {
-                &self.state.project_name
-            };
-            
-            let project_folder = folder_path.join(project_name);
-            
-            // Create the project folder if it doesn't exist
-            if !project_folder.exists() {
-                if let Err(e) = std::fs::create_dir_all(&project_folder) {
-                    eprintln!("Failed to create project folder: {}", e);
+    /// Export one file per track, or per Group instance, into `folder`. Every file covers the same
+    /// time range so they line up when imported elsewhere: the selection if there is one, otherwise
+    /// the whole project.
+    ///
+    /// Stems are always dry: tracks have no inserts, so there is no with/without inserts choice
+    /// to make until they do.
+    pub fn export_stems(&self, folder: &Path, settings: &ExportSettings, options: &StemOptions) -> bool {
+        let (tracks, start_time, end_time) = match &self.state.selection {
+            Some(selection) if selection.end_beat > selection.start_beat => (
+                selection.start_track_idx..=selection.end_track_idx.min(self.state.tracks.len().saturating_sub(1)),
+                self.beat_to_time(selection.start_beat),
+                self.beat_to_time(selection.end_beat),
+            ),
+            _ => {
+                let end = self
+                    .state
+                    .tracks
+                    .iter()
+                    .flat_map(|track| &track.samples)
+                    .map(|sample| sample.grid_end_time)
+                    .fold(0.0, f32::max);
+                (0..=self.state.tracks.len().saturating_sub(1), 0.0, end)
+            }
+        };
+        if self.state.tracks.is_empty() || end_time <= start_time {
+            log::warn!(target: "audio", "Cannot export stems: Nothing to export");
+            return false;
+        }
+        if let Err(e) = fs::create_dir_all(folder) {
+            log::error!(target: "audio", "Failed to create stem folder {}: {}", folder.display(), e);
+            return false;
+        }
+
+        // Each stem is (file name, clips it plays)
+        let mut stems: Vec<(String, Vec<&Sample>)> = Vec::new();
+        for track in &self.state.tracks[tracks.clone()] {
+            // Post-fader, a track that isn't heard in the mix gives a silent stem
+            let heard = options.tap == StemTap::PreFader || Self::is_audible(&self.state.tracks, track);
+            match options.source {
+                StemSource::Tracks => {
+                    let clips = if heard { track.samples.iter().collect() } else { Vec::new() };
+                    stems.push((track.name.clone(), clips));
+                }
+                StemSource::Groups => {
+                    for sample in &track.samples {
+                        if sample.item_type != TrackItemType::Group
+                            || sample.grid_end_time <= start_time
+                            || sample.grid_start_time >= end_time
+                        {
+                            continue;
+                        }
+                        let clips = if heard { vec![sample] } else { Vec::new() };
+                        stems.push((format!("{} - {}", track.name, sample.name), clips));
+                    }
+                }
+            }
+        }
+        if stems.is_empty() {
+            log::warn!(target: "audio", "Cannot export stems: No Groups in the exported range");
+            return false;
+        }
+
+        let mix_rate = self.audio.output_config.sample_rate.0;
+
+        // Normalizing each stem on its own would change the balance, so they all get the mix's gain
+        let (gain, stem_settings) = if settings.normalize {
+            let mix = self.mix_down(tracks, start_time, end_time, mix_rate);
+            let gain = export::normalization_gain(&mix, 2, mix_rate, settings);
+            (gain, ExportSettings { normalize: false, ..*settings })
+        } else {
+            (1.0, *settings)
+        };
+
+        let mut exported = 0;
+        for (index, (name, clips)) in stems.iter().enumerate() {
+            let path = folder.join(export::stem_file_name(index, name, settings.format));
+            let mut mixed = Self::mix_clips(clips.iter().copied(), start_time, end_time, mix_rate);
+            if gain != 1.0 {
+                mixed.iter_mut().for_each(|s| *s *= gain);
+            }
+            match export::export(&path, &mixed, 2, mix_rate, &stem_settings) {
+                Ok(()) => exported += 1,
+                Err(e) => log::error!(target: "audio", "Failed to export stem {}: {}", path.display(), e),
+            }
+        }
+
+        log::info!(target: "audio",
+            "Exported {} of {} stems to {}",
+            exported,
+            stems.len(),
+            folder.display()
+        );
+        exported == stems.len()
+    }
+
+    /// Whether the track is heard among `tracks`: not muted, and soloed if any track is
+    fn is_audible(tracks: &[Track], track: &Track) -> bool {
+        let any_soloed = tracks.iter().any(|t| t.soloed);
+        !track.muted && (!any_soloed || track.soloed)
+    }
+
+    /// Mix the audible tracks in `tracks` between `start_time` and `end_time` (seconds) to
+    /// interleaved stereo at `sample_rate`
+    fn mix_down(
+        &self,
+        tracks: RangeInclusive<usize>,
+        start_time: f32,
+        end_time: f32,
+        sample_rate: u32,
+    ) -> Vec<f32> {
+        let clips = self.state.tracks[tracks]
+            .iter()
+            .filter(|track| Self::is_audible(&self.state.tracks, track))
+            .flat_map(|track| &track.samples);
+        Self::mix_clips(clips, start_time, end_time, sample_rate)
+    }
+
+    /// Mix `clips` between `start_time` and `end_time` (seconds) to interleaved stereo at `sample_rate`
+    fn mix_clips<'a>(
+        clips: impl Iterator<Item = &'a Sample>,
+        start_time: f32,
+        end_time: f32,
+        sample_rate: u32,
+    ) -> Vec<f32> {
+        let clips: Vec<MixClip> = clips
+            .filter(|sample| sample.grid_end_time > start_time && sample.grid_start_time < end_time)
+            .filter_map(MixClip::of)
+            .collect();
+        mix(&clips, start_time, end_time, sample_rate)
+    }
+
+    pub fn save_project_as(&mut self) {
+        // Always prompt for folder location for "Save As"
+        if let Some(folder_path) = FileDialog::new()
+            .set_title("Save Project As")
+            .pick_folder()
+        {
+            // Create a folder with the project name
+            let project_name = if self.state.project_name.trim().is_empty() {
+                "Untitled Project"
+            } else {
+                &self.state.project_name
+            };
+            
+            let project_folder = folder_path.join(project_name);
+            
+            // Create the project folder if it doesn't exist
+            if !project_folder.exists() {
+                if let Err(e) = std::fs::create_dir_all(&project_folder) {
+                    log::error!(target: "project", "Failed to create project folder: {}", e);
                     return;
                 }
             }
-            
-            // Create waveforms directory
-            let waveforms_dir = project_folder.join("waveforms");
-            if !waveforms_dir.exists() {
-                if let Err(e) = std::fs::create_dir_all(&waveforms_dir) {
-                    eprintln!("Failed to create waveforms directory: {}", e);
-                }
+            
+            // The new project gets its own copy of every audio file
+            for path in self.import_into_pool(&project_folder) {
+                log::warn!(target: "project", "Could not import {} into the media pool", path.display());
+            }
+
+            // Save the project file inside the folder
+            let project_file_path = project_folder.join("project.json");
+            
+            // Then save the project state to project.json file in the project folder
+            match project::save(&project_file_path, &self.project_state()) {
+                Ok(()) => {
+                    // The unsaved work now lives in a real project
+                    recovery::remove_recovery(self.state.file_path.as_deref());
+
+                    // Update the file_path in the state
+                    self.state.file_path = Some(project_file_path.clone());
+                    
+                    Self::save_config(Some(project_file_path.clone()));
+                    self.on_project_saved();
+                    log::info!(target: "project", "Project saved successfully to {}", project_file_path.display());
+                }
+                Err(e) => log::error!(target: "project", "{}", e),
+            }
+        }
+    }
+
+    // Bring every sample's audio into the media pool of the project in `project_folder`.
+    // Returns the files that could not be imported.
+    fn import_into_pool(&mut self, project_folder: &Path) -> Vec<PathBuf> {
+        let mut pool = MediaPool::open(project_folder);
+        // Source path -> pool entry, so a file used by several samples is hashed once
+        let mut imported: HashMap<PathBuf, PoolEntry> = HashMap::new();
+        let mut failed = Vec::new();
+
+        for track in self.state.all_tracks_mut() {
+            for sample in &mut track.samples {
+                let Some(source) = sample.audio_file.clone() else {
+                    continue;
+                };
+                let in_pool = sample.pool_id.as_deref().and_then(|id| pool.path_of(id)) == Some(source.clone());
+                if in_pool || failed.contains(&source) {
+                    continue;
+                }
+
+                if let Some(entry) = imported.get(&source) {
+                    sample.set_pool_entry(&pool, entry);
+                    continue;
+                }
+
+                if !source.exists() {
+                    failed.push(source);
+                    continue;
+                }
+
+                match pool.import(&source) {
+                    Ok(entry) => {
+                        sample.set_pool_entry(&pool, &entry);
+                        imported.insert(source, entry);
+                    }
+                    Err(e) => {
+                        log::error!(target: "project", "{}", e);
+                        failed.push(source);
+                    }
+                }
+            }
+        }
+
+        if !imported.is_empty() {
+            log::info!(target: "project",
+                "Imported {} audio files into {}",
+                imported.len(),
+                MediaPool::folder_for(project_folder).display()
+            );
+        }
+        failed
+    }
+
+    // Copy every audio file the project uses from outside its folder into <project>/audio/ and save.
+    // Saving indexes the copies in the media pool where they are.
+    pub fn collect_and_save(&mut self) {
+        // An unsaved project has no folder to collect into yet
+        if self.state.file_path.is_none() {
+            self.save_project();
+        }
+        let Some(project_folder) = self
+            .state
+            .file_path
+            .as_ref()
+            .and_then(|p| p.parent())
+            .map(|p| p.to_path_buf())
+        else {
+            return;
+        };
+
+        let audio_dir = project_folder.join(media_pool::COLLECT_DIR);
+        // Source path -> copied path, so a file used by several samples is copied once
+        let mut collected: HashMap<PathBuf, PathBuf> = HashMap::new();
+        let mut failed = Vec::new();
+
+        for sample in self.state.all_tracks_mut().flat_map(|track| &mut track.samples) {
+            let Some(source) = sample.audio_file.clone() else {
+                continue;
+            };
+            if source.starts_with(&project_folder) || failed.contains(&source) {
+                continue;
+            }
+
+            if let Some(target) = collected.get(&source) {
+                sample.audio_file = Some(target.clone());
+                sample.pool_id = None;
+                continue;
+            }
+
+            if !source.exists() {
+                failed.push(source);
+                continue;
+            }
+
+            if let Err(e) = fs::create_dir_all(&audio_dir) {
+                log::error!(target: "project", "Failed to create audio folder: {}", e);
+                return;
+            }
+
+            let target = unique_file_path(&audio_dir, &source);
+            match fs::copy(&source, &target) {
+                Ok(_) => {
+                    log::debug!(target: "project", "Collected {} into {}", source.display(), target.display());
+                    sample.audio_file = Some(target.clone());
+                    sample.pool_id = None;
+                    collected.insert(source, target);
+                }
+                Err(e) => {
+                    log::error!(target: "project", "Failed to copy {}: {}", source.display(), e);
+                    failed.push(source);
+                }
+            }
+        }
+
+        log::info!(target: "project", "Collected {} audio files into {}", collected.len(), audio_dir.display());
+
+        if !failed.is_empty() {
+            let list: Vec<String> = failed.iter().map(|p| p.display().to_string()).collect();
+            MessageDialog::new()
+                .set_title("Collect and Save")
+                .set_description(format!(
+                    "These files could not be copied into the project:\n\n{}",
+                    list.join("\n")
+                ))
+                .show();
+        }
+
+        self.save_project();
+    }
+
+    /// Arrangement key of the Group variant the active tab edits, if it edits one
+    pub fn active_group(&self) -> Option<String> {
+        self.state
+            .tabs
+            .iter()
+            .find(|t| t.id == self.state.active_tab_id)
+            .and_then(|t| t.group_key())
+    }
+
+    // The state as the project file sees it, with the main arrangement even while a Group tab is active
+    fn project_state(&self) -> Cow<'_, DawState> {
+        let main = self
+            .state
+            .tabs
+            .iter()
+            .find(|t| !t.is_group)
+            .and_then(|t| t.arrangement.as_ref());
+        match main {
+            Some(main) => {
+                let mut state = self.state.clone();
+                state.bpm = main.bpm;
+                state.tracks = main.tracks.clone();
+                state.next_track_id = main.next_track_id;
+                Cow::Owned(state)
+            }
+            None => Cow::Borrowed(&self.state),
+        }
+    }
+
+    // Make `tab_id` the active tab. The arrangement of the tab that was active is parked in it,
+    // and a Group that was being edited is written to its folder.
+    fn activate_tab(&mut self, tab_id: usize) {
+        if tab_id == self.state.active_tab_id {
+            return;
+        }
+        let Some(incoming) = self
+            .state
+            .tabs
+            .iter_mut()
+            .find(|t| t.id == tab_id)
+            .and_then(|t| t.arrangement.take())
+        else {
+            log::warn!(target: "ui", "Tab {} has no arrangement to show", tab_id);
+            return;
+        };
+
+        // Playback goes on from the incoming tab's playhead, with the arrangement it plays
+        self.stop_main_audition();
+        let leaving_group = self.active_group();
+
+        let mut outgoing = Arrangement {
+            bpm: self.state.bpm,
+            tracks: std::mem::take(&mut self.state.tracks),
+            next_track_id: self.state.next_track_id,
+        };
+        for sample in outgoing.tracks.iter_mut().flat_map(Track::clips_mut) {
+            sample.reset_playback();
+        }
+        let outgoing_view = TabView::of(&self.state);
+        let active_id = self.state.active_tab_id;
+        if let Some(tab) = self.state.tabs.iter_mut().find(|t| t.id == active_id) {
+            tab.arrangement = Some(outgoing);
+            tab.view = Some(outgoing_view);
+        }
+
+        let incoming_view = self
+            .state
+            .tabs
+            .iter_mut()
+            .find(|t| t.id == tab_id)
+            .and_then(|t| t.view.take())
+            .unwrap_or_default();
+        incoming_view.apply(&mut self.state);
+        self.state.bpm = incoming.bpm;
+        self.state.tracks = incoming.tracks;
+        self.state.next_track_id = incoming.next_track_id;
+        self.state.active_tab_id = tab_id;
+        self.update_track_timings();
+        self.last_update = Instant::now();
+
+        if let Some(name) = leaving_group {
+            self.save_group(&name);
+        }
+    }
+
+    // Remember the active tab's arrangement before an edit, so it can be undone
+    fn record_history(&mut self, action: &DawAction) {
+        let (bpm, next_track_id, tracks) = (self.state.bpm, self.state.next_track_id, &self.state.tracks);
+        let active_id = self.state.active_tab_id;
+        if let Some(tab) = self.state.tabs.iter_mut().find(|t| t.id == active_id) {
+            tab.history.record(action, || Arrangement { bpm, tracks: tracks.clone(), next_track_id });
+        }
+    }
+
+    // Drop the undo history of tabs whose arrangement changed in a way snapshots can't follow
+    fn forget_history(&mut self, tab_ids: &[usize]) {
+        for tab in self.state.tabs.iter_mut().filter(|t| tab_ids.contains(&t.id)) {
+            tab.history = History::default();
+        }
+    }
+
+    /// Whether the active tab has an edit to undo and one to redo
+    pub fn history_state(&self) -> (bool, bool) {
+        self.state
+            .tabs
+            .iter()
+            .find(|t| t.id == self.state.active_tab_id)
+            .map_or((false, false), |t| (t.history.can_undo(), t.history.can_redo()))
+    }
+
+    // Undo or redo an edit in the active tab by swapping its arrangement for a snapshot
+    fn step_history(&mut self, redo: bool) {
+        let active_id = self.state.active_tab_id;
+        let Some(tab_index) = self.state.tabs.iter().position(|t| t.id == active_id) else {
+            return;
+        };
+        let history = &self.state.tabs[tab_index].history;
+        if !(if redo { history.can_redo() } else { history.can_undo() }) {
+            return;
+        }
+
+        let mut current = Arrangement {
+            bpm: self.state.bpm,
+            tracks: std::mem::take(&mut self.state.tracks),
+            next_track_id: self.state.next_track_id,
+        };
+        for sample in current.tracks.iter_mut().flat_map(Track::clips_mut) {
+            sample.reset_playback();
+        }
+        let history = &mut self.state.tabs[tab_index].history;
+        let Some(restored) = (if redo { history.redo(current) } else { history.undo(current) }) else {
+            return;
+        };
+
+        // Snapshots don't hold audio, it comes back from the decode cache
+        self.state.bpm = restored.bpm;
+        self.state.tracks = restored.tracks;
+        self.state.next_track_id = restored.next_track_id;
+        let bpm = self.state.bpm;
+        for sample in self.state.tracks.iter_mut().flat_map(Track::clips_mut) {
+            sample.is_playing = false;
+            if sample.audio_file.as_ref().is_some_and(|p| p.exists()) {
+                sample.request_audio(bpm, &self.audio, false);
+            } else {
+                sample.load_saved_waveform();
+            }
+        }
+        self.update_track_timings();
+        self.state.modified = true;
+        log::debug!(target: "ui", "{} in tab {}", if redo { "Redo" } else { "Undo" }, active_id);
+    }
+
+    // Whether the active tab plays the main arrangement instead of its own
+    fn auditions_main(&self) -> bool {
+        self.state
+            .tabs
+            .iter()
+            .find(|t| t.id == self.state.active_tab_id)
+            .is_some_and(|t| t.is_group && t.playback_scope == PlaybackScope::Main)
+    }
+
+    // The main arrangement while a Group tab is active
+    fn parked_main_mut(&mut self) -> Option<&mut Arrangement> {
+        self.state
+            .tabs
+            .iter_mut()
+            .find(|t| !t.is_group)
+            .and_then(|t| t.arrangement.as_mut())
+    }
+
+    // Silence the main arrangement if it was playing behind a Group tab
+    fn stop_main_audition(&mut self) {
+        if let Some(main) = self.parked_main_mut() {
+            for sample in main.tracks.iter_mut().flat_map(Track::clips_mut) {
+                if sample.is_playing {
+                    sample.reset_playback();
+                }
+            }
+        }
+    }
+
+    // Folder of the Group with this name, inside the project folder. Given a variant key, the
+    // folder of that variant.
+    fn group_path(&self, name: &str) -> Option<PathBuf> {
+        self.state.file_path.as_ref()?.parent().map(|dir| dir.join(name))
+    }
+
+    // The arrangement of an open Group variant: bpm, tracks and next track id
+    fn group_arrangement(&self, key: &str) -> Option<(f32, &[Track], usize)> {
+        let Some(tab) = self.state.tabs.iter().find(|t| t.group_key().as_deref() == Some(key)) else {
+            return self
+                .state
+                .background_groups
+                .get(key)
+                .map(|a| (a.bpm, a.tracks.as_slice(), a.next_track_id));
+        };
+        if tab.id == self.state.active_tab_id {
+            Some((self.state.bpm, &self.state.tracks, self.state.next_track_id))
+        } else {
+            tab.arrangement
+                .as_ref()
+                .map(|a| (a.bpm, a.tracks.as_slice(), a.next_track_id))
+        }
+    }
+
+    // Keys of the Group variants with instances directly inside a Group variant, from its loaded
+    // arrangement or its folder
+    fn group_children(&self, key: &str) -> Vec<String> {
+        let mut children: Vec<String> = match self.group_arrangement(key) {
+            Some((_, tracks, _)) => tracks
+                .iter()
+                .flat_map(|t| &t.samples)
+                .filter_map(Sample::group_key)
+                .collect(),
+            None => self
+                .group_path(key)
+                .and_then(|path| match project::load_group(&path) {
+                    Ok(GroupLoad::Loaded(group_file)) => Some(group_file),
+                    _ => None,
+                })
+                .map(|group_file| {
+                    group_file
+                        .tracks
+                        .iter()
+                        .flat_map(|t| &t.clips)
+                        .filter(|c| c.item_type == TrackItemType::Group)
+                        .map(|c| group::variant_key(&c.name, c.variant.as_deref()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+        children.sort();
+        children.dedup();
+        children
+    }
+
+    // Whether `outer` has an instance of `inner` anywhere inside it, however deep
+    fn group_contains(&self, outer: &str, inner: &str) -> bool {
+        self.group_descendants(outer).contains(inner)
+    }
+
+    // Keys of every Group variant with an instance anywhere inside `key`, however deep
+    fn group_descendants(&self, key: &str) -> HashSet<String> {
+        group::descendants(key, |key| self.group_children(key))
+    }
+
+    // Group variants with an instance of this one directly inside them
+    fn group_parents(&self, key: &str) -> Vec<String> {
+        let mut groups: Vec<String> = self.state.audio_boxes.clone();
+        groups.extend(self.state.tabs.iter().filter_map(|t| t.group_name.clone()));
+        groups.sort();
+        groups.dedup();
+        let mut keys = Vec::new();
+        for name in groups {
+            keys.push(name.clone());
+            if let Some(manifest) = self.group_path(&name).and_then(|path| group::load_manifest(&path)) {
+                keys.extend(manifest.variants.iter().map(|v| group::variant_key(&name, Some(v))));
+            }
+        }
+        keys.into_iter()
+            .filter(|parent| parent != key && self.group_children(parent).iter().any(|child| child == key))
+            .collect()
+    }
+
+    // Read the arrangement saved in a Group folder and start loading its audio
+    fn load_group_arrangement(&self, group_path: &Path) -> Option<Arrangement> {
+        let project_folder = self.state.file_path.as_ref().and_then(|p| p.parent());
+        let mut arrangement = match project::load_group(group_path) {
+            Ok(GroupLoad::Loaded(group_file)) => Arrangement {
+                bpm: group_file.bpm,
+                next_track_id: group_file.next_track_id,
+                tracks: group_file.into_tracks(project_folder),
+            },
+            Ok(GroupLoad::Legacy) => {
+                if let Err(e) = project::set_aside_legacy_group(group_path) {
+                    log::error!(target: "groups", "{}", e);
+                }
+                self.arrangement_from_media(group_path)
+            }
+            Ok(GroupLoad::Missing) => self.arrangement_from_media(group_path),
+            Err(e) => {
+                log::error!(target: "groups", "{}", e);
+                return None;
+            }
+        };
+
+        // Never hand out a track id that is already taken
+        let max_track_id = arrangement.tracks.iter().map(|t| t.id + 1).max().unwrap_or(0);
+        arrangement.next_track_id = arrangement.next_track_id.max(max_track_id);
+
+        let bpm = arrangement.bpm;
+        for sample in arrangement.tracks.iter_mut().flat_map(Track::clips_mut) {
+            if sample.audio_file.as_ref().is_some_and(|p| p.exists()) {
+                sample.request_audio(bpm, &self.audio, false);
+            } else {
+                sample.load_saved_waveform();
+            }
+        }
+        Some(arrangement)
+    }
+
+    // A Group that has never been opened only lists its audio files. Each one starts on its own
+    // track at the first beat.
+    fn arrangement_from_media(&self, group_path: &Path) -> Arrangement {
+        let bpm = group::load_manifest(group_path).map_or(self.state.bpm, |manifest| manifest.bpm);
+        let mut arrangement = Arrangement::new(bpm);
+        let mut media: Vec<PathBuf> = match group_path.parent() {
+            Some(project_folder) => {
+                let pool = MediaPool::open(project_folder);
+                group::load_media(group_path)
+                    .iter()
+                    .filter_map(|id| pool.path_of(id))
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        // Groups created before the media pool existed keep their files in a samples folder
+        if let Ok(entries) = fs::read_dir(group_path.join("samples")) {
+            media.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()));
+        }
+        media.retain(|path| audio::is_audio_file(path));
+        // With nothing else to go on, a copy of the last render keeps the Group from rendering silence
+        if media.is_empty() {
+            if let Some(render) = self.render_in_pool(group_path) {
+                media.push(render);
+            }
+        }
+
+        for (index, path) in media.into_iter().enumerate() {
+            if index == arrangement.tracks.len() {
+                let id = arrangement.next_track_id;
+                arrangement.next_track_id += 1;
+                arrangement.tracks.push(Track::new(id, format!("Track {}", id)));
+            }
+            let mut sample = Sample::default();
+            sample.name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("Unknown")
+                .to_string();
+            sample.audio_file = Some(path);
+            arrangement.tracks[index].add_sample(sample);
+        }
+        arrangement
+    }
+
+    // Import a Group's render into the media pool, so the Group can be rebuilt from it
+    fn render_in_pool(&self, group_path: &Path) -> Option<PathBuf> {
+        let render = group::render_path(group_path);
+        let project_folder = group_path.parent()?;
+        if !render.exists() {
+            return None;
+        }
+        let mut pool = MediaPool::open(project_folder);
+        match pool.import(&render) {
+            Ok(entry) => pool.path_of(&entry.id),
+            Err(e) => {
+                log::error!(target: "groups", "{}", e);
+                None
+            }
+        }
+    }
+
+    // Write an open Group variant's arrangement to its folder
+    fn save_group(&mut self, key: &str) -> bool {
+        let (name, variant) = group::split_variant_key(key);
+        let Some(group_path) = self.group_path(key) else {
+            log::warn!(target: "groups", "No project path available to save Group '{}'", key);
+            return false;
+        };
+        let project_folder = self.state.file_path.as_ref().and_then(|p| p.parent()).map(Path::to_path_buf);
+        let Some(group_file) = self
+            .group_arrangement(key)
+            .map(|(bpm, tracks, next_track_id)| {
+                project::GroupFile::from_tracks(bpm, next_track_id, tracks, project_folder.as_deref())
+            })
+        else {
+            log::warn!(target: "groups", "Group '{}' is not open", key);
+            return false;
+        };
+
+        if let Err(e) = fs::create_dir_all(&group_path) {
+            log::error!(target: "groups", "Failed to create Group directory: {:?}", e);
+            return false;
+        }
+        // The manifest follows the tempo of the Group's main arrangement
+        let bpm = group_file.bpm;
+        let manifest_path = group_path.clone();
+        let mut manifest = group::load_manifest(&manifest_path).unwrap_or_else(|| group::GroupManifest::new(name));
+        if variant.is_none() && manifest.bpm != bpm {
+            manifest.bpm = bpm;
+            if let Err(e) = group::save_manifest(&manifest_path, &manifest) {
+                log::error!(target: "groups", "{}", e);
+            }
+        }
+        if let Err(e) = project::set_aside_legacy_group(&group_path) {
+            log::error!(target: "groups", "{}", e);
+            return false;
+        }
+        match project::save_group(&group_path, &group_file) {
+            Ok(()) => {
+                if !self.state.audio_boxes.iter().any(|n| n == name) {
+                    self.state.audio_boxes.push(name.to_string());
+                }
+                log::debug!(target: "groups", "Saved arrangement of Group '{}'", key);
+                true
+            }
+            Err(e) => {
+                log::error!(target: "groups", "{}", e);
+                false
+            }
+        }
+    }
+
+    // Rename a Group's folder and everything that refers to it: instances in every tab and in the
+    // Groups holding them, its tabs, arrangements kept in the background and its renders
+    fn rename_group(&mut self, old_name: &str, new_name: &str) {
+        let Some(old_path) = self.group_path(old_name).filter(|p| p.is_dir()) else {
+            log::warn!(target: "groups", "Group '{}' does not exist", old_name);
+            return;
+        };
+        let Some(project_dir) = old_path.parent().map(Path::to_path_buf) else {
+            return;
+        };
+
+        // Groups holding instances are loaded, so their saved instances are renamed like open ones
+        let variants = group::load_manifest(&old_path).map(|m| m.variants).unwrap_or_default();
+        let old_keys: Vec<String> = std::iter::once(old_name.to_string())
+            .chain(variants.iter().map(|v| group::variant_key(old_name, Some(v))))
+            .collect();
+        let mut parents: Vec<String> = old_keys
+            .iter()
+            .flat_map(|key| self.group_parents(key))
+            .filter(|parent| group::split_variant_key(parent).0 != old_name)
+            .collect();
+        parents.sort();
+        parents.dedup();
+        let closed_parents: HashMap<String, Arrangement> = parents
+            .iter()
+            .filter(|parent| self.group_arrangement(parent).is_none())
+            .filter_map(|parent| {
+                let arrangement = self.group_path(parent).and_then(|path| self.load_group_arrangement(&path))?;
+                Some((parent.clone(), arrangement))
+            })
+            .collect();
+
+        let renamed = Group::load(&old_path).and_then(|mut group| group.rename(new_name, &project_dir));
+        if let Err(e) = renamed {
+            log::error!(target: "groups", "Failed to rename Group '{}': {}", old_name, e);
+            MessageDialog::new()
+                .set_title("Rename Group")
+                .set_description(format!("Failed to rename Group '{}': {}", old_name, e))
+                .show();
+            return;
+        }
+
+        for key in &old_keys {
+            self.group_renderer.forget(key);
+        }
+        self.state.background_groups.extend(closed_parents);
+        let background_keys: Vec<String> = self
+            .state
+            .background_groups
+            .keys()
+            .filter(|key| group::split_variant_key(key).0 == old_name)
+            .cloned()
+            .collect();
+        for key in background_keys {
+            if let Some(arrangement) = self.state.background_groups.remove(&key) {
+                let variant = group::split_variant_key(&key).1;
+                self.state.background_groups.insert(group::variant_key(new_name, variant), arrangement);
+            }
+        }
+        for tab in &mut self.state.tabs {
+            if tab.group_name.as_deref() == Some(old_name) {
+                tab.group_name = Some(new_name.to_string());
+                tab.name = match &tab.variant {
+                    Some(variant) => format!("Box: {} ({})", new_name, variant),
+                    None => format!("Box: {}", new_name),
+                };
+            }
+        }
+
+        let hosts: Vec<InstanceHost> = self
+            .state
+            .tabs
+            .iter()
+            .map(|t| InstanceHost::Tab(t.id))
+            .chain(self.state.background_groups.keys().cloned().map(InstanceHost::Group))
+            .collect();
+        let mut instances = 0;
+        for host in hosts {
+            let tracks = match &host {
+                InstanceHost::Tab(tab_id) => self.state.tab_tracks_mut(*tab_id),
+                InstanceHost::Group(key) => self.state.group_tracks_mut(key),
+            };
+            let Some((bpm, tracks)) = tracks else {
+                continue;
+            };
+            for sample in tracks.iter_mut().flat_map(|t| &mut t.samples) {
+                if sample.item_type != TrackItemType::Group || sample.name != old_name {
+                    continue;
+                }
+                instances += 1;
+                sample.name = new_name.to_string();
+                let render_path = group::render_path(&project_dir.join(group::variant_key(new_name, sample.variant.as_deref())));
+                sample.audio_file = render_path.exists().then_some(render_path);
+                // A decode of the old path would fail now that the file moved
+                if sample.decoding.take().is_some() {
+                    sample.request_audio(bpm, &self.audio, false);
+                }
+            }
+        }
+
+        // Undo and redo bring back instances under the new name, not ones of a folder that moved
+        for tab in &mut self.state.tabs {
+            tab.history.patch_clips(|sample| {
+                if sample.item_type == TrackItemType::Group && sample.name == old_name {
+                    sample.name = new_name.to_string();
+                    let render_path = group::render_path(&project_dir.join(group::variant_key(new_name, sample.variant.as_deref())));
+                    sample.audio_file = render_path.exists().then_some(render_path);
+                }
+            });
+        }
+
+        // Groups that held instances are saved with the new name
+        for parent in &parents {
+            self.group_renderer.invalidate(parent);
+        }
+
+        match self.state.audio_boxes.iter_mut().find(|n| *n == old_name) {
+            Some(entry) => *entry = new_name.to_string(),
+            None => self.state.audio_boxes.push(new_name.to_string()),
+        }
+        self.state.modified = true;
+        log::info!(target: "groups", "Renamed Group '{}' to '{}' ({} instance(s))", old_name, new_name, instances);
+    }
+
+    // Delete a Group: its instances are removed or kept as audio clips, its tab is closed and its
+    // folder goes to the project trash so the deletion can be undone
+    fn delete_group(&mut self, name: &str) {
+        // Only Group folders, never the project's own folders such as the media pool
+        let group_path = self
+            .group_path(name)
+            .filter(|p| group::is_valid_group_name(name) && group::is_group_folder(p));
+        let Some(group_path) = group_path else {
+            log::warn!(target: "groups", "Not deleting '{}', it is not a Group", name);
+            return;
+        };
+
+        // Groups holding instances are loaded, so their saved instances are handled like open ones
+        let variants = group::load_manifest(&group_path).map(|m| m.variants).unwrap_or_default();
+        let mut parents: Vec<String> = std::iter::once(name.to_string())
+            .chain(variants.iter().map(|v| group::variant_key(name, Some(v))))
+            .flat_map(|key| self.group_parents(&key))
+            .filter(|parent| group::split_variant_key(parent).0 != name)
+            .collect();
+        parents.sort();
+        parents.dedup();
+        let closed_parents: HashMap<String, Arrangement> = parents
+            .iter()
+            .filter(|parent| self.group_arrangement(parent).is_none())
+            .filter_map(|parent| {
+                let arrangement = self.group_path(parent).and_then(|path| self.load_group_arrangement(&path))?;
+                Some((parent.clone(), arrangement))
+            })
+            .collect();
+
+        let instances = self
+            .state
+            .tracks
+            .iter()
+            .chain(self.state.tabs.iter().filter_map(|t| t.arrangement.as_ref()).flat_map(|a| &a.tracks))
+            .chain(self.state.background_groups.values().flat_map(|a| &a.tracks))
+            .chain(closed_parents.values().flat_map(|a| &a.tracks))
+            .flat_map(|track| &track.samples)
+            .filter(|s| s.item_type == TrackItemType::Group && s.name == name)
+            .count();
+        let keep_as_clips = if instances > 0 {
+            let answer = MessageDialog::new()
+                .set_title("Delete Group")
+                .set_description(format!(
+                    "Group '{}' is used {} time(s) in this project.\n\n\
+                     Yes keeps them as audio clips of the Group's current render.\n\
+                     No removes them.",
+                    name, instances
+                ))
+                .set_buttons(MessageButtons::YesNoCancel)
+                .show();
+            match answer {
+                MessageDialogResult::Yes => true,
+                MessageDialogResult::No => false,
+                _ => return,
+            }
+        } else {
+            false
+        };
+
+        // Clips that are kept play a copy of their variant's render from the media pool
+        let mut render_copies: HashMap<Option<String>, PathBuf> = HashMap::new();
+        if keep_as_clips {
+            let project_folder = group_path.parent().unwrap_or(Path::new("."));
+            let mut pool = MediaPool::open(project_folder);
+            for variant in std::iter::once(None).chain(variants.iter().cloned().map(Some)) {
+                let Some(variant_path) = self.group_path(&group::variant_key(name, variant.as_deref())) else {
+                    continue;
+                };
+                match pool.import(&group::render_path(&variant_path)) {
+                    Ok(entry) => {
+                        if let Some(copy) = pool.path_of(&entry.id) {
+                            render_copies.insert(variant, copy);
+                        }
+                    }
+                    Err(e) => {
+                        log::warn!(target: "groups", "Instances of '{}' will be removed: {}", name, e);
+                    }
+                }
+            }
+        }
+
+        // The tabs of the Group and its variants go away without writing them back to the folder
+        let group_tabs: Vec<usize> = self
+            .state
+            .tabs
+            .iter()
+            .filter(|t| t.group_name.as_deref() == Some(name))
+            .map(|t| t.id)
+            .collect();
+        if group_tabs.contains(&self.state.active_tab_id) {
+            if let Some(main_id) = self.state.tabs.iter().find(|t| !t.is_group).map(|t| t.id) {
+                self.activate_tab(main_id);
+            }
+        }
+        self.state.tabs.retain(|t| !group_tabs.contains(&t.id));
+        self.group_renderer.forget(name);
+        for variant in &variants {
+            self.group_renderer.forget(&group::variant_key(name, Some(variant)));
+        }
+        self.state
+            .background_groups
+            .retain(|key, _| group::split_variant_key(key).0 != name);
+
+        let trash_path = match group::move_to_trash(&group_path) {
+            Ok(path) => path,
+            Err(e) => {
+                log::error!(target: "groups", "Failed to delete Group '{}': {}", name, e);
+                return;
+            }
+        };
+
+        let mut deleted = DeletedGroup {
+            name: name.to_string(),
+            trash_path,
+            instances: Vec::new(),
+        };
+        self.state.background_groups.extend(closed_parents);
+        let hosts: Vec<InstanceHost> = self
+            .state
+            .tabs
+            .iter()
+            .map(|t| InstanceHost::Tab(t.id))
+            .chain(self.state.background_groups.keys().cloned().map(InstanceHost::Group))
+            .collect();
+        for host in hosts {
+            let tracks = match &host {
+                InstanceHost::Tab(tab_id) => self.state.tab_tracks_mut(*tab_id),
+                InstanceHost::Group(key) => self.state.group_tracks_mut(key),
+            };
+            let Some((bpm, tracks)) = tracks else {
+                continue;
+            };
+            for track in tracks.iter_mut() {
+                for sample in &mut track.samples {
+                    if sample.item_type != TrackItemType::Group || sample.name != name {
+                        continue;
+                    }
+                    let render_copy = render_copies.get(&sample.variant);
+                    deleted.instances.push(DeletedInstance {
+                        host: match &host {
+                            InstanceHost::Tab(tab_id) => InstanceHost::Tab(*tab_id),
+                            InstanceHost::Group(key) => InstanceHost::Group(key.clone()),
+                        },
+                        track_id: track.id,
+                        sample_id: sample.id,
+                        variant: sample.variant.clone(),
+                        position: sample.grid_position,
+                        trim: (sample.trim_start, sample.trim_end),
+                        kept: render_copy.is_some(),
+                    });
+                    if sample.is_playing {
+                        sample.pause();
+                    }
+                    if let Some(render_copy) = render_copy {
+                        sample.item_type = TrackItemType::Sample;
+                        sample.audio_file = Some(render_copy.clone());
+                        sample.request_audio(bpm, &self.audio, false);
+                    }
+                }
+                track.samples.retain(|s| {
+                    s.item_type != TrackItemType::Group || s.name != name || render_copies.contains_key(&s.variant)
+                });
+            }
+        }
+
+        // Groups that held instances are saved and rendered without them
+        for parent in &parents {
+            self.group_renderer.invalidate(parent);
+        }
+
+        // Undo can't bring instances of a Group in the trash back, and restoring the Group puts
+        // them back itself, so the tabs that held them start a new history
+        let affected_tabs: Vec<usize> = self
+            .state
+            .tabs
+            .iter()
+            .filter(|tab| {
+                deleted.instances.iter().any(|i| matches!(i.host, InstanceHost::Tab(id) if id == tab.id))
+                    || tab.history.has_clip(|s| s.item_type == TrackItemType::Group && s.name == name)
+            })
+            .map(|tab| tab.id)
+            .collect();
+        self.forget_history(&affected_tabs);
+
+        self.state.audio_boxes.retain(|n| n != name);
+        self.state.modified = true;
+        log::info!(target: "groups", "Deleted Group '{}' ({} instance(s) {})", name, deleted.instances.len(),
+            if keep_as_clips { "kept as clips" } else { "removed" });
+        self.deleted_groups.push(deleted);
+    }
+
+    /// Name of the Group that undoing a deletion would bring back
+    pub fn last_deleted_group(&self) -> Option<&str> {
+        self.deleted_groups.last().map(|d| d.name.as_str())
+    }
+
+    // Bring the most recently deleted Group back from the trash, with its instances
+    fn undo_delete_group(&mut self) {
+        let Some(deleted) = self.deleted_groups.pop() else {
+            return;
+        };
+        let Some(group_path) = self.group_path(&deleted.name) else {
+            return;
+        };
+        if let Err(e) = group::restore_from_trash(&deleted.trash_path, &group_path) {
+            log::error!(target: "groups", "Failed to restore Group '{}': {}", deleted.name, e);
+            return;
+        }
+
+        // Groups without a tab are loaded again to put their instances back
+        let mut hosts: Vec<String> = Vec::new();
+        for instance in &deleted.instances {
+            let key = match &instance.host {
+                InstanceHost::Tab(tab_id) => self.state.tabs.iter().find(|t| t.id == *tab_id).and_then(Tab::group_key),
+                InstanceHost::Group(key) => Some(key.clone()),
+            };
+            let Some(key) = key.filter(|key| !hosts.contains(key)) else {
+                continue;
+            };
+            if self.group_arrangement(&key).is_none() {
+                let Some(arrangement) = self.group_path(&key).and_then(|path| self.load_group_arrangement(&path)) else {
+                    continue;
+                };
+                self.state.background_groups.insert(key.clone(), arrangement);
             }
-            
-            // Save the project file inside the folder
-            let project_file_path = project_folder.join("project.json");
-            
-            // First save waveform data for each sample in each track
-            for track in &self.state.tracks {
-                for sample in &track.samples {
-                    if let Some(waveform) = &sample.waveform {
-                        let waveform_data = WaveformData {
-                            samples: waveform.samples.clone(),
-                            sample_rate: waveform.sample_rate,
-                            duration: waveform.duration,
-                        };
-                        if let Some(waveform_path) =
-                            save_waveform_data(&project_file_path, &sample.name, &waveform_data)
-                        {
-                            eprintln!("Saved waveform data to {}", waveform_path.display());
-                        }
+            hosts.push(key);
+        }
+
+        for instance in &deleted.instances {
+            let variant_path = match &instance.variant {
+                Some(variant) => group_path.join(group::VARIANTS_DIR).join(variant),
+                None => group_path.clone(),
+            };
+            let tracks = match &instance.host {
+                InstanceHost::Tab(tab_id) => self.state.tab_tracks_mut(*tab_id),
+                InstanceHost::Group(key) => self.state.group_tracks_mut(key),
+            };
+            let Some((bpm, tracks)) = tracks else {
+                continue;
+            };
+            let Some(track) = tracks.iter_mut().find(|t| t.id == instance.track_id) else {
+                continue;
+            };
+            if instance.kept {
+                // Clips that were kept turn back into instances, unless they were deleted since
+                if let Some(sample) = track.samples.iter_mut().find(|s| s.id == instance.sample_id && s.name == deleted.name) {
+                    if sample.is_playing {
+                        sample.pause();
                     }
+                    sample.item_type = TrackItemType::Group;
+                    sample.audio_file = Some(group::render_path(&variant_path));
+                    sample.request_audio(bpm, &self.audio, false);
                 }
+            } else {
+                // A variant's render sizes the instance once it is loaded
+                let mut sample = Sample::group_instance(&deleted.name, &variant_path, instance.position, bpm, &self.audio);
+                sample.variant = instance.variant.clone();
+                (sample.trim_start, sample.trim_end) = instance.trim;
+                track.add_sample(sample);
             }
+        }
 
-            // Then save the project state to project.json file in the project folder
-            if let Ok(serialized) = serde_json::to_string_pretty(&self.state) {
-                if fs::write(&project_file_path, serialized).is_ok() {
-                    // Update the file_path in the state
-                    self.state.file_path = Some(project_file_path.clone());
-                    
-                    // Save updated state with new file path
-                    if let Ok(updated_serialized) = serde_json::to_string_pretty(&self.state) {
-                        let _ = fs::write(&project_file_path, updated_serialized);
+        for key in &hosts {
+            self.group_renderer.invalidate(key);
+        }
+
+        // Snapshots taken since the deletion hold the instances as they were while it was deleted
+        let affected_tabs: Vec<usize> = deleted
+            .instances
+            .iter()
+            .filter_map(|i| match i.host {
+                InstanceHost::Tab(tab_id) => Some(tab_id),
+                InstanceHost::Group(_) => None,
+            })
+            .collect();
+        self.forget_history(&affected_tabs);
+
+        if !self.state.audio_boxes.contains(&deleted.name) {
+            self.state.audio_boxes.push(deleted.name.clone());
+        }
+        self.state.modified = true;
+        log::info!(target: "groups", "Restored Group '{}'", deleted.name);
+    }
+
+    /// A name for a new Group that no folder in the project uses yet
+    pub fn unused_group_name(&self) -> String {
+        (1..)
+            .map(|n| format!("Group {}", n))
+            .find(|name| self.group_path(name).map_or(true, |path| !path.exists()))
+            .unwrap_or_default()
+    }
+
+    // Move the clips in the selection into a new Group and put an instance of it where they were.
+    // The clips keep their timing and trims, on one Group track per track they came from.
+    fn group_selection(&mut self, name: &str) {
+        let Some(selection) = self.state.selection.clone() else {
+            log::warn!(target: "groups", "Cannot group: No selection active");
+            return;
+        };
+        let Some(project_folder) = self.state.file_path.as_ref().and_then(|p| p.parent()).map(Path::to_path_buf) else {
+            MessageDialog::new()
+                .set_title("Group Selection")
+                .set_description("Save the project first, Groups are stored in its folder.")
+                .show();
+            return;
+        };
+        if project_folder.join(name).exists() {
+            MessageDialog::new()
+                .set_title("Group Selection")
+                .set_description(format!("There already is a Group called '{}'.", name))
+                .show();
+            return;
+        }
+
+        let in_selection = |s: &Sample| {
+            s.grid_position < selection.end_beat && s.grid_position + s.grid_length > selection.start_beat
+        };
+        let (first_track, last_track) = (
+            selection.start_track_idx.min(selection.end_track_idx),
+            selection.start_track_idx.max(selection.end_track_idx),
+        );
+        let selected = self.state.tracks[first_track.min(self.state.tracks.len())..(last_track + 1).min(self.state.tracks.len())]
+            .iter()
+            .flat_map(|t| t.samples.iter().filter(|s| in_selection(s)));
+        let (start, end) = selected.fold((f32::MAX, f32::MIN), |(start, end), s| {
+            (start.min(s.grid_position), end.max(s.grid_position + s.grid_length))
+        });
+        if start > end {
+            log::warn!(target: "groups", "Cannot group: No clips in the selection");
+            return;
+        }
+
+        let bpm = self.state.bpm;
+        let mut group = match Group::new(name, &project_folder) {
+            Ok(group) => group,
+            Err(e) => {
+                log::error!(target: "groups", "Failed to create Group: {}", e);
+                MessageDialog::new()
+                    .set_title("Group Selection")
+                    .set_description(format!("Failed to create Group: {}", e))
+                    .show();
+                return;
+            }
+        };
+        group.manifest.bpm = bpm;
+        group.manifest.length_beats = Some(end - start);
+        if let Err(e) = group::save_manifest(&group.path, &group.manifest) {
+            log::error!(target: "groups", "{}", e);
+        }
+
+        // The clips move into the Group, starting from its beginning
+        let mut arrangement = Arrangement { bpm, tracks: Vec::new(), next_track_id: 0 };
+        let mut instance_track = None;
+        for track in self.state.tracks.iter_mut().take(last_track + 1).skip(first_track) {
+            let (moved, kept): (Vec<Sample>, Vec<Sample>) = track.samples.drain(..).partition(|s| in_selection(s));
+            track.samples = kept;
+            if moved.is_empty() {
+                continue;
+            }
+            instance_track.get_or_insert(track.id);
+
+            let mut group_track = Track::new(arrangement.next_track_id, track.name.clone());
+            group_track.muted = track.muted;
+            arrangement.next_track_id += 1;
+            for mut sample in moved {
+                if sample.is_playing {
+                    sample.pause();
+                }
+                sample.grid_position -= start;
+                sample.update_grid_times(bpm);
+                group_track.add_sample(sample);
+            }
+            arrangement.tracks.push(group_track);
+        }
+
+        let mut pool_ids: Vec<String> = Vec::new();
+        for id in arrangement.tracks.iter().flat_map(|t| &t.samples).filter_map(|s| s.pool_id.clone()) {
+            if !pool_ids.contains(&id) {
+                pool_ids.push(id);
+            }
+        }
+        if let Err(e) = group::save_media(&group.path, &pool_ids) {
+            log::error!(target: "groups", "{}", e);
+        }
+
+        // The Group is rendered in the background and the instance picks the render up when it is done
+        let clips = arrangement.tracks.iter().map(|t| t.samples.len()).sum::<usize>();
+        self.state.background_groups.insert(name.to_string(), arrangement);
+        self.save_group(name);
+        self.group_renderer.invalidate(name);
+
+        if let Some(track) = instance_track.and_then(|id| self.state.tracks.iter_mut().find(|t| t.id == id)) {
+            track.add_sample(Sample::group_instance(name, &group.path, start, bpm, &self.audio));
+        }
+        // Undoing to a snapshot from before would bring the clips back next to the new Group and
+        // its instance; Ungroup is the way back
+        self.forget_history(&[self.state.active_tab_id]);
+        self.state.modified = true;
+        log::info!(target: "groups", "Grouped {} clip(s) into '{}'", clips, name);
+    }
+
+    // Replace a Group instance with the clips of the variant it plays. Only the part of the Group the
+    // instance shows comes back, on the instance's track and the ones below it.
+    fn ungroup(&mut self, track_id: usize, sample_id: usize) {
+        let Some(track_idx) = self.state.tracks.iter().position(|t| t.id == track_id) else {
+            return;
+        };
+        let Some(instance) = self.state.tracks[track_idx].get_sample(sample_id).cloned() else {
+            return;
+        };
+        let Some(key) = instance.group_key() else {
+            return;
+        };
+        let arrangement = match self.group_arrangement(&key) {
+            Some((bpm, tracks, next_track_id)) => Some(Arrangement { bpm, tracks: tracks.to_vec(), next_track_id }),
+            None => self.group_path(&key).and_then(|path| self.load_group_arrangement(&path)),
+        };
+        let Some(arrangement) = arrangement else {
+            log::warn!(target: "groups", "Group '{}' has no arrangement to ungroup", key);
+            return;
+        };
+
+        // Times in the Group's render that the instance plays
+        let bpm = self.state.bpm;
+        let window_start = instance.trim_start;
+        let window_end = if instance.trim_end > 0.0 { instance.trim_end } else { f32::MAX };
+        let offset = instance.grid_start_time - window_start;
+
+        // Only what the render plays comes out: muted tracks, and unsoloed ones while any is
+        // soloed, stay behind in the Group
+        let audible: Vec<bool> = arrangement
+            .tracks
+            .iter()
+            .map(|t| Self::is_audible(&arrangement.tracks, t))
+            .collect();
+        let mut clips = 0;
+        for (i, group_track) in arrangement.tracks.into_iter().enumerate() {
+            if group_track.samples.is_empty() || !audible[i] {
+                continue;
+            }
+            let target_idx = track_idx + i;
+            if target_idx >= self.state.tracks.len() {
+                let id = self.state.next_track_id;
+                self.state.next_track_id += 1;
+                self.state.tracks.push(Track::new(id, group_track.name.clone()));
+            }
+            let track = &mut self.state.tracks[target_idx];
+
+            for mut sample in group_track.samples {
+                let mut clip_start = sample.grid_position * 60.0 / arrangement.bpm;
+                let mut clip_end = (sample.grid_position + sample.grid_length) * 60.0 / arrangement.bpm;
+                if clip_end <= window_start || clip_start >= window_end {
+                    continue;
+                }
+                // Clips cut by the instance's trims keep only the part that was heard
+                if clip_start < window_start {
+                    sample.trim_start += window_start - clip_start;
+                    clip_start = window_start;
+                }
+                if clip_end > window_end {
+                    clip_end = window_end;
+                    sample.trim_end = sample.trim_start + (clip_end - clip_start);
+                }
+
+                sample.grid_position = (offset + clip_start) * bpm / 60.0;
+                sample.grid_length = (clip_end - clip_start) * bpm / 60.0;
+                sample.update_grid_times(bpm);
+                if sample.audio_file.as_ref().is_some_and(|p| p.exists()) {
+                    sample.request_audio(bpm, &self.audio, false);
+                }
+                track.add_sample(sample);
+                clips += 1;
+            }
+        }
+
+        if let Some(track) = self.state.tracks.get_mut(track_idx) {
+            if let Some(mut instance) = track.remove_sample(sample_id) {
+                if instance.is_playing {
+                    instance.pause();
+                }
+            }
+        }
+        self.state.modified = true;
+        log::info!(target: "groups", "Ungrouped '{}' into {} clip(s)", key, clips);
+    }
+
+    // Mix clips between two times with the offline renderer and add the result to the project's
+    // media pool, for a clip that plays it in their place
+    fn render_to_pool<'a>(
+        &self,
+        clips: impl Iterator<Item = &'a Sample>,
+        start_time: f32,
+        end_time: f32,
+        name: &str,
+    ) -> Result<Sample, String> {
+        let project_folder = self
+            .state
+            .file_path
+            .as_ref()
+            .and_then(|p| p.parent())
+            .ok_or_else(|| "Save the project first, renders are kept in its media folder.".to_string())?;
+
+        let mix_rate = self.audio.output_config.sample_rate.0;
+        let mixed = Self::mix_clips(clips, start_time, end_time, mix_rate);
+        let settings = ExportSettings {
+            bit_depth: export::BitDepth::Float32,
+            sample_rate: mix_rate,
+            dither: false,
+            ..ExportSettings::default()
+        };
+
+        // Written to a temporary file first, the pool keeps its own copy
+        let millis = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let temp_path = env::temp_dir().join(format!("monlam-render-{}.wav", millis));
+        export::export(&temp_path, &mixed, 2, mix_rate, &settings)
+            .map_err(|e| format!("Failed to render {}: {}", name, e))?;
+        let mut pool = MediaPool::open(project_folder);
+        let imported = pool.import(&temp_path);
+        let _ = fs::remove_file(&temp_path);
+        let entry = imported?;
+
+        let bpm = self.state.bpm;
+        let mut sample = Sample::default();
+        sample.name = name.to_string();
+        sample.set_pool_entry(&pool, &entry);
+        sample.grid_position = start_time * bpm / 60.0;
+        sample.grid_length = (end_time - start_time) * bpm / 60.0;
+        sample.update_grid_times(bpm);
+        sample.request_audio(bpm, &self.audio, false);
+        Ok(sample)
+    }
+
+    // Render a track's clips and play the render instead, so its clips cost no decoding or mixing
+    // while it stays frozen
+    fn freeze_track(&mut self, track_id: usize) {
+        let Some(track) = self.state.tracks.iter().find(|t| t.id == track_id) else {
+            return;
+        };
+        if track.samples.is_empty() {
+            log::warn!(target: "audio", "Track '{}' has no clips to freeze", track.name);
+            return;
+        }
+        // Clips still loading would be missing from the render
+        if track.samples.iter().any(|s| s.decoding.is_some()) {
+            log::warn!(target: "audio", "Track '{}' is still loading, freeze it once its clips are loaded", track.name);
+            return;
+        }
+
+        let start_time = track.samples.iter().map(|s| s.grid_start_time).fold(f32::MAX, f32::min);
+        let end_time = track.samples.iter().map(|s| s.grid_end_time).fold(f32::MIN, f32::max);
+        let name = format!("{} (frozen)", track.name);
+        let fingerprint = track.clips_fingerprint(self.state.bpm);
+        let clip = match self.render_to_pool(track.samples.iter(), start_time, end_time, &name) {
+            Ok(clip) => clip,
+            Err(e) => {
+                log::error!(target: "audio", "Failed to freeze track '{}': {}", track.name, e);
+                MessageDialog::new()
+                    .set_title("Freeze Track")
+                    .set_description(format!("Failed to freeze track '{}': {}", track.name, e))
+                    .show();
+                return;
+            }
+        };
+
+        let Some(track) = self.state.tracks.iter_mut().find(|t| t.id == track_id) else {
+            return;
+        };
+        for sample in track.clips_mut() {
+            if sample.is_playing {
+                sample.pause();
+            }
+        }
+        track.frozen = Some(FrozenTrack { clip, fingerprint });
+        self.state.modified = true;
+        log::info!(target: "audio", "Froze track '{}'", track.name);
+    }
+
+    // A frozen track whose clips were edited plays its clips again
+    fn thaw_edited_tracks(&mut self) {
+        let bpm = self.state.bpm;
+        for track in &mut self.state.tracks {
+            if !track.frozen.as_ref().is_some_and(|f| f.fingerprint != track.clips_fingerprint(bpm)) {
+                continue;
+            }
+            if let Some(mut frozen) = track.frozen.take() {
+                if frozen.clip.is_playing {
+                    frozen.clip.pause();
+                }
+            }
+            log::info!(target: "audio", "Track '{}' was edited and is no longer frozen", track.name);
+        }
+    }
+
+    // Replace the selected clips of each track with one clip playing their mix, from the first
+    // selected clip's start to the last one's end
+    fn bounce_selection(&mut self) {
+        let Some(selection) = self.state.selection.clone() else {
+            log::warn!(target: "audio", "Cannot bounce: No selection active");
+            return;
+        };
+        let in_selection = |s: &Sample| {
+            s.grid_position < selection.end_beat && s.grid_position + s.grid_length > selection.start_beat
+        };
+        let first_track = selection.start_track_idx.min(selection.end_track_idx);
+        let last_track = selection.start_track_idx.max(selection.end_track_idx);
+
+        // Every track is rendered before any clip is replaced, so a failure leaves all of them as
+        // they were
+        let mut bounces = Vec::new();
+        for track_idx in first_track..=last_track.min(self.state.tracks.len().saturating_sub(1)) {
+            let track = &self.state.tracks[track_idx];
+            let selected: Vec<&Sample> = track.samples.iter().filter(|s| in_selection(s)).collect();
+            if selected.is_empty() {
+                continue;
+            }
+            if selected.iter().any(|s| s.decoding.is_some()) {
+                log::warn!(target: "audio", "Track '{}' is still loading and was not bounced", track.name);
+                continue;
+            }
+
+            let start_time = selected.iter().map(|s| s.grid_start_time).fold(f32::MAX, f32::min);
+            let end_time = selected.iter().map(|s| s.grid_end_time).fold(f32::MIN, f32::max);
+            let name = format!("{} (bounce)", track.name);
+            let ids: Vec<usize> = selected.iter().map(|s| s.id).collect();
+            let bounced = match self.render_to_pool(selected.into_iter(), start_time, end_time, &name) {
+                Ok(bounced) => bounced,
+                Err(e) => {
+                    log::error!(target: "audio", "Failed to bounce track '{}': {}", track.name, e);
+                    MessageDialog::new()
+                        .set_title("Bounce in Place")
+                        .set_description(format!("Failed to bounce track '{}': {}", track.name, e))
+                        .show();
+                    return;
+                }
+            };
+            bounces.push((track_idx, ids, bounced, name));
+        }
+
+        for (track_idx, ids, bounced, name) in bounces {
+            let track = &mut self.state.tracks[track_idx];
+            for id in ids {
+                if let Some(mut sample) = track.remove_sample(id) {
+                    if sample.is_playing {
+                        sample.pause();
                     }
-                    
-                    Self::save_config(Some(project_file_path.clone()));
-                    eprintln!("Project saved successfully to {}", project_file_path.display());
-                } else {
-                    eprintln!("Failed to write project file to {}", project_file_path.display());
                 }
-            } else {
-                eprintln!("Failed to serialize project state");
             }
+            track.add_sample(bounced);
+            self.state.modified = true;
+            log::info!(target: "audio", "Bounced {} in place", name);
+        }
+    }
+
+    // Write the arrangements of all open Groups to their folders
+    fn save_open_groups(&mut self) {
+        let keys: Vec<String> = self.state.tabs.iter().filter_map(|t| t.group_key()).collect();
+        for key in keys {
+            self.save_group(&key);
+        }
+    }
+
+    // Mix an open Group's arrangement into its render.wav on a background thread, from its start to
+    // the end of its last clip
+    fn start_group_render(&mut self, key: &str) {
+        let (Some(group_path), Some((_, tracks, _))) = (self.group_path(key), self.group_arrangement(key)) else {
+            return;
+        };
+        // A Group with a length renders exactly that long, so its instances line up on the grid.
+        // Its variants share the length.
+        let name = group::split_variant_key(key).0;
+        let manifest = self
+            .group_path(name)
+            .and_then(|path| group::load_manifest(&path))
+            .unwrap_or_else(|| group::GroupManifest::new(name));
+        let end_time = manifest.length_seconds().unwrap_or_else(|| {
+            tracks
+                .iter()
+                .flat_map(|track| &track.samples)
+                .map(|sample| sample.grid_end_time)
+                .fold(0.0, f32::max)
+        });
+        let clips: Vec<MixClip> = tracks
+            .iter()
+            .filter(|track| Self::is_audible(tracks, track))
+            .flat_map(|track| &track.samples)
+            .filter_map(MixClip::of)
+            .collect();
+
+        let mix_rate = manifest.render.sample_rate.unwrap_or(self.audio.output_config.sample_rate.0);
+        log::debug!(target: "groups", "Rendering Group '{}' ({:.2}s)", key, end_time);
+        self.group_renderer
+            .start(key, &group_path, mix_rate, move || mix(&clips, 0.0, end_time, mix_rate));
+    }
+
+    // Render Groups whose edits have settled and hand finished renders to their instances.
+    // Called once per frame.
+    pub fn update_group_renders(&mut self) {
+        for name in self.group_renderer.due() {
+            let Some((_, tracks, _)) = self.group_arrangement(&name) else {
+                // Closed Groups were rendered when their tab closed
+                self.group_renderer.forget(&name);
+                continue;
+            };
+            // Clips still loading would be missing from the render
+            let loading = tracks.iter().flat_map(|t| &t.samples).any(|s| s.decoding.is_some());
+            // Groups inside go first, their new render is part of this one. What is inside is looked
+            // up once per edit, it may mean reading Group files.
+            let descendants = match self.group_renderer.descendants(&name) {
+                Some(descendants) => descendants.clone(),
+                None => {
+                    let descendants = self.group_descendants(&name);
+                    self.group_renderer.set_descendants(&name, descendants.clone());
+                    descendants
+                }
+            };
+            let waiting = self
+                .group_renderer
+                .pending()
+                .any(|inner| inner != name && descendants.contains(inner));
+            if !loading && !waiting && self.save_group(&name) {
+                self.start_group_render(&name);
+            }
+        }
+
+        for (name, result) in self.group_renderer.poll() {
+            // A background Group is let go once rendered, unless it changed again meanwhile
+            if !self.group_renderer.is_stale(&name) {
+                self.state.background_groups.remove(&name);
+            }
+            match result {
+                Ok(()) => {
+                    log::info!(target: "groups", "Rendered Group '{}'", name);
+                    self.refresh_group_instances(&name);
+                    self.invalidate_group_parents(&name);
+                }
+                Err(e) => log::error!(target: "groups", "Failed to render Group '{}': {}", name, e),
+            }
+        }
+    }
+
+    // Render the Groups that hold instances of this one again. Those without a tab are loaded in
+    // the background for it.
+    fn invalidate_group_parents(&mut self, name: &str) {
+        for parent in self.group_parents(name) {
+            if self.group_contains(&parent, &parent) {
+                log::warn!(target: "groups", "Group '{}' contains itself and won't be rendered again", parent);
+                continue;
+            }
+            if self.group_arrangement(&parent).is_none() {
+                let Some(arrangement) = self.group_path(&parent).and_then(|path| self.load_group_arrangement(&path)) else {
+                    continue;
+                };
+                self.state.background_groups.insert(parent.clone(), arrangement);
+            }
+            log::debug!(target: "groups", "Group '{}' changed, rendering '{}' again", name, parent);
+            self.group_renderer.invalidate(&parent);
         }
     }
 
-    // Add these methods after existing DawApp implementation methods but before any other impl blocks
+    // Point every instance of a Group variant in every open arrangement at its current render.
+    // Their audio, waveform and length are loaded again.
+    fn refresh_group_instances(&mut self, key: &str) {
+        let Some(group_path) = self.group_path(key) else {
+            return;
+        };
+        let render_path = group::render_path(&group_path);
+        decode::invalidate(&render_path);
+
+        let audio = &self.audio;
+        let refresh = |tracks: &mut Vec<Track>, bpm: f32| {
+            for track in tracks.iter_mut() {
+                if !track.samples.iter().any(|s| s.group_key().as_deref() == Some(key)) {
+                    continue;
+                }
+                // A freeze render holding the old Group render is out of date
+                if let Some(mut frozen) = track.frozen.take() {
+                    if frozen.clip.is_playing {
+                        frozen.clip.pause();
+                    }
+                    log::info!(target: "audio", "Track '{}' is no longer frozen, '{}' changed", track.name, key);
+                }
+            }
+            for sample in tracks.iter_mut().flat_map(|t| &mut t.samples) {
+                if sample.group_key().as_deref() != Some(key) {
+                    continue;
+                }
+                if sample.is_playing {
+                    sample.pause();
+                }
+                // Trims are kept, and clamped to the new render length once it is decoded
+                sample.audio_file = Some(render_path.clone());
+                sample.request_audio(bpm, audio, false);
+            }
+        };
+        refresh(&mut self.state.tracks, self.state.bpm);
+        for arrangement in self.state.parked_arrangements_mut() {
+            let bpm = arrangement.bpm;
+            refresh(&mut arrangement.tracks, bpm);
+        }
+    }
 
     // Switch to the previous tab in the tabs list
     pub fn switch_to_previous_tab(&mut self) {
@@ -2610,38 +4776,216 @@ impl DawApp {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+/// A deleted Group waiting in the project trash, and where its instances were
+struct DeletedGroup {
+    name: String,
+    trash_path: PathBuf,
+    instances: Vec<DeletedInstance>,
+}
+
+// The arrangement an instance of a deleted Group was in
+enum InstanceHost {
+    Tab(usize),
+    Group(String), // Variant key of a Group without a tab
+}
+
+struct DeletedInstance {
+    host: InstanceHost,
+    track_id: usize,
+    sample_id: usize,
+    variant: Option<String>,
+    position: f32, // Beats
+    trim: (f32, f32),
+    kept: bool, // Whether the instance was kept as an audio clip rather than removed
+}
+
+/// The part of an audio file a clip plays and where it sits on the timeline. It owns its audio,
+/// so it can be mixed on another thread.
+struct MixClip {
+    audio: Arc<Pcm>,
+    channels: usize,
+    sample_rate: u32,
+    trim_start: f32, // Seconds into the file
+    start_time: f32, // Seconds on the timeline
+    end_time: f32,
+}
+
+impl MixClip {
+    fn of(sample: &Sample) -> Option<Self> {
+        let (Some(waveform), Some(audio)) = (&sample.waveform, sample.audio_data()) else {
+            return None;
+        };
+        Some(Self {
+            audio,
+            channels: sample.channels.max(1) as usize,
+            sample_rate: waveform.sample_rate,
+            trim_start: sample.trim_start,
+            start_time: sample.grid_start_time,
+            end_time: sample.grid_end_time,
+        })
+    }
+}
+
+/// Mix `clips` between `start_time` and `end_time` (seconds) to interleaved stereo at `sample_rate`
+fn mix(clips: &[MixClip], start_time: f32, end_time: f32, sample_rate: u32) -> Vec<f32> {
+    let frames = ((end_time - start_time) * sample_rate as f32).max(0.0) as usize;
+    let mut mixed = vec![0.0f32; frames * 2];
+
+    for source in clips {
+        if source.end_time <= start_time || source.start_time >= end_time {
+            continue;
+        }
+
+        // The part of the file the clip plays, as stereo at the file's rate
+        let channels = source.channels;
+        let source_rate = source.sample_rate;
+        let audio_data = &source.audio;
+        let available = audio_data.len() / channels;
+        let first = ((source.trim_start * source_rate as f32).max(0.0) as usize).min(available);
+        let clip_seconds = source.end_time - source.start_time;
+        let last = (first + (clip_seconds * source_rate as f32) as usize).min(available);
+        let mut clip = Vec::with_capacity((last - first) * 2);
+        for frame in audio_data[first * channels..last * channels].chunks(channels) {
+            clip.push(frame[0]);
+            clip.push(frame[1.min(channels - 1)]);
+        }
+        if source_rate != sample_rate {
+            clip = audio::resample(&clip, 2, source_rate, sample_rate);
+        }
+
+        // Clips that start before the range are cut at its start
+        let offset = ((source.start_time - start_time) * sample_rate as f32) as i64;
+        for (i, frame) in clip.chunks(2).enumerate() {
+            let target = offset + i as i64;
+            if target < 0 {
+                continue;
+            }
+            let target = target as usize;
+            if target >= frames {
+                break;
+            }
+            mixed[target * 2] += frame[0];
+            mixed[target * 2 + 1] += frame[1];
+        }
+    }
+    mixed
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
 struct Config {
     latest_project: Option<PathBuf>,
+    #[serde(default)]
+    log_level: Option<String>,
+    #[serde(default)]
+    pcm_cache_limit_mb: Option<u64>,
+    #[serde(default)]
+    export: ExportSettings,
+    #[serde(default)]
+    stems: StemOptions,
 }
 
-// Helper function to create a downsampled waveform for visualization
-fn generate_waveform(samples: &[f32], target_size: usize) -> Vec<f32> {
-    if samples.is_empty() {
-        return Vec::new();
+// A path in `dir` for a copy of `source` that doesn't overwrite anything: "kick.wav", then "kick (2).wav"
+fn unique_file_path(dir: &Path, source: &Path) -> PathBuf {
+    let stem = source.file_stem().and_then(|s| s.to_str()).unwrap_or("audio");
+    let extension = source.extension().and_then(|e| e.to_str());
+    let file_name = |n: usize| {
+        let name = if n == 1 { stem.to_string() } else { format!("{} ({})", stem, n) };
+        match extension {
+            Some(ext) => format!("{}.{}", name, ext),
+            None => name,
+        }
+    };
+
+    let mut n = 1;
+    while dir.join(file_name(n)).exists() {
+        n += 1;
     }
-    
-    let samples_per_point = (samples.len() as f32 / target_size as f32).max(1.0) as usize;
-    let mut waveform = Vec::with_capacity(target_size);
-    
-    for i in 0..target_size {
-        let start = (i * samples_per_point).min(samples.len());
-        let end = ((i + 1) * samples_per_point).min(samples.len());
-        
-        if start < end {
-            // Find the maximum amplitude in this segment
-            let mut max_amplitude = 0.0f32;
-            for j in start..end {
-                let amplitude = samples[j].abs();
-                if amplitude > max_amplitude {
-                    max_amplitude = amplitude;
-                }
-            }
-            waveform.push(max_amplitude);
-        } else {
-            break;
+    dir.join(file_name(n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arrangement(bpm: f32) -> Arrangement {
+        Arrangement { bpm, tracks: Vec::new(), next_track_id: 0 }
+    }
+
+    #[test]
+    fn undo_and_redo_trade_snapshots() {
+        let mut history = History::default();
+        history.record(&DawAction::CreateTrack, || arrangement(100.0));
+        history.record(&DawAction::DeleteSample(0, 0), || arrangement(110.0));
+        assert!(history.can_undo() && !history.can_redo());
+
+        assert_eq!(history.undo(arrangement(120.0)).map(|a| a.bpm), Some(110.0));
+        assert_eq!(history.undo(arrangement(110.0)).map(|a| a.bpm), Some(100.0));
+        assert!(history.undo(arrangement(100.0)).is_none());
+        assert_eq!(history.redo(arrangement(100.0)).map(|a| a.bpm), Some(110.0));
+        assert_eq!(history.redo(arrangement(110.0)).map(|a| a.bpm), Some(120.0));
+        assert!(!history.can_redo());
+
+        // A new edit after undoing drops what could be redone
+        history.undo(arrangement(120.0));
+        history.record(&DawAction::CreateTrack, || arrangement(110.0));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn steps_of_one_drag_are_undone_together() {
+        let mut history = History::default();
+        history.record(&DawAction::MoveSample(1, 2, 0.5), || arrangement(100.0));
+        history.record(&DawAction::MoveSample(1, 2, 1.0), || arrangement(101.0));
+        history.record(&DawAction::MoveSample(1, 2, 1.5), || arrangement(102.0));
+        assert_eq!(history.undo(arrangement(103.0)).map(|a| a.bpm), Some(100.0));
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn separate_edits_are_undone_one_by_one() {
+        let mut history = History::default();
+        // Dragging another clip, or another kind of edit of the same clip
+        history.record(&DawAction::MoveSample(1, 2, 0.5), || arrangement(100.0));
+        history.record(&DawAction::MoveSample(1, 3, 0.5), || arrangement(101.0));
+        history.record(&DawAction::SetSampleLength(1, 3, 2.0), || arrangement(102.0));
+        // Discrete edits in quick succession
+        history.record(&DawAction::DeleteSample(1, 2), || arrangement(103.0));
+        history.record(&DawAction::DeleteSample(1, 3), || arrangement(104.0));
+
+        let mut undone = Vec::new();
+        let mut current = arrangement(105.0);
+        while let Some(previous) = history.undo(current) {
+            undone.push(previous.bpm);
+            current = previous;
         }
+        assert_eq!(undone, vec![104.0, 103.0, 102.0, 101.0, 100.0]);
+    }
+
+    #[test]
+    fn a_drag_after_undo_is_a_new_step() {
+        let mut history = History::default();
+        history.record(&DawAction::MoveSample(1, 2, 0.5), || arrangement(100.0));
+        history.undo(arrangement(101.0));
+        history.record(&DawAction::MoveSample(1, 2, 1.0), || arrangement(100.0));
+        history.record(&DawAction::MoveSample(1, 2, 1.5), || arrangement(100.5));
+        assert_eq!(history.undo(arrangement(102.0)).map(|a| a.bpm), Some(100.0));
+    }
+
+    #[test]
+    fn oldest_snapshots_are_dropped_past_the_limit() {
+        let mut history = History::default();
+        for i in 0..HISTORY_LIMIT + 10 {
+            history.record(&DawAction::CreateTrack, || arrangement(i as f32));
+        }
+        let mut steps = 0;
+        let mut oldest = None;
+        let mut current = arrangement(0.0);
+        while let Some(previous) = history.undo(current) {
+            steps += 1;
+            oldest = Some(previous.bpm);
+            current = previous;
+        }
+        assert_eq!(steps, HISTORY_LIMIT);
+        assert_eq!(oldest, Some(10.0));
     }
-    
-    waveform
 }
@@ -0,0 +1,341 @@
+use crate::audio::{decode_audio, resample, AudioError, AudioReader};
+use crate::media;
+use crate::pcm_cache::{self, Pcm};
+use crate::stream::Playback;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+
+// Background decoding.
+//
+// Audio files are decoded by a small pool of worker threads. Every file is decoded once and the
+// result is kept in a shared cache, so clips, streams, peaks and renders using the same file all
+// get the same samples. Entries no clip uses are dropped when a project is closed. The UI polls
+// for finished jobs once per frame. Decoded audio is resampled to the output rate and also kept
+// on disk by pcm_cache.rs, so the next load only maps a file.
+//
+// Files that are streamed from disk (see stream.rs) go through the same queue, but only their
+// length and hash are read; their samples are never held in memory.
+
+/// A fully decoded audio file, or the details of one that is streamed
+pub struct DecodedAudio {
+    pub samples: Arc<Pcm>, // Interleaved, empty when streamed
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub frames: u64,
+    pub streamed: bool,
+    pub size: Option<u64>,    // File size when it was decoded
+    pub hash: Option<String>, // Content hash of the file
+    pub damaged_packets: usize, // Packets skipped while decoding; 0 when mapped from the PCM cache
+}
+
+impl DecodedAudio {
+    /// Duration the rest of the app works with (samples / rate, as `load_audio` callers always did)
+    pub fn duration(&self) -> f32 {
+        (self.frames * self.channels as u64) as f32 / self.sample_rate as f32
+    }
+}
+
+/// Rate decoded audio is resampled to, normally the output device's. 0 keeps each file's own rate.
+static TARGET_RATE: AtomicU32 = AtomicU32::new(0);
+
+pub fn set_target_rate(sample_rate: u32) {
+    TARGET_RATE.store(sample_rate, Ordering::Relaxed);
+}
+
+pub type DecodeResult = Result<Arc<DecodedAudio>, String>;
+
+/// Progress of a queued or running decode, shared with whoever asked for it
+#[derive(Clone)]
+pub struct DecodeHandle {
+    progress: Arc<AtomicU32>, // Fraction done, stored as f32 bits
+    cancelled: Arc<AtomicBool>,
+}
+
+impl DecodeHandle {
+    fn new() -> Self {
+        Self {
+            progress: Arc::new(AtomicU32::new(0f32.to_bits())),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn progress(&self) -> f32 {
+        f32::from_bits(self.progress.load(Ordering::Relaxed))
+    }
+
+    fn is_same(&self, other: &DecodeHandle) -> bool {
+        Arc::ptr_eq(&self.progress, &other.progress)
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Default)]
+struct PoolState {
+    queue: VecDeque<PathBuf>,
+    jobs: HashMap<PathBuf, (DecodeHandle, Playback)>, // Queued or running
+    cache: HashMap<PathBuf, Arc<DecodedAudio>>,
+    finished: Vec<(PathBuf, DecodeResult)>, // Not yet collected by `take_finished`
+}
+
+struct DecodePool {
+    state: Mutex<PoolState>,
+    work: Condvar,
+}
+
+fn pool() -> &'static Arc<DecodePool> {
+    static POOL: OnceLock<Arc<DecodePool>> = OnceLock::new();
+    POOL.get_or_init(|| {
+        let pool = Arc::new(DecodePool {
+            state: Mutex::new(PoolState::default()),
+            work: Condvar::new(),
+        });
+        let workers = thread::available_parallelism()
+            .map(|n| n.get().clamp(1, 4))
+            .unwrap_or(2);
+        for i in 0..workers {
+            let pool = Arc::clone(&pool);
+            thread::Builder::new()
+                .name(format!("decode-{}", i))
+                .spawn(move || worker(&pool))
+                .expect("failed to start decode worker");
+        }
+        log::debug!(target: "audio", "Started {} decode workers", workers);
+        pool
+    })
+}
+
+fn worker(pool: &DecodePool) {
+    loop {
+        let (path, handle, playback) = {
+            let mut state = pool.state.lock().unwrap();
+            loop {
+                if let Some(path) = state.queue.pop_front() {
+                    if let Some((handle, playback)) = state.jobs.get(&path).cloned() {
+                        break (path, handle, playback);
+                    }
+                    continue; // Cancelled while queued
+                }
+                state = pool.work.wait(state).unwrap();
+            }
+        };
+
+        let result = decode(&path, &handle, playback);
+
+        let mut state = pool.state.lock().unwrap();
+        // The file may have been cancelled and requested again while this job ran
+        if state.jobs.get(&path).is_some_and(|(job, _)| job.is_same(&handle)) {
+            state.jobs.remove(&path);
+        }
+        match result {
+            Ok(decoded) => {
+                let decoded = Arc::new(decoded);
+                state.cache.insert(path.clone(), Arc::clone(&decoded));
+                state.finished.push((path, Ok(decoded)));
+            }
+            Err(AudioError::Cancelled) => {
+                log::debug!(target: "audio", "Cancelled decoding {}", path.display());
+            }
+            Err(e) => state.finished.push((path, Err(e.to_string()))),
+        }
+    }
+}
+
+fn decode(path: &Path, handle: &DecodeHandle, playback: Playback) -> Result<DecodedAudio, AudioError> {
+    let mut on_progress = |progress: f32| {
+        handle.progress.store(progress.to_bits(), Ordering::Relaxed);
+        !handle.is_cancelled()
+    };
+    let hash = media::hash_file(path).ok();
+    let size = media::file_size(path);
+    let target_rate = TARGET_RATE.load(Ordering::Relaxed);
+
+    // Mapped audio costs no memory, so it's used however the clip wants to play
+    if let Some(cached) = hash.as_deref().and_then(|hash| pcm_cache::read(hash, target_rate)) {
+        let channels = cached.channels;
+        return Ok(DecodedAudio {
+            frames: (cached.samples.len() / channels as usize) as u64,
+            samples: Arc::new(cached.samples),
+            sample_rate: cached.sample_rate,
+            channels,
+            streamed: false,
+            size,
+            hash,
+            damaged_packets: 0,
+        });
+    }
+
+    let reader = AudioReader::open(path)?;
+    let seconds = reader
+        .total_frames
+        .map(|frames| frames as f32 / reader.sample_rate as f32);
+    if playback.streams(seconds) {
+        let mut decoded = scan(path, reader, &mut on_progress)?;
+        decoded.size = size;
+        decoded.hash = hash;
+        return Ok(decoded);
+    }
+    let channels = reader.channels.max(1);
+    drop(reader);
+
+    log::debug!(target: "audio", "Decoding {}", path.display());
+    let decoded = decode_audio(path, &mut on_progress)?;
+    let (mut samples, mut sample_rate) = (decoded.samples, decoded.sample_rate);
+    if target_rate != 0 && target_rate != sample_rate {
+        log::debug!(target: "audio", "Resampling {} from {}Hz to {}Hz", path.display(), sample_rate, target_rate);
+        samples = resample(&samples, channels, sample_rate, target_rate);
+        sample_rate = target_rate;
+    }
+    if let Some(hash) = &hash {
+        if let Err(e) = pcm_cache::write(hash, sample_rate, channels, &samples) {
+            log::warn!(target: "audio", "Failed to cache decoded {}: {}", path.display(), e);
+        }
+    }
+
+    Ok(DecodedAudio {
+        frames: (samples.len() / channels as usize) as u64,
+        samples: Arc::new(Pcm::from(samples)),
+        sample_rate,
+        channels,
+        streamed: false,
+        size,
+        hash,
+        damaged_packets: decoded.damaged_packets,
+    })
+}
+
+/// Details of a file that will be streamed. Its length is read from the headers, or counted
+/// by decoding the file once when the container doesn't record it.
+fn scan(
+    path: &Path,
+    mut reader: AudioReader,
+    on_progress: &mut dyn FnMut(f32) -> bool,
+) -> Result<DecodedAudio, AudioError> {
+    log::debug!(target: "audio", "Scanning {} for streaming", path.display());
+    let channels = reader.channels.max(1);
+    let frames = match reader.total_frames {
+        Some(frames) => frames,
+        None => {
+            let mut frames = 0;
+            let mut packet = Vec::new();
+            while reader.read_packet(&mut packet)?.is_some() {
+                frames += (packet.len() / channels as usize) as u64;
+                packet.clear();
+                if !on_progress(0.0) {
+                    return Err(AudioError::Cancelled);
+                }
+            }
+            frames
+        }
+    };
+
+    Ok(DecodedAudio {
+        samples: Arc::new(Pcm::default()),
+        sample_rate: reader.sample_rate,
+        channels,
+        frames,
+        streamed: true,
+        size: None,
+        hash: None,
+        damaged_packets: reader.damaged_packets,
+    })
+}
+
+pub enum Request {
+    Ready(Arc<DecodedAudio>),
+    Pending(DecodeHandle),
+}
+
+/// Queue `path` for decoding. A file that is already decoded, queued or running isn't decoded again.
+pub fn request(path: &Path, playback: Playback) -> Request {
+    let pool = pool();
+    let mut state = pool.state.lock().unwrap();
+    if let Some(decoded) = state.cache.get(path) {
+        let wanted = match playback {
+            Playback::Auto => true,
+            Playback::Memory => !decoded.streamed,
+            Playback::Disk => decoded.streamed || decoded.samples.is_mapped(),
+        };
+        if wanted {
+            return Request::Ready(Arc::clone(decoded));
+        }
+    }
+    if let Some((handle, _)) = state.jobs.get(path) {
+        return Request::Pending(handle.clone());
+    }
+
+    let handle = DecodeHandle::new();
+    state.jobs.insert(path.to_path_buf(), (handle.clone(), playback));
+    state.queue.push_back(path.to_path_buf());
+    pool.work.notify_one();
+    Request::Pending(handle)
+}
+
+/// Stop decoding `path`. Nothing is reported for a cancelled file.
+pub fn cancel(path: &Path) {
+    let mut state = pool().state.lock().unwrap();
+    if let Some((handle, _)) = state.jobs.remove(path) {
+        handle.cancelled.store(true, Ordering::Relaxed);
+        log::info!(target: "audio", "Cancelled loading {}", path.display());
+    }
+}
+
+/// Stop every queued and running decode
+pub fn cancel_all() {
+    let mut state = pool().state.lock().unwrap();
+    for (_, (handle, _)) in state.jobs.drain() {
+        handle.cancelled.store(true, Ordering::Relaxed);
+    }
+    state.queue.clear();
+}
+
+/// Drop decoded audio that no clip plays anymore, such as that of a project that was closed.
+/// A file that is requested again is mapped back from the PCM cache.
+pub fn evict_unused() {
+    let mut state = pool().state.lock().unwrap();
+    let before = state.cache.len();
+    state
+        .cache
+        .retain(|_, decoded| Arc::strong_count(decoded) > 1 || Arc::strong_count(&decoded.samples) > 1);
+    log::debug!(target: "audio", "Dropped {} unused decoded files", before - state.cache.len());
+}
+
+/// Forget the decoded audio of a file that was rewritten in place, so the next request reads it again
+pub fn invalidate(path: &Path) {
+    let mut state = pool().state.lock().unwrap();
+    state.cache.remove(path);
+    if let Some((handle, _)) = state.jobs.remove(path) {
+        handle.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn cached(path: &Path) -> Option<Arc<DecodedAudio>> {
+    pool().state.lock().unwrap().cache.get(path).cloned()
+}
+
+/// Decodes that finished since the last call
+pub fn take_finished() -> Vec<(PathBuf, DecodeResult)> {
+    std::mem::take(&mut pool().state.lock().unwrap().finished)
+}
+
+/// Decode `path` into memory on the calling thread unless it is already cached.
+/// For code paths that need the samples right away.
+pub fn decode_now(path: &Path) -> DecodeResult {
+    if let Some(decoded) = cached(path).filter(|decoded| !decoded.streamed) {
+        return Ok(decoded);
+    }
+    let decoded = decode(path, &DecodeHandle::new(), Playback::Memory).map_err(|e| e.to_string())?;
+    let decoded = Arc::new(decoded);
+    pool()
+        .state
+        .lock()
+        .unwrap()
+        .cache
+        .insert(path.to_path_buf(), Arc::clone(&decoded));
+    Ok(decoded)
+}
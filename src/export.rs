@@ -0,0 +1,464 @@
+use crate::audio::{self, AudioError};
+use crate::flac;
+use crate::loudness;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Audio export.
+//
+// Rendered audio is float at the session rate. Exporting converts it to the delivery sample rate,
+// then to the delivery bit depth. Going to 16 or 24 bit adds TPDF dither so the rounding error
+// becomes a constant, signal independent noise floor instead of distortion, and can shape that
+// noise towards the top of the spectrum where it is hardest to hear.
+
+/// Sample rates offered in the export dialog
+pub const SAMPLE_RATES: &[u32] = &[44100, 48000, 88200, 96000];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Wav,
+    Aiff,
+    Flac,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Wav, ExportFormat::Aiff, ExportFormat::Flac];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Wav => "WAV",
+            ExportFormat::Aiff => "AIFF",
+            ExportFormat::Flac => "FLAC",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Wav => "wav",
+            ExportFormat::Aiff => "aiff",
+            ExportFormat::Flac => "flac",
+        }
+    }
+
+    /// Bit depths the format can store
+    pub fn bit_depths(self) -> &'static [BitDepth] {
+        match self {
+            ExportFormat::Wav => &[BitDepth::Int16, BitDepth::Int24, BitDepth::Float32],
+            ExportFormat::Aiff | ExportFormat::Flac => &[BitDepth::Int16, BitDepth::Int24],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum BitDepth {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl BitDepth {
+    pub fn label(self) -> &'static str {
+        match self {
+            BitDepth::Int16 => "16-bit",
+            BitDepth::Int24 => "24-bit",
+            BitDepth::Float32 => "32-bit float",
+        }
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            BitDepth::Int16 => 16,
+            BitDepth::Int24 => 24,
+            BitDepth::Float32 => 32,
+        }
+    }
+}
+
+/// Delivery format of an export, remembered in the config between exports
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct ExportSettings {
+    pub format: ExportFormat,
+    pub bit_depth: BitDepth,
+    pub sample_rate: u32,
+    pub dither: bool,        // TPDF dither when reducing to 16 or 24 bit
+    pub noise_shaping: bool, // Move the dither noise up the spectrum, only with dither on
+    pub normalize: bool,     // Bring the integrated loudness to target_lufs
+    pub target_lufs: f32,
+    pub true_peak_ceiling: f32, // dBTP the normalization gain never pushes the true peak above
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Wav,
+            bit_depth: BitDepth::Int24,
+            sample_rate: 48000,
+            dither: true,
+            noise_shaping: false,
+            normalize: false,
+            target_lufs: -14.0,
+            true_peak_ceiling: -1.0,
+        }
+    }
+}
+
+impl ExportSettings {
+    /// The same settings with the bit depth moved to one the format can store
+    pub fn normalized(mut self) -> Self {
+        if !self.format.bit_depths().contains(&self.bit_depth) {
+            self.bit_depth = BitDepth::Int24;
+        }
+        self
+    }
+}
+
+/// What each stem file holds
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum StemSource {
+    Tracks, // One file per track
+    Groups, // One file per Group instance placed on a track
+}
+
+impl StemSource {
+    pub fn label(self) -> &'static str {
+        match self {
+            StemSource::Tracks => "Every track",
+            StemSource::Groups => "Every Group instance",
+        }
+    }
+}
+
+/// Where in the track's signal path stems are taken from. Tracks have no gain yet, so the fader
+/// stage is their mute and solo state.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum StemTap {
+    PreFader,  // Every track as recorded, muted or not
+    PostFader, // As heard in the mix: muted tracks, and unsoloed ones while any is soloed, are silent
+}
+
+impl StemTap {
+    pub fn label(self) -> &'static str {
+        match self {
+            StemTap::PreFader => "Pre-fader",
+            StemTap::PostFader => "Post-fader",
+        }
+    }
+}
+
+/// Stem export options, remembered in the config between exports
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct StemOptions {
+    pub source: StemSource,
+    pub tap: StemTap,
+}
+
+impl Default for StemOptions {
+    fn default() -> Self {
+        Self {
+            source: StemSource::Tracks,
+            tap: StemTap::PostFader,
+        }
+    }
+}
+
+/// File name for a stem, without the characters file systems reject
+pub fn stem_file_name(index: usize, name: &str, format: ExportFormat) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c })
+        .collect();
+    let name = name.trim();
+    let name = if name.is_empty() { "Stem" } else { name };
+    format!("{:02} {}.{}", index + 1, name, format.extension())
+}
+
+/// Loudness targets offered in the export dialog, as (LUFS, what they are for)
+pub const LOUDNESS_TARGETS: &[(f32, &str)] = &[
+    (-14.0, "Streaming"),
+    (-16.0, "Podcasts, Apple Music"),
+    (-23.0, "EBU R 128 broadcast"),
+    (-24.0, "ATSC A/85 broadcast"),
+];
+
+/// Gain that brings `samples` to the loudness target in `settings`. Stems share the gain of the
+/// whole mix, so they are measured together rather than each on its own.
+pub fn normalization_gain(samples: &[f32], channels: u16, sample_rate: u32, settings: &ExportSettings) -> f32 {
+    let measured = loudness::analyze(samples, channels, sample_rate);
+    let gain = measured.normalization_gain(settings.target_lufs as f64, settings.true_peak_ceiling as f64);
+    log::info!(target: "audio",
+        "Measured {:.1} LUFS integrated, {:.1} dBTP true peak; normalizing by {:+.1} dB",
+        measured.integrated,
+        measured.true_peak,
+        20.0 * gain.log10()
+    );
+    gain as f32
+}
+
+/// Write interleaved float samples to `path` in the format given by `settings`
+pub fn export(
+    path: &Path,
+    samples: &[f32],
+    channels: u16,
+    sample_rate: u32,
+    settings: &ExportSettings,
+) -> Result<(), AudioError> {
+    let settings = settings.normalized();
+    let channels = channels.max(1);
+
+    let samples = if sample_rate != settings.sample_rate {
+        log::debug!(target: "audio", "Converting export from {} Hz to {} Hz", sample_rate, settings.sample_rate);
+        Cow::Owned(audio::resample(samples, channels, sample_rate, settings.sample_rate))
+    } else {
+        Cow::Borrowed(samples)
+    };
+
+    // Measured after rate conversion, so the true peak ceiling holds for the delivered file
+    let samples = if settings.normalize {
+        let gain = normalization_gain(&samples, channels, settings.sample_rate, &settings);
+        Cow::Owned(samples.iter().map(|s| s * gain).collect())
+    } else {
+        samples
+    };
+
+    match settings.bit_depth {
+        BitDepth::Float32 => write_wav_float(path, &samples, channels, settings.sample_rate)?,
+        depth => {
+            let bits = depth.bits();
+            let mut quantizer = Quantizer::new(bits, channels, settings.dither, settings.noise_shaping);
+            let quantized = quantizer.quantize(&samples);
+            match settings.format {
+                ExportFormat::Wav => write_wav_int(path, &quantized, channels, bits, settings.sample_rate)?,
+                ExportFormat::Aiff => write_aiff(path, &quantized, channels, bits, settings.sample_rate)?,
+                ExportFormat::Flac => flac::write(path, &quantized, channels, bits, settings.sample_rate)?,
+            }
+        }
+    }
+
+    log::info!(target: "audio",
+        "Exported {} as {} {} at {} Hz",
+        path.display(),
+        settings.bit_depth.label(),
+        settings.format.label(),
+        settings.sample_rate
+    );
+    Ok(())
+}
+
+// Error feedback filter for noise shaping (Wannamaker's 3 tap), which lowers the noise where the
+// ear is most sensitive, around 3 to 4 kHz, and raises it towards Nyquist
+const SHAPING: [f32; 3] = [1.623, -0.982, 0.109];
+
+/// Rounds float samples to integers, with optional dither and noise shaping
+struct Quantizer {
+    scale: f32, // Full scale of the target bit depth
+    dither: bool,
+    noise_shaping: bool,
+    errors: Vec<[f32; 3]>, // Last rounding errors of each channel, newest first
+    seed: u32,
+}
+
+impl Quantizer {
+    fn new(bits: u32, channels: u16, dither: bool, noise_shaping: bool) -> Self {
+        Self {
+            scale: (1u32 << (bits - 1)) as f32,
+            dither,
+            noise_shaping: dither && noise_shaping,
+            errors: vec![[0.0; 3]; channels as usize],
+            seed: 0x9e37_79b9,
+        }
+    }
+
+    // Uniform in [0, 1), from a xorshift generator; dither needs no better randomness than this
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        (self.seed >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    fn quantize(&mut self, samples: &[f32]) -> Vec<i32> {
+        let channels = self.errors.len();
+        let mut quantized = Vec::with_capacity(samples.len());
+        for (index, &sample) in samples.iter().enumerate() {
+            let channel = index % channels;
+            let mut value = sample * self.scale;
+            if self.noise_shaping {
+                let errors = self.errors[channel];
+                value -= SHAPING[0] * errors[0] + SHAPING[1] * errors[1] + SHAPING[2] * errors[2];
+            }
+            // Triangular dither, the difference of two uniform values, spanning +-1 LSB
+            let dither = if self.dither {
+                self.random() - self.random()
+            } else {
+                0.0
+            };
+            let rounded = (value + dither).round().clamp(-self.scale, self.scale - 1.0);
+            if self.noise_shaping {
+                // Clipped samples would feed back huge errors, so limit what is carried over
+                let error = (rounded - value).clamp(-2.0, 2.0);
+                let errors = &mut self.errors[channel];
+                *errors = [error, errors[0], errors[1]];
+            }
+            quantized.push(rounded as i32);
+        }
+        quantized
+    }
+}
+
+fn wav_error(e: hound::Error) -> AudioError {
+    AudioError::Io(io::Error::other(e))
+}
+
+fn write_wav_float(path: &Path, samples: &[f32], channels: u16, sample_rate: u32) -> Result<(), AudioError> {
+    let spec = WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut writer = WavWriter::create(path, spec).map_err(wav_error)?;
+    for &sample in samples {
+        writer.write_sample(sample).map_err(wav_error)?;
+    }
+    writer.finalize().map_err(wav_error)
+}
+
+fn write_wav_int(
+    path: &Path,
+    samples: &[i32],
+    channels: u16,
+    bits: u32,
+    sample_rate: u32,
+) -> Result<(), AudioError> {
+    let spec = WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: bits as u16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec).map_err(wav_error)?;
+    for &sample in samples {
+        writer.write_sample(sample).map_err(wav_error)?;
+    }
+    writer.finalize().map_err(wav_error)
+}
+
+// AIFF is big endian throughout and stores the sample rate as an 80 bit extended float
+fn write_aiff(path: &Path, samples: &[i32], channels: u16, bits: u32, sample_rate: u32) -> io::Result<()> {
+    let bytes_per_sample = bits as usize / 8;
+    let frames = (samples.len() / channels as usize) as u32;
+    let data_len = frames as usize * channels as usize * bytes_per_sample;
+    let pad = data_len % 2; // Chunks are padded to an even length
+    let ssnd_len = 8 + data_len;
+    let form_len = 4 + (8 + 18) + (8 + ssnd_len + pad);
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(b"FORM")?;
+    out.write_all(&(form_len as u32).to_be_bytes())?;
+    out.write_all(b"AIFF")?;
+
+    out.write_all(b"COMM")?;
+    out.write_all(&18u32.to_be_bytes())?;
+    out.write_all(&channels.to_be_bytes())?;
+    out.write_all(&frames.to_be_bytes())?;
+    out.write_all(&(bits as u16).to_be_bytes())?;
+    out.write_all(&extended(sample_rate))?;
+
+    out.write_all(b"SSND")?;
+    out.write_all(&(ssnd_len as u32).to_be_bytes())?;
+    out.write_all(&0u32.to_be_bytes())?; // Offset
+    out.write_all(&0u32.to_be_bytes())?; // Block size
+    for &sample in &samples[..frames as usize * channels as usize] {
+        out.write_all(&sample.to_be_bytes()[4 - bytes_per_sample..])?;
+    }
+    if pad == 1 {
+        out.write_all(&[0])?;
+    }
+    out.flush()
+}
+
+// An integer as an IEEE 754 80 bit extended float: sign and exponent, then a 64 bit mantissa with
+// an explicit leading one
+fn extended(value: u32) -> [u8; 10] {
+    let mut bytes = [0u8; 10];
+    if value == 0 {
+        return bytes;
+    }
+    let shift = value.leading_zeros();
+    let exponent = 16383 + 31 - shift as u16;
+    let mantissa = (value as u64) << (32 + shift);
+    bytes[..2].copy_from_slice(&exponent.to_be_bytes());
+    bytes[2..].copy_from_slice(&mantissa.to_be_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::load_audio;
+
+    #[test]
+    fn extended_sample_rates() {
+        assert_eq!(extended(0), [0; 10]);
+        assert_eq!(extended(1), [0x3f, 0xff, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(extended(44100), [0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(extended(48000), [0x40, 0x0e, 0xbb, 0x80, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(extended(96000), [0x40, 0x0f, 0xbb, 0x80, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rounds_and_clips_without_dither() {
+        let mut quantizer = Quantizer::new(16, 1, false, false);
+        assert_eq!(
+            quantizer.quantize(&[0.0, 0.5, -0.5, 1.0, -1.0, 2.0]),
+            vec![0, 16384, -16384, 32767, -32768, 32767]
+        );
+
+        let mut quantizer = Quantizer::new(24, 2, false, false);
+        assert_eq!(quantizer.quantize(&[0.25, -0.25]), vec![1 << 21, -(1 << 21)]);
+    }
+
+    #[test]
+    fn dither_stays_within_one_step() {
+        let mut quantizer = Quantizer::new(16, 2, true, false);
+        let input = vec![0.1; 10000];
+        let exact = 0.1 * 32768.0;
+        let output = quantizer.quantize(&input);
+        assert!(output.iter().all(|&s| (s as f32 - exact).abs() <= 1.5));
+        // Not every sample rounds the same way, and on average they land on the exact value
+        assert!(output.iter().any(|&s| s != output[0]));
+        let mean = output.iter().map(|&s| s as f64).sum::<f64>() / output.len() as f64;
+        assert!((mean - exact as f64).abs() < 0.05);
+    }
+
+    #[test]
+    fn noise_shaping_keeps_the_error_small() {
+        let mut quantizer = Quantizer::new(16, 1, true, true);
+        let input: Vec<f32> = (0..48000).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+        let output = quantizer.quantize(&input);
+        for (&sample, &quantized) in input.iter().zip(&output) {
+            assert!((sample * 32768.0 - quantized as f32).abs() < 6.0);
+        }
+    }
+
+    #[test]
+    fn aiff_round_trip() {
+        let path = std::env::temp_dir().join(format!("monlam-aiff-{}.aiff", std::process::id()));
+        let samples: Vec<i32> = (0..1001).flat_map(|i| [i * 16, -i * 16]).collect();
+        write_aiff(&path, &samples, 2, 16, 44100).unwrap();
+
+        let (decoded, sample_rate) = load_audio(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(sample_rate, 44100);
+        assert_eq!(decoded.len(), samples.len());
+        for (&original, &read) in samples.iter().zip(&decoded) {
+            assert!((original as f32 / 32768.0 - read).abs() < 1e-6);
+        }
+    }
+}
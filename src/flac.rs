@@ -0,0 +1,386 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// FLAC encoder.
+//
+// A small encoder for exports: fixed-block frames, each channel coded independently with the best
+// of the fixed polynomial predictors (orders 0 to 4) and partitioned Rice coding of the residual.
+// That gets most of the compression of reference FLAC at its faster settings without LPC analysis.
+// The MD5 signature in STREAMINFO is left zero, which the format allows for "not computed".
+
+const BLOCK_SIZE: usize = 4096;
+const MAX_PARTITION_ORDER: u32 = 8;
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u64,
+    filled: u32, // Bits held in `current`
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            current: 0,
+            filled: 0,
+        }
+    }
+
+    /// Write the low `bits` bits of `value`, most significant first. `bits` is at most 32.
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits == 0 {
+            return;
+        }
+        self.current = (self.current << bits) | (value & ((1u64 << bits) - 1));
+        self.filled += bits;
+        while self.filled >= 8 {
+            self.filled -= 8;
+            self.bytes.push((self.current >> self.filled) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    fn write_unary(&mut self, zeros: u64) {
+        let mut zeros = zeros;
+        while zeros >= 32 {
+            self.write(0, 32);
+            zeros -= 32;
+        }
+        self.write(1, zeros as u32 + 1);
+    }
+
+    /// Pad with zero bits up to the next byte
+    fn align(&mut self) {
+        if self.filled > 0 {
+            self.write(0, 8 - self.filled);
+        }
+    }
+}
+
+/// Write interleaved integer samples of `bits_per_sample` bits (16 or 24) as a FLAC file
+pub fn write(
+    path: &Path,
+    samples: &[i32],
+    channels: u16,
+    bits_per_sample: u32,
+    sample_rate: u32,
+) -> io::Result<()> {
+    if !(1..=8).contains(&channels) || !(4..=32).contains(&bits_per_sample) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("FLAC can't hold {} channels of {} bit audio", channels, bits_per_sample),
+        ));
+    }
+    let channels = channels as usize;
+    let frames = samples.len() / channels;
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(b"fLaC")?;
+    out.write_all(&stream_info(channels, bits_per_sample, sample_rate, frames as u64))?;
+
+    let mut channel = Vec::with_capacity(BLOCK_SIZE);
+    for (index, block) in samples[..frames * channels].chunks(BLOCK_SIZE * channels).enumerate() {
+        let block_frames = block.len() / channels;
+        let mut writer = BitWriter::new();
+        frame_header(&mut writer, index as u64, block_frames, channels, bits_per_sample);
+        for c in 0..channels {
+            channel.clear();
+            channel.extend(block.iter().skip(c).step_by(channels).map(|&s| s as i64));
+            subframe(&mut writer, &channel, bits_per_sample);
+        }
+        writer.align();
+        let crc = crc16(&writer.bytes);
+        writer.write(crc as u64, 16);
+        out.write_all(&writer.bytes)?;
+    }
+
+    out.flush()
+}
+
+fn stream_info(channels: usize, bits_per_sample: u32, sample_rate: u32, frames: u64) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    writer.write(1, 1); // Last metadata block
+    writer.write(0, 7); // STREAMINFO
+    writer.write(34, 24);
+    writer.write(BLOCK_SIZE as u64, 16); // Minimum block size
+    writer.write(BLOCK_SIZE as u64, 16); // Maximum block size
+    writer.write(0, 24); // Minimum frame size, unknown
+    writer.write(0, 24); // Maximum frame size, unknown
+    writer.write(sample_rate as u64, 20);
+    writer.write(channels as u64 - 1, 3);
+    writer.write(bits_per_sample as u64 - 1, 5);
+    writer.write(frames >> 4, 32);
+    writer.write(frames & 0xf, 4);
+    for _ in 0..4 {
+        writer.write(0, 32); // MD5, not computed
+    }
+    writer.bytes
+}
+
+fn frame_header(
+    writer: &mut BitWriter,
+    index: u64,
+    block_frames: usize,
+    channels: usize,
+    bits_per_sample: u32,
+) {
+    writer.write(0b11111111111110, 14); // Sync code
+    writer.write(0, 1);
+    writer.write(0, 1); // Fixed block size, frames are numbered
+    let block_code = if block_frames == BLOCK_SIZE { 0b1100 } else { 0b0111 };
+    writer.write(block_code, 4);
+    writer.write(0b0000, 4); // Sample rate from STREAMINFO
+    writer.write(channels as u64 - 1, 4); // Independent channels
+    let size_code = match bits_per_sample {
+        8 => 0b001,
+        12 => 0b010,
+        16 => 0b100,
+        20 => 0b101,
+        24 => 0b110,
+        _ => 0b000, // From STREAMINFO
+    };
+    writer.write(size_code, 3);
+    writer.write(0, 1);
+    for byte in utf8_number(index) {
+        writer.write(byte as u64, 8);
+    }
+    if block_code == 0b0111 {
+        writer.write(block_frames as u64 - 1, 16);
+    }
+    // The header is byte aligned here, so its CRC covers everything written so far
+    let crc = crc8(&writer.bytes);
+    writer.write(crc as u64, 8);
+}
+
+// Frame numbers are stored with the same variable length coding as UTF-8
+fn utf8_number(value: u64) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    let mut continuation = Vec::new();
+    let mut value = value;
+    let mut first_bits = 6; // Payload bits left in the first byte
+    while value >= (1 << first_bits) {
+        continuation.push(0x80 | (value & 0x3f) as u8);
+        value >>= 6;
+        first_bits -= 1;
+    }
+    let length = continuation.len() + 1;
+    let prefix = !(0xffu8 >> length);
+    let mut bytes = vec![prefix | value as u8];
+    bytes.extend(continuation.iter().rev());
+    bytes
+}
+
+fn subframe(writer: &mut BitWriter, samples: &[i64], bits_per_sample: u32) {
+    writer.write(0, 1);
+    if samples.iter().all(|&s| s == samples[0]) {
+        writer.write(0b000000, 6); // Constant
+        writer.write(0, 1);
+        writer.write_signed(samples[0], bits_per_sample);
+        return;
+    }
+
+    // Pick the fixed predictor with the smallest residual
+    let max_order = 4.min(samples.len() - 1);
+    let (order, residual) = (0..=max_order)
+        .map(|order| (order, fixed_residual(samples, order)))
+        .min_by_key(|(_, residual)| residual.iter().map(|r| r.unsigned_abs()).sum::<u64>())
+        .unwrap();
+    let (rice, rice_bits) = rice_partitions(&residual, samples.len(), order);
+
+    let verbatim_bits = samples.len() as u64 * bits_per_sample as u64;
+    if rice_bits + (order as u64 * bits_per_sample as u64) >= verbatim_bits {
+        writer.write(0b000001, 6); // Verbatim
+        writer.write(0, 1);
+        for &sample in samples {
+            writer.write_signed(sample, bits_per_sample);
+        }
+        return;
+    }
+
+    writer.write(0b001000 | order as u64, 6); // Fixed predictor
+    writer.write(0, 1);
+    for &sample in &samples[..order] {
+        writer.write_signed(sample, bits_per_sample);
+    }
+    write_residual(writer, &residual, &rice, samples.len(), order);
+}
+
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| {
+            let s = |k: usize| samples[i - k];
+            match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            }
+        })
+        .collect()
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+struct RicePartitions {
+    order: u32,
+    parameters: Vec<u32>,
+}
+
+// Choose the partition order and Rice parameters that code the residual in the fewest bits.
+// Returns them along with that size.
+fn rice_partitions(residual: &[i64], block_frames: usize, predictor_order: usize) -> (RicePartitions, u64) {
+    let mut best: Option<(RicePartitions, u64)> = None;
+    for order in 0..=MAX_PARTITION_ORDER {
+        let partitions = 1usize << order;
+        if !block_frames.is_multiple_of(partitions) || block_frames / partitions <= predictor_order {
+            break;
+        }
+        let mut parameters = Vec::with_capacity(partitions);
+        let mut bits = 0;
+        let mut start = 0;
+        for partition in 0..partitions {
+            let length = block_frames / partitions - if partition == 0 { predictor_order } else { 0 };
+            let values = &residual[start..start + length];
+            start += length;
+            let (parameter, size) = best_parameter(values);
+            parameters.push(parameter);
+            bits += size + 5;
+        }
+        if best.as_ref().is_none_or(|(_, best_bits)| bits < *best_bits) {
+            best = Some((RicePartitions { order, parameters }, bits));
+        }
+    }
+    best.unwrap()
+}
+
+fn best_parameter(values: &[i64]) -> (u32, u64) {
+    let sum: u64 = values.iter().map(|&v| zigzag(v)).sum();
+    let mean = sum / values.len().max(1) as u64;
+    let estimate = if mean == 0 { 0 } else { 63 - mean.leading_zeros() };
+    (estimate.saturating_sub(1)..=(estimate + 1).min(30))
+        .map(|k| {
+            let size = values.iter().map(|&v| (zigzag(v) >> k) + 1 + k as u64).sum::<u64>();
+            (k, size)
+        })
+        .min_by_key(|(_, size)| *size)
+        .unwrap()
+}
+
+fn write_residual(
+    writer: &mut BitWriter,
+    residual: &[i64],
+    rice: &RicePartitions,
+    block_frames: usize,
+    predictor_order: usize,
+) {
+    // Parameters above 14 need the 5 bit variant of the coding
+    let wide = rice.parameters.iter().any(|&k| k > 14);
+    writer.write(if wide { 0b01 } else { 0b00 }, 2);
+    writer.write(rice.order as u64, 4);
+
+    // The first partition is shorter by the warm-up samples, which aren't part of the residual
+    let partition_frames = block_frames >> rice.order;
+    let mut start = 0;
+    for (partition, &k) in rice.parameters.iter().enumerate() {
+        let length = partition_frames - if partition == 0 { predictor_order } else { 0 };
+        writer.write(k as u64, if wide { 5 } else { 4 });
+        for &value in &residual[start..start + length] {
+            let u = zigzag(value);
+            writer.write_unary(u >> k);
+            writer.write(u & ((1u64 << k) - 1), k);
+        }
+        start += length;
+    }
+}
+
+// CRC-8 of frame headers, polynomial x^8 + x^2 + x + 1
+fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+// CRC-16 of whole frames, polynomial x^16 + x^15 + x^2 + 1
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::load_audio;
+    use std::fs;
+
+    // Interleaved test signal: a sine on the left, pseudo-random noise on the right, and the
+    // extremes of the sample range so the widest residuals are exercised
+    fn test_signal(frames: usize, bits_per_sample: u32) -> Vec<i32> {
+        let max = (1i64 << (bits_per_sample - 1)) - 1;
+        let mut seed = 0x1234_5678u32;
+        let mut samples = Vec::with_capacity(frames * 2);
+        for frame in 0..frames {
+            let sine = (frame as f64 * 0.05).sin() * max as f64 * 0.8;
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let noise = (seed >> 8) as i64 % (2 * max) - max;
+            samples.push(sine as i32);
+            samples.push(noise as i32);
+        }
+        samples[1000] = max as i32;
+        samples[1001] = -(max as i32) - 1;
+        samples
+    }
+
+    fn round_trip(bits_per_sample: u32) {
+        let frames = BLOCK_SIZE * 2 + 1234; // Ends with a short block
+        let samples = test_signal(frames, bits_per_sample);
+        let path = std::env::temp_dir().join(format!("monlam-flac-{}-{}.flac", bits_per_sample, std::process::id()));
+        write(&path, &samples, 2, bits_per_sample, 48000).unwrap();
+
+        let decoded = load_audio(&path);
+        let _ = fs::remove_file(&path);
+        let (decoded, sample_rate) = decoded.unwrap();
+
+        assert_eq!(sample_rate, 48000);
+        assert_eq!(decoded.len(), samples.len());
+        let scale = (1i64 << (bits_per_sample - 1)) as f32;
+        for (index, (&original, &decoded)) in samples.iter().zip(&decoded).enumerate() {
+            assert!(
+                (original as f32 / scale - decoded).abs() < 1e-6,
+                "sample {} was {} and decoded as {}",
+                index,
+                original,
+                decoded * scale
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_16_bit() {
+        round_trip(16);
+    }
+
+    #[test]
+    fn decodes_24_bit() {
+        round_trip(24);
+    }
+}
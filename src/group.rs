@@ -1,47 +1,137 @@
-use crate::audio::load_audio;
+use crate::audio::probe_audio;
+use crate::decode::{self, Request};
+use crate::stream::Playback;
+use crate::project;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// How long a Group has to go without edits before it is rendered again
+const RENDER_DELAY: Duration = Duration::from_millis(500);
+
+// Named variants of a Group live in folders under this one inside the Group folder
+pub const VARIANTS_DIR: &str = "variants";
+
+// Deleted Groups are moved here inside the project folder, so deleting one can be undone
+const TRASH_DIR: &str = ".trash";
+
+/// Folders the project keeps next to its Groups (media pool, backups, waveforms and collected
+/// audio). None of them is a Group, and no Group can take their name.
+pub const RESERVED_NAMES: &[&str] = &["media", "backups", "waveforms", "audio"];
+
+pub fn is_reserved_name(name: &str) -> bool {
+    RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(name))
+}
+
+/// Whether a folder in the project folder is a Group: it has a manifest, or an arrangement saved
+/// in the current format. Hidden and reserved folders never are.
+pub fn is_group_folder(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    if name.starts_with('.') || is_reserved_name(name) || !path.is_dir() {
+        return false;
+    }
+    path.join("group.json").is_file() || project::has_versioned_group(path)
+}
 
 /// A Group is a container for audio content that can be saved and reused in projects.
 /// Each Group is stored as a folder in the project directory.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct Group {
     /// User-friendly name of the Group
     pub name: String,
     
     /// Path to the Group folder
-    #[serde(skip)]
     pub path: PathBuf,
     
     /// Path to the rendered audio file (typically {group_name}/render.wav)
-    #[serde(skip)]
     pub render_path: PathBuf,
     
+    /// Properties stored in {group_name}/group.json
+    pub manifest: GroupManifest,
+    
     /// Waveform data for visualization
-    #[serde(skip)]
     pub waveform: Vec<f32>,
     
     /// Sample rate of the rendered audio
-    #[serde(skip)]
     pub sample_rate: u32,
     
     /// Duration in seconds
-    #[serde(skip)]
     pub duration: f32,
 }
 
+/// Properties of a Group, stored as {group_name}/group.json
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupManifest {
+    pub name: String,
+    #[serde(default)]
+    pub color: Option<[u8; 3]>,
+    #[serde(default = "default_bpm")]
+    pub bpm: f32,
+    /// Length in the Group's own beats. Without one the Group ends with its last clip.
+    #[serde(default)]
+    pub length_beats: Option<f32>,
+    #[serde(default = "default_time_signature")]
+    pub time_signature: (u32, u32),
+    /// Name of the project the Group was made in
+    #[serde(default)]
+    pub source_project: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub render: RenderSettings,
+    /// Names of the variants besides the main arrangement. Each has its own arrangement and
+    /// render under variants/<name>.
+    #[serde(default)]
+    pub variants: Vec<String>,
+}
+
+/// How a Group's arrangement is mixed into its render.wav
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RenderSettings {
+    /// Sample rate of the render. Without one the output device's rate is used.
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+}
+
+fn default_bpm() -> f32 {
+    120.0
+}
+
+fn default_time_signature() -> (u32, u32) {
+    (4, 4)
+}
+
+impl GroupManifest {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            color: None,
+            bpm: default_bpm(),
+            length_beats: None,
+            time_signature: default_time_signature(),
+            source_project: None,
+            tags: Vec::new(),
+            render: RenderSettings::default(),
+            variants: Vec::new(),
+        }
+    }
+
+    /// Length of the Group in seconds, if it has a fixed one
+    pub fn length_seconds(&self) -> Option<f32> {
+        self.length_beats.map(|beats| beats * 60.0 / self.bpm)
+    }
+}
+
 impl Group {
     /// Create a new Group with the given name in the project directory
     pub fn new(name: &str, project_dir: &Path) -> Result<Self, String> {
-        // Validate the name
-        if name.contains('/') {
-            return Err("Group name cannot contain '/'".to_string());
-        }
-        
-        if name.trim().is_empty() {
-            return Err("Group name cannot be empty".to_string());
-        }
+        check_group_name(name)?;
         
         // Create the group directory
         let group_path = project_dir.join(name);
@@ -53,12 +143,16 @@ impl Group {
             Ok(_) => {
                 // Create empty render.wav placeholder
                 // The actual rendering happens separately
-                let render_path = group_path.join("render.wav");
+                let render_path = render_path(&group_path);
+                let mut manifest = GroupManifest::new(name);
+                manifest.source_project = project_dir.file_name().map(|n| n.to_string_lossy().into_owned());
+                save_manifest(&group_path, &manifest)?;
                 
                 Ok(Self {
                     name: name.to_string(),
                     path: group_path,
                     render_path,
+                    manifest,
                     waveform: Vec::new(),
                     sample_rate: 44100, // Default sample rate
                     duration: 0.0,
@@ -80,27 +174,31 @@ impl Group {
             .unwrap_or("Unknown")
             .to_string();
         
-        let render_path = group_path.join("render.wav");
+        let render_path = render_path(group_path);
+        
+        // The folder name wins over the manifest, it is what the project refers to
+        let mut manifest = load_manifest(group_path).unwrap_or_else(|| GroupManifest::new(&name));
+        manifest.name = name.clone();
         
         let mut result = Self {
             name,
             path: group_path.to_path_buf(),
             render_path: render_path.clone(),
+            manifest,
             waveform: Vec::new(),
             sample_rate: 44100,
             duration: 0.0,
         };
         
-        // Load waveform data if render.wav exists
+        // Only the headers of render.wav are read here; the waveform is filled in by `update_waveform`
         if render_path.exists() {
-            match load_audio(&render_path) {
-                Ok((samples, sample_rate)) => {
-                    result.waveform = generate_waveform(&samples, 1000);
-                    result.sample_rate = sample_rate;
-                    result.duration = samples.len() as f32 / sample_rate as f32;
+            match probe_audio(&render_path) {
+                Ok(info) => {
+                    result.sample_rate = info.sample_rate.unwrap_or(result.sample_rate);
+                    result.duration = info.duration.unwrap_or(0.0) as f32;
                 }
                 Err(e) => {
-                    eprintln!("Failed to load Group audio: {:?}", e);
+                    log::error!(target: "groups", "Failed to read Group audio: {:?}", e);
                 }
             }
         }
@@ -108,50 +206,20 @@ impl Group {
         Ok(result)
     }
     
-    /// Render the Group contents to render.wav
-    pub fn render(&mut self, audio_data: &[f32], sample_rate: u32) -> Result<(), String> {
-        use hound::{SampleFormat, WavSpec, WavWriter};
-        
-        // Create the necessary directories if they don't exist
-        if let Some(parent) = self.render_path.parent() {
-            if !parent.exists() {
-                if let Err(e) = fs::create_dir_all(parent) {
-                    return Err(format!("Failed to create Group directory: {}", e));
-                }
-            }
+    /// Fill in the waveform once render.wav has been decoded in the background.
+    /// Queues the decode on the first call; cheap to call every frame.
+    pub fn update_waveform(&mut self) {
+        if !self.waveform.is_empty() || !self.render_path.exists() {
+            return;
         }
-        
-        // Create WAV spec
-        let spec = WavSpec {
-            channels: 2, // Stereo output
-            sample_rate,
-            bits_per_sample: 32,
-            sample_format: SampleFormat::Float,
-        };
-        
-        // Create WAV writer
-        let writer = match WavWriter::create(&self.render_path, spec) {
-            Ok(writer) => writer,
-            Err(e) => return Err(format!("Failed to create WAV file: {}", e)),
-        };
-        
-        // Convert mono to stereo if necessary and write samples
-        let result = if audio_data.len() % 2 == 0 {
-            // Data is already stereo format
-            write_audio_to_wav(writer, audio_data)
-        } else {
-            // Data is mono, duplicate to stereo
-            let mut stereo_data = Vec::with_capacity(audio_data.len() * 2);
-            for sample in audio_data {
-                stereo_data.push(*sample); // Left channel
-                stereo_data.push(*sample); // Right channel
-            }
-            write_audio_to_wav(writer, &stereo_data)
-        };
-        
-        if let Err(e) = result {
-            return Err(format!("Failed to write audio data: {}", e));
+        if let Request::Ready(decoded) = decode::request(&self.render_path, Playback::Memory) {
+            self.waveform = generate_waveform(&decoded.samples, 1000);
         }
+    }
+    
+    /// Render the Group contents to render.wav
+    pub fn render(&mut self, audio_data: &[f32], sample_rate: u32) -> Result<(), String> {
+        write_render(&self.render_path, audio_data, sample_rate)?;
         
         // Update waveform data
         self.waveform = generate_waveform(audio_data, 1000);
@@ -163,14 +231,7 @@ impl Group {
     
     /// Rename the Group (updates both name and directory)
     pub fn rename(&mut self, new_name: &str, project_dir: &Path) -> Result<(), String> {
-        // Validate the new name
-        if new_name.contains('/') {
-            return Err("Group name cannot contain '/'".to_string());
-        }
-        
-        if new_name.trim().is_empty() {
-            return Err("Group name cannot be empty".to_string());
-        }
+        check_group_name(new_name)?;
         
         // Create the new group directory path
         let new_group_path = project_dir.join(new_name);
@@ -186,10 +247,330 @@ impl Group {
         // Update the Group object
         self.name = new_name.to_string();
         self.path = new_group_path;
-        self.render_path = self.path.join("render.wav");
-        
-        Ok(())
+        self.render_path = render_path(&self.path);
+        self.manifest.name = self.name.clone();
+        save_manifest(&self.path, &self.manifest)
+    }
+}
+
+/// Read {group_name}/group.json. Groups made before it existed have none.
+pub fn load_manifest(group_path: &Path) -> Option<GroupManifest> {
+    let path = group_path.join("group.json");
+    let contents = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&contents) {
+        Ok(manifest) => Some(manifest),
+        Err(e) => {
+            log::error!(target: "groups", "Failed to parse {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub fn save_manifest(group_path: &Path, manifest: &GroupManifest) -> Result<(), String> {
+    let serialized = serde_json::to_string_pretty(manifest)
+        .map_err(|e| format!("Failed to serialize Group manifest: {}", e))?;
+    project::write_atomic(&group_path.join("group.json"), serialized.as_bytes())
+        .map_err(|e| format!("Failed to write Group manifest: {}", e))
+}
+
+/// Where a variant of a Group keeps its arrangement and render, relative to the project folder.
+/// The main arrangement (`None`) is the Group folder itself. Open arrangements are keyed by it.
+pub fn variant_key(name: &str, variant: Option<&str>) -> String {
+    match variant {
+        Some(variant) => format!("{}/{}/{}", name, VARIANTS_DIR, variant),
+        None => name.to_string(),
+    }
+}
+
+/// The Group name and variant of a key made by `variant_key`
+pub fn split_variant_key(key: &str) -> (&str, Option<&str>) {
+    match key.split_once(&format!("/{}/", VARIANTS_DIR)) {
+        Some((name, variant)) => (name, Some(variant)),
+        None => (key, None),
+    }
+}
+
+/// Keys of every Group variant reachable from `key` through `children`, however deep. Each is
+/// visited once, so a Group that ends up inside itself shows up in its own descendants instead of
+/// looping forever.
+pub fn descendants(key: &str, children: impl Fn(&str) -> Vec<String>) -> HashSet<String> {
+    let mut visited: HashSet<String> = HashSet::new();
+    let mut pending = children(key);
+    while let Some(name) = pending.pop() {
+        if visited.insert(name.clone()) {
+            pending.extend(children(&name));
+        }
+    }
+    visited
+}
+
+/// Whether `variant` can name a variant folder: a single plain path component, not hidden
+pub fn is_valid_variant_name(variant: &str) -> bool {
+    let mut components = Path::new(variant).components();
+    let single_normal = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+    single_normal
+        && variant.trim() == variant
+        && !variant.starts_with('.')
+        && !variant.contains(['/', '\\'])
+}
+
+/// Whether `name` can name a Group folder: valid as a variant name, and not one of the project's
+/// own folders
+pub fn is_valid_group_name(name: &str) -> bool {
+    is_valid_variant_name(name) && !is_reserved_name(name)
+}
+
+fn check_group_name(name: &str) -> Result<(), String> {
+    if is_reserved_name(name) {
+        return Err(format!("'{}' is reserved for the project's own files", name));
+    }
+    if !is_valid_group_name(name) {
+        return Err(format!("'{}' is not a valid Group name", name));
+    }
+    Ok(())
+}
+
+/// Add a variant to a Group, starting as a copy of the arrangement and render of `from`
+pub fn create_variant(group_path: &Path, variant: &str, from: Option<&str>) -> Result<(), String> {
+    if !is_valid_variant_name(variant) {
+        return Err(format!("'{}' is not a valid variant name", variant));
+    }
+    let name = group_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("Unknown");
+    let mut manifest = load_manifest(group_path).unwrap_or_else(|| GroupManifest::new(name));
+    if manifest.variants.iter().any(|v| v == variant) {
+        return Err(format!("Group '{}' already has a variant '{}'", name, variant));
+    }
+
+    let project_folder = group_path.parent().unwrap_or(Path::new("."));
+    let source = project_folder.join(variant_key(name, from));
+    let target = project_folder.join(variant_key(name, Some(variant)));
+    fs::create_dir_all(&target).map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+    for file in ["state.json", "render.wav"] {
+        if source.join(file).exists() {
+            fs::copy(source.join(file), target.join(file))
+                .map_err(|e| format!("Failed to copy {} into variant '{}': {}", file, variant, e))?;
+        }
+    }
+
+    manifest.variants.push(variant.to_string());
+    save_manifest(group_path, &manifest)
+}
+
+/// Path of the audio a Group's instances play
+pub fn render_path(group_path: &Path) -> PathBuf {
+    group_path.join("render.wav")
+}
+
+/// Move a Group folder into the project's trash. Returns where it went.
+pub fn move_to_trash(group_path: &Path) -> Result<PathBuf, String> {
+    let (Some(project_folder), Some(name)) = (group_path.parent(), group_path.file_name()) else {
+        return Err(format!("{} is not inside a project folder", group_path.display()));
+    };
+    if !is_group_folder(group_path) {
+        return Err(format!("{} is not a Group", group_path.display()));
+    }
+    let trash = project_folder.join(TRASH_DIR);
+    fs::create_dir_all(&trash).map_err(|e| format!("Failed to create trash folder: {}", e))?;
+
+    // The same Group can be deleted more than once, so each copy gets the time it was deleted
+    let deleted = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let trash_path = trash.join(format!("{}-{}", name.to_string_lossy(), deleted));
+    fs::rename(group_path, &trash_path)
+        .map_err(|e| format!("Failed to move {} to the trash: {}", group_path.display(), e))?;
+    Ok(trash_path)
+}
+
+/// Delete every Group in the project's trash for good. Called when the project is closed, as the
+/// deletions can't be undone after that.
+pub fn empty_trash(project_folder: &Path) {
+    let trash = project_folder.join(TRASH_DIR);
+    if !trash.exists() {
+        return;
+    }
+    match fs::remove_dir_all(&trash) {
+        Ok(()) => log::debug!(target: "groups", "Emptied the trash of {}", project_folder.display()),
+        Err(e) => log::warn!(target: "groups", "Failed to empty {}: {}", trash.display(), e),
+    }
+}
+
+/// Move a Group folder out of the trash to `group_path`
+pub fn restore_from_trash(trash_path: &Path, group_path: &Path) -> Result<(), String> {
+    if group_path.exists() {
+        return Err(format!("{} already exists", group_path.display()));
+    }
+    fs::rename(trash_path, group_path)
+        .map_err(|e| format!("Failed to restore {}: {}", group_path.display(), e))
+}
+
+/// Write audio to a Group's render file. Interleaved stereo is written as is, mono is doubled.
+fn write_render(render_path: &Path, audio_data: &[f32], sample_rate: u32) -> Result<(), String> {
+    use hound::{SampleFormat, WavSpec, WavWriter};
+    
+    // Create the necessary directories if they don't exist
+    if let Some(parent) = render_path.parent() {
+        if !parent.exists() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                return Err(format!("Failed to create Group directory: {}", e));
+            }
+        }
+    }
+    
+    // Create WAV spec
+    let spec = WavSpec {
+        channels: 2, // Stereo output
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    
+    // Write next to the render and swap it in at the end, so instances reading the old render
+    // never see a partial file
+    let tmp_path = render_path.with_extension("wav.tmp");
+    let writer = match WavWriter::create(&tmp_path, spec) {
+        Ok(writer) => writer,
+        Err(e) => return Err(format!("Failed to create WAV file: {}", e)),
+    };
+    
+    // Convert mono to stereo if necessary and write samples
+    let result = if audio_data.len() % 2 == 0 {
+        // Data is already stereo format
+        write_audio_to_wav(writer, audio_data)
+    } else {
+        // Data is mono, duplicate to stereo
+        let mut stereo_data = Vec::with_capacity(audio_data.len() * 2);
+        for sample in audio_data {
+            stereo_data.push(*sample); // Left channel
+            stereo_data.push(*sample); // Right channel
+        }
+        write_audio_to_wav(writer, &stereo_data)
+    };
+    
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(format!("Failed to write audio data: {}", e));
+    }
+    fs::rename(&tmp_path, render_path).map_err(|e| format!("Failed to replace {}: {}", render_path.display(), e))?;
+
+    Ok(())
+}
+
+/// Renders Groups into their render.wav on background threads. Edits are collected for a moment
+/// first, so dragging a clip inside a Group renders it once rather than on every frame.
+pub struct GroupRenderer {
+    edited: HashMap<String, Instant>, // Groups with edits that aren't rendered yet, and when the last one happened
+    running: HashSet<String>,
+    descendants: HashMap<String, HashSet<String>>, // Groups inside each Group, however deep, as of the last edit
+    sender: Sender<(String, Result<(), String>)>,
+    receiver: Receiver<(String, Result<(), String>)>,
+}
+
+impl Default for GroupRenderer {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            edited: HashMap::new(),
+            running: HashSet::new(),
+            descendants: HashMap::new(),
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl GroupRenderer {
+    /// Note that the Group's contents changed, so its render is out of date
+    pub fn invalidate(&mut self, name: &str) {
+        self.edited.insert(name.to_string(), Instant::now());
+        // Any edit can put Groups inside others
+        self.descendants.clear();
+    }
+
+    /// Whether the Group was edited since its render was last started
+    pub fn is_stale(&self, name: &str) -> bool {
+        self.edited.contains_key(name)
+    }
+
+    /// Drop pending edits of a Group that can't be rendered anymore
+    pub fn forget(&mut self, name: &str) {
+        self.edited.remove(name);
+        self.descendants.clear();
+    }
+
+    /// Groups inside a Group as last noted with `set_descendants`, unless something was edited since
+    pub fn descendants(&self, name: &str) -> Option<&HashSet<String>> {
+        self.descendants.get(name)
+    }
+
+    pub fn set_descendants(&mut self, name: &str, descendants: HashSet<String>) {
+        self.descendants.insert(name.to_string(), descendants);
+    }
+
+    /// Groups that are edited or being rendered
+    pub fn pending(&self) -> impl Iterator<Item = &str> {
+        self.edited.keys().chain(self.running.iter()).map(String::as_str)
     }
+
+    /// Groups whose edits have settled and that aren't being rendered already
+    pub fn due(&self) -> Vec<String> {
+        self.edited
+            .iter()
+            .filter(|(name, edited)| edited.elapsed() >= RENDER_DELAY && !self.running.contains(*name))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Mix the Group with `mix` on a background thread and write the result to its render file
+    pub fn start(
+        &mut self,
+        name: &str,
+        group_path: &Path,
+        sample_rate: u32,
+        mix: impl FnOnce() -> Vec<f32> + Send + 'static,
+    ) {
+        self.edited.remove(name);
+        self.running.insert(name.to_string());
+        let name = name.to_string();
+        let render_path = render_path(group_path);
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            let result = write_render(&render_path, &mix(), sample_rate);
+            let _ = sender.send((name, result));
+        });
+    }
+
+    /// Groups whose render finished since the last call
+    pub fn poll(&mut self) -> Vec<(String, Result<(), String>)> {
+        let finished: Vec<_> = self.receiver.try_iter().collect();
+        for (name, _) in &finished {
+            self.running.remove(name);
+        }
+        finished
+    }
+}
+
+/// Pool ids of the audio files used by the Group, stored in {group_name}/media.json.
+/// The files themselves live in the project's media pool.
+pub fn load_media(group_path: &Path) -> Vec<String> {
+    fs::read_to_string(group_path.join("media.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_media(group_path: &Path, pool_ids: &[String]) -> Result<(), String> {
+    let serialized = serde_json::to_string_pretty(pool_ids)
+        .map_err(|e| format!("Failed to serialize Group media: {}", e))?;
+    project::write_atomic(&group_path.join("media.json"), serialized.as_bytes())
+        .map_err(|e| format!("Failed to write Group media: {}", e))
 }
 
 /// Helper function to write audio data to a WAV file
@@ -233,4 +614,138 @@ fn generate_waveform(samples: &[f32], target_size: usize) -> Vec<f32> {
     }
     
     waveform
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_folders_with_a_manifest_or_saved_arrangement_are_groups() {
+        let project = std::env::temp_dir().join(format!("monlam-group-folders-{}", std::process::id()));
+        for folder in ["Drums", "Bass", "Legacy", "Empty", "backups", "media", ".trash"] {
+            fs::create_dir_all(project.join(folder)).unwrap();
+        }
+        save_manifest(&project.join("Drums"), &GroupManifest::new("Drums")).unwrap();
+        fs::write(project.join("Bass").join("state.json"), r#"{"version": 2, "tracks": []}"#).unwrap();
+        fs::write(project.join("Legacy").join("state.json"), r#"{"tracks": []}"#).unwrap();
+        // Even with a manifest, reserved and hidden folders are not Groups
+        save_manifest(&project.join("media"), &GroupManifest::new("media")).unwrap();
+        save_manifest(&project.join(".trash"), &GroupManifest::new(".trash")).unwrap();
+
+        let mut groups: Vec<String> = fs::read_dir(&project)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| is_group_folder(&e.path()))
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .collect();
+        groups.sort();
+        let _ = fs::remove_dir_all(&project);
+        assert_eq!(groups, vec!["Bass", "Drums"]);
+    }
+
+    #[test]
+    fn only_groups_go_to_the_trash() {
+        let project = std::env::temp_dir().join(format!("monlam-trash-{}", std::process::id()));
+        fs::create_dir_all(project.join("media")).unwrap();
+        fs::create_dir_all(project.join("Drums")).unwrap();
+        save_manifest(&project.join("Drums"), &GroupManifest::new("Drums")).unwrap();
+
+        let media = move_to_trash(&project.join("media"));
+        let drums = move_to_trash(&project.join("Drums"));
+        let media_kept = project.join("media").is_dir();
+        let _ = fs::remove_dir_all(&project);
+
+        assert!(media.is_err());
+        assert!(media_kept);
+        assert!(drums.unwrap().starts_with(project.join(TRASH_DIR)));
+    }
+
+    #[test]
+    fn variant_keys_round_trip() {
+        assert_eq!(variant_key("Drums", None), "Drums");
+        assert_eq!(variant_key("Drums", Some("Fill")), "Drums/variants/Fill");
+        assert_eq!(split_variant_key("Drums"), ("Drums", None));
+        assert_eq!(split_variant_key("Drums/variants/Fill"), ("Drums", Some("Fill")));
+        assert_eq!(split_variant_key(&variant_key("Two Words", Some("B side"))), ("Two Words", Some("B side")));
+    }
+
+    #[test]
+    fn names_are_single_plain_folders() {
+        for valid in ["Fill", "Verse 2", "élan", "a.b"] {
+            assert!(is_valid_variant_name(valid), "{}", valid);
+            assert!(is_valid_group_name(valid), "{}", valid);
+        }
+        for invalid in ["", " ", " Fill", "Fill ", ".hidden", "..", ".", "a/b", "a\\b", "/abs"] {
+            assert!(!is_valid_variant_name(invalid), "{:?}", invalid);
+            assert!(!is_valid_group_name(invalid), "{:?}", invalid);
+        }
+        // Reserved names are fine for variants, which live inside a Group
+        for reserved in ["media", "Backups", "waveforms", "audio"] {
+            assert!(is_valid_variant_name(reserved));
+            assert!(!is_valid_group_name(reserved), "{}", reserved);
+        }
+    }
+
+    #[test]
+    fn groups_cant_take_reserved_names() {
+        let project = std::env::temp_dir().join(format!("monlam-group-names-{}", std::process::id()));
+        fs::create_dir_all(&project).unwrap();
+
+        let reserved = Group::new("media", &project);
+        let hidden = Group::new(".hidden", &project);
+        let mut group = Group::new("Drums", &project).unwrap();
+        let renamed_reserved = group.rename("backups", &project);
+        let renamed = group.rename("Beat", &project);
+        let beat_exists = project.join("Beat").join("group.json").is_file();
+        let _ = fs::remove_dir_all(&project);
+
+        assert!(reserved.is_err());
+        assert!(hidden.is_err());
+        assert!(renamed_reserved.is_err());
+        renamed.unwrap();
+        assert!(beat_exists);
+        assert_eq!(group.manifest.name, "Beat");
+    }
+
+    // Children lookup for a made-up tree of Groups
+    fn tree<'a>(edges: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Vec<String> + 'a {
+        move |key| {
+            edges
+                .iter()
+                .filter(|(parent, _)| *parent == key)
+                .map(|(_, child)| child.to_string())
+                .collect()
+        }
+    }
+
+    fn sorted(set: HashSet<String>) -> Vec<String> {
+        let mut keys: Vec<String> = set.into_iter().collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn descendants_go_all_the_way_down() {
+        let edges = [("Song", "Drums"), ("Drums", "Kit"), ("Drums", "Kit/variants/Soft"), ("Song", "Bass")];
+        assert_eq!(sorted(descendants("Song", tree(&edges))), vec!["Bass", "Drums", "Kit", "Kit/variants/Soft"]);
+        assert_eq!(sorted(descendants("Kit", tree(&edges))), Vec::<String>::new());
+    }
+
+    #[test]
+    fn shared_groups_are_visited_once() {
+        let edges = [("A", "B"), ("A", "C"), ("B", "D"), ("C", "D")];
+        assert_eq!(sorted(descendants("A", tree(&edges))), vec!["B", "C", "D"]);
+    }
+
+    #[test]
+    fn cycles_end_and_are_detected() {
+        let edges = [("A", "B"), ("B", "C"), ("C", "A")];
+        let found = descendants("A", tree(&edges));
+        // A Group inside itself, which adding an instance is checked against
+        assert!(found.contains("A"));
+        assert_eq!(sorted(found), vec!["A", "B", "C"]);
+
+        let edges = [("A", "A")];
+        assert!(descendants("A", tree(&edges)).contains("A"));
+    }
+}
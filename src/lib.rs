@@ -1,4 +1,17 @@
 pub mod audio;
 pub mod config;
 pub mod daw;
+pub mod decode;
+pub mod export;
+pub mod flac;
 pub mod group;
+pub mod library;
+pub mod logging;
+pub mod loudness;
+pub mod media;
+pub mod media_pool;
+pub mod pcm_cache;
+pub mod peaks;
+pub mod project;
+pub mod recovery;
+pub mod stream;
@@ -0,0 +1,181 @@
+use crate::daw::TrackItemType;
+use crate::group::{self, Group};
+use crate::media_pool::MediaPool;
+use crate::project::{self, GroupFile, GroupLoad};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// User-wide Group library.
+//
+// Groups exported from a project are kept in ~/.monlam/library so any other project can import
+// them. Each entry is laid out like a small project: <entry>/<group name>/ is the Group folder and
+// <entry>/media/ is a media pool holding every audio file the Group uses. Paths in the Group's
+// state.json are relative to the entry, the same way they are relative to a project folder. Groups
+// inside the Group are not part of the entry; their instances are kept as clips of their render.
+
+pub fn folder() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".monlam").join("library")
+}
+
+/// Folder of the Group inside the library entry with this name
+pub fn group_path(name: &str) -> PathBuf {
+    folder().join(name).join(name)
+}
+
+/// Every Group in the library, sorted by name
+pub fn groups() -> Vec<Group> {
+    let mut groups: Vec<Group> = match fs::read_dir(folder()) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_str().map(str::to_string))
+            .filter(|name| !name.starts_with('.'))
+            .filter_map(|name| Group::load(&group_path(&name)).ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    groups.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+    groups
+}
+
+/// Whether a library Group matches a search. Every word has to be found in its name or one of its tags.
+pub fn matches(group: &Group, query: &str) -> bool {
+    query.split_whitespace().all(|word| {
+        let word = word.to_lowercase();
+        group.name.to_lowercase().contains(&word)
+            || group.manifest.tags.iter().any(|tag| tag.to_lowercase().contains(&word))
+    })
+}
+
+/// Copy a project's Group into the library with the audio it uses. An entry with the same name is replaced.
+pub fn export(project_folder: &Path, name: &str) -> Result<(), String> {
+    let entry = folder().join(name);
+    // Build the entry next to the old one and swap it in, so a failed export keeps the old entry
+    let staging = folder().join(format!(".{}.tmp", name));
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(|e| format!("Failed to clear {}: {}", staging.display(), e))?;
+    }
+
+    if let Err(e) = copy_group(project_folder, name, &staging, name) {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    if entry.exists() {
+        fs::remove_dir_all(&entry).map_err(|e| format!("Failed to replace {}: {}", entry.display(), e))?;
+    }
+    fs::rename(&staging, &entry).map_err(|e| format!("Failed to write {}: {}", entry.display(), e))?;
+    log::info!(target: "groups", "Exported Group '{}' to {}", name, entry.display());
+    Ok(())
+}
+
+/// Copy a library Group into a project with the audio it uses. Returns the name it got in the project,
+/// which differs from the library name when the project already has a Group called that.
+pub fn import(name: &str, project_folder: &Path) -> Result<String, String> {
+    let mut target_name = name.to_string();
+    let mut n = 2;
+    while project_folder.join(&target_name).exists() {
+        target_name = format!("{} {}", name, n);
+        n += 1;
+    }
+
+    if let Err(e) = copy_group(&folder().join(name), name, project_folder, &target_name) {
+        let _ = fs::remove_dir_all(project_folder.join(&target_name));
+        return Err(e);
+    }
+    log::info!(target: "groups", "Imported Group '{}' from the library as '{}'", name, target_name);
+    Ok(target_name)
+}
+
+// Copy the Group `name` of the project-like folder `from` to `to` as `to_name`. Its audio is imported
+// into the media pool of `to` and its arrangement is rewritten to point there.
+fn copy_group(from: &Path, name: &str, to: &Path, to_name: &str) -> Result<(), String> {
+    let source = from.join(name);
+    let target = to.join(to_name);
+    if !source.is_dir() {
+        return Err(format!("Group folder not found: {}", source.display()));
+    }
+    fs::create_dir_all(&target).map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+
+    let source_pool = MediaPool::open(from);
+    let mut pool = MediaPool::open(to);
+
+    // The files the Group was made from
+    let mut media = Vec::new();
+    for id in group::load_media(&source) {
+        let Some(path) = source_pool.path_of(&id) else {
+            continue;
+        };
+        media.push(pool.import(&path)?.id);
+    }
+    group::save_media(&target, &media)?;
+
+    // The main arrangement and every variant, each with its render
+    let mut manifest = group::load_manifest(&source).unwrap_or_else(|| group::GroupManifest::new(name));
+    copy_arrangement(from, &source, to, &target, &mut pool)?;
+    for variant in &manifest.variants {
+        let folder = Path::new(group::VARIANTS_DIR).join(variant);
+        copy_arrangement(from, &source.join(&folder), to, &target.join(&folder), &mut pool)?;
+    }
+
+    manifest.name = to_name.to_string();
+    group::save_manifest(&target, &manifest)
+}
+
+// Copy the arrangement and render of one Group folder, with every clip playing a copy in the target pool
+fn copy_arrangement(from: &Path, source: &Path, to: &Path, target: &Path, pool: &mut MediaPool) -> Result<(), String> {
+    fs::create_dir_all(target).map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+    match project::load_group(source)? {
+        GroupLoad::Loaded(mut group_file) => {
+            relocate_clips(&mut group_file, from, to, pool)?;
+            project::save_group(target, &group_file)?;
+        }
+        // Rebuilt from its render when it is opened
+        GroupLoad::Legacy => {
+            log::warn!(target: "groups", "Not copying the unversioned arrangement in {}", source.display());
+        }
+        GroupLoad::Missing => {}
+    }
+
+    let render = group::render_path(source);
+    if render.exists() {
+        fs::copy(&render, group::render_path(target))
+            .map_err(|e| format!("Failed to copy {}: {}", render.display(), e))?;
+    }
+    Ok(())
+}
+
+fn relocate_clips(group_file: &mut GroupFile, from: &Path, to: &Path, pool: &mut MediaPool) -> Result<(), String> {
+    // Freeze renders are a playback cache and are made again where the Group ends up
+    for track in &mut group_file.tracks {
+        track.frozen = None;
+    }
+    for clip in group_file.tracks.iter_mut().flat_map(|t| &mut t.clips) {
+        // Groups inside don't come along, their instances become clips of the inner Group's render
+        if clip.item_type == TrackItemType::Group {
+            let render = group::render_path(&from.join(group::variant_key(&clip.name, clip.variant.as_deref())));
+            clip.item_type = TrackItemType::Sample;
+            clip.variant = None;
+            clip.audio_file = render.exists().then_some(render);
+            if clip.audio_file.is_none() {
+                log::warn!(target: "groups", "Group '{}' has no render and its instance is left silent", clip.name);
+            }
+        }
+        let Some(audio_file) = clip.audio_file.take() else {
+            continue;
+        };
+        let source = project::resolve_project_path(audio_file, Some(from));
+        let entry = pool.import(&source)?;
+        let copy = pool
+            .path_of(&entry.id)
+            .ok_or_else(|| format!("{} is missing from the media pool", entry.file_name))?;
+        clip.audio_file = Some(project::to_project_relative(&copy, Some(to)));
+        clip.pool_id = Some(entry.id);
+        clip.audio_hash = clip.pool_id.clone();
+        clip.audio_size = Some(entry.size);
+        // Waveform caches belong to the folder they were made in and are rebuilt from the audio
+        clip.waveform_file = None;
+    }
+    Ok(())
+}
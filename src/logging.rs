@@ -0,0 +1,126 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+// Maximum number of log entries kept in memory for the log panel
+const MAX_ENTRIES: usize = 2000;
+
+/// Subsystems used as log targets throughout the app.
+/// Every log call passes one of these as `target:` so the log panel can filter on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Subsystem {
+    Audio,
+    Project,
+    Groups,
+    Ui,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 4] = [
+        Subsystem::Audio,
+        Subsystem::Project,
+        Subsystem::Groups,
+        Subsystem::Ui,
+    ];
+
+    pub fn target(&self) -> &'static str {
+        match self {
+            Subsystem::Audio => "audio",
+            Subsystem::Project => "project",
+            Subsystem::Groups => "groups",
+            Subsystem::Ui => "ui",
+        }
+    }
+
+    pub fn from_target(target: &str) -> Option<Subsystem> {
+        Subsystem::ALL.iter().copied().find(|s| s.target() == target)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: SystemTime,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+struct AppLogger {
+    entries: Mutex<VecDeque<LogEntry>>,
+}
+
+static LOGGER: OnceLock<AppLogger> = OnceLock::new();
+
+impl Log for AppLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = record.args().to_string();
+        eprintln!("[{:<5} {}] {}", record.level(), record.target(), message);
+
+        if let Ok(mut entries) = self.entries.lock() {
+            if entries.len() >= MAX_ENTRIES {
+                entries.pop_front();
+            }
+            entries.push_back(LogEntry {
+                time: SystemTime::now(),
+                level: record.level(),
+                target: record.target().to_string(),
+                message,
+            });
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the app logger. Safe to call more than once; only the first call has an effect.
+pub fn init(level: LevelFilter) {
+    let logger = LOGGER.get_or_init(|| AppLogger {
+        entries: Mutex::new(VecDeque::with_capacity(MAX_ENTRIES)),
+    });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+pub fn level() -> LevelFilter {
+    log::max_level()
+}
+
+pub fn set_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+// Log levels selectable at runtime, in order of verbosity
+pub const LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+/// Returns a copy of the buffered entries, oldest first.
+pub fn entries() -> Vec<LogEntry> {
+    LOGGER
+        .get()
+        .and_then(|logger| logger.entries.lock().ok().map(|e| e.iter().cloned().collect()))
+        .unwrap_or_default()
+}
+
+pub fn clear() {
+    if let Some(logger) = LOGGER.get() {
+        if let Ok(mut entries) = logger.entries.lock() {
+            entries.clear();
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use crate::audio::{decode_audio, AudioError};
+use std::f64::consts::PI;
+use std::path::Path;
+
+// Loudness measurement after ITU-R BS.1770-4 and EBU R 128.
+//
+// Each channel goes through the K-weighting filter (a high shelf for the head, then a high pass),
+// and loudness is the weighted mean square power over a window: 400 ms for momentary, 3 s for
+// short-term. Integrated loudness averages the 400 ms blocks that pass a -70 LUFS absolute gate
+// and a gate 10 LU below the mean of those. Loudness range (EBU Tech 3342) is the spread between
+// the 10th and 95th percentile of gated short-term values. True peak is the sample peak after
+// oversampling to at least 192 kHz, which catches the overs a DAC's reconstruction filter makes.
+
+const BLOCK_SECONDS: f64 = 0.4;
+const SHORT_TERM_SECONDS: f64 = 3.0;
+const STEP_SECONDS: f64 = 0.1; // Blocks overlap by 75%, giving values at 10 Hz
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+
+/// Loudness of a piece of audio. Values are LUFS (loudness), LU (range) and dBTP (peaks);
+/// silence gives negative infinity.
+#[derive(Debug, Clone)]
+pub struct Loudness {
+    pub integrated: f64,
+    pub momentary_max: f64,  // Loudest 400 ms window
+    pub short_term_max: f64, // Loudest 3 s window
+    pub range: f64,
+    pub true_peak: f64,
+    pub sample_peak: f64, // dBFS
+}
+
+impl Loudness {
+    /// The measurements as lines of text, for reports
+    pub fn summary(&self) -> String {
+        let value = |v: f64, unit: &str| {
+            if v.is_finite() {
+                format!("{:.1} {}", v, unit)
+            } else {
+                format!("-inf {}", unit)
+            }
+        };
+        format!(
+            "Integrated: {}\nShort-term max: {}\nMomentary max: {}\nLoudness range: {}\nTrue peak: {}\nSample peak: {}",
+            value(self.integrated, "LUFS"),
+            value(self.short_term_max, "LUFS"),
+            value(self.momentary_max, "LUFS"),
+            value(self.range, "LU"),
+            value(self.true_peak, "dBTP"),
+            value(self.sample_peak, "dBFS"),
+        )
+    }
+
+    /// Linear gain that brings the integrated loudness to `target` LUFS, lowered if needed so the
+    /// true peak stays at or under `ceiling` dBTP. Silent audio keeps unity gain.
+    pub fn normalization_gain(&self, target: f64, ceiling: f64) -> f64 {
+        if !self.integrated.is_finite() {
+            return 1.0;
+        }
+        let mut gain_db = target - self.integrated;
+        if self.true_peak.is_finite() {
+            gain_db = gain_db.min(ceiling - self.true_peak);
+        }
+        10f64.powf(gain_db / 20.0)
+    }
+}
+
+/// Measure interleaved samples
+pub fn analyze(samples: &[f32], channels: u16, sample_rate: u32) -> Loudness {
+    let channels = channels.max(1) as usize;
+    let frames = samples.len() / channels;
+    let rate = sample_rate.max(1) as f64;
+
+    // K-weighted power of each frame, summed over channels with their weights
+    let mut filters: Vec<KWeighting> = (0..channels).map(|_| KWeighting::new(rate)).collect();
+    let weights: Vec<f64> = (0..channels).map(|c| channel_weight(c, channels)).collect();
+    let mut power = Vec::with_capacity(frames);
+    for frame in samples[..frames * channels].chunks(channels) {
+        let mut sum = 0.0;
+        for (c, &sample) in frame.iter().enumerate() {
+            let weighted = filters[c].process(sample as f64);
+            sum += weights[c] * weighted * weighted;
+        }
+        power.push(sum);
+    }
+
+    // Running sums make every window an O(1) lookup
+    let mut cumulative = Vec::with_capacity(frames + 1);
+    cumulative.push(0.0);
+    let mut total = 0.0;
+    for p in &power {
+        total += p;
+        cumulative.push(total);
+    }
+    let window = |length: usize| -> Vec<f64> {
+        let step = ((STEP_SECONDS * rate) as usize).max(1);
+        if frames < length || length == 0 {
+            return Vec::new();
+        }
+        (0..=(frames - length) / step)
+            .map(|i| {
+                let start = i * step;
+                (cumulative[start + length] - cumulative[start]) / length as f64
+            })
+            .collect()
+    };
+    let block_power = window((BLOCK_SECONDS * rate) as usize);
+    let short_term_power = window((SHORT_TERM_SECONDS * rate) as usize);
+
+    let momentary: Vec<f64> = block_power.iter().map(|&p| loudness_of(p)).collect();
+    let short_term: Vec<f64> = short_term_power.iter().map(|&p| loudness_of(p)).collect();
+
+    let (sample_peak, true_peak) = peaks(samples, channels, sample_rate);
+
+    Loudness {
+        integrated: integrated(&block_power),
+        momentary_max: momentary.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        short_term_max: short_term.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        range: range(&short_term),
+        true_peak: to_db(true_peak),
+        sample_peak: to_db(sample_peak),
+    }
+}
+
+/// Measure an audio file
+pub fn analyze_file(path: &Path) -> Result<Loudness, AudioError> {
+    let channels = crate::audio::AudioReader::open(path)?.channels;
+    let decoded = decode_audio(path, &mut |_| true)?;
+    Ok(analyze(&decoded.samples, channels, decoded.sample_rate))
+}
+
+fn loudness_of(power: f64) -> f64 {
+    if power <= 0.0 {
+        f64::NEG_INFINITY
+    } else {
+        -0.691 + 10.0 * power.log10()
+    }
+}
+
+fn to_db(amplitude: f64) -> f64 {
+    if amplitude <= 0.0 {
+        f64::NEG_INFINITY
+    } else {
+        20.0 * amplitude.log10()
+    }
+}
+
+// Left, right and centre count fully, surrounds at +1.5 dB and the LFE not at all (5.1 order)
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    if channels < 6 {
+        return 1.0;
+    }
+    match channel {
+        3 => 0.0,
+        4 | 5 => 1.41,
+        _ => 1.0,
+    }
+}
+
+fn integrated(block_power: &[f64]) -> f64 {
+    let above: Vec<f64> = block_power
+        .iter()
+        .copied()
+        .filter(|&p| loudness_of(p) > ABSOLUTE_GATE)
+        .collect();
+    if above.is_empty() {
+        return f64::NEG_INFINITY;
+    }
+    let gate = loudness_of(above.iter().sum::<f64>() / above.len() as f64) + RELATIVE_GATE;
+    let gated: Vec<f64> = above.into_iter().filter(|&p| loudness_of(p) > gate).collect();
+    if gated.is_empty() {
+        return f64::NEG_INFINITY;
+    }
+    loudness_of(gated.iter().sum::<f64>() / gated.len() as f64)
+}
+
+fn range(short_term: &[f64]) -> f64 {
+    let mut above: Vec<f64> = short_term.iter().copied().filter(|&l| l > ABSOLUTE_GATE).collect();
+    if above.is_empty() {
+        return 0.0;
+    }
+    // The relative gate works on power, not on the loudness values themselves
+    let mean_power = above.iter().map(|&l| 10f64.powf((l + 0.691) / 10.0)).sum::<f64>() / above.len() as f64;
+    let gate = loudness_of(mean_power) + RANGE_RELATIVE_GATE;
+    above.retain(|&l| l > gate);
+    if above.len() < 2 {
+        return 0.0;
+    }
+    above.sort_by(|a, b| a.total_cmp(b));
+    let percentile = |p: f64| above[((above.len() - 1) as f64 * p).round() as usize];
+    percentile(0.95) - percentile(0.10)
+}
+
+/// The K-weighting filter of one channel: two biquads, with coefficients derived for any rate
+struct KWeighting {
+    stages: [Biquad; 2],
+}
+
+impl KWeighting {
+    fn new(rate: f64) -> Self {
+        // High shelf modelling the head, +4 dB above about 1.7 kHz
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        // High pass at about 38 Hz (the "RLB" curve)
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        Self {
+            stages: [shelf, high_pass],
+        }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        let shelved = self.stages[0].process(sample);
+        self.stages[1].process(shelved)
+    }
+}
+
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2], // a1 and a2, a0 is normalized to 1
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, state: [0.0; 2] }
+    }
+
+    // Transposed direct form II
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+// Sample peak and true peak, as linear amplitudes
+fn peaks(samples: &[f32], channels: usize, sample_rate: u32) -> (f64, f64) {
+    const TAPS: usize = 8; // Interpolation filter half-width in input samples
+
+    let sample_peak = samples.iter().fold(0.0f64, |peak, &s| peak.max(s.abs() as f64));
+    let factor = match sample_rate {
+        0..=95_999 => 4,
+        96_000..=191_999 => 2,
+        _ => 1,
+    };
+    if factor == 1 {
+        return (sample_peak, sample_peak);
+    }
+
+    // One Hann windowed sinc kernel per in-between position
+    let kernels: Vec<Vec<f64>> = (1..factor)
+        .map(|phase| {
+            let offset = phase as f64 / factor as f64;
+            (0..2 * TAPS)
+                .map(|i| {
+                    let x = i as f64 - (TAPS as f64 - 1.0) - offset;
+                    let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                    let window = 0.5 + 0.5 * (PI * x / TAPS as f64).cos();
+                    sinc * window
+                })
+                .collect()
+        })
+        .collect();
+
+    let frames = samples.len() / channels;
+    let mut true_peak = sample_peak;
+    for c in 0..channels {
+        let at = |frame: isize| -> f64 {
+            if frame < 0 || frame as usize >= frames {
+                0.0
+            } else {
+                samples[frame as usize * channels + c] as f64
+            }
+        };
+        for frame in 0..frames as isize {
+            for kernel in &kernels {
+                let mut value = 0.0;
+                for (i, weight) in kernel.iter().enumerate() {
+                    value += weight * at(frame - (TAPS as isize - 1) + i as isize);
+                }
+                true_peak = true_peak.max(value.abs());
+            }
+        }
+    }
+    (sample_peak, true_peak)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // EBU Tech 3341 test 1: a stereo 1 kHz sine at -23 dBFS reads -23 LUFS
+    #[test]
+    fn reference_tone() {
+        let sample_rate = 48000;
+        let amplitude = 10f32.powf(-23.0 / 20.0);
+        let samples: Vec<f32> = (0..sample_rate as usize * 20)
+            .flat_map(|frame| {
+                let value = amplitude * (2.0 * std::f32::consts::PI * 1000.0 * frame as f32 / sample_rate as f32).sin();
+                [value, value]
+            })
+            .collect();
+
+        let loudness = analyze(&samples, 2, sample_rate);
+        assert!((loudness.integrated + 23.0).abs() <= 0.1, "integrated {}", loudness.integrated);
+        assert!((loudness.momentary_max + 23.0).abs() <= 0.1, "momentary {}", loudness.momentary_max);
+        assert!((loudness.short_term_max + 23.0).abs() <= 0.1, "short-term {}", loudness.short_term_max);
+        assert!(loudness.range.abs() <= 0.1, "range {}", loudness.range);
+    }
+
+    #[test]
+    fn silence() {
+        let loudness = analyze(&[0.0; 48000 * 2], 2, 48000);
+        assert_eq!(loudness.integrated, f64::NEG_INFINITY);
+        assert_eq!(loudness.normalization_gain(-23.0, -1.0), 1.0);
+    }
+}
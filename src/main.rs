@@ -1,13 +1,29 @@
 mod audio;
 mod config;
 mod daw;
+mod decode;
+mod export;
+mod flac;
 mod ui;
 mod group;
+mod library;
+mod logging;
+mod loudness;
+mod media;
+mod media_pool;
+mod pcm_cache;
+mod peaks;
+mod project;
+mod recovery;
+mod stream;
 
 use daw::DawApp;
 use eframe::egui;
 
 fn main() -> eframe::Result<()> {
+    // Start logging before anything else so early messages are captured
+    logging::init(DawApp::load_log_level().unwrap_or(log::LevelFilter::Info));
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([1200.0, 800.0]),
         ..Default::default()
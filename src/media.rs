@@ -0,0 +1,202 @@
+use crate::daw::DawState;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// An audio file referenced by the project that is no longer where the project expects it
+#[derive(Debug, Clone)]
+pub struct MissingMedia {
+    pub path: PathBuf,
+    pub size: Option<u64>,     // Size recorded the last time the file was seen
+    pub hash: Option<String>,  // Content hash recorded the last time the file was seen
+    pub clip_count: usize,     // Number of clips using this file
+}
+
+impl MissingMedia {
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+}
+
+pub fn file_size(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|m| m.len())
+}
+
+/// BLAKE3 hash of the file contents as a hex string
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0u8; 1 << 16];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Collect the audio files referenced by the project that don't exist on disk, one entry per path
+pub fn find_missing(state: &DawState) -> Vec<MissingMedia> {
+    let mut missing: Vec<MissingMedia> = Vec::new();
+
+    for track in &state.tracks {
+        for sample in &track.samples {
+            let Some(path) = &sample.audio_file else {
+                continue;
+            };
+            if path.exists() {
+                continue;
+            }
+            match missing.iter_mut().find(|m| &m.path == path) {
+                Some(entry) => {
+                    entry.clip_count += 1;
+                    entry.size = entry.size.or(sample.audio_size);
+                    entry.hash = entry.hash.clone().or_else(|| sample.audio_hash.clone());
+                }
+                None => missing.push(MissingMedia {
+                    path: path.clone(),
+                    size: sample.audio_size,
+                    hash: sample.audio_hash.clone(),
+                    clip_count: 1,
+                }),
+            }
+        }
+    }
+
+    missing
+}
+
+// Every file under `folder`, skipping hidden entries
+fn walk_files(folder: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            walk_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn matches_content(candidate: &Path, missing: &MissingMedia) -> bool {
+    if let Some(size) = missing.size {
+        if file_size(candidate) != Some(size) {
+            return false;
+        }
+    }
+    if let Some(hash) = &missing.hash {
+        return hash_file(candidate).is_ok_and(|h| &h == hash);
+    }
+    true
+}
+
+/// Search `folders` for replacements of the missing files.
+///
+/// A file with the same name wins if its size and hash match what was recorded.
+/// Files that were renamed are found by size and then hash; that only works for
+/// files whose hash was recorded. Gives up, returning what it found so far, once
+/// `cancelled` is set.
+pub fn search(
+    missing: &[MissingMedia],
+    folders: &[PathBuf],
+    cancelled: &AtomicBool,
+) -> HashMap<PathBuf, PathBuf> {
+    let mut files = Vec::new();
+    for folder in folders {
+        walk_files(folder, &mut files);
+    }
+
+    let mut found = HashMap::new();
+    for entry in missing {
+        if cancelled.load(Ordering::Relaxed) {
+            log::info!(target: "project", "Search for missing files cancelled");
+            break;
+        }
+        let name = entry.file_name().to_lowercase();
+
+        // Same file name first
+        let by_name = files.iter().find(|candidate| {
+            candidate
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().to_lowercase() == name)
+                && matches_content(candidate, entry)
+        });
+
+        // Then any file with the same size and content
+        let by_content = || {
+            entry.hash.as_ref()?;
+            files
+                .iter()
+                .filter(|candidate| entry.size.is_none() || file_size(candidate) == entry.size)
+                .find(|candidate| matches_content(candidate, entry))
+        };
+
+        if let Some(replacement) = by_name.or_else(by_content) {
+            log::info!(target: "project",
+                "Found {} at {}",
+                entry.path.display(),
+                replacement.display()
+            );
+            found.insert(entry.path.clone(), replacement.clone());
+        }
+    }
+
+    found
+}
+
+/// A search running on its own thread, owned by whoever started it.
+/// Clones share the same search.
+#[derive(Clone)]
+pub struct MediaSearch {
+    found: Arc<Mutex<Option<HashMap<PathBuf, PathBuf>>>>, // Set once the search has finished
+    cancelled: Arc<AtomicBool>,
+}
+
+impl MediaSearch {
+    /// Run `search` on a background thread, as hashing candidates can take a while
+    pub fn start(missing: Vec<MissingMedia>, folders: Vec<PathBuf>) -> Result<Self, String> {
+        let handle = MediaSearch {
+            found: Arc::new(Mutex::new(None)),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        let found = handle.found.clone();
+        let cancelled = handle.cancelled.clone();
+        thread::Builder::new()
+            .name("media-search".to_string())
+            .spawn(move || {
+                let result = search(&missing, &folders, &cancelled);
+                *found.lock().unwrap() = Some(result);
+            })
+            .map_err(|e| format!("Failed to start searching for missing files: {}", e))?;
+        Ok(handle)
+    }
+
+    /// Replacements found, once the search has finished
+    pub fn results(&self) -> Option<HashMap<PathBuf, PathBuf>> {
+        self.found.lock().unwrap().clone()
+    }
+
+    /// Stop the search early; nobody is waiting for its results any more
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::audio::probe_audio;
+use crate::media;
+use crate::project;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Project media pool.
+//
+// Every audio file a project uses is copied once into <project>/media/ and named after its
+// content hash, so identical files share one copy no matter how many clips or Groups use them.
+// Files that "Collect All and Save" copied into <project>/audio/ keep their readable names and are
+// indexed where they are. media/index.json keeps what we know about each file beyond its contents.
+
+const POOL_DIR: &str = "media";
+/// Folder of the project that "Collect All and Save" copies external audio into
+pub const COLLECT_DIR: &str = "audio";
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PoolEntry {
+    pub id: String,                   // BLAKE3 hash of the file contents
+    pub file_name: String,            // Name of the copy inside media/
+    #[serde(default)]
+    pub location: Option<PathBuf>,    // Project-relative path of a file indexed outside media/
+    pub original_name: String,        // File name it was imported under
+    pub source_path: Option<PathBuf>, // Where it was imported from
+    pub format: String,               // Codec reported by the decoder, e.g. "pcm_s16le" or "mp3"
+    pub channels: Option<u16>,
+    pub sample_rate: Option<u32>,
+    #[serde(default)]
+    pub bit_depth: Option<u32>,
+    #[serde(default)]
+    pub duration: Option<f64>, // Seconds
+    #[serde(default)]
+    pub tags: Vec<(String, String)>,
+    pub size: u64,
+    pub imported: u64, // Unix time in seconds
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PoolIndex {
+    entries: Vec<PoolEntry>,
+}
+
+pub struct MediaPool {
+    folder: PathBuf,
+    index: PoolIndex,
+}
+
+impl MediaPool {
+    /// Folder holding the pool of the project in `project_folder`
+    pub fn folder_for(project_folder: &Path) -> PathBuf {
+        project_folder.join(POOL_DIR)
+    }
+
+    /// Open the pool of the project in `project_folder`. A missing or unreadable index gives an empty pool.
+    pub fn open(project_folder: &Path) -> Self {
+        let folder = Self::folder_for(project_folder);
+        let index = match fs::read_to_string(folder.join(INDEX_FILE)) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                log::error!(target: "project", "Failed to parse media index: {}", e);
+                PoolIndex::default()
+            }),
+            Err(_) => PoolIndex::default(),
+        };
+        Self { folder, index }
+    }
+
+    pub fn entry(&self, id: &str) -> Option<&PoolEntry> {
+        self.index.entries.iter().find(|e| e.id == id)
+    }
+
+    pub fn path_of(&self, id: &str) -> Option<PathBuf> {
+        self.entry(id).map(|e| match &e.location {
+            Some(location) => self.project_folder().join(location),
+            None => self.folder.join(&e.file_name),
+        })
+    }
+
+    fn project_folder(&self) -> &Path {
+        self.folder.parent().unwrap_or(Path::new(""))
+    }
+
+    /// Copy `source` into the pool unless a file with the same contents is already there.
+    /// Files collected into the project's audio folder are indexed without a copy.
+    /// Returns the entry for the file.
+    pub fn import(&mut self, source: &Path) -> Result<PoolEntry, String> {
+        let id = media::hash_file(source)
+            .map_err(|e| format!("Failed to read {}: {}", source.display(), e))?;
+
+        if let Some(entry) = self.entry(&id) {
+            if self.path_of(&id).is_some_and(|path| path.exists()) {
+                log::debug!(target: "project", "{} is already in the media pool", source.display());
+                return Ok(entry.clone());
+            }
+        }
+
+        fs::create_dir_all(&self.folder)
+            .map_err(|e| format!("Failed to create media folder: {}", e))?;
+
+        let collected = source.starts_with(self.project_folder().join(COLLECT_DIR));
+        let (file_name, location, target) = if collected {
+            let file_name = source
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| id.clone());
+            let location = project::to_project_relative(source, Some(self.project_folder()));
+            (file_name, Some(location), source.to_path_buf())
+        } else {
+            let file_name = match source.extension().and_then(|e| e.to_str()) {
+                Some(ext) => format!("{}.{}", id, ext.to_lowercase()),
+                None => id.clone(),
+            };
+            let target = self.folder.join(&file_name);
+            (file_name, None, target)
+        };
+        if source != target {
+            // Copy next to the target first so a failed copy never leaves a partial file under the final name
+            let tmp = self.folder.join(format!("{}.tmp", file_name));
+            fs::copy(source, &tmp)
+                .and_then(|_| fs::rename(&tmp, &target))
+                .map_err(|e| {
+                    let _ = fs::remove_file(&tmp);
+                    format!("Failed to copy {} into the media pool: {}", source.display(), e)
+                })?;
+        }
+        let info = probe_audio(source).ok();
+        let entry = PoolEntry {
+            id: id.clone(),
+            file_name,
+            location,
+            original_name: source
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| id.clone()),
+            source_path: Some(source.to_path_buf()),
+            format: info.as_ref().map(|i| i.codec.clone()).unwrap_or_default(),
+            channels: info.as_ref().and_then(|i| i.channels),
+            sample_rate: info.as_ref().and_then(|i| i.sample_rate),
+            bit_depth: info.as_ref().and_then(|i| i.bit_depth),
+            duration: info.as_ref().and_then(|i| i.duration),
+            tags: info.map(|i| i.tags).unwrap_or_default(),
+            size: media::file_size(&target).unwrap_or(0),
+            imported: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        };
+
+        self.index.entries.retain(|e| e.id != id);
+        self.index.entries.push(entry.clone());
+        self.save()?;
+
+        log::info!(target: "project",
+            "Imported {} into the media pool as {}",
+            source.display(),
+            entry.file_name
+        );
+        Ok(entry)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let serialized = serde_json::to_string_pretty(&self.index)
+            .map_err(|e| format!("Failed to serialize media index: {}", e))?;
+        project::write_atomic(&self.folder.join(INDEX_FILE), serialized.as_bytes())
+            .map_err(|e| format!("Failed to write media index: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("monlam-pool-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn write_wav(path: &Path, samples: &[i16]) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for &sample in samples {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn identical_files_share_one_copy() {
+        let project = project_folder("dedup");
+        let sources = project_folder("dedup-sources");
+        write_wav(&sources.join("kick.wav"), &[0, 1000, -1000, 0]);
+        fs::copy(sources.join("kick.wav"), sources.join("kick copy.wav")).unwrap();
+        write_wav(&sources.join("snare.wav"), &[0, 2000, -2000, 0]);
+
+        let mut pool = MediaPool::open(&project);
+        let kick = pool.import(&sources.join("kick.wav")).unwrap();
+        let copy = pool.import(&sources.join("kick copy.wav")).unwrap();
+        let snare = pool.import(&sources.join("snare.wav")).unwrap();
+
+        assert_eq!(kick.id, copy.id);
+        assert_ne!(kick.id, snare.id);
+        assert_eq!(kick.original_name, "kick.wav");
+        assert_eq!(kick.channels, Some(1));
+        let path = pool.path_of(&kick.id).unwrap();
+        assert_eq!(path, project.join(POOL_DIR).join(format!("{}.wav", kick.id)));
+        assert_eq!(fs::read(&path).unwrap(), fs::read(sources.join("kick.wav")).unwrap());
+
+        // The index survives reopening the pool
+        let reopened = MediaPool::open(&project);
+        assert_eq!(reopened.path_of(&snare.id), pool.path_of(&snare.id));
+        let files = fs::read_dir(project.join(POOL_DIR)).unwrap().count();
+        assert_eq!(files, 3); // Two copies and the index
+
+        let _ = fs::remove_dir_all(&project);
+        let _ = fs::remove_dir_all(&sources);
+    }
+
+    #[test]
+    fn collected_files_are_indexed_in_place() {
+        let project = project_folder("collected");
+        fs::create_dir_all(project.join(COLLECT_DIR)).unwrap();
+        let collected = project.join(COLLECT_DIR).join("bass.wav");
+        write_wav(&collected, &[0, 500, -500, 0]);
+
+        let mut pool = MediaPool::open(&project);
+        let entry = pool.import(&collected).unwrap();
+
+        assert_eq!(entry.location, Some(PathBuf::from("audio/bass.wav")));
+        assert_eq!(pool.path_of(&entry.id), Some(collected));
+        assert!(!project.join(POOL_DIR).join(format!("{}.wav", entry.id)).exists());
+
+        let _ = fs::remove_dir_all(&project);
+    }
+}
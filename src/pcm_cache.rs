@@ -0,0 +1,258 @@
+use memmap2::Mmap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+// Decoded PCM cache.
+//
+// Decoding MP3, AAC or FLAC takes a while, so audio decoded into memory is also written to
+// ~/.monlam/pcm, already resampled to the output rate. Files are keyed by the content hash of the
+// source and that rate, so they are shared between projects. Reopening a project maps these
+// files instead of decoding again. The cache stays under the size limit set in the preferences
+// by dropping the least recently used files first.
+//
+// File layout, little endian:
+//   "MPC1", channels: u16, sample_rate: u32, frames: u64, zero padding up to HEADER_LEN
+//   then frames * channels f32 samples, interleaved
+
+const MAGIC: &[u8; 4] = b"MPC1";
+const HEADER_LEN: usize = 32; // Keeps the mapped samples aligned
+pub const DEFAULT_LIMIT_MB: u64 = 4096;
+
+static LIMIT_MB: AtomicU64 = AtomicU64::new(DEFAULT_LIMIT_MB);
+
+/// Interleaved samples, either decoded into memory or mapped from a cache file
+pub enum Pcm {
+    Owned(Vec<f32>),
+    Mapped(Mmap),
+}
+
+impl Default for Pcm {
+    fn default() -> Self {
+        Pcm::Owned(Vec::new())
+    }
+}
+
+impl From<Vec<f32>> for Pcm {
+    fn from(samples: Vec<f32>) -> Self {
+        Pcm::Owned(samples)
+    }
+}
+
+impl Deref for Pcm {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        match self {
+            Pcm::Owned(samples) => samples,
+            Pcm::Mapped(map) => {
+                let bytes = &map[HEADER_LEN..];
+                // SAFETY: `read` only maps files whose sample data has the expected length, the
+                // map is page aligned so the data after the header is aligned for f32, and only
+                // little endian targets map files at all.
+                unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const f32, bytes.len() / 4) }
+            }
+        }
+    }
+}
+
+impl Pcm {
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, Pcm::Mapped(_))
+    }
+}
+
+pub struct CachedPcm {
+    pub samples: Pcm,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+fn cache_dir() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".monlam").join("pcm")
+}
+
+fn cache_path(hash: &str, sample_rate: u32) -> PathBuf {
+    cache_dir().join(format!("{}-{}.pcm", hash, sample_rate))
+}
+
+/// Size limit in megabytes. 0 turns the cache off.
+pub fn limit_mb() -> u64 {
+    LIMIT_MB.load(Ordering::Relaxed)
+}
+
+pub fn set_limit_mb(limit: u64) {
+    LIMIT_MB.store(limit, Ordering::Relaxed);
+    evict();
+}
+
+/// Map the cached audio for a source file at `sample_rate`, if there is any
+pub fn read(hash: &str, sample_rate: u32) -> Option<CachedPcm> {
+    if cfg!(target_endian = "big") || limit_mb() == 0 {
+        return None;
+    }
+    let path = cache_path(hash, sample_rate);
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .or_else(|_| File::open(&path))
+        .ok()?;
+    // SAFETY: cache files are only ever replaced by renaming a new file over them, never
+    // written in place, so the mapped contents can't change underneath us.
+    let map = unsafe { Mmap::map(&file) }.ok()?;
+
+    let header = map.get(..HEADER_LEN)?;
+    if &header[..4] != MAGIC {
+        log::warn!(target: "audio", "Ignoring invalid PCM cache file {}", path.display());
+        return None;
+    }
+    let channels = u16::from_le_bytes(header[4..6].try_into().unwrap());
+    let rate = u32::from_le_bytes(header[6..10].try_into().unwrap());
+    let frames = u64::from_le_bytes(header[10..18].try_into().unwrap());
+    let expected = frames
+        .checked_mul(channels as u64 * 4)
+        .and_then(|length| length.checked_add(HEADER_LEN as u64));
+    if channels == 0 || rate != sample_rate || expected != Some(map.len() as u64) {
+        log::warn!(target: "audio", "Ignoring invalid PCM cache file {}", path.display());
+        return None;
+    }
+
+    // The modification time doubles as the last use, for eviction
+    let _ = file.set_modified(SystemTime::now());
+    log::debug!(target: "audio", "Mapped {} from the PCM cache", path.display());
+    Some(CachedPcm {
+        samples: Pcm::Mapped(map),
+        channels,
+        sample_rate,
+    })
+}
+
+/// Store decoded audio, then trim the cache back under its limit
+pub fn write(hash: &str, sample_rate: u32, channels: u16, samples: &[f32]) -> io::Result<()> {
+    let channels = channels.max(1);
+    let length = (HEADER_LEN + samples.len() * 4) as u64;
+    if length > limit_mb() * 1024 * 1024 {
+        return Ok(());
+    }
+
+    let path = cache_path(hash, sample_rate);
+    fs::create_dir_all(cache_dir())?;
+    // Written next to the target and renamed, so a mapped file is never changed in place
+    let tmp = path.with_extension("pcm.tmp");
+    let result = (|| {
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        let mut header = [0u8; HEADER_LEN];
+        header[..4].copy_from_slice(MAGIC);
+        header[4..6].copy_from_slice(&channels.to_le_bytes());
+        header[6..10].copy_from_slice(&sample_rate.to_le_bytes());
+        header[10..18].copy_from_slice(&((samples.len() / channels as usize) as u64).to_le_bytes());
+        writer.write_all(&header)?;
+        for sample in &samples[..samples.len() / channels as usize * channels as usize] {
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result?;
+
+    log::debug!(target: "audio", "Wrote {} to the PCM cache", path.display());
+    evict();
+    Ok(())
+}
+
+fn entries() -> Vec<(PathBuf, u64, SystemTime)> {
+    let Ok(dir) = fs::read_dir(cache_dir()) else {
+        return Vec::new();
+    };
+    dir.filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "pcm"))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            Some((entry.path(), metadata.len(), modified))
+        })
+        .collect()
+}
+
+/// Bytes used by the cache
+pub fn usage() -> u64 {
+    entries().iter().map(|(_, size, _)| size).sum()
+}
+
+/// Remove every cache file
+pub fn clear() {
+    for (path, _, _) in entries() {
+        if let Err(e) = fs::remove_file(&path) {
+            log::warn!(target: "audio", "Failed to remove {}: {}", path.display(), e);
+        }
+    }
+    log::info!(target: "audio", "Cleared the PCM cache");
+}
+
+// Drop the least recently used files until the cache fits its limit
+fn evict() {
+    for path in to_evict(entries(), limit_mb() * 1024 * 1024) {
+        match fs::remove_file(&path) {
+            Ok(()) => log::debug!(target: "audio", "Evicted {} from the PCM cache", path.display()),
+            Err(e) => log::warn!(target: "audio", "Failed to evict {}: {}", path.display(), e),
+        }
+    }
+}
+
+// The least recently used of `entries` that have to go for the rest to fit in `limit` bytes
+fn to_evict(mut entries: Vec<(PathBuf, u64, SystemTime)>, limit: u64) -> Vec<PathBuf> {
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    entries.sort_by_key(|(_, _, modified)| *modified);
+    let mut evicted = Vec::new();
+    for (path, size, _) in entries {
+        if total <= limit {
+            break;
+        }
+        total -= size;
+        evicted.push(path);
+    }
+    evicted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn entry(name: &str, size: u64, age_seconds: u64) -> (PathBuf, u64, SystemTime) {
+        (PathBuf::from(name), size, SystemTime::now() - Duration::from_secs(age_seconds))
+    }
+
+    #[test]
+    fn keeps_everything_under_the_limit() {
+        let entries = vec![entry("a", 10, 5), entry("b", 20, 1)];
+        assert!(to_evict(entries, 30).is_empty());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let entries = vec![
+            entry("recent", 40, 1),
+            entry("oldest", 10, 300),
+            entry("old", 30, 200),
+            entry("middle", 20, 100),
+        ];
+        // 100 bytes in use, 60 allowed: the two oldest files make room
+        assert_eq!(to_evict(entries, 60), vec![PathBuf::from("oldest"), PathBuf::from("old")]);
+    }
+
+    #[test]
+    fn a_zero_limit_empties_the_cache() {
+        let entries = vec![entry("a", 10, 2), entry("b", 20, 1)];
+        assert_eq!(to_evict(entries, 0), vec![PathBuf::from("a"), PathBuf::from("b")]);
+    }
+}
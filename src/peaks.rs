@@ -0,0 +1,451 @@
+use crate::audio::{AudioError, AudioReader};
+use crate::decode::DecodedAudio;
+use crate::project;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+// Peak cache.
+//
+// For every audio file we keep min/max pairs per channel at several block sizes, so the grid can
+// draw a waveform at any zoom level without touching the audio. Files are keyed by the content
+// hash of the audio and live in ~/.monlam/peaks, so they are shared between projects.
+//
+// File layout, little endian:
+//   "MPK1", channels: u16, sample_rate: u32, frames: u64, level count: u32
+//   per level: block_size: u32, block count: u64,
+//              then for each block, for each channel: min: i16, max: i16
+
+const MAGIC: &[u8; 4] = b"MPK1";
+const BLOCK_SIZES: [u32; 6] = [64, 256, 1024, 4096, 16384, 65536];
+
+/// Min/max pairs for one block size, interleaved by channel
+pub struct PeakLevel {
+    pub block_size: u32,
+    data: Vec<(i16, i16)>,
+}
+
+pub struct Peaks {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub frames: u64,
+    levels: Vec<PeakLevel>,
+}
+
+fn quantize(value: f32) -> i16 {
+    (value.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// Builds peaks from interleaved samples fed in any number of pieces, for files read packet by packet
+pub struct PeakBuilder {
+    channels: usize,
+    frames: usize,
+    block: Vec<(f32, f32)>, // Min and max per channel of the block being filled
+    data: Vec<(i16, i16)>,  // Finished blocks of the finest level
+}
+
+impl PeakBuilder {
+    pub fn new(channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            channels,
+            frames: 0,
+            block: vec![(f32::MAX, f32::MIN); channels],
+            data: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        let base = BLOCK_SIZES[0] as usize;
+        for frame in samples.chunks(self.channels) {
+            for (channel, &s) in frame.iter().enumerate() {
+                let (min, max) = &mut self.block[channel];
+                *min = min.min(s);
+                *max = max.max(s);
+            }
+            self.frames += 1;
+            if self.frames.is_multiple_of(base) {
+                self.finish_block();
+            }
+        }
+    }
+
+    fn finish_block(&mut self) {
+        for (min, max) in &mut self.block {
+            self.data.push((quantize(*min), quantize(*max)));
+            *min = f32::MAX;
+            *max = f32::MIN;
+        }
+    }
+
+    /// All levels, the coarser ones merged from the finest
+    pub fn finish(mut self, sample_rate: u32) -> Peaks {
+        if !self.frames.is_multiple_of(BLOCK_SIZES[0] as usize) {
+            self.finish_block();
+        }
+        let channel_count = self.channels;
+        let mut levels: Vec<PeakLevel> = Vec::with_capacity(BLOCK_SIZES.len());
+        levels.push(PeakLevel {
+            block_size: BLOCK_SIZES[0],
+            data: self.data,
+        });
+
+        for &block_size in &BLOCK_SIZES[1..] {
+            let previous = levels.last().unwrap();
+            let factor = (block_size / previous.block_size) as usize;
+            let mut data = Vec::with_capacity(previous.data.len() / factor + channel_count);
+            for group in previous.data.chunks(factor * channel_count) {
+                for channel in 0..channel_count {
+                    let merged = group
+                        .iter()
+                        .skip(channel)
+                        .step_by(channel_count)
+                        .fold((i16::MAX, i16::MIN), |(min, max), &(lo, hi)| (min.min(lo), max.max(hi)));
+                    data.push(merged);
+                }
+            }
+            levels.push(PeakLevel { block_size, data });
+        }
+
+        Peaks {
+            channels: channel_count as u16,
+            sample_rate,
+            frames: self.frames as u64,
+            levels,
+        }
+    }
+}
+
+impl Peaks {
+    /// Build all levels from interleaved samples
+    pub fn build(samples: &[f32], channels: u16, sample_rate: u32) -> Self {
+        let mut builder = PeakBuilder::new(channels);
+        builder.push(samples);
+        builder.finish(sample_rate)
+    }
+
+    /// Coarsest level whose blocks are no larger than `frames_per_pixel`
+    pub fn level_for(&self, frames_per_pixel: f32) -> &PeakLevel {
+        self.levels
+            .iter()
+            .rev()
+            .find(|level| level.block_size as f32 <= frames_per_pixel)
+            .unwrap_or(&self.levels[0])
+    }
+
+    /// Min and max over all channels for frames `start..end`, in -1.0..=1.0
+    pub fn range(&self, level: &PeakLevel, start: u64, end: u64) -> Option<(f32, f32)> {
+        let channels = self.channels as usize;
+        let blocks = level.data.len() / channels;
+        let first = (start / level.block_size as u64) as usize;
+        let last = ((end.max(start + 1) - 1) / level.block_size as u64) as usize;
+        if first >= blocks {
+            return None;
+        }
+
+        let (min, max) = level.data[first * channels..(last.min(blocks - 1) + 1) * channels]
+            .iter()
+            .fold((i16::MAX, i16::MIN), |(min, max), &(lo, hi)| (min.min(lo), max.max(hi)));
+        Some((min as f32 / i16::MAX as f32, max as f32 / i16::MAX as f32))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.channels.to_le_bytes());
+        bytes.extend_from_slice(&self.sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&self.frames.to_le_bytes());
+        bytes.extend_from_slice(&(self.levels.len() as u32).to_le_bytes());
+        for level in &self.levels {
+            bytes.extend_from_slice(&level.block_size.to_le_bytes());
+            bytes.extend_from_slice(&((level.data.len() / self.channels as usize) as u64).to_le_bytes());
+            for (min, max) in &level.data {
+                bytes.extend_from_slice(&min.to_le_bytes());
+                bytes.extend_from_slice(&max.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut pos = 0;
+        let mut take = |n: usize| take_bytes(bytes, &mut pos, n);
+
+        if take(4)? != MAGIC {
+            return Err(invalid_data());
+        }
+        let channels = u16::from_le_bytes(take(2)?.try_into().unwrap());
+        let sample_rate = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let frames = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let level_count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        if channels == 0 || level_count == 0 {
+            return Err(invalid_data());
+        }
+
+        let mut levels = Vec::with_capacity(level_count as usize);
+        for _ in 0..level_count {
+            let block_size = u32::from_le_bytes(take(4)?.try_into().unwrap());
+            let blocks = u64::from_le_bytes(take(8)?.try_into().unwrap()) as usize;
+            let length = blocks
+                .checked_mul(channels as usize * 4)
+                .ok_or_else(invalid_data)?;
+            let raw = take(length)?;
+            let data = raw
+                .chunks_exact(4)
+                .map(|c| {
+                    (
+                        i16::from_le_bytes([c[0], c[1]]),
+                        i16::from_le_bytes([c[2], c[3]]),
+                    )
+                })
+                .collect();
+            levels.push(PeakLevel { block_size, data });
+        }
+
+        Ok(Self {
+            channels,
+            sample_rate,
+            frames,
+            levels,
+        })
+    }
+}
+
+fn invalid_data() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Invalid peak file")
+}
+
+fn take_bytes<'a>(bytes: &'a [u8], pos: &mut usize, n: usize) -> io::Result<&'a [u8]> {
+    let end = pos.checked_add(n).ok_or_else(invalid_data)?;
+    let slice = bytes.get(*pos..end).ok_or_else(invalid_data)?;
+    *pos = end;
+    Ok(slice)
+}
+
+fn cache_dir() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".monlam").join("peaks")
+}
+
+pub fn peak_path(hash: &str) -> PathBuf {
+    cache_dir().join(format!("{}.peaks", hash))
+}
+
+/// Read the cached peaks for an audio file, if they were generated before
+pub fn read(hash: &str) -> Option<Peaks> {
+    let bytes = fs::read(peak_path(hash)).ok()?;
+    match Peaks::from_bytes(&bytes) {
+        Ok(peaks) => Some(peaks),
+        Err(e) => {
+            log::warn!(target: "audio", "Discarding peak file for {}: {}", hash, e);
+            None
+        }
+    }
+}
+
+fn write(hash: &str, peaks: &Peaks) {
+    let path = peak_path(hash);
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Err(e) = project::write_atomic(&path, &peaks.to_bytes()) {
+        log::warn!(target: "audio", "Failed to write peak file {}: {}", path.display(), e);
+    }
+}
+
+fn build_from_file(path: &Path) -> Result<Peaks, AudioError> {
+    let mut reader = AudioReader::open(path)?;
+    let mut builder = PeakBuilder::new(reader.channels);
+    let mut packet = Vec::new();
+    while reader.read_packet(&mut packet)?.is_some() {
+        builder.push(&packet);
+        packet.clear();
+    }
+    Ok(builder.finish(reader.sample_rate))
+}
+
+// What a background job found out about the peaks of one file
+enum PeakJob {
+    Ready(Peaks),
+    NotCached, // No peak file, build one from the decoded audio
+    Failed,
+}
+
+/// Peaks loaded so far. Cached files are read and new peaks built on background threads.
+pub struct PeakCache {
+    loaded: HashMap<String, Arc<Peaks>>,
+    pending: HashSet<String>,
+    uncached: HashSet<String>, // No peak file yet, waiting for the audio to be decoded
+    failed: HashSet<String>,   // Building failed, not retried until the next eviction
+    sender: Sender<(String, PeakJob)>,
+    receiver: Receiver<(String, PeakJob)>,
+}
+
+impl Default for PeakCache {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            loaded: HashMap::new(),
+            pending: HashSet::new(),
+            uncached: HashSet::new(),
+            failed: HashSet::new(),
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl PeakCache {
+    /// Peaks for the audio with this hash. Returns None and starts reading the peak file if it isn't loaded yet.
+    pub fn get(&mut self, hash: &str) -> Option<Arc<Peaks>> {
+        if let Some(peaks) = self.loaded.get(hash) {
+            return Some(Arc::clone(peaks));
+        }
+        if !self.uncached.contains(hash) && !self.failed.contains(hash) && self.pending.insert(hash.to_string()) {
+            let hash = hash.to_string();
+            let sender = self.sender.clone();
+            thread::spawn(move || {
+                let job = match read(&hash) {
+                    Some(peaks) => PeakJob::Ready(peaks),
+                    None => PeakJob::NotCached,
+                };
+                let _ = sender.send((hash, job));
+            });
+        }
+        None
+    }
+
+    /// True when the audio with this hash has no peak file and its peaks have to be built
+    pub fn needs_build(&self, hash: &str) -> bool {
+        self.uncached.contains(hash)
+    }
+
+    /// Build peaks from decoded audio on a background thread and write them to the cache.
+    /// Streamed files are read from `path` packet by packet.
+    pub fn build(&mut self, hash: &str, path: &Path, decoded: &DecodedAudio) {
+        if !self.uncached.remove(hash) {
+            return;
+        }
+        self.pending.insert(hash.to_string());
+        let hash = hash.to_string();
+        let path = path.to_path_buf();
+        let samples = Arc::clone(&decoded.samples);
+        let (channels, sample_rate, streamed) = (decoded.channels, decoded.sample_rate, decoded.streamed);
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            let peaks = if streamed {
+                match build_from_file(&path) {
+                    Ok(peaks) => peaks,
+                    Err(e) => {
+                        log::error!(target: "audio", "Failed to build peaks for {}: {}", path.display(), e);
+                        let _ = sender.send((hash, PeakJob::Failed));
+                        return;
+                    }
+                }
+            } else {
+                Peaks::build(&samples, channels, sample_rate)
+            };
+            write(&hash, &peaks);
+            let _ = sender.send((hash, PeakJob::Ready(peaks)));
+        });
+    }
+
+    /// Collect finished background jobs
+    pub fn poll(&mut self) {
+        while let Ok((hash, job)) = self.receiver.try_recv() {
+            self.pending.remove(&hash);
+            match job {
+                PeakJob::Ready(peaks) => {
+                    log::debug!(target: "audio", "Peaks ready for {}", hash);
+                    self.loaded.insert(hash, Arc::new(peaks));
+                }
+                PeakJob::NotCached => {
+                    self.uncached.insert(hash);
+                }
+                PeakJob::Failed => {
+                    self.failed.insert(hash);
+                }
+            }
+        }
+    }
+
+    /// Drop peaks no clip holds anymore, such as those of a project that was closed.
+    /// They are read back from the peak file when needed again.
+    pub fn evict_unused(&mut self) {
+        let before = self.loaded.len();
+        self.loaded.retain(|_, peaks| Arc::strong_count(peaks) > 1);
+        self.failed.clear();
+        log::debug!(target: "audio", "Dropped {} unused peaks", before - self.loaded.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp() -> Peaks {
+        // Stereo, 10000 frames, left rising and right falling
+        let samples: Vec<f32> = (0..10000)
+            .flat_map(|i| {
+                let x = i as f32 / 10000.0;
+                [x, -x]
+            })
+            .collect();
+        Peaks::build(&samples, 2, 48000)
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let peaks = ramp();
+        let read = Peaks::from_bytes(&peaks.to_bytes()).unwrap();
+        assert_eq!(read.channels, 2);
+        assert_eq!(read.sample_rate, 48000);
+        assert_eq!(read.frames, 10000);
+        assert_eq!(read.levels.len(), BLOCK_SIZES.len());
+        for (a, b) in peaks.levels.iter().zip(&read.levels) {
+            assert_eq!(a.block_size, b.block_size);
+            assert_eq!(a.data, b.data);
+        }
+    }
+
+    #[test]
+    fn rejects_damaged_bytes() {
+        let bytes = ramp().to_bytes();
+        assert!(Peaks::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Peaks::from_bytes(&bytes[..10]).is_err());
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(Peaks::from_bytes(&wrong_magic).is_err());
+
+        // A block count so large that the length overflows
+        let mut huge = bytes;
+        let count_at = 4 + 2 + 4 + 8 + 4 + 4;
+        huge[count_at..count_at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(Peaks::from_bytes(&huge).is_err());
+    }
+
+    #[test]
+    fn picks_the_coarsest_level_that_fits() {
+        let peaks = ramp();
+        assert_eq!(peaks.level_for(1.0).block_size, 64);
+        assert_eq!(peaks.level_for(64.0).block_size, 64);
+        assert_eq!(peaks.level_for(300.0).block_size, 256);
+        assert_eq!(peaks.level_for(5000.0).block_size, 4096);
+        assert_eq!(peaks.level_for(1_000_000.0).block_size, 65536);
+    }
+
+    #[test]
+    fn range_covers_all_channels() {
+        let peaks = ramp();
+        let level = peaks.level_for(64.0);
+        let (min, max) = peaks.range(level, 0, 10000).unwrap();
+        assert!(min < -0.99 && max > 0.99);
+        assert!(peaks.range(level, 20000, 20100).is_none());
+    }
+}
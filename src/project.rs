@@ -0,0 +1,793 @@
+use crate::daw::{DawState, FrozenTrack, Sample, Tab, TrackItemType, Track};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// On-disk project schema.
+//
+// `project.json` used to be a raw dump of `DawState`, UI and runtime fields included.
+// The types below are the only things written to disk now. Every change to them
+// bumps CURRENT_VERSION and adds a step to MIGRATIONS so older files keep opening.
+
+type Migration = fn(Value, &Path) -> Result<Value, String>;
+
+// MIGRATIONS[n] upgrades a version n file to version n + 1
+const MIGRATIONS: &[Migration] = &[migrate_v0_to_v1, migrate_v1_to_v2];
+
+pub const CURRENT_VERSION: u32 = MIGRATIONS.len() as u32;
+
+// Number of previous project files kept in <project>/backups
+const BACKUP_COUNT: usize = 5;
+
+// Where an unversioned Group state.json is moved when the Group is opened or saved
+const LEGACY_GROUP_FILE: &str = "state.legacy.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProjectFile {
+    pub version: u32,
+    pub name: String,
+    pub bpm: f32,
+    pub grid_division: f32,
+    pub loop_enabled: bool,
+    pub loop_range: Option<(f32, f32)>,
+    pub next_track_id: usize,
+    pub groups: Vec<String>, // Names of the Groups in this project
+    pub tracks: Vec<TrackFile>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrackFile {
+    pub id: usize,
+    pub name: String,
+    pub muted: bool,
+    pub soloed: bool,
+    pub clips: Vec<ClipFile>,
+    #[serde(default)]
+    pub frozen: Option<FrozenFile>, // Render the track plays instead of its clips
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FrozenFile {
+    pub clip: ClipFile,
+    pub fingerprint: u64, // Of the clips the render was made from
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ClipFile {
+    pub id: usize,
+    pub name: String,
+    pub item_type: TrackItemType,
+    pub audio_file: Option<PathBuf>,    // Relative to the project folder when inside it
+    pub waveform_file: Option<PathBuf>, // Same as audio_file
+    #[serde(default)]
+    pub audio_size: Option<u64>,        // Used to find the audio file again if it moves
+    #[serde(default)]
+    pub audio_hash: Option<String>,     // BLAKE3 hash of the audio file contents
+    #[serde(default)]
+    pub pool_id: Option<String>,        // Media pool entry the audio file belongs to
+    #[serde(default)]
+    pub variant: Option<String>,        // Variant a Group instance plays, None for the main one
+    pub grid_position: f32, // In beats
+    pub grid_length: f32,   // In beats
+    pub trim_start: f32,    // In seconds
+    pub trim_end: f32,      // In seconds
+}
+
+impl ProjectFile {
+    // `project_path` is where the file will be written. Paths inside its folder are stored relative.
+    pub fn from_state(state: &DawState, project_path: Option<&Path>) -> Self {
+        let project_folder = project_path.and_then(|p| p.parent());
+        Self {
+            version: CURRENT_VERSION,
+            name: state.project_name.clone(),
+            bpm: state.bpm,
+            grid_division: state.grid_division,
+            loop_enabled: state.loop_enabled,
+            loop_range: state.loop_range,
+            next_track_id: state.next_track_id,
+            groups: state.audio_boxes.clone(),
+            tracks: tracks_to_files(&state.tracks, project_folder),
+        }
+    }
+
+    // Build a fresh runtime state. UI state (scroll, zoom, tabs, selection) starts from defaults.
+    // Relative paths are resolved against the folder of `project_path`.
+    pub fn into_state(self, project_path: Option<&Path>) -> DawState {
+        let project_folder = project_path.and_then(|p| p.parent());
+        let mut state = DawState {
+            project_name: self.name,
+            bpm: self.bpm,
+            grid_division: self.grid_division,
+            loop_enabled: self.loop_enabled,
+            loop_range: self.loop_range,
+            next_track_id: self.next_track_id,
+            audio_boxes: self.groups,
+            tabs: vec![Tab::default()],
+            ..DawState::default()
+        };
+
+        state.tracks = tracks_from_files(self.tracks, self.bpm, project_folder);
+
+        // Never hand out a track id that is already taken
+        let max_track_id = state.tracks.iter().map(|t| t.id + 1).max().unwrap_or(0);
+        state.next_track_id = state.next_track_id.max(max_track_id);
+
+        state
+    }
+}
+
+/// A Group's own arrangement, stored as `state.json` in the Group folder. It shares the track
+/// schema and migrations of project files. Paths are relative to the project folder, where the
+/// media pool lives.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GroupFile {
+    pub version: u32,
+    pub bpm: f32,
+    pub next_track_id: usize,
+    pub tracks: Vec<TrackFile>,
+}
+
+impl GroupFile {
+    pub fn from_tracks(bpm: f32, next_track_id: usize, tracks: &[Track], project_folder: Option<&Path>) -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            bpm,
+            next_track_id,
+            tracks: tracks_to_files(tracks, project_folder),
+        }
+    }
+
+    pub fn into_tracks(self, project_folder: Option<&Path>) -> Vec<Track> {
+        tracks_from_files(self.tracks, self.bpm, project_folder)
+    }
+}
+
+fn tracks_to_files(tracks: &[Track], project_folder: Option<&Path>) -> Vec<TrackFile> {
+    tracks
+        .iter()
+        .map(|track| TrackFile {
+            id: track.id,
+            name: track.name.clone(),
+            muted: track.muted,
+            soloed: track.soloed,
+            clips: track.samples.iter().map(|sample| clip_to_file(sample, project_folder)).collect(),
+            frozen: track.frozen.as_ref().map(|frozen| FrozenFile {
+                clip: clip_to_file(&frozen.clip, project_folder),
+                fingerprint: frozen.fingerprint,
+            }),
+        })
+        .collect()
+}
+
+fn clip_to_file(sample: &Sample, project_folder: Option<&Path>) -> ClipFile {
+    ClipFile {
+        id: sample.id,
+        name: sample.name.clone(),
+        item_type: sample.item_type,
+        audio_file: sample
+            .audio_file
+            .as_deref()
+            .map(|p| to_project_relative(p, project_folder)),
+        waveform_file: sample
+            .waveform_file
+            .as_deref()
+            .map(|p| to_project_relative(p, project_folder)),
+        audio_size: sample.audio_size,
+        audio_hash: sample.audio_hash.clone(),
+        pool_id: sample.pool_id.clone(),
+        variant: sample.variant.clone(),
+        grid_position: sample.grid_position,
+        grid_length: sample.grid_length,
+        trim_start: sample.trim_start,
+        trim_end: sample.trim_end,
+    }
+}
+
+// Relative paths are resolved against `project_folder`
+fn tracks_from_files(tracks: Vec<TrackFile>, bpm: f32, project_folder: Option<&Path>) -> Vec<Track> {
+    tracks
+        .into_iter()
+        .map(|track_file| {
+            let mut track = Track::new(track_file.id, track_file.name);
+            track.muted = track_file.muted;
+            track.soloed = track_file.soloed;
+            for clip in track_file.clips {
+                // Keep the saved clip id instead of going through add_sample
+                track.samples.push(clip_from_file(clip, bpm, project_folder));
+            }
+            track.frozen = track_file.frozen.map(|frozen| FrozenTrack {
+                clip: clip_from_file(frozen.clip, bpm, project_folder),
+                fingerprint: frozen.fingerprint,
+            });
+            track
+        })
+        .collect()
+}
+
+fn clip_from_file(clip: ClipFile, bpm: f32, project_folder: Option<&Path>) -> Sample {
+    let mut sample = Sample::default();
+    sample.id = clip.id;
+    sample.name = clip.name;
+    sample.item_type = clip.item_type;
+    sample.audio_file = clip.audio_file.map(|p| resolve_project_path(p, project_folder));
+    sample.waveform_file = clip.waveform_file.map(|p| resolve_project_path(p, project_folder));
+    sample.audio_size = clip.audio_size;
+    sample.audio_hash = clip.audio_hash;
+    sample.pool_id = clip.pool_id;
+    sample.variant = clip.variant;
+    sample.grid_position = clip.grid_position;
+    sample.grid_length = clip.grid_length;
+    sample.trim_start = clip.trim_start;
+    sample.trim_end = clip.trim_end;
+    sample.update_grid_times(bpm);
+    sample
+}
+
+// Paths inside the project folder are stored relative to it, with '/' separators,
+// so the folder can be moved or opened on another machine
+pub fn to_project_relative(path: &Path, project_folder: Option<&Path>) -> PathBuf {
+    match project_folder.and_then(|folder| path.strip_prefix(folder).ok()) {
+        Some(relative) => {
+            let parts: Vec<String> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            PathBuf::from(parts.join("/"))
+        }
+        None => path.to_path_buf(),
+    }
+}
+
+pub fn resolve_project_path(path: PathBuf, project_folder: Option<&Path>) -> PathBuf {
+    match project_folder {
+        Some(folder) if path.is_relative() => folder.join(path),
+        _ => path,
+    }
+}
+
+/// Serialize the runtime state into the current on-disk format, to be written at `project_path`
+pub fn serialize(state: &DawState, project_path: Option<&Path>) -> Result<String, String> {
+    serde_json::to_string_pretty(&ProjectFile::from_state(state, project_path))
+        .map_err(|e| format!("Failed to serialize project: {}", e))
+}
+
+/// Save the state to `path`, keeping a backup of the previous file.
+/// The write is atomic: a crash leaves either the old or the new project, never a partial one.
+pub fn save(path: &Path, state: &DawState) -> Result<(), String> {
+    let serialized = serialize(state, Some(path))?;
+
+    if path.exists() {
+        if let Err(e) = backup(path) {
+            // A failed backup shouldn't stop the save itself
+            log::warn!(target: "project", "Failed to back up {}: {}", path.display(), e);
+        }
+    }
+
+    write_atomic(path, serialized.as_bytes())
+        .map_err(|e| format!("Failed to write project file {}: {}", path.display(), e))
+}
+
+/// Write to a temporary file next to `path`, flush it to disk and rename it into place
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = (|| {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return result;
+    }
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+
+    Ok(())
+}
+
+// Copy the current project file into <project>/backups and drop the oldest backups
+fn backup(path: &Path) -> io::Result<()> {
+    let project_folder = path.parent().unwrap_or(Path::new(""));
+    let backups_dir = project_folder.join("backups");
+    fs::create_dir_all(&backups_dir)?;
+
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("project");
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let backup_path = backups_dir.join(format!("{}-{}.json", stem, timestamp));
+    fs::copy(path, &backup_path)?;
+
+    // Timestamps sort the same as their names, oldest first
+    let prefix = format!("{}-", stem);
+    let mut backups: Vec<PathBuf> = fs::read_dir(&backups_dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".json"))
+        })
+        .collect();
+    backups.sort_by_key(|p| {
+        p.file_stem()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n[prefix.len()..].parse::<u128>().ok())
+            .unwrap_or(0)
+    });
+
+    while backups.len() > BACKUP_COUNT {
+        let oldest = backups.remove(0);
+        fs::remove_file(&oldest)?;
+    }
+
+    log::debug!(target: "project", "Backed up project to {}", backup_path.display());
+    Ok(())
+}
+
+/// Read a project file of any known version, migrating it to the current format
+pub fn load(path: &Path) -> Result<ProjectFile, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read project file {}: {}", path.display(), e))?;
+    let value: Value = serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to parse project file {}: {}", path.display(), e))?;
+
+    let value = migrate(value, path)?;
+
+    serde_json::from_value(value)
+        .map_err(|e| format!("Invalid project file {}: {}", path.display(), e))
+}
+
+/// Write a Group's arrangement to `<group>/state.json`. The Group folder sits in the project folder.
+pub fn save_group(group_path: &Path, group_file: &GroupFile) -> Result<(), String> {
+    let serialized = serde_json::to_string_pretty(group_file)
+        .map_err(|e| format!("Failed to serialize Group: {}", e))?;
+    write_atomic(&group_path.join("state.json"), serialized.as_bytes())
+        .map_err(|e| format!("Failed to write Group file in {}: {}", group_path.display(), e))
+}
+
+/// What `load_group` found in a Group folder
+pub enum GroupLoad {
+    Missing,           // Never saved
+    Legacy,            // Older Groups stored a dump of the whole parent project, without a version field
+    Loaded(GroupFile),
+}
+
+/// Read a Group's arrangement. Only reads; a legacy file is left where it is until the Group is
+/// opened or saved, see `set_aside_legacy_group`.
+pub fn load_group(group_path: &Path) -> Result<GroupLoad, String> {
+    let path = group_path.join("state.json");
+    if !path.exists() {
+        return Ok(GroupLoad::Missing);
+    }
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read Group file {}: {}", path.display(), e))?;
+    let value: Value = serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to parse Group file {}: {}", path.display(), e))?;
+
+    if value.get("version").is_none() {
+        return Ok(GroupLoad::Legacy);
+    }
+
+    // Migrations resolve paths against the folder of the project file
+    let project_folder = group_path.parent().unwrap_or(Path::new(""));
+    let value = migrate(value, &project_folder.join("project.json"))?;
+
+    serde_json::from_value(value)
+        .map(GroupLoad::Loaded)
+        .map_err(|e| format!("Invalid Group file {}: {}", path.display(), e))
+}
+
+/// Whether the folder holds an arrangement saved with a format version
+pub fn has_versioned_group(group_path: &Path) -> bool {
+    fs::read_to_string(group_path.join("state.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str::<Value>(&contents).ok())
+        .is_some_and(|value| value.get("version").is_some())
+}
+
+/// Move a legacy `state.json` to `state.legacy.json`, so the Group can be saved without losing it.
+/// Does nothing for a Group saved in the current format.
+pub fn set_aside_legacy_group(group_path: &Path) -> Result<(), String> {
+    if !matches!(load_group(group_path), Ok(GroupLoad::Legacy)) {
+        return Ok(());
+    }
+    let path = group_path.join("state.json");
+    let legacy_path = group_path.join(LEGACY_GROUP_FILE);
+    fs::rename(&path, &legacy_path)
+        .map_err(|e| format!("Failed to set aside legacy Group file {}: {}", path.display(), e))?;
+    log::warn!(
+        target: "project",
+        "Group file {} predates versioning, moved it to {}",
+        path.display(),
+        legacy_path.display()
+    );
+    Ok(())
+}
+
+fn migrate(mut value: Value, project_path: &Path) -> Result<Value, String> {
+    // Files written before versioning have no version field
+    let mut version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+
+    if version > CURRENT_VERSION {
+        return Err(format!(
+            "Project was saved by a newer version of Monlam (format {}, supported up to {})",
+            version, CURRENT_VERSION
+        ));
+    }
+
+    while version < CURRENT_VERSION {
+        value = MIGRATIONS[version as usize](value, project_path)?;
+        version += 1;
+        value["version"] = json!(version);
+        log::info!(target: "project", "Migrated project file to format version {}", version);
+    }
+
+    Ok(value)
+}
+
+// --- Version 0: raw DawState dump ---
+
+#[derive(Deserialize)]
+struct V0Project {
+    #[serde(default)]
+    project_name: String,
+    #[serde(default = "v0_default_bpm")]
+    bpm: f32,
+    #[serde(default = "v0_default_grid_division")]
+    grid_division: f32,
+    #[serde(default)]
+    loop_enabled: bool,
+    #[serde(default)]
+    loop_range: Option<(f32, f32)>,
+    #[serde(default)]
+    next_track_id: usize,
+    #[serde(default)]
+    audio_boxes: Vec<String>,
+    #[serde(default)]
+    tracks: Vec<V0Track>,
+}
+
+#[derive(Deserialize)]
+struct V0Track {
+    id: usize,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    muted: bool,
+    #[serde(default)]
+    soloed: bool,
+    #[serde(default)]
+    samples: Vec<V0Sample>,
+}
+
+#[derive(Deserialize)]
+struct V0Sample {
+    id: usize,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    audio_file: Option<PathBuf>,
+    #[serde(default)]
+    waveform_file: Option<PathBuf>,
+    #[serde(default)]
+    grid_position: f32,
+    #[serde(default)]
+    grid_length: f32,
+    #[serde(default)]
+    trim_start: f32,
+    #[serde(default)]
+    trim_end: f32,
+    #[serde(default)]
+    item_type: TrackItemType,
+}
+
+fn v0_default_bpm() -> f32 {
+    120.0
+}
+
+fn v0_default_grid_division() -> f32 {
+    0.25
+}
+
+// Version 0 -> 1:
+// - keep only project data, dropping UI and runtime fields
+// - `audio_boxes` becomes `groups`
+// - `samples` becomes `clips`
+// - waveform caches kept under ~/.monlam/projects/<project> move into the project's waveforms folder
+fn migrate_v0_to_v1(value: Value, project_path: &Path) -> Result<Value, String> {
+    let old: V0Project = serde_json::from_value(value)
+        .map_err(|e| format!("Failed to read version 0 project: {}", e))?;
+
+    let project_folder = project_path.parent().unwrap_or(Path::new(""));
+
+    let tracks: Vec<Value> = old
+        .tracks
+        .into_iter()
+        .map(|track| {
+            let clips: Vec<Value> = track
+                .samples
+                .into_iter()
+                .map(|sample| {
+                    let waveform_file =
+                        migrate_legacy_waveform(project_folder, &sample.name, sample.waveform_file);
+                    json!({
+                        "id": sample.id,
+                        "name": sample.name,
+                        "item_type": sample.item_type,
+                        "audio_file": sample.audio_file,
+                        "waveform_file": waveform_file,
+                        "grid_position": sample.grid_position,
+                        "grid_length": sample.grid_length,
+                        "trim_start": sample.trim_start,
+                        "trim_end": sample.trim_end,
+                    })
+                })
+                .collect();
+            json!({
+                "id": track.id,
+                "name": track.name,
+                "muted": track.muted,
+                "soloed": track.soloed,
+                "clips": clips,
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "version": 1,
+        "name": old.project_name,
+        "bpm": old.bpm,
+        "grid_division": old.grid_division,
+        "loop_enabled": old.loop_enabled,
+        "loop_range": old.loop_range,
+        "next_track_id": old.next_track_id,
+        "groups": old.audio_boxes,
+        "tracks": tracks,
+    }))
+}
+
+// Copy a waveform cache from the legacy ~/.monlam/projects location into <project>/waveforms.
+// Returns the waveform path the clip should use from now on.
+fn migrate_legacy_waveform(
+    project_folder: &Path,
+    sample_name: &str,
+    waveform_file: Option<PathBuf>,
+) -> Option<PathBuf> {
+    let waveforms_dir = project_folder.join("waveforms");
+    let file_name = format!("{}.json", sample_name);
+    let target = waveforms_dir.join(&file_name);
+
+    if target.exists() {
+        return Some(target);
+    }
+
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    let project_dir_name = project_folder
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unnamed");
+    let legacy_dir = Path::new(&home)
+        .join(".monlam")
+        .join("projects")
+        .join(project_dir_name);
+
+    // Prefer the path recorded in the file, then the conventional legacy location
+    let candidates = waveform_file
+        .iter()
+        .cloned()
+        .chain(std::iter::once(legacy_dir.join(&file_name)));
+
+    for source in candidates {
+        if !source.exists() {
+            continue;
+        }
+        if source.starts_with(&waveforms_dir) {
+            return Some(source);
+        }
+        if let Err(e) = fs::create_dir_all(&waveforms_dir) {
+            log::error!(target: "project", "Failed to create waveforms directory: {}", e);
+            return Some(source);
+        }
+        match fs::copy(&source, &target) {
+            Ok(_) => {
+                log::info!(target: "project",
+                    "Copied legacy waveform {} into {}",
+                    source.display(),
+                    target.display()
+                );
+                return Some(target);
+            }
+            Err(e) => {
+                log::error!(target: "project", "Failed to migrate waveform {}: {}", source.display(), e);
+                return Some(source);
+            }
+        }
+    }
+
+    waveform_file
+}
+
+// Version 1 -> 2: audio and waveform paths inside the project folder become relative
+fn migrate_v1_to_v2(mut value: Value, project_path: &Path) -> Result<Value, String> {
+    let project_folder = project_path.parent();
+
+    if let Some(tracks) = value.get_mut("tracks").and_then(|t| t.as_array_mut()) {
+        for track in tracks {
+            let Some(clips) = track.get_mut("clips").and_then(|c| c.as_array_mut()) else {
+                continue;
+            };
+            for clip in clips {
+                for key in ["audio_file", "waveform_file"] {
+                    if let Some(path) = clip.get(key).and_then(|p| p.as_str()) {
+                        let relative = to_project_relative(Path::new(path), project_folder);
+                        clip[key] = json!(relative);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A project as the first versions wrote it: the whole DawState with absolute paths
+    fn v0_project(folder: &Path) -> Value {
+        json!({
+            "project_name": "Old Song",
+            "bpm": 96.0,
+            "grid_division": 0.5,
+            "loop_enabled": true,
+            "loop_range": [4.0, 12.0],
+            "next_track_id": 2,
+            "audio_boxes": ["Drums"],
+            "timeline_position": 3.5,
+            "tracks": [
+                {
+                    "id": 0,
+                    "name": "Kick",
+                    "muted": false,
+                    "soloed": true,
+                    "samples": [
+                        {
+                            "id": 0,
+                            "name": "kick.wav",
+                            "audio_file": folder.join("audio").join("kick.wav"),
+                            "grid_position": 1.0,
+                            "grid_length": 2.0,
+                            "trim_start": 0.25,
+                            "trim_end": 1.5,
+                        },
+                    ],
+                },
+                {
+                    "id": 1,
+                    "name": "Elsewhere",
+                    "samples": [
+                        {
+                            "id": 0,
+                            "name": "Drums",
+                            "audio_file": "/somewhere/else/drums.wav",
+                            "item_type": "Group",
+                        },
+                    ],
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn migrates_v0_project() {
+        let folder = std::env::temp_dir().join(format!("monlam-v0-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("project.json");
+        fs::write(&path, v0_project(&folder).to_string()).unwrap();
+
+        let loaded = load(&path);
+        let _ = fs::remove_dir_all(&folder);
+        let project = loaded.unwrap();
+
+        assert_eq!(project.version, CURRENT_VERSION);
+        assert_eq!(project.name, "Old Song");
+        assert_eq!(project.bpm, 96.0);
+        assert_eq!(project.grid_division, 0.5);
+        assert!(project.loop_enabled);
+        assert_eq!(project.loop_range, Some((4.0, 12.0)));
+        assert_eq!(project.next_track_id, 2);
+        assert_eq!(project.groups, vec!["Drums".to_string()]);
+        assert_eq!(project.tracks.len(), 2);
+
+        let kick = &project.tracks[0];
+        assert_eq!(kick.name, "Kick");
+        assert!(kick.soloed);
+        let clip = &kick.clips[0];
+        assert_eq!(clip.item_type, TrackItemType::Sample);
+        // Paths inside the project folder become relative, others stay as they were
+        assert_eq!(clip.audio_file, Some(Path::new("audio").join("kick.wav")));
+        assert_eq!((clip.grid_position, clip.grid_length), (1.0, 2.0));
+        assert_eq!((clip.trim_start, clip.trim_end), (0.25, 1.5));
+
+        let group = &project.tracks[1].clips[0];
+        assert_eq!(group.item_type, TrackItemType::Group);
+        assert_eq!(group.audio_file, Some(PathBuf::from("/somewhere/else/drums.wav")));
+    }
+
+    #[test]
+    fn paths_inside_the_project_become_relative() {
+        let folder = Path::new("/music/Song");
+        let inside = folder.join("audio").join("kick.wav");
+        let relative = to_project_relative(&inside, Some(folder));
+        assert_eq!(relative, PathBuf::from("audio/kick.wav"));
+        assert_eq!(resolve_project_path(relative, Some(folder)), inside);
+
+        let outside = Path::new("/samples/kick.wav");
+        assert_eq!(to_project_relative(outside, Some(folder)), outside);
+        assert_eq!(resolve_project_path(outside.to_path_buf(), Some(folder)), outside);
+        assert_eq!(to_project_relative(&inside, None), inside);
+    }
+
+    #[test]
+    fn migrates_v1_paths() {
+        let v1 = json!({
+            "version": 1,
+            "tracks": [{ "clips": [{
+                "audio_file": "/music/Song/audio/kick.wav",
+                "waveform_file": "/elsewhere/kick.json",
+            }] }],
+        });
+        let v2 = migrate_v1_to_v2(v1, Path::new("/music/Song/project.json")).unwrap();
+        let clip = &v2["tracks"][0]["clips"][0];
+        assert_eq!(clip["audio_file"], json!("audio/kick.wav"));
+        assert_eq!(clip["waveform_file"], json!("/elsewhere/kick.json"));
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let newer = json!({ "version": CURRENT_VERSION + 1 });
+        assert!(migrate(newer, Path::new("project.json")).is_err());
+    }
+
+    #[test]
+    fn legacy_group_is_only_moved_when_asked() {
+        let folder = std::env::temp_dir().join(format!("monlam-legacy-group-{}", std::process::id()));
+        let group = folder.join("Drums");
+        fs::create_dir_all(&group).unwrap();
+        fs::write(group.join("state.json"), v0_project(&folder).to_string()).unwrap();
+
+        let loaded = load_group(&group);
+        let still_there = group.join("state.json").exists();
+        let moved = set_aside_legacy_group(&group);
+        let legacy_exists = group.join(LEGACY_GROUP_FILE).exists();
+        let state_exists = group.join("state.json").exists();
+        let _ = fs::remove_dir_all(&folder);
+
+        assert!(matches!(loaded, Ok(GroupLoad::Legacy)));
+        assert!(still_there);
+        moved.unwrap();
+        assert!(legacy_exists);
+        assert!(!state_exists);
+    }
+}
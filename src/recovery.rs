@@ -0,0 +1,175 @@
+use crate::project;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Crash recovery.
+//
+// While the app runs, a session marker sits in ~/.monlam/sessions/<pid>.json and the open
+// project is autosaved periodically to a recovery file. A clean exit removes both.
+// Finding a marker on startup whose process is no longer running means that session crashed.
+
+/// How often the open project is written to its recovery file
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SessionMarker {
+    pub pid: u32,
+    pub started: u64,                 // Unix time in seconds
+    pub project: Option<PathBuf>,     // Project open in that session, None for an unsaved project
+}
+
+fn monlam_dir() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".monlam")
+}
+
+fn sessions_dir() -> PathBuf {
+    monlam_dir().join("sessions")
+}
+
+fn marker_path() -> PathBuf {
+    sessions_dir().join(format!("{}.json", std::process::id()))
+}
+
+/// Whether a process with this pid is still running
+fn is_running(pid: u32) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        Path::new("/proc").join(pid.to_string()).exists()
+    }
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        std::process::Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    }
+    #[cfg(windows)]
+    {
+        std::process::Command::new("tasklist")
+            .args(["/NH", "/FI", &format!("PID eq {}", pid)])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+            .unwrap_or(false)
+    }
+}
+
+/// Recovery file for a project: `project.recovery.json` next to `project.json`,
+/// or a shared file under ~/.monlam/recovery for projects that were never saved
+pub fn recovery_path(project_path: Option<&Path>) -> PathBuf {
+    match project_path {
+        Some(path) => path.with_extension("recovery.json"),
+        None => monlam_dir().join("recovery").join("untitled.recovery.json"),
+    }
+}
+
+/// Start a new session. Returns the marker left by the most recent session that did not exit
+/// cleanly. Markers of instances that are still running are left alone.
+pub fn begin_session(project: Option<&Path>) -> Option<SessionMarker> {
+    let own_pid = std::process::id();
+    let mut crashed: Vec<SessionMarker> = Vec::new();
+
+    if let Ok(entries) = fs::read_dir(sessions_dir()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(marker) = fs::read_to_string(&path)
+                .ok()
+                .and_then(|contents| serde_json::from_str::<SessionMarker>(&contents).ok())
+            else {
+                continue;
+            };
+
+            // A marker with our own pid can only be left over from an earlier process
+            if marker.pid != own_pid && is_running(marker.pid) {
+                log::info!(target: "project", "Another session (pid {}) is still running", marker.pid);
+                continue;
+            }
+
+            log::warn!(target: "project",
+                "Previous session (pid {}) did not exit cleanly",
+                marker.pid
+            );
+            let _ = fs::remove_file(&path);
+            crashed.push(marker);
+        }
+    }
+
+    update_session(project);
+    crashed.into_iter().max_by_key(|marker| marker.started)
+}
+
+/// Record which project the running session has open
+pub fn update_session(project: Option<&Path>) {
+    let marker = SessionMarker {
+        pid: std::process::id(),
+        started: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        project: project.map(|p| p.to_path_buf()),
+    };
+
+    let path = marker_path();
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Ok(serialized) = serde_json::to_string_pretty(&marker) {
+        if let Err(e) = project::write_atomic(&path, serialized.as_bytes()) {
+            log::error!(target: "project", "Failed to write session marker: {}", e);
+        }
+    }
+}
+
+/// Mark the session as cleanly finished
+pub fn end_session() {
+    let _ = fs::remove_file(marker_path());
+}
+
+pub fn write_recovery(project_path: Option<&Path>, contents: &str) -> bool {
+    let path = recovery_path(project_path);
+    if let Some(parent) = path.parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            log::error!(target: "project", "Failed to create recovery folder: {}", e);
+            return false;
+        }
+    }
+    match project::write_atomic(&path, contents.as_bytes()) {
+        Ok(()) => {
+            log::debug!(target: "project", "Autosaved recovery file {}", path.display());
+            true
+        }
+        Err(e) => {
+            log::error!(target: "project", "Failed to write recovery file {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+pub fn remove_recovery(project_path: Option<&Path>) {
+    let _ = fs::remove_file(recovery_path(project_path));
+}
+
+/// Returns the recovery file for a crashed session if it holds newer work than the project itself
+pub fn find_recovery(previous: &SessionMarker) -> Option<PathBuf> {
+    let recovery = recovery_path(previous.project.as_deref());
+    let recovery_time = fs::metadata(&recovery).and_then(|m| m.modified()).ok()?;
+
+    let project_time = previous
+        .project
+        .as_ref()
+        .and_then(|p| fs::metadata(p).and_then(|m| m.modified()).ok());
+
+    match project_time {
+        Some(project_time) if project_time >= recovery_time => {
+            // The project was saved after the last autosave, nothing to recover
+            let _ = fs::remove_file(&recovery);
+            None
+        }
+        _ => Some(recovery),
+    }
+}
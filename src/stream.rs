@@ -0,0 +1,390 @@
+use crate::audio::{AudioError, AudioReader};
+use serde::{Deserialize, Serialize};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+// Disk streaming.
+//
+// Long recordings are played straight from disk instead of being decoded into memory. Each
+// streamed clip has a reader thread that decodes a few seconds ahead of the playhead into a ring
+// buffer, and the audio callback only ever reads from that buffer. The buffer is a lock-free
+// single producer, single consumer ring, so the callback never waits on the reader.
+//
+// When the playhead jumps, the callback (or a seek) asks for a new frame by bumping a seek count.
+// The callback drops what is buffered until the reader has seeked and marked the count as done;
+// the reader only does that once the buffer is empty, so everything buffered after it belongs to
+// the new position.
+
+/// Files longer than this are streamed when a clip is left on automatic
+pub const AUTO_STREAM_SECONDS: f32 = 600.0;
+
+const READ_AHEAD_SECONDS: f32 = 4.0;
+
+// How long the reader sleeps when the buffer is full or it waits for the callback
+const IDLE: Duration = Duration::from_millis(5);
+
+/// How a clip's audio is made available for playback
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Playback {
+    #[default]
+    Auto, // Stream files longer than AUTO_STREAM_SECONDS, keep the rest in memory
+    Memory,
+    Disk,
+}
+
+impl Playback {
+    /// Whether a file of this length should be streamed
+    pub fn streams(self, seconds: Option<f32>) -> bool {
+        match self {
+            Playback::Auto => seconds.is_some_and(|s| s > AUTO_STREAM_SECONDS),
+            Playback::Memory => false,
+            Playback::Disk => true,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Playback::Auto => "Automatic",
+            Playback::Memory => "Load into Memory",
+            Playback::Disk => "Stream from Disk",
+        }
+    }
+}
+
+struct Shared {
+    wanted: AtomicU64,          // Frame the last seek asked for
+    seeks_requested: AtomicU64, // Seeks asked for so far
+    seeks_done: AtomicU64,      // Seeks the reader has made; buffered audio is from the last one on
+    start: AtomicU64,           // Frame the buffered audio of the last seek starts at
+    end_of_file: AtomicBool,    // Whether everything after the last seek has been buffered
+    stopped: AtomicBool,
+}
+
+impl Shared {
+    fn request_seek(&self, frame: u64) {
+        self.wanted.store(frame, Ordering::Release);
+        self.seeks_requested.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+/// A file being read ahead of the playhead on its own thread. The thread stops when this is dropped.
+pub struct DiskStream {
+    shared: Arc<Shared>,
+    reader: Thread,
+    pub sample_rate: u32,
+}
+
+impl DiskStream {
+    /// Start reading the file. The returned window is the audio callback's end of the stream.
+    pub fn open(path: &Path) -> Result<(Self, StreamWindow), AudioError> {
+        let reader = AudioReader::open(path)?;
+        let channels = reader.channels.max(1);
+        let sample_rate = reader.sample_rate;
+        let shared = Arc::new(Shared {
+            wanted: AtomicU64::new(0),
+            seeks_requested: AtomicU64::new(0),
+            seeks_done: AtomicU64::new(0),
+            start: AtomicU64::new(0),
+            end_of_file: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+        });
+        let capacity = ((sample_rate as f32 * READ_AHEAD_SECONDS) as usize * channels as usize).max(1);
+        let (producer, consumer) = HeapRb::new(capacity).split();
+
+        let thread_shared = Arc::clone(&shared);
+        let name = path.display().to_string();
+        let handle = thread::Builder::new()
+            .name("disk-stream".to_string())
+            .spawn(move || read_ahead(reader, producer, &thread_shared, &name))
+            .map_err(AudioError::Io)?;
+
+        log::debug!(target: "audio", "Streaming {} from disk", path.display());
+        let window = StreamWindow {
+            consumer,
+            shared: Arc::clone(&shared),
+            channels: channels as usize,
+            start: 0,
+            seeks_done: 0,
+            seeking: false,
+        };
+        let stream = Self {
+            shared,
+            reader: handle.thread().clone(),
+            sample_rate,
+        };
+        Ok((stream, window))
+    }
+
+    /// Move the playhead to `frame` so the reader starts filling from there
+    pub fn seek(&self, frame: u64) {
+        self.shared.request_seek(frame);
+        self.reader.unpark();
+    }
+}
+
+impl Drop for DiskStream {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Release);
+        self.reader.unpark();
+    }
+}
+
+/// The audio callback's end of a stream: the buffered audio, read without locking
+pub struct StreamWindow {
+    consumer: HeapConsumer<f32>, // Interleaved
+    shared: Arc<Shared>,
+    channels: usize,
+    start: u64,      // Frame of the first buffered sample
+    seeks_done: u64, // Seek the buffered audio belongs to
+    seeking: bool,   // Whether a seek is asked for and the reader hasn't made it yet
+}
+
+impl StreamWindow {
+    /// Catch up with the reader at the start of an audio callback. Audio from before a seek that
+    /// is still being made is dropped, and the window stays empty until the reader is done.
+    pub fn sync(&mut self) {
+        // The buffer length is read before the seek count: audio counted here was pushed after the
+        // seek the count is at, since the reader only seeks once the buffer is empty
+        let buffered = self.consumer.len();
+        let seeks_done = self.shared.seeks_done.load(Ordering::Acquire);
+        if seeks_done != self.seeks_done {
+            self.seeks_done = seeks_done;
+            self.start = self.shared.start.load(Ordering::Acquire);
+        }
+        self.seeking = self.shared.seeks_requested.load(Ordering::Acquire) != seeks_done;
+        if self.seeking {
+            self.consumer.skip(buffered);
+        }
+    }
+
+    /// Sample at `frame` for `channel`, or None if the reader hasn't got there yet.
+    /// Channels the file doesn't have repeat its last one, so mono plays on both sides.
+    pub fn sample(&self, frame: u64, channel: usize) -> Option<f32> {
+        if self.seeking {
+            return None;
+        }
+        let offset = frame.checked_sub(self.start)? as usize;
+        let index = offset * self.channels + channel.min(self.channels - 1);
+        let (first, second) = self.consumer.as_slices();
+        match index.checked_sub(first.len()) {
+            None => first.get(index).copied(),
+            Some(index) => second.get(index).copied(),
+        }
+    }
+
+    fn buffered_end(&self) -> u64 {
+        self.start + (self.consumer.len() / self.channels) as u64
+    }
+
+    /// Whether the whole file has been read and `frame` is past its end
+    pub fn is_past_end(&self, frame: u64) -> bool {
+        !self.seeking && self.shared.end_of_file.load(Ordering::Acquire) && frame >= self.buffered_end()
+    }
+
+    /// Drop everything before `frame`, the position the next callback starts at. A frame outside
+    /// the buffered audio makes the reader seek there.
+    pub fn advance(&mut self, frame: u64) {
+        if self.seeking {
+            return;
+        }
+        let end = self.buffered_end();
+        if frame >= self.start && frame <= end {
+            self.consumer.skip((frame - self.start) as usize * self.channels);
+            self.start = frame;
+        } else if !self.is_past_end(frame) {
+            self.shared.request_seek(frame);
+            self.seeking = true;
+        }
+    }
+}
+
+fn read_ahead(mut reader: AudioReader, mut producer: HeapProducer<f32>, shared: &Shared, name: &str) {
+    let channels = reader.channels.max(1) as usize;
+    let mut packet = Vec::new();
+    let mut pushed = 0; // Samples of the packet already in the buffer
+    let mut skip = 0; // Samples to drop after a seek landed before the wanted frame
+    let mut seeks_done = 0;
+    let mut end_of_file = false;
+
+    loop {
+        if shared.stopped.load(Ordering::Acquire) {
+            return;
+        }
+
+        let seeks_requested = shared.seeks_requested.load(Ordering::Acquire);
+        if seeks_requested != seeks_done {
+            // The callback empties the buffer once it sees the request
+            if !producer.is_empty() {
+                thread::park_timeout(IDLE);
+                continue;
+            }
+            let frame = shared.wanted.load(Ordering::Acquire);
+            packet.clear();
+            pushed = 0;
+            match reader.seek(frame) {
+                Ok(landed) => {
+                    skip = frame.saturating_sub(landed) as usize * channels;
+                    end_of_file = false;
+                }
+                Err(e) => {
+                    // Nothing past a failed seek can be trusted to be at the right frame
+                    log::warn!(target: "audio", "Failed to seek {} to frame {}, stream stopped there: {}", name, frame, e);
+                    end_of_file = true;
+                }
+            }
+            shared.end_of_file.store(false, Ordering::Release);
+            shared.start.store(frame, Ordering::Release);
+            seeks_done = seeks_requested;
+            shared.seeks_done.store(seeks_done, Ordering::Release);
+            continue;
+        }
+
+        if pushed < packet.len() {
+            pushed += producer.push_slice(&packet[pushed..]);
+            if pushed < packet.len() {
+                thread::park_timeout(IDLE);
+            }
+            continue;
+        }
+
+        if end_of_file {
+            shared.end_of_file.store(true, Ordering::Release);
+            thread::park_timeout(IDLE);
+            continue;
+        }
+
+        packet.clear();
+        end_of_file = match reader.read_packet(&mut packet) {
+            Ok(Some(_)) => false,
+            Ok(None) => true,
+            Err(e) => {
+                log::error!(target: "audio", "Stopped streaming {}: {}", name, e);
+                true
+            }
+        };
+        let dropped = skip.min(packet.len());
+        skip -= dropped;
+        pushed = dropped;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A window without a reader thread; the test plays the reader through the producer and `Shared`
+    fn window(channels: usize) -> (HeapProducer<f32>, Arc<Shared>, StreamWindow) {
+        let shared = Arc::new(Shared {
+            wanted: AtomicU64::new(0),
+            seeks_requested: AtomicU64::new(0),
+            seeks_done: AtomicU64::new(0),
+            start: AtomicU64::new(0),
+            end_of_file: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+        });
+        let (producer, consumer) = HeapRb::new(64).split();
+        let window = StreamWindow {
+            consumer,
+            shared: Arc::clone(&shared),
+            channels,
+            start: 0,
+            seeks_done: 0,
+            seeking: false,
+        };
+        (producer, shared, window)
+    }
+
+    // What read_ahead does once the buffer is empty after a seek request
+    fn finish_seek(shared: &Shared) {
+        shared.start.store(shared.wanted.load(Ordering::Acquire), Ordering::Release);
+        shared
+            .seeks_done
+            .store(shared.seeks_requested.load(Ordering::Acquire), Ordering::Release);
+    }
+
+    #[test]
+    fn reads_buffered_frames() {
+        let (mut producer, _, mut window) = window(2);
+        producer.push_slice(&[0.0, 0.5, 1.0, 1.5, 2.0, 2.5]);
+        window.sync();
+        assert_eq!(window.sample(0, 0), Some(0.0));
+        assert_eq!(window.sample(2, 1), Some(2.5));
+        assert_eq!(window.sample(3, 0), None);
+
+        window.advance(2);
+        assert_eq!(window.sample(1, 0), None);
+        assert_eq!(window.sample(2, 0), Some(2.0));
+    }
+
+    #[test]
+    fn mono_plays_on_every_channel() {
+        let (mut producer, _, mut window) = window(1);
+        producer.push_slice(&[0.25, 0.75]);
+        window.sync();
+        assert_eq!(window.sample(1, 0), Some(0.75));
+        assert_eq!(window.sample(1, 1), Some(0.75));
+    }
+
+    #[test]
+    fn jumping_outside_the_buffer_seeks() {
+        let (mut producer, shared, mut window) = window(1);
+        producer.push_slice(&[0.0, 1.0, 2.0]);
+        window.sync();
+
+        window.advance(100);
+        assert_eq!(shared.seeks_requested.load(Ordering::Acquire), 1);
+        assert_eq!(shared.wanted.load(Ordering::Acquire), 100);
+        assert_eq!(window.sample(0, 0), None);
+
+        // Audio from before the seek is dropped, and nothing plays until the reader has seeked
+        window.sync();
+        assert!(producer.is_empty());
+        assert_eq!(window.sample(100, 0), None);
+
+        finish_seek(&shared);
+        producer.push_slice(&[100.0, 101.0]);
+        window.sync();
+        assert_eq!(window.sample(100, 0), Some(100.0));
+        assert_eq!(window.sample(101, 0), Some(101.0));
+    }
+
+    #[test]
+    fn a_seek_from_the_ui_drops_stale_audio() {
+        let (mut producer, shared, mut window) = window(1);
+        producer.push_slice(&[0.0, 1.0]);
+        shared.request_seek(10);
+
+        // Pushed before the reader noticed the request, so it belongs to the old position
+        window.sync();
+        assert_eq!(window.sample(0, 0), None);
+        assert!(producer.is_empty());
+
+        // A second seek while the first is being made only counts once it is done too
+        shared.request_seek(20);
+        finish_seek(&shared);
+        producer.push_slice(&[20.0]);
+        window.sync();
+        assert_eq!(window.sample(20, 0), Some(20.0));
+        assert_eq!(window.sample(10, 0), None);
+    }
+
+    #[test]
+    fn past_end_only_once_the_file_is_read() {
+        let (mut producer, shared, mut window) = window(1);
+        producer.push_slice(&[0.0, 1.0]);
+        window.sync();
+        assert!(!window.is_past_end(2));
+
+        shared.end_of_file.store(true, Ordering::Release);
+        assert!(!window.is_past_end(1));
+        assert!(window.is_past_end(2));
+
+        // Advancing past the end doesn't make the reader seek
+        window.advance(5);
+        assert_eq!(shared.seeks_requested.load(Ordering::Acquire), 0);
+    }
+}
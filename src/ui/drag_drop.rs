@@ -1,16 +1,13 @@
+use crate::audio;
 use crate::daw::{DawAction, DawApp};
 use crate::group::Group;
 use crate::ui::main::{BASE_PIXELS_PER_BEAT, TRACK_HEIGHT, TRACK_SPACING};
 use eframe::egui;
 use std::path::{Path, PathBuf};
 
-/// Check if a file has a supported audio extension
+/// Check if a file holds audio the decoder can play
 pub fn is_supported_audio_file(path: &Path) -> bool {
-    if let Some(ext) = path.extension() {
-        let ext = ext.to_string_lossy().to_lowercase();
-        return ext == "wav" || ext == "mp3" || ext == "ogg" || ext == "flac";
-    }
-    false
+    audio::is_audio_file(path)
 }
 
 /// Filter dropped files to only include audio files
@@ -34,7 +31,7 @@ pub fn draw_drop_overlay(ctx: &egui::Context, drag_active: bool) {
     
     // Get the grid rect
     if let Some(grid_rect) = ctx.memory(|mem| mem.data.get_temp::<egui::Rect>(egui::Id::new("grid_rect"))) {
-        eprintln!("Drawing drag overlay over grid rect: {:?}", grid_rect);
+        log::trace!(target: "ui", "Drawing drag overlay over grid rect: {:?}", grid_rect);
         
         // Determine if this is a group drag
         let is_group_drag = ctx.memory(|mem| 
@@ -94,7 +91,7 @@ pub fn draw_drop_overlay(ctx: &egui::Context, drag_active: bool) {
                 );
             });
     } else {
-        eprintln!("Cannot draw drag overlay - grid rect not found");
+        log::warn!(target: "ui", "Cannot draw drag overlay - grid rect not found");
     }
 }
 
@@ -122,24 +119,24 @@ pub fn is_drag_active(ctx: &egui::Context) -> bool {
     let external_drag = !ctx.input(|i| i.raw.hovered_files.is_empty());
     
     if internal_file_drag {
-        eprintln!("DEBUG: Internal file is being dragged");
+        log::trace!(target: "ui", "Internal file is being dragged");
     }
     
     if group_drag {
-        eprintln!("DEBUG: dragged_group is present in memory");
+        log::trace!(target: "ui", "dragged_group is present in memory");
     }
     
     if group_drag_active {
-        eprintln!("DEBUG: group_drag_active flag is true");
+        log::trace!(target: "ui", "group_drag_active flag is true");
     }
 
     if external_drag {
-        eprintln!("DEBUG: External files are being dragged: {}", ctx.input(|i| i.raw.hovered_files.len()));
+        log::trace!(target: "ui", "External files are being dragged: {}", ctx.input(|i| i.raw.hovered_files.len()));
     }
     
     let is_active = internal_file_drag || external_drag || group_drag_active || group_drag;
     if is_active {
-        eprintln!("DEBUG: Drag is active (internal={}, external={}, group_flag={}, group_obj={})",
+        log::trace!(target: "ui", "Drag is active (internal={}, external={}, group_flag={}, group_obj={})",
             internal_file_drag, external_drag, group_drag_active, group_drag);
     }
     
@@ -152,17 +149,17 @@ pub fn calculate_drop_target(
     ctx: &egui::Context,
     mouse_pos: egui::Pos2,
 ) -> Option<DropTarget> {
-    eprintln!("DEBUG: Calculating drop target for mouse position: {:?}", mouse_pos);
+    log::trace!(target: "ui", "Calculating drop target for mouse position: {:?}", mouse_pos);
     
     // Access the grid rect directly from memory
     if let Some(grid_rect) = ctx.memory(|mem| mem.data.get_temp::<egui::Rect>(egui::Id::new("grid_rect"))) {
         // Only process if inside the grid
         if !grid_rect.contains(mouse_pos) {
-            eprintln!("DEBUG: Mouse position {:?} is outside grid rect {:?}", mouse_pos, grid_rect);
+            log::trace!(target: "ui", "Mouse position {:?} is outside grid rect {:?}", mouse_pos, grid_rect);
             return None;
         }
         
-        eprintln!("DEBUG: Mouse position {:?} is inside grid rect {:?}", mouse_pos, grid_rect);
+        log::trace!(target: "ui", "Mouse position {:?} is inside grid rect {:?}", mouse_pos, grid_rect);
         
         // Calculate the beat position based on mouse position
         let h_scroll_offset = app.state.h_scroll_offset;
@@ -179,14 +176,14 @@ pub fn calculate_drop_target(
         let pos_y = mouse_pos.y - grid_rect.top() + v_scroll_offset;
         let track_idx = (pos_y / (TRACK_HEIGHT + TRACK_SPACING)).floor() as usize;
         
-        eprintln!("DEBUG: Track index calculated: {} (pos_y={}, v_scroll_offset={})", 
+        log::trace!(target: "ui", "Track index calculated: {} (pos_y={}, v_scroll_offset={})", 
             track_idx, pos_y, v_scroll_offset);
-        eprintln!("DEBUG: Beat position calculated: {} (pos_x={}, h_scroll_offset={})",
+        log::trace!(target: "ui", "Beat position calculated: {} (pos_x={}, h_scroll_offset={})",
             beat_position, pos_x, h_scroll_offset);
         
         // Determine which track to use
         if app.state.tracks.is_empty() {
-            eprintln!("DEBUG: No tracks available in the project");
+            log::trace!(target: "ui", "No tracks available in the project");
             return None;
         }
         
@@ -195,23 +192,23 @@ pub fn calculate_drop_target(
             app.state.tracks[track_idx].id
         } else {
             // If we're beyond the last track, use the last available track
-            eprintln!("DEBUG: Track index {} is beyond available tracks (count={}), using last track", 
+            log::trace!(target: "ui", "Track index {} is beyond available tracks (count={}), using last track", 
                 track_idx, app.state.tracks.len());
             app.state.tracks.last().unwrap().id
         };
         
-        eprintln!("DEBUG: Using track ID: {} for drop target", track_id);
+        log::trace!(target: "ui", "Using track ID: {} for drop target", track_id);
         
         // Snap the beat position to the grid
         let snapped_beat = app.snap_to_grid(beat_position);
-        eprintln!("DEBUG: Snapped beat position: {} (original: {})", snapped_beat, beat_position);
+        log::trace!(target: "ui", "Snapped beat position: {} (original: {})", snapped_beat, beat_position);
         
         return Some(DropTarget {
             track_id,
             beat_position: snapped_beat,
         });
     } else {
-        eprintln!("DEBUG: Grid rect not found in memory");
+        log::trace!(target: "ui", "Grid rect not found in memory");
     }
     
     None
@@ -224,7 +221,7 @@ pub fn handle_external_file_drop(app: &mut DawApp, ctx: &egui::Context) {
         return;
     }
 
-    eprintln!("Files dropped: {:?}", dropped_files.len());
+    log::debug!(target: "ui", "Files dropped: {:?}", dropped_files.len());
     
     // Get the current mouse position to determine the target track and position
     if let Some(mouse_pos) = ctx.input(|i| i.pointer.hover_pos()) {
@@ -232,11 +229,11 @@ pub fn handle_external_file_drop(app: &mut DawApp, ctx: &egui::Context) {
             // Process each dropped file
             for file in dropped_files {
                 if let Some(path) = file.path {
-                    eprintln!("Processing dropped file: {:?}", path);
+                    log::debug!(target: "ui", "Processing dropped file: {:?}", path);
                     
                     // Check if the file is an audio file
                     if is_supported_audio_file(&path) {
-                        eprintln!("Adding sample at beat position: {}", target.beat_position);
+                        log::debug!(target: "ui", "Adding sample at beat position: {}", target.beat_position);
                         
                         // Add the sample to the track
                         app.dispatch(DawAction::AddSampleToTrack(target.track_id, path.clone()));
@@ -245,7 +242,7 @@ pub fn handle_external_file_drop(app: &mut DawApp, ctx: &egui::Context) {
                         if let Some(track) = app.state.tracks.iter_mut().find(|t| t.id == target.track_id) {
                             if !track.samples.is_empty() {
                                 let sample_id = track.samples.last().unwrap().id;
-                                eprintln!("Moving sample ID {} to position {}", sample_id, target.beat_position);
+                                log::debug!(target: "ui", "Moving sample ID {} to position {}", sample_id, target.beat_position);
                                 
                                 // Move the sample to the drop position without handling overlaps
                                 if let Some(sample) = track.get_sample_mut(sample_id) {
@@ -253,10 +250,10 @@ pub fn handle_external_file_drop(app: &mut DawApp, ctx: &egui::Context) {
                                     sample.update_grid_times(app.state.bpm);
                                 }
                             } else {
-                                eprintln!("No samples found in track after adding");
+                                log::warn!(target: "ui", "No samples found in track after adding");
                             }
                         } else {
-                            eprintln!("Could not find track with ID {}", target.track_id);
+                            log::warn!(target: "ui", "Could not find track with ID {}", target.track_id);
                         }
                     }
                 }
@@ -283,7 +280,7 @@ pub fn handle_internal_file_drop(app: &mut DawApp, ctx: &egui::Context) {
     }
     
     if let Some(dragged_path) = ctx.memory(|mem| mem.data.get_temp::<PathBuf>(egui::Id::new("dragged_file"))) {
-        eprintln!("Internal file drag detected: {:?}", dragged_path);
+        log::debug!(target: "ui", "Internal file drag detected: {:?}", dragged_path);
         
         // Check if it's an audio file
         if !is_supported_audio_file(&dragged_path) {
@@ -296,10 +293,10 @@ pub fn handle_internal_file_drop(app: &mut DawApp, ctx: &egui::Context) {
         
         // Get mouse position
         if let Some(mouse_pos) = ctx.input(|i| i.pointer.interact_pos()) {
-            eprintln!("Mouse position on release: {:?}", mouse_pos);
+            log::debug!(target: "ui", "Mouse position on release: {:?}", mouse_pos);
             
             if let Some(target) = calculate_drop_target(app, ctx, mouse_pos) {
-                eprintln!("Adding sample at beat position: {}", target.beat_position);
+                log::debug!(target: "ui", "Adding sample at beat position: {}", target.beat_position);
                 
                 // Add the sample to the track
                 app.dispatch(DawAction::AddSampleToTrack(target.track_id, dragged_path));
@@ -308,15 +305,15 @@ pub fn handle_internal_file_drop(app: &mut DawApp, ctx: &egui::Context) {
                 if let Some(track) = app.state.tracks.iter_mut().find(|t| t.id == target.track_id) {
                     if !track.samples.is_empty() {
                         let sample_id = track.samples.last().unwrap().id;
-                        eprintln!("Moving sample ID {} to position {}", sample_id, target.beat_position);
+                        log::debug!(target: "ui", "Moving sample ID {} to position {}", sample_id, target.beat_position);
                         
                         // Move the sample to the drop position
                         app.dispatch(DawAction::MoveSample(target.track_id, sample_id, target.beat_position));
                     } else {
-                        eprintln!("No samples found in track after adding");
+                        log::warn!(target: "ui", "No samples found in track after adding");
                     }
                 } else {
-                    eprintln!("Could not find track with ID {}", target.track_id);
+                    log::warn!(target: "ui", "Could not find track with ID {}", target.track_id);
                 }
             }
         }
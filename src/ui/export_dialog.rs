@@ -0,0 +1,191 @@
+use crate::export::{
+    ExportFormat, ExportSettings, StemOptions, StemSource, StemTap, LOUDNESS_TARGETS, SAMPLE_RATES,
+};
+use eframe::egui;
+
+/// What the user asked to export
+pub enum ExportRequest {
+    Selection(ExportSettings),
+    Stems(ExportSettings, StemOptions),
+}
+
+/// Floating window for choosing what to export and its delivery format
+#[derive(Clone, Default)]
+pub struct ExportDialog {
+    pub open: bool,
+    settings: Option<ExportSettings>, // Filled from the last export when the window opens
+    stem_options: StemOptions,
+    stems: bool, // Export stems rather than the selection mix
+}
+
+impl ExportDialog {
+    pub fn load(ctx: &egui::Context) -> Self {
+        ctx.memory(|mem| mem.data.get_temp::<ExportDialog>(egui::Id::new("export_dialog")))
+            .unwrap_or_default()
+    }
+
+    pub fn store(self, ctx: &egui::Context) {
+        ctx.memory_mut(|mem| mem.data.insert_temp(egui::Id::new("export_dialog"), self));
+    }
+
+    /// Open the window with `settings` and `stem_options` selected
+    pub fn show(&mut self, settings: ExportSettings, stem_options: StemOptions) {
+        self.open = true;
+        self.settings = Some(settings);
+        self.stem_options = stem_options;
+    }
+
+    /// Draws the window if it is open. Returns what to export once the user confirms.
+    /// Without a selection only stems of the whole project can be exported.
+    pub fn draw(&mut self, ctx: &egui::Context, has_selection: bool) -> Option<ExportRequest> {
+        let mut confirmed = None;
+        let mut open = self.open;
+        let settings = self.settings.get_or_insert_with(ExportSettings::default);
+        let stem_options = &mut self.stem_options;
+        let stems = &mut self.stems;
+        if !has_selection {
+            *stems = true;
+        }
+
+        egui::Window::new("Export")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add_enabled_ui(has_selection, |ui| {
+                        ui.radio_value(stems, false, "Selection mix");
+                    });
+                    ui.radio_value(stems, true, "Stems");
+                });
+                if *stems {
+                    ui.horizontal(|ui| {
+                        for source in [StemSource::Tracks, StemSource::Groups] {
+                            ui.radio_value(&mut stem_options.source, source, source.label());
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        for tap in [StemTap::PreFader, StemTap::PostFader] {
+                            ui.radio_value(&mut stem_options.tap, tap, tap.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Tracks have no volume yet, so this only decides whether muted tracks, \
+                         and unsoloed ones while any is soloed, give silent stems as in the mix",
+                    );
+                    ui.add_enabled(false, egui::Checkbox::new(&mut false, "Include inserts"))
+                        .on_disabled_hover_text("Tracks have no inserts yet, so stems are always dry");
+                    let range = if has_selection { "the selection" } else { "the whole project" };
+                    ui.label(format!("Every stem covers {}, so they line up on import.", range));
+                }
+                ui.separator();
+
+                egui::Grid::new("export_settings")
+                    .num_columns(2)
+                    .spacing([12.0, 6.0])
+                    .show(ui, |ui| {
+                        ui.label("Format:");
+                        egui::ComboBox::from_id_salt("export_format")
+                            .selected_text(settings.format.label())
+                            .show_ui(ui, |ui| {
+                                for format in ExportFormat::ALL {
+                                    ui.selectable_value(&mut settings.format, format, format.label());
+                                }
+                            });
+                        ui.end_row();
+
+                        // AIFF and FLAC have no float variant here
+                        *settings = settings.normalized();
+                        ui.label("Bit depth:");
+                        egui::ComboBox::from_id_salt("export_bit_depth")
+                            .selected_text(settings.bit_depth.label())
+                            .show_ui(ui, |ui| {
+                                for &depth in settings.format.bit_depths() {
+                                    ui.selectable_value(&mut settings.bit_depth, depth, depth.label());
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Sample rate:");
+                        egui::ComboBox::from_id_salt("export_sample_rate")
+                            .selected_text(format!("{} Hz", settings.sample_rate))
+                            .show_ui(ui, |ui| {
+                                for &rate in SAMPLE_RATES {
+                                    ui.selectable_value(&mut settings.sample_rate, rate, format!("{} Hz", rate));
+                                }
+                            });
+                        ui.end_row();
+                    });
+
+                // Dither only applies when rounding to integer samples
+                ui.add_space(4.0);
+                let integer = settings.bit_depth.bits() < 32;
+                ui.add_enabled_ui(integer, |ui| {
+                    ui.checkbox(&mut settings.dither, "Dither (TPDF)")
+                        .on_hover_text("Add low-level noise so rounding to fewer bits doesn't distort quiet passages");
+                    ui.add_enabled_ui(settings.dither, |ui| {
+                        ui.checkbox(&mut settings.noise_shaping, "Noise shaping")
+                            .on_hover_text("Move the dither noise towards frequencies the ear is least sensitive to");
+                    });
+                });
+
+                // Loudness normalization, measured after rate conversion
+                ui.add_space(4.0);
+                ui.checkbox(&mut settings.normalize, "Normalize loudness");
+                ui.add_enabled_ui(settings.normalize, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Target:");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.target_lufs)
+                                .range(-40.0..=0.0)
+                                .speed(0.1)
+                                .fixed_decimals(1)
+                                .suffix(" LUFS"),
+                        );
+                        let preset = LOUDNESS_TARGETS
+                            .iter()
+                            .find(|(lufs, _)| *lufs == settings.target_lufs)
+                            .map_or("Custom", |(_, name)| *name);
+                        egui::ComboBox::from_id_salt("export_loudness_target")
+                            .selected_text(preset)
+                            .show_ui(ui, |ui| {
+                                for &(lufs, name) in LOUDNESS_TARGETS {
+                                    ui.selectable_value(
+                                        &mut settings.target_lufs,
+                                        lufs,
+                                        format!("{} ({} LUFS)", name, lufs),
+                                    );
+                                }
+                            });
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("True peak ceiling:");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.true_peak_ceiling)
+                                .range(-12.0..=0.0)
+                                .speed(0.1)
+                                .fixed_decimals(1)
+                                .suffix(" dBTP"),
+                        )
+                        .on_hover_text("The gain is lowered if reaching the target would peak above this");
+                    });
+                });
+
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Export…").clicked() {
+                        confirmed = Some(if *stems {
+                            ExportRequest::Stems(*settings, *stem_options)
+                        } else {
+                            ExportRequest::Selection(*settings)
+                        });
+                    }
+                });
+            });
+
+        // The file dialog takes over once the user confirms
+        self.open = open && confirmed.is_none();
+        confirmed
+    }
+}
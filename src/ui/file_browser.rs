@@ -1,11 +1,61 @@
+use crate::audio;
 use eframe::egui;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Which files of the listed folder hold audio, filled in by a background thread
+#[derive(Clone, Default)]
+struct Probes {
+    is_audio: Arc<Mutex<HashMap<PathBuf, bool>>>,
+    done: Arc<AtomicBool>,
+    cancelled: Arc<AtomicBool>, // Set when another folder is listed
+}
+
+impl Probes {
+    // Probe `files` on a background thread, as opening every file on the UI thread stalls it
+    fn start(files: Vec<PathBuf>) -> Self {
+        let probes = Probes::default();
+        let shared = probes.clone();
+        let spawned = thread::Builder::new().name("file-probe".to_string()).spawn(move || {
+            for path in files {
+                if shared.cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                let is_audio = audio::is_audio_file(&path);
+                shared.is_audio.lock().unwrap().insert(path, is_audio);
+            }
+            shared.done.store(true, Ordering::Relaxed);
+        });
+        if let Err(e) = spawned {
+            log::error!(target: "ui", "Failed to start probing files: {}", e);
+            probes.done.store(true, Ordering::Relaxed);
+        }
+        probes
+    }
+
+    // None while the file hasn't been probed yet
+    fn is_audio(&self, path: &Path) -> Option<bool> {
+        self.is_audio.lock().unwrap().get(path).copied()
+    }
+
+    fn is_done(&self) -> bool {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
 
 /// A panel that displays files in the current project folder.
 /// Allows users to browse directories and drag audio files to the grid.
 #[derive(Clone)]
 pub struct FileBrowserPanel {
-    files: Vec<(String, PathBuf, bool)>, // (filename, path, is_audio)
+    files: Vec<(String, PathBuf)>, // (filename, path)
+    probes: Probes,
     current_folder: PathBuf,
     show_panel: bool,
 }
@@ -19,15 +69,17 @@ impl FileBrowserPanel {
         };
         
         let files = Self::scan_directory(&current_folder);
+        let probes = Self::probe(&files);
         
         Self {
             files,
+            probes,
             current_folder,
             show_panel: true,
         }
     }
     
-    fn scan_directory(path: &Path) -> Vec<(String, PathBuf, bool)> {
+    fn scan_directory(path: &Path) -> Vec<(String, PathBuf)> {
         let mut files = Vec::new();
         
         if let Ok(entries) = std::fs::read_dir(path) {
@@ -38,20 +90,7 @@ impl FileBrowserPanel {
                     .unwrap_or("Unknown")
                     .to_string();
                 
-                // Check if it's an audio file
-                let is_audio = if file_path.is_file() {
-                    match file_path.extension() {
-                        Some(ext) => {
-                            let ext = ext.to_string_lossy().to_lowercase();
-                            ext == "wav" || ext == "mp3" || ext == "ogg" || ext == "flac"
-                        }
-                        None => false,
-                    }
-                } else {
-                    false
-                };
-                
-                files.push((file_name, file_path, is_audio));
+                files.push((file_name, file_path));
             }
         }
         
@@ -72,8 +111,19 @@ impl FileBrowserPanel {
         files
     }
     
+    fn probe(files: &[(String, PathBuf)]) -> Probes {
+        let paths = files
+            .iter()
+            .map(|(_, path)| path.clone())
+            .filter(|path| !path.is_dir())
+            .collect();
+        Probes::start(paths)
+    }
+    
     pub fn refresh(&mut self) {
+        self.probes.cancel();
         self.files = Self::scan_directory(&self.current_folder);
+        self.probes = Self::probe(&self.files);
     }
     
     pub fn navigate_to(&mut self, path: PathBuf) {
@@ -98,6 +148,10 @@ impl FileBrowserPanel {
         ui.label(format!("📂 {}", self.current_folder.display()));
         ui.separator();
         
+        if !self.probes.is_done() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        }
+        
         // File list with scrolling
         egui::ScrollArea::vertical().show(ui, |ui| {
             // Check if we're in the default directory instead of a project folder
@@ -113,9 +167,15 @@ impl FileBrowserPanel {
                 ui.separator();
             }
             
-            for (i, (name, path, is_audio)) in self.files.iter().enumerate() {
+            for (i, (name, path)) in self.files.iter().enumerate() {
                 let is_dir = path.is_dir();
-                let icon = if is_dir { "📁" } else if *is_audio { "🔊" } else { "📄" };
+                let is_audio = self.probes.is_audio(path);
+                let icon = match is_audio {
+                    _ if is_dir => "📁",
+                    Some(true) => "🔊",
+                    Some(false) => "📄",
+                    None => "…",
+                };
                 
                 let response = ui.horizontal(|ui| {
                     ui.label(format!("{} {}", icon, name));
@@ -130,17 +190,17 @@ impl FileBrowserPanel {
                 }
                 
                 // Make audio files draggable
-                if *is_audio {
+                if is_audio == Some(true) {
                     // Handle drag and drop manually
                     if response.dragged() {
                         file_dragged = true;
                         
-                        eprintln!("Dragging audio file: {}", name);
+                        log::trace!(target: "ui", "Dragging audio file: {}", name);
                         
                         // Store the dragged path
                         ctx.memory_mut(|mem| {
                             mem.data.insert_temp(egui::Id::new("dragged_file"), path.clone());
-                            eprintln!("Stored dragged file path in memory: {:?}", path);
+                            log::trace!(target: "ui", "Stored dragged file path in memory: {:?}", path);
                         });
                         
                         // Show drag visual
@@ -155,7 +215,7 @@ impl FileBrowserPanel {
                     // Also check if the response was clicked and the pointer is still down
                     // This helps with touch/mobile drag detection
                     if response.clicked() && ctx.input(|i| i.pointer.any_down()) {
-                        eprintln!("Audio file clicked, preparing for potential drag: {}", name);
+                        log::trace!(target: "ui", "Audio file clicked, preparing for potential drag: {}", name);
                     }
                 }
             }
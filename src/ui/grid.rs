@@ -6,7 +6,12 @@ use crate::ui::main::{
 };
 use crate::ui::grid_item::{GridItem, GridItemDragging, GridItemHelper};
 use crate::daw::TrackItemType;
+use crate::peaks::Peaks;
+use crate::stream::Playback;
 use egui::{Color32, Stroke};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
 
 pub struct Grid<'a> {
     pub timeline_position: f32,
@@ -21,11 +26,17 @@ pub struct Grid<'a> {
         bool,
         Vec<(usize, String, f32, f32, Vec<f32>, u32, f32, f32, f32, TrackItemType)>,
     )>, // Track ID, Name, muted, soloed, recording, samples: (Sample ID, name, position, length, waveform, sample_rate, duration, audio_start_time, audio_end_time, item_type)
+    pub peaks: &'a HashMap<(usize, usize), Arc<Peaks>>, // (track_id, sample_id) -> peaks, drawn instead of the waveform when present
+    pub loading: &'a HashMap<(usize, usize), f32>,      // (track_id, sample_id) -> decode progress of clips still loading
+    pub playback: &'a HashMap<(usize, usize), Playback>, // (track_id, sample_id) -> memory or disk, for audio clips
+    pub groups: &'a HashMap<(usize, usize), (Option<String>, PathBuf)>, // (track_id, sample_id) -> variant and Group folder, for Group instances
     pub on_track_drag: &'a mut dyn FnMut(usize, usize, f32), // track_id, sample_id, new_position
     pub on_cross_track_move: &'a mut dyn FnMut(usize, usize, usize, f32), // source_track_id, sample_id, target_track_id, new_position
     pub on_track_mute: &'a mut dyn FnMut(usize),                          // track_id
     pub on_track_solo: &'a mut dyn FnMut(usize),                          // track_id
     pub on_track_record: &'a mut dyn FnMut(usize),                        // track_id
+    pub frozen: &'a HashSet<usize>,                                       // Ids of the tracks playing a freeze render
+    pub on_track_freeze: &'a mut dyn FnMut(usize),                        // track_id, freezes or unfreezes
     pub on_delete_sample: &'a mut dyn FnMut(usize, usize), // track_id, sample_id - Callback when a sample is deleted using backspace/delete key
     pub h_scroll_offset: f32,                              // Horizontal scroll offset in seconds
     pub v_scroll_offset: f32,                              // Vertical scroll offset in pixels
@@ -423,9 +434,6 @@ impl<'a> Grid<'a> {
         // Store the grid rect in memory for drag and drop functionality
         ui.ctx().memory_mut(|mem| {
             mem.data.insert_temp(egui::Id::new("grid_rect"), grid_rect);
-            
-            // Debug output to verify the grid rect is being stored
-            // eprintln!("Grid rect stored: {:?}", grid_rect);
         });
 
         // --- Define Coordinate Helper Functions HERE (Moved Earlier) ---
@@ -439,20 +447,20 @@ impl<'a> Grid<'a> {
         let screen_y_to_track_index = move |screen_y: f32| -> Option<usize> {
             let y_relative_to_grid = screen_y - grid_rect.top();
             if y_relative_to_grid < 0.0 {
-                eprintln!("Clicked above the grid: y_relative={}", y_relative_to_grid);
+                log::trace!(target: "ui", "Clicked above the grid: y_relative={}", y_relative_to_grid);
                 return None; // Clicked above the grid
             }
             let scrolled_y = v_scroll_offset + y_relative_to_grid;
             let track_index_f = scrolled_y / (TRACK_HEIGHT + TRACK_SPACING);
             let track_index = track_index_f.floor() as usize;
 
-            eprintln!("Track index calculation: y_relative={}, scrolled_y={}, track_index_f={}, track_index={}, tracks_len={}", 
+            log::trace!(target: "ui", "Track index calculation: y_relative={}, scrolled_y={}, track_index_f={}, track_index={}, tracks_len={}", 
                       y_relative_to_grid, scrolled_y, track_index_f, track_index, tracks_len);
 
             if track_index < tracks_len {
                 Some(track_index)
             } else {
-                eprintln!("Clicked below the last track: track_index={}, tracks_len={}", track_index, tracks_len);
+                log::trace!(target: "ui", "Clicked below the last track: track_index={}, tracks_len={}", track_index, tracks_len);
                 None // Clicked below the last track
             }
         };
@@ -623,6 +631,12 @@ impl<'a> Grid<'a> {
                 button_size,
             );
 
+            let freeze_rect = egui::Rect::from_min_size(
+                egui::Pos2::new(control_left + 3.0 * (button_size.x + 5.0), control_top),
+                button_size,
+            );
+            let frozen = self.frozen.contains(track_id);
+
             // Draw button backgrounds and text
             let mute_color = if *muted {
                 Color32::from_rgb(150, 50, 50)
@@ -642,10 +656,17 @@ impl<'a> Grid<'a> {
                 Color32::from_rgb(60, 60, 60)
             };
 
+            let freeze_color = if frozen {
+                Color32::from_rgb(50, 100, 150)
+            } else {
+                Color32::from_rgb(60, 60, 60)
+            };
+
             // Draw button backgrounds
             painter.rect_filled(mute_rect, 4.0, mute_color);
             painter.rect_filled(solo_rect, 4.0, solo_color);
             painter.rect_filled(record_rect, 4.0, record_color);
+            painter.rect_filled(freeze_rect, 4.0, freeze_color);
 
             // Draw button borders
             painter.rect_stroke(
@@ -666,6 +687,12 @@ impl<'a> Grid<'a> {
                 Stroke::new(1.0, Color32::from_rgb(80, 80, 80)),
                 egui::StrokeKind::Inside,
             );
+            painter.rect_stroke(
+                freeze_rect,
+                4.0,
+                Stroke::new(1.0, Color32::from_rgb(80, 80, 80)),
+                egui::StrokeKind::Inside,
+            );
 
             // Draw button text
             painter.text(
@@ -692,14 +719,26 @@ impl<'a> Grid<'a> {
                 TRACK_TEXT_COLOR,
             );
 
+            painter.text(
+                freeze_rect.center(),
+                egui::Align2::CENTER_CENTER,
+                if frozen { "❄" } else { "F" },
+                egui::FontId::proportional(14.0),
+                TRACK_TEXT_COLOR,
+            );
+
             // Handle button clicks
             let id_mute = ui.id().with(format!("mute_track_{}", track_id));
             let id_solo = ui.id().with(format!("solo_track_{}", track_id));
             let id_record = ui.id().with(format!("record_track_{}", track_id));
+            let id_freeze = ui.id().with(format!("freeze_track_{}", track_id));
 
             let mute_response = ui.interact(mute_rect, id_mute, egui::Sense::click());
             let solo_response = ui.interact(solo_rect, id_solo, egui::Sense::click());
             let record_response = ui.interact(record_rect, id_record, egui::Sense::click());
+            let freeze_response = ui
+                .interact(freeze_rect, id_freeze, egui::Sense::click())
+                .on_hover_text(if frozen { "Unfreeze: play the clips again" } else { "Freeze: render the track and play the render" });
 
             if mute_response.clicked() {
                 (self.on_track_mute)(*track_id);
@@ -713,6 +752,10 @@ impl<'a> Grid<'a> {
                 (self.on_track_record)(*track_id);
             }
 
+            if freeze_response.clicked() {
+                (self.on_track_freeze)(*track_id);
+            }
+
             // Draw each sample in the track
             for (
                 sample_index,
@@ -741,6 +784,13 @@ impl<'a> Grid<'a> {
                     position: *position,
                     length: *length,
                     waveform,
+                    peaks: self.peaks.get(&(*track_id, *sample_id)).map(|p| p.as_ref()),
+                    progress: self.loading.get(&(*track_id, *sample_id)).copied(),
+                    playback: self.playback.get(&(*track_id, *sample_id)).copied(),
+                    group: self
+                        .groups
+                        .get(&(*track_id, *sample_id))
+                        .map(|(variant, path)| (variant.as_deref(), path.as_path())),
                     sample_rate: *sample_rate,
                     duration: *duration,
                     audio_start_time: *audio_start_time,
@@ -878,7 +928,7 @@ impl<'a> Grid<'a> {
                     self.clicked_position = snapped_seconds_position;
                     
                     // Debug the clicked track
-                    eprintln!("Clicked at y={}, track_idx={:?}, position={}s (clicked_pos={}s)", 
+                    log::trace!(target: "ui", "Clicked at y={}, track_idx={:?}, position={}s (clicked_pos={}s)", 
                               pointer_pos.y, clicked_track, snapped_seconds_position, self.clicked_position);
 
                     // Update both the clicked position and timeline position, but use SEPARATE callbacks
@@ -1042,7 +1092,7 @@ impl<'a> Grid<'a> {
                     // Update the callback
                     (self.on_clicked_position_change)(new_position);
                     
-                    eprintln!("Moved marker left to {}s ({})", new_position, snapped_beat_pos);
+                    log::trace!(target: "ui", "Moved marker left to {}s ({})", new_position, snapped_beat_pos);
                 }
                 
                 if ui.input(|i| i.key_pressed(egui::Key::ArrowRight)) {
@@ -1058,7 +1108,7 @@ impl<'a> Grid<'a> {
                     // Update the callback
                     (self.on_clicked_position_change)(new_position);
                     
-                    eprintln!("Moved marker right to {}s ({})", new_position, snapped_beat_pos);
+                    log::trace!(target: "ui", "Moved marker right to {}s ({})", new_position, snapped_beat_pos);
                 }
             }
         }
@@ -1,8 +1,20 @@
 use crate::daw::{SelectionRect, TrackItemType};
+use crate::group;
+use crate::peaks::Peaks;
+use crate::stream::Playback;
 use crate::ui::main::{
     GROUP_COLOR, SAMPLE_BORDER_COLOR, TRACK_HEIGHT, TRACK_TEXT_COLOR, WAVEFORM_COLOR,
 };
 use egui::{Color32, Stroke};
+use std::path::Path;
+
+/// egui memory key for a clip whose playback was changed from its context menu: (track_id, sample_id, playback)
+pub const PLAYBACK_REQUEST_ID: &str = "clip_playback_request";
+pub const LOUDNESS_REQUEST_ID: &str = "clip_loudness_request";
+/// egui memory key for a Group instance whose variant was changed from its context menu: (track_id, sample_id, variant)
+pub const VARIANT_REQUEST_ID: &str = "clip_variant_request";
+/// egui memory key for a Group instance to be replaced by its clips: (track_id, sample_id)
+pub const UNGROUP_REQUEST_ID: &str = "clip_ungroup_request";
 
 /// Unified interface for grid items (samples or groups)
 pub struct GridItem<'a> {
@@ -15,6 +27,10 @@ pub struct GridItem<'a> {
     pub position: f32,
     pub length: f32,
     pub waveform: &'a Vec<f32>,
+    pub peaks: Option<&'a Peaks>, // Preferred over `waveform` when loaded
+    pub progress: Option<f32>,    // Decode progress while the audio is still loading
+    pub playback: Option<Playback>, // How the clip's audio is loaded, None for Groups
+    pub group: Option<(Option<&'a str>, &'a Path)>, // Variant a Group instance plays and the Group's folder, None for samples
     pub sample_rate: u32,
     pub duration: f32,
     pub audio_start_time: f32,
@@ -101,7 +117,10 @@ impl<'a> GridItem<'a> {
         if visible_width > 20.0 {
             let display_name = match self.item_type {
                 TrackItemType::Sample => self.item_name.to_string(),
-                TrackItemType::Group => format!("📦 {}", self.item_name), // Add box icon for groups
+                TrackItemType::Group => match self.group.and_then(|(variant, _)| variant) {
+                    Some(variant) => format!("📦 {} ({})", self.item_name, variant),
+                    None => format!("📦 {}", self.item_name), // Add box icon for groups
+                },
             };
             
             painter.text(
@@ -150,6 +169,18 @@ impl<'a> GridItem<'a> {
         region_left: f32,
         region_width: f32,
     ) {
+        if let Some(progress) = self.progress {
+            self.draw_progress(progress, painter, region_rect, visible_left - region_left, region_width);
+            return;
+        }
+
+        if let Some(peaks) = self.peaks {
+            if self.duration > 0.0 && peaks.frames > 0 {
+                self.draw_peaks(peaks, painter, region_rect, visible_left - region_left, visible_width, region_width);
+                return;
+            }
+        }
+
         // Draw waveform if data is available
         if !self.waveform.is_empty() && self.duration > 0.0 {
             let waveform_length = self.waveform.len();
@@ -204,6 +235,79 @@ impl<'a> GridItem<'a> {
         }
     }
 
+    /// Draw a progress bar across a clip whose audio is still loading
+    fn draw_progress(
+        &self,
+        progress: f32,
+        painter: &egui::Painter,
+        region_rect: &egui::Rect,
+        offset: f32, // Pixels of the item scrolled out of view on the left
+        region_width: f32,
+    ) {
+        let bar_top = region_rect.center().y - 2.0;
+        let bar_right = region_rect.left() - offset + region_width * progress.clamp(0.0, 1.0);
+        if bar_right > region_rect.left() {
+            let bar = egui::Rect::from_min_max(
+                egui::Pos2::new(region_rect.left(), bar_top),
+                egui::Pos2::new(bar_right.min(region_rect.right()), bar_top + 4.0),
+            );
+            painter.rect_filled(bar, 2.0, WAVEFORM_COLOR);
+        }
+
+        if region_rect.width() > 60.0 {
+            painter.text(
+                egui::Pos2::new(region_rect.left() + 4.0, bar_top + 8.0),
+                egui::Align2::LEFT_TOP,
+                format!("Loading {:.0}%", progress * 100.0),
+                egui::FontId::proportional(10.0),
+                TRACK_TEXT_COLOR,
+            );
+        }
+    }
+
+    /// Draw min/max peaks at the level of detail matching the current zoom
+    fn draw_peaks(
+        &self,
+        peaks: &Peaks,
+        painter: &egui::Painter,
+        region_rect: &egui::Rect,
+        hidden_width: f32, // Part of the region scrolled out of view on the left
+        visible_width: f32,
+        region_width: f32,
+    ) {
+        // Trim points as fractions of the audio, mapped onto its frames
+        let trim_start_ratio = self.audio_start_time / self.duration;
+        let trim_end_ratio = self.audio_end_time / self.duration;
+        let frames = peaks.frames as f32;
+        let frames_per_pixel = (trim_end_ratio - trim_start_ratio) * frames / region_width;
+        let level = peaks.level_for(frames_per_pixel);
+
+        let waveform_color = match self.item_type {
+            TrackItemType::Sample => WAVEFORM_COLOR,
+            TrackItemType::Group => Color32::from_rgb(160, 180, 200),
+        };
+        let center_y = region_rect.center().y;
+        let scale = region_rect.height() / 2.5;
+
+        for x in 0..visible_width as usize {
+            let full_region_pos = (hidden_width + x as f32) / region_width;
+            let start_ratio = trim_start_ratio + full_region_pos * (trim_end_ratio - trim_start_ratio);
+            let start_frame = (start_ratio * frames).max(0.0) as u64;
+            let end_frame = start_frame + frames_per_pixel.max(1.0) as u64;
+
+            if let Some((min, max)) = peaks.range(level, start_frame, end_frame) {
+                let x_pos = region_rect.left() + x as f32;
+                painter.line_segment(
+                    [
+                        egui::Pos2::new(x_pos, center_y - max * scale),
+                        egui::Pos2::new(x_pos, center_y - min * scale + 1.0),
+                    ],
+                    Stroke::new(1.0, waveform_color),
+                );
+            }
+        }
+    }
+
     /// Handle interaction with the grid item
     fn handle_interaction(
         &self,
@@ -227,6 +331,60 @@ impl<'a> GridItem<'a> {
 
         let mut interaction_occurred = false;
 
+        // Choose between holding the clip's audio in memory and streaming it from disk.
+        // The choice is picked up by the app through egui memory after the grid is drawn.
+        if let Some(current) = self.playback {
+            region_response.context_menu(|ui| {
+                for playback in [Playback::Auto, Playback::Memory, Playback::Disk] {
+                    if ui.radio(current == playback, playback.label()).clicked() {
+                        ui.memory_mut(|mem| {
+                            mem.data.insert_temp(
+                                egui::Id::new(PLAYBACK_REQUEST_ID),
+                                (self.track_id, self.item_id, playback),
+                            )
+                        });
+                        ui.close_menu();
+                    }
+                }
+                ui.separator();
+                if ui.button("Analyze Loudness").clicked() {
+                    ui.memory_mut(|mem| {
+                        mem.data
+                            .insert_temp(egui::Id::new(LOUDNESS_REQUEST_ID), (self.track_id, self.item_id))
+                    });
+                    ui.close_menu();
+                }
+            });
+        }
+
+        // Choose which of the Group's variants the instance plays. The variants are read from the
+        // Group's manifest only while the menu is open.
+        if let Some((current, group_path)) = self.group {
+            region_response.context_menu(|ui| {
+                let variants = group::load_manifest(group_path).map(|m| m.variants).unwrap_or_default();
+                let choices = std::iter::once(None).chain(variants.iter().map(|v| Some(v.as_str())));
+                for variant in choices {
+                    if ui.radio(current == variant, variant.unwrap_or("Main")).clicked() {
+                        ui.memory_mut(|mem| {
+                            mem.data.insert_temp(
+                                egui::Id::new(VARIANT_REQUEST_ID),
+                                (self.track_id, self.item_id, variant.map(str::to_string)),
+                            )
+                        });
+                        ui.close_menu();
+                    }
+                }
+                ui.separator();
+                if ui.button("Ungroup").clicked() {
+                    ui.memory_mut(|mem| {
+                        mem.data
+                            .insert_temp(egui::Id::new(UNGROUP_REQUEST_ID), (self.track_id, self.item_id))
+                    });
+                    ui.close_menu();
+                }
+            });
+        }
+
         // Handle single click (but skip if this is a double click on a group, which is handled separately)
         let is_group_double_click = region_response.double_clicked() && self.item_type == TrackItemType::Group;
         
@@ -1,8 +1,20 @@
-use crate::group::Group;
+use crate::group::{self, Group};
+use crate::library;
 use crate::ui::main::{SAMPLE_BORDER_COLOR, TRACK_TEXT_COLOR, WAVEFORM_COLOR};
 use eframe::egui;
 use std::path::{Path, PathBuf};
 
+/// What the user asked the Group panel to do with a Group
+pub enum GroupPanelAction {
+    Open(Group),
+    OpenVariant(String, String),
+    NewVariant(String, String),
+    Rename(String, String),
+    Delete(String),
+    ExportToLibrary(String),
+    ImportFromLibrary(String),
+}
+
 /// A panel that displays and manages Groups (audio containers).
 #[derive(Clone)]
 pub struct GroupPanel {
@@ -13,6 +25,12 @@ pub struct GroupPanel {
     new_name_buffer: String,
     new_group_name: String,
     show_create_dialog: bool,
+    variant_dialog: Option<String>, // Group a new variant is being named for
+    new_variant_name: String,
+    library_mode: bool, // Browsing the user's Group library instead of the project's Groups
+    library_groups: Vec<Group>,
+    library_search: String,
+    selected_library_idx: Option<usize>,
 }
 
 impl GroupPanel {
@@ -33,6 +51,12 @@ impl GroupPanel {
             new_name_buffer: String::new(),
             new_group_name: String::new(),
             show_create_dialog: false,
+            variant_dialog: None,
+            new_variant_name: String::new(),
+            library_mode: false,
+            library_groups: Vec::new(),
+            library_search: String::new(),
+            selected_library_idx: None,
         }
     }
     
@@ -43,16 +67,9 @@ impl GroupPanel {
         if let Ok(entries) = std::fs::read_dir(path) {
             for entry in entries.filter_map(|e| e.ok()) {
                 let dir_path = entry.path();
-                
-                // Check if it's a directory that might be a Group
-                if dir_path.is_dir() {
-                    let render_path = dir_path.join("render.wav");
-                    
-                    // If the directory has a render.wav file, it's a Group
-                    if render_path.exists() || true { // For now, consider all directories as potential Groups
-                        if let Ok(group) = Group::load(&dir_path) {
-                            groups.push(group);
-                        }
+                if group::is_group_folder(&dir_path) {
+                    if let Ok(group) = Group::load(&dir_path) {
+                        groups.push(group);
                     }
                 }
             }
@@ -70,12 +87,27 @@ impl GroupPanel {
     }
     
     /// Draw the Group panel UI
-    pub fn draw(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) -> Option<Group> {
-        let mut group_to_open = None;
+    pub fn draw(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) -> Option<GroupPanelAction> {
+        let mut action = None;
         
         ui.heading("Groups");
+        ui.horizontal(|ui| {
+            if ui.selectable_label(!self.library_mode, "Project").clicked() && self.library_mode {
+                self.library_mode = false;
+                self.refresh();
+            }
+            if ui.selectable_label(self.library_mode, "Library").clicked() && !self.library_mode {
+                self.library_mode = true;
+                self.library_groups = library::groups();
+                self.selected_library_idx = None;
+            }
+        });
         ui.separator();
         
+        if self.library_mode {
+            return self.draw_library(ui);
+        }
+        
         // Create Group button
         if ui.button("Create Group").clicked() {
             self.show_create_dialog = true;
@@ -101,8 +133,7 @@ impl GroupPanel {
                         
                         if ui.button("Create").clicked() {
                             if !self.new_group_name.trim().is_empty() {
-                                // Validate name (no slashes)
-                                if !self.new_group_name.contains('/') {
+                                if group::is_valid_group_name(&self.new_group_name) {
                                     match Group::new(&self.new_group_name, &self.current_folder) {
                                         Ok(group) => {
                                             // Add the new Group to the list
@@ -112,11 +143,11 @@ impl GroupPanel {
                                         }
                                         Err(e) => {
                                             // Show error
-                                            eprintln!("Error creating Group: {}", e);
+                                            log::error!(target: "groups", "Error creating Group: {}", e);
                                         }
                                     }
                                 } else {
-                                    eprintln!("Group name cannot contain '/'");
+                                    log::warn!(target: "groups", "'{}' is not a valid Group name", self.new_group_name);
                                 }
                             }
                         }
@@ -124,6 +155,32 @@ impl GroupPanel {
                 });
         }
         
+        // New variant dialog
+        if let Some(group_name) = self.variant_dialog.clone() {
+            egui::Window::new("New Variant")
+                .fixed_size([300.0, 100.0])
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label(format!("Variant of '{}':", group_name));
+                    ui.text_edit_singleline(&mut self.new_variant_name);
+                    
+                    ui.add_space(10.0);
+                    
+                    ui.horizontal(|ui| {
+                        if ui.button("Cancel").clicked() {
+                            self.variant_dialog = None;
+                        }
+                        
+                        let name = self.new_variant_name.trim();
+                        if ui.add_enabled(group::is_valid_variant_name(name), egui::Button::new("Create")).clicked() {
+                            action = Some(GroupPanelAction::NewVariant(group_name.clone(), name.to_string()));
+                            self.variant_dialog = None;
+                        }
+                    });
+                });
+        }
+        
         ui.separator();
         
         // Group list with scrolling
@@ -145,14 +202,14 @@ impl GroupPanel {
             let mut group_to_rename: Option<(usize, String)> = None;
             let mut group_to_delete: Option<usize> = None;
             
-            for (i, group) in self.groups.iter().enumerate() {
+            for (i, group) in self.groups.iter_mut().enumerate() {
                 let is_selected = self.selected_group_idx == Some(i);
                 let is_renaming = self.renaming_group_idx == Some(i);
                 
                 if is_renaming {
                     ui.horizontal(|ui| {
                         if ui.text_edit_singleline(&mut self.new_name_buffer).lost_focus() {
-                            if !self.new_name_buffer.trim().is_empty() && !self.new_name_buffer.contains('/') {
+                            if group::is_valid_group_name(&self.new_name_buffer) {
                                 // Store the rename operation for later
                                 group_to_rename = Some((i, self.new_name_buffer.clone()));
                             }
@@ -160,7 +217,7 @@ impl GroupPanel {
                         }
                         
                         if ui.button("✓").clicked() {
-                            if !self.new_name_buffer.trim().is_empty() && !self.new_name_buffer.contains('/') {
+                            if group::is_valid_group_name(&self.new_name_buffer) {
                                 // Store the rename operation for later
                                 group_to_rename = Some((i, self.new_name_buffer.clone()));
                             }
@@ -169,6 +226,10 @@ impl GroupPanel {
                     });
                 } else {
                     ui.horizontal(|ui| {
+                        if let Some([r, g, b]) = group.manifest.color {
+                            let (rect, _) = ui.allocate_exact_size(egui::vec2(8.0, 8.0), egui::Sense::hover());
+                            ui.painter().rect_filled(rect, 2.0, egui::Color32::from_rgb(r, g, b));
+                        }
                         let response = ui.selectable_label(is_selected, format!("📦 {}", group.name));
                         
                         if response.clicked() {
@@ -177,7 +238,7 @@ impl GroupPanel {
                         
                         // Double-click to open
                         if response.double_clicked() {
-                            group_to_open = Some(group.clone());
+                            action = Some(GroupPanelAction::Open(group.clone()));
                         }
                         
                         // Add a drag source
@@ -191,7 +252,27 @@ impl GroupPanel {
                         // Context menu
                         response.context_menu(|ui| {
                             if ui.button("Open").clicked() {
-                                group_to_open = Some(group.clone());
+                                action = Some(GroupPanelAction::Open(group.clone()));
+                                ui.close_menu();
+                            }
+                            
+                            if !group.manifest.variants.is_empty() {
+                                ui.menu_button("Open Variant", |ui| {
+                                    for variant in &group.manifest.variants {
+                                        if ui.button(variant).clicked() {
+                                            action = Some(GroupPanelAction::OpenVariant(
+                                                group.name.clone(),
+                                                variant.clone(),
+                                            ));
+                                            ui.close_menu();
+                                        }
+                                    }
+                                });
+                            }
+                            
+                            if ui.button("New Variant...").clicked() {
+                                self.variant_dialog = Some(group.name.clone());
+                                self.new_variant_name.clear();
                                 ui.close_menu();
                             }
                             
@@ -201,6 +282,11 @@ impl GroupPanel {
                                 ui.close_menu();
                             }
                             
+                            if ui.button("Export to Library").clicked() {
+                                action = Some(GroupPanelAction::ExportToLibrary(group.name.clone()));
+                                ui.close_menu();
+                            }
+                            
                             if ui.button("Delete").clicked() {
                                 // Mark for deletion after the loop
                                 group_to_delete = Some(i);
@@ -211,42 +297,125 @@ impl GroupPanel {
                 }
             }
             
-            // Process rename operation after the loop
+            // Renaming goes through the app, which also renames the Group's instances and tabs
             if let Some((i, new_name)) = group_to_rename {
-                if i < self.groups.len() {
-                    let mut group_clone = self.groups[i].clone();
-                    if let Err(e) = group_clone.rename(&new_name, &self.current_folder) {
-                        eprintln!("Error renaming Group: {}", e);
-                    } else {
-                        // Replace the group with the renamed one
-                        self.groups[i] = group_clone;
-                    }
+                if let Some(group) = self.groups.get(i) {
+                    action = Some(GroupPanelAction::Rename(group.name.clone(), new_name));
                 }
             }
             
-            // Process delete operation after the loop
+            if let Some(group) = self.selected_group_idx.and_then(|i| self.groups.get(i)) {
+                ui.separator();
+                Self::draw_details(ui, group);
+            }
+            
+            // Deleting goes through the app, which also cleans up the Group's instances and tab
             if let Some(i) = group_to_delete {
-                if i < self.groups.len() {
-                    if let Ok(_) = std::fs::remove_dir_all(&self.groups[i].path) {
-                        // Remove from the list
-                        self.groups.remove(i);
-                        if let Some(selected) = self.selected_group_idx {
-                            if selected == i || selected >= self.groups.len() {
-                                self.selected_group_idx = None;
-                            }
-                        }
-                    } else {
-                        eprintln!("Failed to delete Group: {}", self.groups[i].name);
-                    }
+                if let Some(group) = self.groups.get(i) {
+                    action = Some(GroupPanelAction::Delete(group.name.clone()));
+                    self.selected_group_idx = None;
+                }
+            }
+        });
+        
+        action
+    }
+    
+    /// Draw the Groups of the user's library, filtered by the search
+    fn draw_library(&mut self, ui: &mut egui::Ui) -> Option<GroupPanelAction> {
+        let mut action = None;
+        
+        ui.horizontal(|ui| {
+            ui.label("🔍");
+            ui.add(egui::TextEdit::singleline(&mut self.library_search).hint_text("Name or tags"));
+            if ui.button("⟳").clicked() {
+                self.library_groups = library::groups();
+                self.selected_library_idx = None;
+            }
+        });
+        ui.separator();
+        
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            if self.library_groups.is_empty() {
+                ui.label("The library is empty. Export a Group from its context menu to add it.");
+            }
+            
+            for (i, group) in self.library_groups.iter().enumerate() {
+                if !library::matches(group, &self.library_search) {
+                    continue;
                 }
+                let response = ui.selectable_label(self.selected_library_idx == Some(i), format!("📦 {}", group.name));
+                if response.clicked() {
+                    self.selected_library_idx = Some(i);
+                }
+                if response.double_clicked() {
+                    action = Some(GroupPanelAction::ImportFromLibrary(group.name.clone()));
+                }
+                response.context_menu(|ui| {
+                    if ui.button("Import into Project").clicked() {
+                        action = Some(GroupPanelAction::ImportFromLibrary(group.name.clone()));
+                        ui.close_menu();
+                    }
+                });
+            }
+            
+            if let Some(group) = self.selected_library_idx.and_then(|i| self.library_groups.get(i)) {
+                ui.separator();
+                Self::draw_details(ui, group);
             }
         });
         
-        group_to_open
+        action
+    }
+    
+    /// Show the properties from the Group's manifest
+    fn draw_details(ui: &mut egui::Ui, group: &Group) {
+        let manifest = &group.manifest;
+        egui::Grid::new("group_details").num_columns(2).show(ui, |ui| {
+            ui.label("Tempo");
+            ui.label(format!("{:.1} BPM", manifest.bpm));
+            ui.end_row();
+            
+            ui.label("Length");
+            match manifest.length_beats {
+                Some(beats) => ui.label(format!("{} beats", beats)),
+                None => ui.label(format!("{:.2}s (to last clip)", group.duration)),
+            };
+            ui.end_row();
+            
+            ui.label("Time signature");
+            ui.label(format!("{}/{}", manifest.time_signature.0, manifest.time_signature.1));
+            ui.end_row();
+            
+            if let Some(project) = &manifest.source_project {
+                ui.label("From");
+                ui.label(project);
+                ui.end_row();
+            }
+            
+            if !manifest.variants.is_empty() {
+                ui.label("Variants");
+                ui.label(manifest.variants.join(", "));
+                ui.end_row();
+            }
+            
+            if !manifest.tags.is_empty() {
+                ui.label("Tags");
+                ui.label(manifest.tags.join(", "));
+                ui.end_row();
+            }
+            
+            ui.label("Render rate");
+            match manifest.render.sample_rate {
+                Some(rate) => ui.label(format!("{} Hz", rate)),
+                None => ui.label("Output device"),
+            };
+            ui.end_row();
+        });
     }
     
     /// Make the Group draggable with a waveform preview
-    fn make_group_draggable(ui: &mut egui::Ui, group: &Group, ctx: &egui::Context) -> bool {
+    fn make_group_draggable(ui: &mut egui::Ui, group: &mut Group, ctx: &egui::Context) -> bool {
         let mut dragged = false;
         
         // Create a smaller interaction area for better drag detection
@@ -255,25 +424,26 @@ impl GroupPanel {
         
         // Debug output for interaction
         if response.hovered() {
-            eprintln!("Hovering over group: {}", group.name);
+            log::trace!(target: "ui", "Hovering over group: {}", group.name);
         }
         
         if response.dragged() {
-            eprintln!("Dragging group: {} (response.dragged=true, drag_delta={:?})", 
+            log::trace!(target: "ui", "Dragging group: {} (response.dragged=true, drag_delta={:?})", 
                      group.name, ctx.input(|i| i.pointer.delta()));
             dragged = true;
+            group.update_waveform();
             
             // Store the dragged Group
             ctx.memory_mut(|mem| {
                 mem.data.insert_temp(egui::Id::new("dragged_group"), group.clone());
                 // Also set a flag that a group is being dragged to notify other systems
                 mem.data.insert_temp(egui::Id::new("group_drag_active"), true);
-                eprintln!("DEBUG: Stored dragged group in memory: {} (group_drag_active=true)", group.name);
+                log::trace!(target: "ui", "Stored dragged group in memory: {} (group_drag_active=true)", group.name);
             });
             
             // Show drag visual with waveform - ALWAYS following the cursor as a popup
             if let Some(pointer_pos) = ctx.pointer_hover_pos() {
-                eprintln!("Showing drag visual at {:?}", pointer_pos);
+                log::trace!(target: "ui", "Showing drag visual at {:?}", pointer_pos);
                 
                 // Create a floating area that follows the pointer instead of using popup
                 egui::Area::new(egui::Id::new("dragged_group_preview"))
@@ -360,25 +530,25 @@ impl GroupPanel {
         } else {
             // Clear the drag state when not dragging
             if ctx.input(|i| i.pointer.any_released()) {
-                eprintln!("DEBUG: Pointer released while not dragging, group: {}", group.name);
+                log::trace!(target: "ui", "Pointer released while not dragging, group: {}", group.name);
                 ctx.memory_mut(|mem| {
                     // Double-check if this is actually the group being dragged before clearing
                     if let Some(dragged) = mem.data.get_temp::<Group>(egui::Id::new("dragged_group")) {
                         if dragged.name == group.name {
-                            eprintln!("DEBUG: Removing dragged group from memory: {}", group.name);
+                            log::trace!(target: "ui", "Removing dragged group from memory: {}", group.name);
                             mem.data.remove::<Group>(egui::Id::new("dragged_group"));
                             mem.data.insert_temp(egui::Id::new("group_drag_active"), false);
-                            eprintln!("DEBUG: Set group_drag_active=false for {}", group.name);
+                            log::trace!(target: "ui", "Set group_drag_active=false for {}", group.name);
                         }
                     }
                     
                     // Even if no dragged group is found, clear the active flag to be safe
                     if mem.data.get_temp::<bool>(egui::Id::new("group_drag_active")).unwrap_or(false) {
                         mem.data.insert_temp(egui::Id::new("group_drag_active"), false);
-                        eprintln!("DEBUG: Force set group_drag_active=false");
+                        log::trace!(target: "ui", "Force set group_drag_active=false");
                     }
                 });
-                eprintln!("DEBUG: Cleared drag state for group: {}", group.name);
+                log::trace!(target: "ui", "Cleared drag state for group: {}", group.name);
             }
         }
         
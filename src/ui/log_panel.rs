@@ -0,0 +1,146 @@
+use crate::logging::{self, Subsystem};
+use eframe::egui;
+use egui::{Color32, RichText};
+use log::{Level, LevelFilter};
+use std::time::UNIX_EPOCH;
+
+/// Floating window showing the in-memory log, filterable by subsystem
+#[derive(Clone)]
+pub struct LogPanel {
+    pub open: bool,
+    enabled: [bool; 4], // Indexed like Subsystem::ALL
+    show_other: bool,   // Entries logged by dependencies with their own targets
+    filter_text: String,
+}
+
+impl Default for LogPanel {
+    fn default() -> Self {
+        Self {
+            open: false,
+            enabled: [true; 4],
+            show_other: false,
+            filter_text: String::new(),
+        }
+    }
+}
+
+impl LogPanel {
+    pub fn load(ctx: &egui::Context) -> Self {
+        ctx.memory(|mem| mem.data.get_temp::<LogPanel>(egui::Id::new("log_panel")))
+            .unwrap_or_default()
+    }
+
+    pub fn store(self, ctx: &egui::Context) {
+        ctx.memory_mut(|mem| mem.data.insert_temp(egui::Id::new("log_panel"), self));
+    }
+
+    fn level_color(level: Level) -> Color32 {
+        match level {
+            Level::Error => Color32::from_rgb(255, 100, 100),
+            Level::Warn => Color32::from_rgb(255, 200, 80),
+            Level::Info => Color32::from_rgb(200, 200, 200),
+            Level::Debug => Color32::from_rgb(130, 170, 255),
+            Level::Trace => Color32::from_rgb(130, 130, 130),
+        }
+    }
+
+    fn is_visible(&self, target: &str) -> bool {
+        match Subsystem::from_target(target) {
+            Some(subsystem) => {
+                let idx = Subsystem::ALL.iter().position(|s| *s == subsystem).unwrap_or(0);
+                self.enabled[idx]
+            }
+            None => self.show_other,
+        }
+    }
+
+    /// Draws the panel if it is open. Returns a new log level if the user changed it.
+    pub fn draw(&mut self, ctx: &egui::Context) -> Option<LevelFilter> {
+        let mut new_level = None;
+        let mut open = self.open;
+
+        egui::Window::new("Log")
+            .open(&mut open)
+            .default_size([640.0, 320.0])
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    // Runtime log level
+                    let current = logging::level();
+                    ui.label("Level:");
+                    egui::ComboBox::from_id_salt("log_level")
+                        .selected_text(current.to_string())
+                        .show_ui(ui, |ui| {
+                            for level in logging::LEVELS {
+                                if ui.selectable_label(current == level, level.to_string()).clicked()
+                                    && current != level
+                                {
+                                    new_level = Some(level);
+                                }
+                            }
+                        });
+
+                    ui.separator();
+
+                    // Subsystem filters
+                    for (i, subsystem) in Subsystem::ALL.iter().enumerate() {
+                        ui.checkbox(&mut self.enabled[i], subsystem.target());
+                    }
+                    ui.checkbox(&mut self.show_other, "other");
+
+                    ui.separator();
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.filter_text)
+                            .hint_text("Filter")
+                            .desired_width(120.0),
+                    );
+
+                    if ui.button("Clear").clicked() {
+                        logging::clear();
+                    }
+                });
+
+                ui.separator();
+
+                let filter = self.filter_text.to_lowercase();
+                let entries: Vec<_> = logging::entries()
+                    .into_iter()
+                    .filter(|entry| self.is_visible(&entry.target))
+                    .filter(|entry| filter.is_empty() || entry.message.to_lowercase().contains(&filter))
+                    .collect();
+
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show_rows(ui, row_height, entries.len(), |ui, range| {
+                        for entry in &entries[range] {
+                            // Time of day in UTC, good enough to correlate entries
+                            let secs = entry
+                                .time
+                                .duration_since(UNIX_EPOCH)
+                                .map(|d| d.as_secs_f64())
+                                .unwrap_or(0.0);
+                            let day_secs = secs % 86400.0;
+                            let time = format!(
+                                "{:02}:{:02}:{:06.3}",
+                                (day_secs / 3600.0) as u32,
+                                ((day_secs % 3600.0) / 60.0) as u32,
+                                day_secs % 60.0
+                            );
+                            ui.label(
+                                RichText::new(format!(
+                                    "{} {:<5} [{}] {}",
+                                    time, entry.level, entry.target, entry.message
+                                ))
+                                .monospace()
+                                .color(Self::level_color(entry.level)),
+                            );
+                        }
+                    });
+            });
+
+        self.open = open;
+        new_level
+    }
+}
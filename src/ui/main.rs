@@ -1,12 +1,23 @@
-use crate::daw::{DawAction, DawApp, SelectionRect, TrackItemType};
+use crate::audio;
+use crate::daw::{DawAction, DawApp, PlaybackScope, SelectionRect, TrackItemType};
 use crate::group::Group;
+use crate::peaks::Peaks;
+use crate::stream::Playback;
 use crate::ui::grid::Grid;
+use crate::ui::grid_item::{LOUDNESS_REQUEST_ID, PLAYBACK_REQUEST_ID, UNGROUP_REQUEST_ID, VARIANT_REQUEST_ID};
+use crate::ui::export_dialog::{ExportDialog, ExportRequest};
 use crate::ui::file_browser::FileBrowserPanel;
-use crate::ui::group_panel::GroupPanel;
+use crate::ui::group_panel::{GroupPanel, GroupPanelAction};
+use crate::ui::log_panel::LogPanel;
+use crate::ui::preferences::{PreferencesChange, PreferencesWindow};
+use crate::ui::relink_dialog::{RelinkDialog, RelinkResult};
 use crate::ui::drag_drop;
 use eframe::egui;
 use egui::{Color32, Key, RichText};
 use rfd::FileDialog;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 // UI Constants
 pub const TRACK_HEIGHT: f32 = 100.0;
@@ -39,9 +50,14 @@ struct TransportControls<'a> {
     on_grid_change: &'a mut dyn FnMut(f32),
     on_save: &'a mut dyn FnMut(),
     on_load: &'a mut dyn FnMut(),
+    on_collect: &'a mut dyn FnMut(),
     on_render: &'a mut dyn FnMut(),
     loop_enabled: bool,
     on_toggle_loop: &'a mut dyn FnMut(),
+    on_toggle_log: &'a mut dyn FnMut(),
+    on_toggle_preferences: &'a mut dyn FnMut(),
+    loading: usize, // Audio files still being decoded
+    on_cancel_loading: &'a mut dyn FnMut(),
 }
 
 impl<'a> TransportControls<'a> {
@@ -147,16 +163,59 @@ impl<'a> TransportControls<'a> {
             {
                 (self.on_load)();
             }
+            if ui
+                .button(RichText::new("📥").size(20.0))
+                .on_hover_text("Collect All and Save: copy external audio into the project's audio folder")
+                .clicked()
+            {
+                (self.on_collect)();
+            }
 
             // Render button
             ui.add_space(16.0);
             if ui
                 .button(RichText::new("🔊").size(20.0))
-                .on_hover_text("Render Selection to WAV")
+                .on_hover_text("Export Selection or Stems")
                 .clicked()
             {
                 (self.on_render)();
             }
+
+            // Log panel toggle
+            if ui
+                .button(RichText::new("📜").size(20.0))
+                .on_hover_text("Show Log")
+                .clicked()
+            {
+                (self.on_toggle_log)();
+            }
+
+            // Preferences window toggle
+            if ui
+                .button(RichText::new("⚙").size(20.0))
+                .on_hover_text("Preferences")
+                .clicked()
+            {
+                (self.on_toggle_preferences)();
+            }
+
+            // Files still decoding in the background
+            if self.loading > 0 {
+                ui.add_space(16.0);
+                ui.spinner();
+                ui.label(format!(
+                    "Loading {} file{}…",
+                    self.loading,
+                    if self.loading == 1 { "" } else { "s" }
+                ));
+                if ui
+                    .button("Cancel")
+                    .on_hover_text("Stop loading and remove clips that are still loading")
+                    .clicked()
+                {
+                    (self.on_cancel_loading)();
+                }
+            }
         });
     }
 }
@@ -234,6 +293,14 @@ impl eframe::App for DawApp {
             self.save_project_as();
         }
 
+        // Handle Cmd+Z to undo and Cmd+Shift+Z to redo, in the active tab
+        if ctx.input(|i| i.key_pressed(Key::Z) && i.modifiers.command && !i.modifiers.shift) {
+            self.dispatch(DawAction::Undo);
+        }
+        if ctx.input(|i| i.key_pressed(Key::Z) && i.modifiers.command && i.modifiers.shift) {
+            self.dispatch(DawAction::Redo);
+        }
+
         // Handle Cmd+Shift+[ to switch to previous tab
         if ctx.input(|i| i.key_pressed(Key::ArrowLeft) && i.modifiers.command && i.modifiers.shift) {
             self.switch_to_previous_tab();
@@ -263,6 +330,43 @@ impl eframe::App for DawApp {
             }
         }
 
+        // Handle Cmd+G to turn the selected clips into a Group
+        if ctx.input(|i| i.key_pressed(Key::G) && i.modifiers.command && !i.modifiers.shift) {
+            let name = self.unused_group_name();
+            self.dispatch(DawAction::GroupSelection(name));
+            ctx.memory_mut(|mem| mem.data.remove::<GroupPanel>(egui::Id::new("group_panel")));
+        }
+
+        // Handle Cmd+Shift+G to turn the selected Group instances back into clips
+        if ctx.input(|i| i.key_pressed(Key::G) && i.modifiers.command && i.modifiers.shift) {
+            if let Some(selection) = self.state.selection.clone() {
+                // The selection can be dragged upwards, so its end track may come first
+                let tracks = selection.start_track_idx.min(selection.end_track_idx)
+                    ..=selection.start_track_idx.max(selection.end_track_idx);
+                let instances: Vec<(usize, usize)> = self
+                    .state
+                    .tracks
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, _)| tracks.contains(idx))
+                    .flat_map(|(_, track)| {
+                        track
+                            .samples
+                            .iter()
+                            .filter(|s| s.item_type == TrackItemType::Group)
+                            .filter(|s| {
+                                s.grid_position < selection.end_beat
+                                    && s.grid_position + s.grid_length > selection.start_beat
+                            })
+                            .map(move |s| (track.id, s.id))
+                    })
+                    .collect();
+                for (track_id, sample_id) in instances {
+                    self.dispatch(DawAction::Ungroup(track_id, sample_id));
+                }
+            }
+        }
+
         // Handle Alt+Up/Down arrow keys for zoom
         if ctx.input(|i| i.modifiers.alt) {
             if ctx.input(|i| i.key_pressed(Key::ArrowUp)) {
@@ -271,7 +375,7 @@ impl eframe::App for DawApp {
                 let old_zoom = self.state.zoom_level;
                 let new_zoom = (self.state.zoom_level * zoom_delta).clamp(0.1, 10.0);
 
-                eprintln!("Alt+Up arrow zoom: {:.2} to {:.2}", old_zoom, new_zoom);
+                log::debug!(target: "ui", "Alt+Up arrow zoom: {:.2} to {:.2}", old_zoom, new_zoom);
                 self.dispatch(DawAction::SetZoomLevel(new_zoom));
             }
             if ctx.input(|i| i.key_pressed(Key::ArrowDown)) {
@@ -280,7 +384,7 @@ impl eframe::App for DawApp {
                 let old_zoom = self.state.zoom_level;
                 let new_zoom = (self.state.zoom_level * zoom_delta).clamp(0.1, 10.0);
 
-                eprintln!("Alt+Down arrow zoom: {:.2} to {:.2}", old_zoom, new_zoom);
+                log::debug!(target: "ui", "Alt+Down arrow zoom: {:.2} to {:.2}", old_zoom, new_zoom);
                 self.dispatch(DawAction::SetZoomLevel(new_zoom));
             }
         }
@@ -288,6 +392,12 @@ impl eframe::App for DawApp {
         // Update timeline position based on audio playback
         self.update_playback();
 
+        // Periodically write unsaved work to the recovery file
+        self.autosave_if_due();
+        self.update_peaks();
+        self.update_decoding();
+        self.update_group_renders();
+
         // Handle drag and drop operations
         
         // Filter dragged files to only include audio files
@@ -330,229 +440,91 @@ impl eframe::App for DawApp {
         let grid_division = self.state.grid_division;
         let last_clicked_bar = self.state.last_clicked_bar;
 
-        // Check if we're in an audio box tab or the main project tab
-        let active_tab = self.state.tabs.iter().find(|t| t.id == self.state.active_tab_id);
-        let is_group_tab = active_tab.map_or(false, |tab| tab.is_group);
-        let group_name = active_tab.and_then(|tab| tab.group_name.clone());
-        
-        // Prepare track info based on active tab
-        let track_info: Vec<(usize, String, bool, bool, bool, Vec<(usize, String, f32, f32, Vec<f32>, u32, f32, f32, f32, TrackItemType)>)>;
-        
-        if is_group_tab {
-            // Create temporary tracks for the audio box - use the same number of tracks as default project
-            let mut temp_tracks = Vec::new();
-            
-            // Create 4 default tracks (same as default project)
-            for i in 0..4 {
-                let track_id = i;
-                let track_name = format!("Track {}", i + 1);
-                let mut samples = Vec::new();
-                
-                // Only put the audio box in the first track
-                if i == 0 && group_name.is_some() {
-                    if let Some(box_name) = &group_name {
-                        // Load the AudioBox data from disk
-                        if let Some(project_path) = self.state.file_path.as_ref() {
-                            if let Some(project_dir) = project_path.parent() {
-                                let box_path = project_dir.join(box_name);
-                                let render_path = box_path.join("render.wav");
-                                
-                                if render_path.exists() {
-                                    // Try to load audio file to get waveform data
-                                    if let Ok((audio_samples, rate)) = crate::audio::load_audio(&render_path) {
-                                        // Generate waveform data
-                                        let duration = audio_samples.len() as f32 / rate as f32;
-                                        
-                                        // Generate waveform for display
-                                        let waveform_data = generate_waveform(&audio_samples, 1000);
-                                        
-                                        // Create a sample entry for the group
-                                        let sample_info = (
-                                            0usize, // sample id
-                                            box_name.clone(),
-                                            0.0f32, // grid position
-                                            4.0f32, // grid length (default to 4 beats)
-                                            waveform_data, // waveform data
-                                            rate as u32, // sample rate
-                                            duration, // full duration
-                                            0.0f32, // audio start time
-                                            0.0f32, // audio end time
-                                            TrackItemType::Group,
-                                        );
-                                        
-                                        // Add the box to the track's samples
-                                        samples.push(sample_info);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                
-                // Add the track to our list
-                temp_tracks.push((
-                    track_id as usize,
-                    track_name,
-                    false, // muted
-                    false, // soloed
-                    false, // recording
-                    samples, // samples list (may be empty)
-                ));
-            }
-            
-            track_info = temp_tracks;
-        } else {
-            // Use the main project tracks
-            track_info = self
-                .state
-                .tracks
-                .iter()
-                .map(|track| {
-                    let samples_info: Vec<_> = track
-                        .samples
-                        .iter()
-                        .map(|sample| {
-                            let (waveform_data, sample_rate) = sample
-                                .waveform
-                                .as_ref()
-                                .map(|w| (w.samples.clone(), w.sample_rate))
-                                .unwrap_or((vec![], 44100));
-
-                            // Get the full audio duration
-                            let full_duration =
-                                sample.waveform.as_ref().map(|w| w.duration).unwrap_or(0.0);
-
-                            // Use trim points for the visual representation
-                            let audio_start_time = sample.trim_start;
-                            let audio_end_time = if sample.trim_end <= 0.0 {
-                                full_duration
-                            } else {
-                                sample.trim_end
-                            };
-
-                            (
-                                sample.id,
-                                sample.name.clone(),
-                                sample.grid_position,
-                                sample.grid_length,
-                                waveform_data,
-                                sample_rate,
-                                full_duration,
-                                audio_start_time,
-                                audio_end_time,
-                                sample.item_type.clone(),
-                            )
-                        })
-                        .collect();
-
-                    (
-                        track.id,
-                        track.name.clone(),
-                        track.muted,
-                        track.soloed,
-                        track.recording,
-                        samples_info,
-                    )
-                })
-                .collect();
-        }
+        // The Group the active tab edits, if any
+        let active_group = self.active_group();
 
-        // Prepare controls info based on active tab
-        let track_controls_info: Vec<_>;
-        
-        if is_group_tab {
-            // For audio box tabs, create matching controls for our temporary tracks
-            let mut temp_controls = Vec::new();
-            
-            // Create 4 default tracks (same as default project)
-            for i in 0..4 {
-                let track_id = i;
-                let track_name = format!("Track {}", i + 1);
-                let mut samples = Vec::new();
-                
-                // Only put the audio box in the first track
-                if i == 0 && group_name.is_some() {
-                    if let Some(box_name) = &group_name {
-                        // Get audio data for duration
-                        let mut duration = 0.0;
-                        
-                        if let Some(project_path) = self.state.file_path.as_ref() {
-                            if let Some(project_dir) = project_path.parent() {
-                                let box_path = project_dir.join(box_name);
-                                let render_path = box_path.join("render.wav");
-                                
-                                if render_path.exists() {
-                                    if let Ok((audio_samples, rate)) = crate::audio::load_audio(&render_path) {
-                                        duration = audio_samples.len() as f32 / rate as f32;
-                                    }
-                                }
-                            }
+        // Peaks by (track id, sample id), for samples whose peaks have finished loading
+        let mut sample_peaks: HashMap<(usize, usize), Arc<Peaks>> = HashMap::new();
+        // Decode progress by (track id, sample id), for samples whose audio is still loading
+        let mut sample_progress: HashMap<(usize, usize), f32> = HashMap::new();
+        // How each audio clip is loaded, for its context menu
+        let mut sample_playback: HashMap<(usize, usize), Playback> = HashMap::new();
+        // Variant and Group folder of each Group instance, for its context menu
+        let mut group_instances: HashMap<(usize, usize), (Option<String>, PathBuf)> = HashMap::new();
+        let project_folder = self.state.file_path.as_ref().and_then(|p| p.parent()).map(Path::to_path_buf);
+        // Tracks playing a freeze render
+        let frozen_tracks: HashSet<usize> =
+            self.state.tracks.iter().filter(|t| t.frozen.is_some()).map(|t| t.id).collect();
+
+        // Prepare track info for the active tab's arrangement
+        let track_info: Vec<(usize, String, bool, bool, bool, Vec<(usize, String, f32, f32, Vec<f32>, u32, f32, f32, f32, TrackItemType)>)> = self
+            .state
+            .tracks
+            .iter()
+            .map(|track| {
+                let samples_info: Vec<_> = track
+                    .samples
+                    .iter()
+                    .map(|sample| {
+                        // The envelope is only needed until the peaks are ready
+                        if let Some(peaks) = &sample.peaks {
+                            sample_peaks.insert((track.id, sample.id), Arc::clone(peaks));
                         }
-                        
-                        // Create a sample entry for controls
-                        let sample_info = (
-                            0, // sample id
-                            box_name.clone(),
-                            0.0, // position
-                            duration * (bpm / 60.0), // length in beats
-                            0.0, // current position
-                            duration, // duration
-                            0.0, // trim_start
-                            0.0, // trim_end
-                        );
-                        
-                        // Add the sample info
-                        samples.push(sample_info);
-                    }
-                }
-                
-                // Add the track to controls
-                temp_controls.push((
-                    track_id,
-                    track_name,
-                    false, // muted
-                    false, // soloed
-                    false, // recording
-                    samples, // samples list (may be empty)
-                ));
-            }
-            
-            track_controls_info = temp_controls;
-        } else {
-            // Use the main project's track controls
-            track_controls_info = self
-                .state
-                .tracks
-                .iter()
-                .map(|track| {
-                    let samples_info: Vec<_> = track
-                        .samples
-                        .iter()
-                        .map(|sample| {
-                            (
-                                sample.id,
-                                sample.name.clone(),
-                                sample.grid_position,
-                                sample.grid_length,
-                                sample.current_position,
-                                sample.waveform.as_ref().map_or(0.0, |w| w.duration),
-                                sample.trim_start,
-                                sample.trim_end,
-                            )
-                        })
-                        .collect();
-
-                    (
-                        track.id,
-                        track.name.clone(),
-                        track.muted,
-                        track.soloed,
-                        track.recording,
-                        samples_info,
-                    )
-                })
-                .collect();
-        }
+                        if let Some(progress) = sample.decode_progress() {
+                            sample_progress.insert((track.id, sample.id), progress);
+                        }
+                        if sample.item_type == TrackItemType::Sample {
+                            sample_playback.insert((track.id, sample.id), sample.playback);
+                        } else if let Some(folder) = &project_folder {
+                            group_instances
+                                .insert((track.id, sample.id), (sample.variant.clone(), folder.join(&sample.name)));
+                        }
+                        let (waveform_data, sample_rate) = sample
+                            .waveform
+                            .as_ref()
+                            .map(|w| {
+                                let envelope = if sample.peaks.is_some() { vec![] } else { w.samples.clone() };
+                                (envelope, w.sample_rate)
+                            })
+                            .unwrap_or((vec![], 44100));
+
+                        // Get the full audio duration
+                        let full_duration =
+                            sample.waveform.as_ref().map(|w| w.duration).unwrap_or(0.0);
+
+                        // Use trim points for the visual representation
+                        let audio_start_time = sample.trim_start;
+                        let audio_end_time = if sample.trim_end <= 0.0 {
+                            full_duration
+                        } else {
+                            sample.trim_end
+                        };
+
+                        (
+                            sample.id,
+                            sample.name.clone(),
+                            sample.grid_position,
+                            sample.grid_length,
+                            waveform_data,
+                            sample_rate,
+                            full_duration,
+                            audio_start_time,
+                            audio_end_time,
+                            sample.item_type.clone(),
+                        )
+                    })
+                    .collect();
+
+                (
+                    track.id,
+                    track.name.clone(),
+                    track.muted,
+                    track.soloed,
+                    track.recording,
+                    samples_info,
+                )
+            })
+            .collect();
 
         // Define UI actions without capturing self
         #[allow(dead_code)]
@@ -565,6 +537,7 @@ impl eframe::App for DawApp {
             SaveProject,
             SaveProjectAs,
             LoadProject,
+            CollectAndSave,
             RenderSelection,
             SetTimelinePosition(f32),
             SetLastClickedPosition(f32),
@@ -603,6 +576,15 @@ impl eframe::App for DawApp {
                 trim_start: f32,
                 trim_end: f32,
             },
+            SetSamplePlayback {
+                track_id: usize,
+                sample_id: usize,
+                playback: Playback,
+            },
+            AnalyzeLoudness {
+                track_id: usize,
+                sample_id: usize,
+            },
             UpdateScrollPosition {
                 h_scroll: f32,
                 v_scroll: f32,
@@ -615,13 +597,34 @@ impl eframe::App for DawApp {
             CreateGroup(String),
             RenameGroup(String, String),
             DeleteGroup(String),
-            AddGroupToTrack(usize, String),
+            UndoDeleteGroup,
+            ExportGroupToLibrary(String),
+            ImportGroupFromLibrary(String),
+            AddGroupToTrack(usize, String, f32),
             RenderGroupFromSelection(String),
-            OpenGroupInNewTab(String),
+            OpenGroupInNewTab(String, Option<String>),
+            CreateGroupVariant(String, String),
+            Ungroup {
+                track_id: usize,
+                sample_id: usize,
+            },
+            SetGroupVariant {
+                track_id: usize,
+                sample_id: usize,
+                variant: Option<String>,
+            },
             SwitchToTab(usize),
+            SetPlaybackScope(PlaybackScope),
+            Undo,
+            Redo,
+            ToggleTrackFreeze(usize),
+            BounceSelection,
             CloseTab(usize),
             SaveGroup(String),
             UpdateLoopRange(bool, f32, f32),
+            ToggleLogPanel,
+            TogglePreferences,
+            CancelLoading,
         }
 
         // Add the top toolbar with transport controls
@@ -655,6 +658,9 @@ impl eframe::App for DawApp {
                 on_load: &mut || {
                     actions_clone.borrow_mut().push(UiAction::LoadProject);
                 },
+                on_collect: &mut || {
+                    actions_clone.borrow_mut().push(UiAction::CollectAndSave);
+                },
                 on_render: &mut || {
                     actions_clone.borrow_mut().push(UiAction::RenderSelection);
                 },
@@ -664,6 +670,16 @@ impl eframe::App for DawApp {
                         .borrow_mut()
                         .push(UiAction::ToggleLoopSelection);
                 },
+                on_toggle_log: &mut || {
+                    actions_clone.borrow_mut().push(UiAction::ToggleLogPanel);
+                },
+                on_toggle_preferences: &mut || {
+                    actions_clone.borrow_mut().push(UiAction::TogglePreferences);
+                },
+                loading: self.loading_count(),
+                on_cancel_loading: &mut || {
+                    actions_clone.borrow_mut().push(UiAction::CancelLoading);
+                },
             }
             .draw(ui);
         });
@@ -765,14 +781,41 @@ impl eframe::App for DawApp {
                 .resizable(false)
                 .show_separator_line(false)
                 .show(ctx, |ui| {
-                    if let Some(group_to_open) = group_panel.draw(ui, ctx) {
+                    match group_panel.draw(ui, ctx) {
                         // When a group is opened, open it in a new tab with empty project and tracks
-                        actions_clone.borrow_mut().push(UiAction::OpenGroupInNewTab(group_to_open.name));
+                        Some(GroupPanelAction::Open(group)) => {
+                            actions_clone.borrow_mut().push(UiAction::OpenGroupInNewTab(group.name, None));
+                        }
+                        Some(GroupPanelAction::OpenVariant(name, variant)) => {
+                            actions_clone.borrow_mut().push(UiAction::OpenGroupInNewTab(name, Some(variant)));
+                        }
+                        Some(GroupPanelAction::NewVariant(name, variant)) => {
+                            actions_clone.borrow_mut().push(UiAction::CreateGroupVariant(name, variant));
+                        }
+                        Some(GroupPanelAction::Rename(old_name, new_name)) => {
+                            actions_clone.borrow_mut().push(UiAction::RenameGroup(old_name, new_name));
+                        }
+                        Some(GroupPanelAction::Delete(name)) => {
+                            actions_clone.borrow_mut().push(UiAction::DeleteGroup(name));
+                        }
+                        Some(GroupPanelAction::ExportToLibrary(name)) => {
+                            actions_clone.borrow_mut().push(UiAction::ExportGroupToLibrary(name));
+                        }
+                        Some(GroupPanelAction::ImportFromLibrary(name)) => {
+                            actions_clone.borrow_mut().push(UiAction::ImportGroupFromLibrary(name));
+                        }
+                        None => {}
+                    }
+
+                    if let Some(name) = self.last_deleted_group() {
+                        if ui.button(format!("↶ Restore {}", name)).clicked() {
+                            actions_clone.borrow_mut().push(UiAction::UndoDeleteGroup);
+                        }
                     }
                     
                     // Handle group dragging - check if a group is being dragged
                     if let Some(dragged_group) = ctx.memory(|mem| mem.data.get_temp::<Group>(egui::Id::new("dragged_group"))) {
-                        eprintln!("DEBUG: Group drag detected in main loop: {} (size: {})", dragged_group.name, dragged_group.waveform.len());
+                        log::trace!(target: "ui", "Group drag detected in main loop: {} (size: {})", dragged_group.name, dragged_group.waveform.len());
                         
                         // Now we need to show a visual indicator that we have a dragged group
                         if let Some(pointer_pos) = ctx.pointer_hover_pos() {
@@ -788,75 +831,56 @@ impl eframe::App for DawApp {
                         // If we detect a drop on the grid area
                         if let Some(grid_rect) = ctx.memory(|mem| mem.data.get_temp::<egui::Rect>(egui::Id::new("grid_rect"))) {
                             if let Some(mouse_pos) = ctx.pointer_hover_pos() {
-                                eprintln!("DEBUG: Mouse position during group drag: {:?}", mouse_pos);
-                                eprintln!("DEBUG: Grid rect: {:?}", grid_rect);
-                                eprintln!("DEBUG: Mouse is inside grid: {}", grid_rect.contains(mouse_pos));
-                                eprintln!("DEBUG: Pointer released: {}", ctx.input(|i| i.pointer.any_released()));
+                                log::trace!(target: "ui", "Mouse position during group drag: {:?}", mouse_pos);
+                                log::trace!(target: "ui", "Grid rect: {:?}", grid_rect);
+                                log::trace!(target: "ui", "Mouse is inside grid: {}", grid_rect.contains(mouse_pos));
+                                log::trace!(target: "ui", "Pointer released: {}", ctx.input(|i| i.pointer.any_released()));
                                 
                                 // For dragging from group panel, just check if mouse released inside grid
                                 let is_released_on_grid = grid_rect.contains(mouse_pos) && ctx.input(|i| i.pointer.any_released());
                                 
                                 if is_released_on_grid {
-                                    eprintln!("DEBUG: Group drop detected on grid (mouse released on grid)");
+                                    log::trace!(target: "ui", "Group drop detected on grid (mouse released on grid)");
                                     
                                     // Calculate drop target using the grid's drop target system
                                     if let Some(target) = drag_drop::calculate_drop_target(self, ctx, mouse_pos) {
-                                        eprintln!("DEBUG: Adding group '{}' to track {} at beat position {}", 
+                                        log::trace!(target: "ui", "Adding group '{}' to track {} at beat position {}", 
                                             dragged_group.name, target.track_id, target.beat_position);
                                             
                                         // Check if we need to create a new track
                                         if self.state.tracks.is_empty() {
-                                            eprintln!("DEBUG: No tracks available, creating a new track for the group");
+                                            log::trace!(target: "ui", "No tracks available, creating a new track for the group");
                                             actions_clone.borrow_mut().push(UiAction::CreateTrack);
                                         }
                                             
                                         let group_name = dragged_group.name.clone();
-                                        actions_clone.borrow_mut().push(UiAction::AddGroupToTrack(target.track_id, group_name));
-                                        
-                                        // After adding the group, move it to the correct position
-                                        // We need to find the newly added group in the target track
-                                        if let Some(track) = self.state.tracks.iter_mut().find(|t| t.id == target.track_id) {
-                                            if let Some(sample) = track.samples.iter_mut().find(|s| 
-                                                s.item_type == TrackItemType::Group && s.name == dragged_group.name) {
-                                                // Move the sample to the drop position
-                                                actions_clone.borrow_mut().push(UiAction::SetSamplePosition {
-                                                    track_id: target.track_id,
-                                                    sample_id: sample.id,
-                                                    position: target.beat_position
-                                                });
-                                                eprintln!("DEBUG: Moving group '{}' to position {}", dragged_group.name, target.beat_position);
-                                            } else {
-                                                eprintln!("DEBUG: Could not find newly added group sample '{}'", dragged_group.name);
-                                            }
-                                        } else {
-                                            eprintln!("DEBUG: Could not find track with ID {}", target.track_id);
-                                        }
+                                        actions_clone.borrow_mut().push(UiAction::AddGroupToTrack(target.track_id, group_name, target.beat_position));
                                     } else {
-                                        eprintln!("DEBUG: Failed to calculate drop target for group");
+                                        log::trace!(target: "ui", "Failed to calculate drop target for group");
                                     }
                                     
                                     // Clear the group drag flag
                                     ctx.memory_mut(|mem| {
                                         mem.data.insert_temp(egui::Id::new("group_drag_active"), false);
-                                        eprintln!("DEBUG: Set group_drag_active=false after drop");
+                                        log::trace!(target: "ui", "Set group_drag_active=false after drop");
                                     });
                                 }
                             } else {
-                                eprintln!("DEBUG: No mouse position available during group drag");
+                                log::trace!(target: "ui", "No mouse position available during group drag");
                             }
                         } else {
-                            eprintln!("DEBUG: Grid rect not found during group drag");
+                            log::trace!(target: "ui", "Grid rect not found during group drag");
                         }
                         
                         // Clear dragged group state if mouse is released (whether over the grid or not)
                         if ctx.input(|i| i.pointer.any_released()) {
-                            eprintln!("DEBUG: Mouse released while dragging group '{}'", dragged_group.name);
+                            log::trace!(target: "ui", "Mouse released while dragging group '{}'", dragged_group.name);
                             ctx.memory_mut(|mem| {
                                 mem.data.remove::<Group>(egui::Id::new("dragged_group"));
                                 mem.data.insert_temp(egui::Id::new("group_drag_active"), false);
-                                eprintln!("DEBUG: Cleared dragged_group and set group_drag_active=false on release");
+                                log::trace!(target: "ui", "Cleared dragged_group and set group_drag_active=false on release");
                             });
-                            eprintln!("DEBUG: Drag ended: cleared dragged group state");
+                            log::trace!(target: "ui", "Drag ended: cleared dragged group state");
                         }
                     }
                 });
@@ -909,6 +933,52 @@ impl eframe::App for DawApp {
                 };
                 tabs_bar.draw(ui);
 
+                // Undo and redo work on the active tab's own edits
+                let (can_undo, can_redo) = self.history_state();
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(can_undo, egui::Button::new("↶ Undo")).clicked() {
+                        actions_clone.borrow_mut().push(UiAction::Undo);
+                    }
+                    if ui.add_enabled(can_redo, egui::Button::new("↷ Redo")).clicked() {
+                        actions_clone.borrow_mut().push(UiAction::Redo);
+                    }
+                    if ui
+                        .add_enabled(self.state.selection.is_some(), egui::Button::new("Bounce in Place"))
+                        .on_hover_text("Replace the selected clips of each track with one clip of their mix")
+                        .clicked()
+                    {
+                        actions_clone.borrow_mut().push(UiAction::BounceSelection);
+                    }
+                });
+
+                // A Group's arrangement is saved to its folder and mixed into its render.wav
+                if let Some(name) = &active_group {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("Save & Render Group")
+                            .on_hover_text("Save this Group's tracks and mix them into the audio its instances play")
+                            .clicked()
+                        {
+                            actions_clone.borrow_mut().push(UiAction::SaveGroup(name.clone()));
+                        }
+
+                        let mut in_context = self
+                            .state
+                            .tabs
+                            .iter()
+                            .find(|t| t.id == self.state.active_tab_id)
+                            .is_some_and(|t| t.playback_scope == PlaybackScope::Main);
+                        if ui
+                            .checkbox(&mut in_context, "Audition in context")
+                            .on_hover_text("Play the main arrangement, with this Group's instances following its edits")
+                            .changed()
+                        {
+                            let scope = if in_context { PlaybackScope::Main } else { PlaybackScope::Tab };
+                            actions_clone.borrow_mut().push(UiAction::SetPlaybackScope(scope));
+                        }
+                    });
+                }
+
                 // Grid in the middle
                 let actions_clone = actions.clone();
                 
@@ -929,6 +999,10 @@ impl eframe::App for DawApp {
                     bpm: self.state.bpm,
                     grid_division: self.state.grid_division,
                     tracks: track_info,
+                    peaks: &sample_peaks,
+                    loading: &sample_progress,
+                    playback: &sample_playback,
+                    groups: &group_instances,
                     on_track_drag: &mut |track_id, sample_id, position| {
                         actions_clone.borrow_mut().push(UiAction::TrackDrag {
                             track_id,
@@ -959,6 +1033,12 @@ impl eframe::App for DawApp {
                             .borrow_mut()
                             .push(UiAction::ToggleTrackRecord(track_id));
                     },
+                    frozen: &frozen_tracks,
+                    on_track_freeze: &mut |track_id| {
+                        actions_clone
+                            .borrow_mut()
+                            .push(UiAction::ToggleTrackFreeze(track_id));
+                    },
                     on_delete_sample: &mut |track_id, sample_id| {
                         actions_clone.borrow_mut().push(UiAction::DeleteSample {
                             track_id,
@@ -995,9 +1075,11 @@ impl eframe::App for DawApp {
                             .push(UiAction::UpdateLoopRange(enabled, start_seconds, end_seconds));
                     },
                     on_group_double_click: &mut |track_id, group_id, group_name| {
+                        // Open the variant the instance plays
+                        let variant = group_instances.get(&(track_id, group_id)).and_then(|(v, _)| v.clone());
                         actions_clone
                             .borrow_mut()
-                            .push(UiAction::OpenGroupInNewTab(group_name.to_string()));
+                            .push(UiAction::OpenGroupInNewTab(group_name.to_string(), variant));
                     },
                     snap_to_grid_enabled: true,
                     seconds_per_pixel: 0.01, // Will be calculated in grid.draw()
@@ -1012,6 +1094,46 @@ impl eframe::App for DawApp {
                 };
                 grid.draw(ui);
 
+                // A clip's playback was changed from its context menu
+                if let Some((track_id, sample_id, playback)) = ctx.memory_mut(|mem| {
+                    mem.data.remove_temp::<(usize, usize, Playback)>(egui::Id::new(PLAYBACK_REQUEST_ID))
+                }) {
+                    actions_clone.borrow_mut().push(UiAction::SetSamplePlayback {
+                        track_id,
+                        sample_id,
+                        playback,
+                    });
+                }
+
+                // A Group instance's variant was changed from its context menu
+                if let Some((track_id, sample_id, variant)) = ctx.memory_mut(|mem| {
+                    mem.data.remove_temp::<(usize, usize, Option<String>)>(egui::Id::new(VARIANT_REQUEST_ID))
+                }) {
+                    actions_clone.borrow_mut().push(UiAction::SetGroupVariant {
+                        track_id,
+                        sample_id,
+                        variant,
+                    });
+                }
+
+                // A Group instance is to be replaced by its clips
+                if let Some((track_id, sample_id)) = ctx.memory_mut(|mem| {
+                    mem.data.remove_temp::<(usize, usize)>(egui::Id::new(UNGROUP_REQUEST_ID))
+                }) {
+                    actions_clone
+                        .borrow_mut()
+                        .push(UiAction::Ungroup { track_id, sample_id });
+                }
+
+                // Loudness analysis was asked for from a clip's context menu
+                if let Some((track_id, sample_id)) = ctx.memory_mut(|mem| {
+                    mem.data.remove_temp::<(usize, usize)>(egui::Id::new(LOUDNESS_REQUEST_ID))
+                }) {
+                    actions_clone
+                        .borrow_mut()
+                        .push(UiAction::AnalyzeLoudness { track_id, sample_id });
+                }
+
                 // Store clicked track and position for the next frame
                 ctx.memory_mut(|mem| {
                     mem.data.insert_temp(egui::Id::new("grid_clicked_track"), grid.clicked_track_idx);
@@ -1032,6 +1154,72 @@ impl eframe::App for DawApp {
             });
         });
 
+        // Draw the log window on top of everything else
+        let mut log_panel = LogPanel::load(ctx);
+        if log_panel.open {
+            if let Some(level) = log_panel.draw(ctx) {
+                self.dispatch(DawAction::SetLogLevel(level));
+            }
+            log_panel.store(ctx);
+        }
+
+        let mut preferences = PreferencesWindow::load(ctx);
+        if preferences.open {
+            match preferences.draw(ctx) {
+                Some(PreferencesChange::PcmCacheLimit(limit)) => self.dispatch(DawAction::SetPcmCacheLimit(limit)),
+                Some(PreferencesChange::ClearPcmCache) => self.dispatch(DawAction::ClearPcmCache),
+                None => {}
+            }
+            preferences.store(ctx);
+        }
+
+        let mut export_dialog = ExportDialog::load(ctx);
+        if export_dialog.open {
+            let request = export_dialog.draw(ctx, self.state.selection.is_some());
+            export_dialog.store(ctx);
+            match request {
+                Some(ExportRequest::Selection(settings)) => {
+                    // Open file dialog to select where to save the export
+                    let format = settings.format;
+                    let name = if self.state.project_name.trim().is_empty() {
+                        "Untitled"
+                    } else {
+                        &self.state.project_name
+                    };
+                    let file_name = format!("{}.{}", name, format.extension());
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter(format.label(), &[format.extension()])
+                        .set_title("Export Selection")
+                        .set_file_name(&file_name)
+                        .save_file()
+                    {
+                        self.dispatch(DawAction::RenderSelection(path, settings));
+                    }
+                }
+                Some(ExportRequest::Stems(settings, options)) => {
+                    if let Some(folder) = rfd::FileDialog::new()
+                        .set_title("Export Stems to Folder")
+                        .pick_folder()
+                    {
+                        self.dispatch(DawAction::ExportStems(folder, settings, options));
+                    }
+                }
+                None => {}
+            }
+        }
+
+        // Offer to relink audio files the project couldn't find
+        if !self.missing_media.is_empty() {
+            let mut relink_dialog = RelinkDialog::load(ctx);
+            let result = relink_dialog.draw(ctx, &self.missing_media);
+            relink_dialog.store(ctx);
+            match result {
+                Some(RelinkResult::Relink(relinks)) => self.dispatch(DawAction::RelinkMedia(relinks)),
+                Some(RelinkResult::Dismiss) => self.dispatch(DawAction::DismissMissingMedia),
+                None => {}
+            }
+        }
+
         // Process the collected actions
         for action in actions.borrow().iter() {
             match action {
@@ -1053,6 +1241,9 @@ impl eframe::App for DawApp {
                 UiAction::SaveProject => {
                     self.save_project();
                 }
+                UiAction::CollectAndSave => {
+                    self.collect_and_save();
+                }
                 UiAction::SaveProjectAs => {
                     self.save_project_as();
                 }
@@ -1060,14 +1251,10 @@ impl eframe::App for DawApp {
                     self.load_project();
                 }
                 UiAction::RenderSelection => {
-                    // Open file dialog to select where to save the rendered WAV
-                    if let Some(path) = rfd::FileDialog::new()
-                        .add_filter("WAV Audio", &["wav"])
-                        .set_title("Save Rendered Audio")
-                        .save_file()
-                    {
-                        self.dispatch(DawAction::RenderSelection(path));
-                    }
+                    // Pick the delivery format first, the file dialog follows
+                    let mut export_dialog = ExportDialog::load(ctx);
+                    export_dialog.show(DawApp::load_export_settings(), DawApp::load_stem_options());
+                    export_dialog.store(ctx);
                 }
                 UiAction::SetTimelinePosition(pos) => {
                     // Snap to grid if close enough
@@ -1119,49 +1306,11 @@ impl eframe::App for DawApp {
                 }
                 UiAction::LoadTrackAudio(track_id) => {
                     if let Some(path) = FileDialog::new()
-                        .add_filter("Audio", &["mp3", "wav", "ogg", "flac"])
+                        .add_filter("Audio", &audio::audio_extensions())
                         .pick_file()
                     {
-                        // Get the path where to copy the sample if we're in an AudioBox
-                        let active_tab = self.state.tabs.iter().find(|t| t.id == self.state.active_tab_id);
-                        let is_group_tab = active_tab.map_or(false, |tab| tab.is_group);
-                        
-                        if is_group_tab {
-                            // If we're in an audio box, we first need to copy the file to the samples directory
-                            if let Some(box_name) = active_tab.and_then(|tab| tab.group_name.clone()) {
-                                if let Some(project_path) = self.state.file_path.as_ref() {
-                                    if let Some(project_dir) = project_path.parent() {
-                                        let box_path = project_dir.join(&box_name);
-                                        let samples_dir = box_path.join("samples");
-                                        
-                                        // Create samples directory if it doesn't exist
-                                        if !samples_dir.exists() {
-                                            if let Err(e) = std::fs::create_dir_all(&samples_dir) {
-                                                eprintln!("Failed to create samples directory: {:?}", e);
-                                                return;
-                                            }
-                                        }
-                                        
-                                        // Copy the file to the samples directory
-                                        let file_name = path.file_name().unwrap_or_default();
-                                        let target_path = samples_dir.join(file_name);
-                                        
-                                        if let Err(e) = std::fs::copy(&path, &target_path) {
-                                            eprintln!("Failed to copy file to AudioBox: {:?}", e);
-                                            return;
-                                        }
-                                        
-                                        // Use the copied file path for the sample
-                                        self.dispatch(DawAction::AddSampleToTrack(*track_id, target_path));
-                                        
-                                eprintln!("Added sample to AudioBox '{}' at track {}", box_name, track_id);
-                                    }
-                                }
-                            }
-                        } else {
-                            // In main project tab, use the normal AddSampleToTrack action with original path
-                            self.dispatch(DawAction::AddSampleToTrack(*track_id, path));
-                        }
+                        // The file is imported into the project's media pool, in Group tabs too
+                        self.dispatch(DawAction::AddSampleToTrack(*track_id, path));
                     }
                 }
                 UiAction::ToggleTrackMute(track_id) => {
@@ -1186,6 +1335,16 @@ impl eframe::App for DawApp {
                         *trim_end,
                     ));
                 }
+                UiAction::SetSamplePlayback {
+                    track_id,
+                    sample_id,
+                    playback,
+                } => {
+                    self.dispatch(DawAction::SetSamplePlayback(*track_id, *sample_id, *playback));
+                }
+                UiAction::AnalyzeLoudness { track_id, sample_id } => {
+                    self.dispatch(DawAction::AnalyzeLoudness(*track_id, *sample_id));
+                }
                 UiAction::UpdateScrollPosition { h_scroll, v_scroll } => {
                     self.dispatch(DawAction::UpdateScrollPosition(*h_scroll, *v_scroll));
                 }
@@ -1223,22 +1382,70 @@ impl eframe::App for DawApp {
                 }
                 UiAction::RenameGroup(old_name, new_name) => {
                     self.dispatch(DawAction::RenameGroup(old_name.clone(), new_name.clone()));
+                    ctx.memory_mut(|mem| mem.data.remove::<GroupPanel>(egui::Id::new("group_panel")));
                 }
                 UiAction::DeleteGroup(name) => {
                     self.dispatch(DawAction::DeleteGroup(name.clone()));
+                    // The Group panel lists the folders again
+                    ctx.memory_mut(|mem| mem.data.remove::<GroupPanel>(egui::Id::new("group_panel")));
+                }
+                UiAction::ExportGroupToLibrary(name) => {
+                    self.dispatch(DawAction::ExportGroupToLibrary(name.clone()));
+                }
+                UiAction::ImportGroupFromLibrary(name) => {
+                    self.dispatch(DawAction::ImportGroupFromLibrary(name.clone()));
+                }
+                UiAction::UndoDeleteGroup => {
+                    self.dispatch(DawAction::UndoDeleteGroup);
+                    ctx.memory_mut(|mem| mem.data.remove::<GroupPanel>(egui::Id::new("group_panel")));
                 }
-                UiAction::AddGroupToTrack(track_id, name) => {
-                    self.dispatch(DawAction::AddGroupToTrack(*track_id, name.clone()));
+                UiAction::AddGroupToTrack(track_id, name, position) => {
+                    self.dispatch(DawAction::AddGroupToTrack(*track_id, name.clone(), *position));
                 }
                 UiAction::RenderGroupFromSelection(name) => {
                     self.dispatch(DawAction::RenderGroupFromSelection(name.clone()));
                 }
-                UiAction::OpenGroupInNewTab(name) => {
-                    self.dispatch(DawAction::OpenGroupInNewTab(name.clone()));
+                UiAction::OpenGroupInNewTab(name, variant) => {
+                    self.dispatch(DawAction::OpenGroupInNewTab(name.clone(), variant.clone()));
+                }
+                UiAction::CreateGroupVariant(name, variant) => {
+                    self.dispatch(DawAction::CreateGroupVariant(name.clone(), variant.clone()));
+                    // The Group panel shows the new variant
+                    ctx.memory_mut(|mem| mem.data.remove::<GroupPanel>(egui::Id::new("group_panel")));
+                }
+                UiAction::Ungroup { track_id, sample_id } => {
+                    self.dispatch(DawAction::Ungroup(*track_id, *sample_id));
+                }
+                UiAction::SetGroupVariant {
+                    track_id,
+                    sample_id,
+                    variant,
+                } => {
+                    self.dispatch(DawAction::SetGroupVariant(*track_id, *sample_id, variant.clone()));
                 }
                 UiAction::SwitchToTab(tab_id) => {
                     self.dispatch(DawAction::SwitchToTab(*tab_id));
                 }
+                UiAction::SetPlaybackScope(scope) => {
+                    self.dispatch(DawAction::SetPlaybackScope(*scope));
+                }
+                UiAction::Undo => {
+                    self.dispatch(DawAction::Undo);
+                }
+                UiAction::ToggleTrackFreeze(track_id) => {
+                    let frozen = self.state.tracks.iter().any(|t| t.id == *track_id && t.frozen.is_some());
+                    if frozen {
+                        self.dispatch(DawAction::UnfreezeTrack(*track_id));
+                    } else {
+                        self.dispatch(DawAction::FreezeTrack(*track_id));
+                    }
+                }
+                UiAction::BounceSelection => {
+                    self.dispatch(DawAction::BounceSelection);
+                }
+                UiAction::Redo => {
+                    self.dispatch(DawAction::Redo);
+                }
                 UiAction::CloseTab(tab_id) => {
                     self.dispatch(DawAction::CloseTab(*tab_id));
                 }
@@ -1252,6 +1459,19 @@ impl eframe::App for DawApp {
                 UiAction::UpdateLoopRange(enabled, start, end) => {
                     self.dispatch(DawAction::UpdateLoopRange(*enabled, *start, *end));
                 }
+                UiAction::ToggleLogPanel => {
+                    let mut log_panel = LogPanel::load(ctx);
+                    log_panel.open = !log_panel.open;
+                    log_panel.store(ctx);
+                }
+                UiAction::TogglePreferences => {
+                    let mut preferences = PreferencesWindow::load(ctx);
+                    preferences.open = !preferences.open;
+                    preferences.store(ctx);
+                }
+                UiAction::CancelLoading => {
+                    self.dispatch(DawAction::CancelLoading);
+                }
             }
         }
     }
@@ -1261,34 +1481,3 @@ impl eframe::App for DawApp {
         DawApp::on_exit(self);
     }
 }
-
-// Helper function to create a downsampled waveform for visualization
-fn generate_waveform(samples: &[f32], target_size: usize) -> Vec<f32> {
-    if samples.is_empty() {
-        return Vec::new();
-    }
-    
-    let samples_per_point = (samples.len() as f32 / target_size as f32).max(1.0) as usize;
-    let mut waveform = Vec::with_capacity(target_size);
-    
-    for i in 0..target_size {
-        let start = (i * samples_per_point).min(samples.len());
-        let end = ((i + 1) * samples_per_point).min(samples.len());
-        
-        if start < end {
-            // Find the maximum amplitude in this segment
-            let mut max_amplitude = 0.0f32;
-            for j in start..end {
-                let amplitude = samples[j].abs();
-                if amplitude > max_amplitude {
-                    max_amplitude = amplitude;
-                }
-            }
-            waveform.push(max_amplitude);
-        } else {
-            break;
-        }
-    }
-    
-    waveform
-}
@@ -1,10 +1,14 @@
 // Export UI components
 pub mod main;
 pub mod drag_drop;
+pub mod export_dialog;
 pub mod file_browser;
 pub mod grid;
 pub mod group_panel;
 pub mod grid_item;
+pub mod log_panel;
+pub mod preferences;
+pub mod relink_dialog;
 
 // Only export the modules, don't re-export main
 // as it would bring in all of main's items
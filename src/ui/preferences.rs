@@ -0,0 +1,81 @@
+use crate::pcm_cache;
+use eframe::egui;
+
+/// A setting changed in the preferences window
+pub enum PreferencesChange {
+    PcmCacheLimit(u64), // Megabytes, 0 turns the cache off
+    ClearPcmCache,
+}
+
+/// Floating window with app-wide settings
+#[derive(Clone, Default)]
+pub struct PreferencesWindow {
+    pub open: bool,
+    cache_usage: Option<u64>, // Bytes, read when the window opens rather than every frame
+    limit_edit: Option<u64>,  // Limit being edited, applied once the user lets go of the field
+}
+
+impl PreferencesWindow {
+    pub fn load(ctx: &egui::Context) -> Self {
+        ctx.memory(|mem| mem.data.get_temp::<PreferencesWindow>(egui::Id::new("preferences")))
+            .unwrap_or_default()
+    }
+
+    pub fn store(self, ctx: &egui::Context) {
+        ctx.memory_mut(|mem| mem.data.insert_temp(egui::Id::new("preferences"), self));
+    }
+
+    /// Draws the window if it is open. Returns the setting the user changed, if any.
+    pub fn draw(&mut self, ctx: &egui::Context) -> Option<PreferencesChange> {
+        let mut change = None;
+        let mut open = self.open;
+        let usage = *self.cache_usage.get_or_insert_with(pcm_cache::usage);
+
+        egui::Window::new("Preferences")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.heading("Decoded audio cache");
+                ui.label("Decoded files are kept on disk so projects reopen without decoding again.");
+                ui.add_space(4.0);
+
+                ui.horizontal(|ui| {
+                    ui.label("Size limit:");
+                    let mut limit = self.limit_edit.unwrap_or_else(pcm_cache::limit_mb);
+                    let response = ui
+                        .add(
+                            egui::DragValue::new(&mut limit)
+                                .range(0..=1_000_000)
+                                .speed(64.0)
+                                .suffix(" MB"),
+                        )
+                        .on_hover_text("0 turns the cache off");
+                    if response.changed() {
+                        self.limit_edit = Some(limit);
+                    }
+                    // Lowering the limit evicts files, so don't apply every step of a drag
+                    if !response.dragged() && !response.has_focus() {
+                        if let Some(limit) = self.limit_edit.take() {
+                            if limit != pcm_cache::limit_mb() {
+                                change = Some(PreferencesChange::PcmCacheLimit(limit));
+                            }
+                        }
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label(format!("In use: {:.1} MB", usage as f64 / (1024.0 * 1024.0)));
+                    if ui.button("Clear").clicked() {
+                        change = Some(PreferencesChange::ClearPcmCache);
+                    }
+                });
+            });
+
+        // Usage is read again after anything that changes it, and next time the window opens
+        if change.is_some() || !open {
+            self.cache_usage = None;
+        }
+        self.open = open;
+        change
+    }
+}
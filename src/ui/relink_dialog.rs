@@ -0,0 +1,180 @@
+use crate::media::{MediaSearch, MissingMedia};
+use eframe::egui;
+use egui::{Color32, RichText};
+use rfd::FileDialog;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// What the user decided in the relink dialog
+pub enum RelinkResult {
+    Relink(Vec<(PathBuf, PathBuf)>), // (missing path, replacement)
+    Dismiss,
+}
+
+/// Dialog listing missing audio files and searching folders for replacements
+#[derive(Clone, Default)]
+pub struct RelinkDialog {
+    search_folders: Vec<PathBuf>,
+    found: HashMap<PathBuf, PathBuf>, // Missing path -> replacement
+    searched: bool,
+    search: Option<MediaSearch>, // Search still running
+}
+
+impl RelinkDialog {
+    pub fn load(ctx: &egui::Context) -> Self {
+        ctx.memory(|mem| mem.data.get_temp::<RelinkDialog>(egui::Id::new("relink_dialog")))
+            .unwrap_or_default()
+    }
+
+    pub fn store(self, ctx: &egui::Context) {
+        ctx.memory_mut(|mem| mem.data.insert_temp(egui::Id::new("relink_dialog"), self));
+    }
+
+    pub fn draw(&mut self, ctx: &egui::Context, missing: &[MissingMedia]) -> Option<RelinkResult> {
+        let mut result = None;
+
+        // Replacements from a search that finished since the last frame
+        if let Some(found) = self.search.as_ref().and_then(|search| search.results()) {
+            self.found.extend(found);
+            self.searched = true;
+            self.search = None;
+        }
+        let searching = self.search.is_some();
+        if searching {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        }
+
+        egui::Window::new("Missing Audio Files")
+            .collapsible(false)
+            .resizable(true)
+            .default_width(560.0)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} audio file(s) used by this project could not be found.",
+                    missing.len()
+                ));
+                ui.add_space(4.0);
+
+                egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                    egui::Grid::new("missing_media_grid")
+                        .num_columns(3)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for entry in missing {
+                                ui.label(RichText::new(entry.file_name()).strong())
+                                    .on_hover_text(entry.path.display().to_string());
+                                ui.label(format!("{} clip(s)", entry.clip_count));
+
+                                ui.horizontal(|ui| {
+                                    match self.found.get(&entry.path) {
+                                        Some(replacement) => {
+                                            ui.label(
+                                                RichText::new("Found")
+                                                    .color(Color32::from_rgb(100, 200, 100)),
+                                            )
+                                            .on_hover_text(replacement.display().to_string());
+                                        }
+                                        None => {
+                                            ui.label(
+                                                RichText::new("Missing")
+                                                    .color(Color32::from_rgb(255, 100, 100)),
+                                            );
+                                        }
+                                    }
+                                    if ui.small_button("Locate…").clicked() {
+                                        if let Some(file) = FileDialog::new()
+                                            .set_title(format!("Locate {}", entry.file_name()))
+                                            .pick_file()
+                                        {
+                                            self.found.insert(entry.path.clone(), file);
+                                        }
+                                    }
+                                });
+                                ui.end_row();
+                            }
+                        });
+                });
+
+                ui.separator();
+
+                // Folders to search
+                ui.horizontal(|ui| {
+                    ui.label("Search in:");
+                    if ui.button("Add Folder…").clicked() {
+                        if let Some(folder) = FileDialog::new().pick_folder() {
+                            if !self.search_folders.contains(&folder) {
+                                self.search_folders.push(folder);
+                            }
+                        }
+                    }
+                });
+                let mut remove = None;
+                for (i, folder) in self.search_folders.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.small_button("✕").clicked() {
+                            remove = Some(i);
+                        }
+                        ui.label(folder.display().to_string());
+                    });
+                }
+                if let Some(i) = remove {
+                    self.search_folders.remove(i);
+                }
+
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    let can_search = !self.search_folders.is_empty() && !searching;
+                    if ui
+                        .add_enabled(can_search, egui::Button::new("Search"))
+                        .on_hover_text("Match by file name, size and content hash")
+                        .clicked()
+                    {
+                        let still_missing: Vec<MissingMedia> = missing
+                            .iter()
+                            .filter(|m| !self.found.contains_key(&m.path))
+                            .cloned()
+                            .collect();
+                        match MediaSearch::start(still_missing, self.search_folders.clone()) {
+                            Ok(search) => self.search = Some(search),
+                            Err(e) => log::error!(target: "project", "{}", e),
+                        }
+                    }
+                    if searching {
+                        ui.spinner();
+                        ui.label("Searching…");
+                    } else if self.searched {
+                        ui.label(format!("{} of {} found", self.found.len(), missing.len()));
+                    }
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(!self.found.is_empty(), egui::Button::new("Relink"))
+                        .clicked()
+                    {
+                        let relinks = self
+                            .found
+                            .iter()
+                            .filter(|(old, _)| missing.iter().any(|m| &m.path == *old))
+                            .map(|(old, new)| (old.clone(), new.clone()))
+                            .collect();
+                        result = Some(RelinkResult::Relink(relinks));
+                    }
+                    if ui.button("Leave Missing").clicked() {
+                        result = Some(RelinkResult::Dismiss);
+                    }
+                });
+            });
+
+        if result.is_some() {
+            // Start fresh the next time files go missing
+            if let Some(search) = &self.search {
+                search.cancel();
+            }
+            *self = RelinkDialog::default();
+        }
+        result
+    }
+}